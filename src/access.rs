@@ -0,0 +1,201 @@
+//! --access-log: Apache and Nginx access logs in the common or combined
+//! format, read as fields (method, path, status, bytes, referrer, user
+//! agent), with requests counted by status and by path.
+//!
+//! `127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /a.gif HTTP/1.0" 200 2326 "http://example.com/" "Mozilla/4.08"`
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fields::{self, Object};
+
+/// The fields of an access log line; None if it isn't one. `msg` is the
+/// method and path, so templates group requests by endpoint, and `level`
+/// comes from the status: 5xx ERROR, 4xx WARN, the rest INFO.
+pub fn parse(line: &str) -> Option<Object> {
+    let (host, rest) = word(line.trim_start())?;
+    let (ident, rest) = word(rest)?;
+    let (user, rest) = word(rest)?;
+    let rest = rest.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let time = &rest[..end];
+    let (request, rest) = quoted(rest[end + 1..].trim_start())?;
+    let (status, rest) = word(rest)?;
+    let status: u16 = status.parse().ok().filter(|s| (100..600).contains(s))?;
+    let (bytes, rest) = word(rest)?;
+    let bytes: u64 = if bytes == "-" { 0 } else { bytes.parse().ok()? };
+    let (referrer, user_agent) = match quoted(rest) {
+        Some((referrer, rest)) => (Some(referrer), quoted(rest).map(|(ua, _)| ua)),
+        None => (None, None),
+    };
+
+    let mut object = Object::new();
+    let mut put = |key: &str, value: &str| {
+        if value != "-" && !value.is_empty() {
+            object.insert(key.to_string(), Value::String(value.to_string()));
+        }
+    };
+    put("host", host);
+    put("ident", ident);
+    put("user", user);
+    put("time", time);
+    put("request", &request);
+    let mut parts = request.split(' ');
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    put("method", method);
+    put("path", path);
+    put("protocol", parts.next().unwrap_or(""));
+    put("referrer", referrer.as_deref().unwrap_or(""));
+    put("user_agent", user_agent.as_deref().unwrap_or(""));
+    let level = match status {
+        500.. => "ERROR",
+        400..=499 => "WARN",
+        _ => "INFO",
+    };
+    put("level", level);
+    // Set even for a "-" request (a probe that sent none), so the host,
+    // date and user agent aren't taken for the message and counted
+    let msg = format!("{} {}", method, path);
+    object.insert("msg".to_string(), Value::String(msg.trim().to_string()));
+    object.insert("status".to_string(), status.into());
+    object.insert("bytes".to_string(), bytes.into());
+    Some(object)
+}
+
+/// The text up to the next space, and the text after it.
+fn word(text: &str) -> Option<(&str, &str)> {
+    let end = text.find(' ').unwrap_or(text.len());
+    if end == 0 {
+        return None;
+    }
+    Some((&text[..end], text[end..].trim_start()))
+}
+
+/// A "quoted" string with `\"` and `\\` escapes, and the text after it.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let inner = text.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = inner.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, inner[i + 1..].trim_start())),
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Requests to one path.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PathStat {
+    pub requests: usize,
+    /// Of those, answered with a 5xx status
+    pub server_errors: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccessTally {
+    pub requests: usize,
+    pub statuses: BTreeMap<u16, usize>,
+    /// By path without its query string
+    pub paths: HashMap<String, PathStat>,
+}
+
+impl AccessTally {
+    /// Counts the request in `object`, as parse made it.
+    pub fn record(&mut self, object: &Object) {
+        let Some(status) = object.get("status").and_then(Value::as_u64) else {
+            return;
+        };
+        self.requests += 1;
+        *self.statuses.entry(status as u16).or_insert(0) += 1;
+        let path = fields::field(object, "path").unwrap_or_default();
+        let path = path.split('?').next().unwrap_or("");
+        let stat = match self.paths.get_mut(path) {
+            Some(stat) => stat,
+            None => self.paths.entry(path.to_string()).or_default(),
+        };
+        stat.requests += 1;
+        if status >= 500 {
+            stat.server_errors += 1;
+        }
+        stat.bytes += object.get("bytes").and_then(Value::as_u64).unwrap_or(0);
+    }
+
+    pub fn merge(&mut self, other: AccessTally) {
+        self.requests += other.requests;
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_insert(0) += count;
+        }
+        for (path, more) in other.paths {
+            let stat = self.paths.entry(path).or_default();
+            stat.requests += more.requests;
+            stat.server_errors += more.server_errors;
+            stat.bytes += more.bytes;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_combined_lines() {
+        let object = parse(
+            r#"10.0.0.7 - bob [10/Oct/2024:13:55:36 +0000] "GET /api/v1?page=2 HTTP/1.1" 503 512 "https://example.com/" "curl/8.5 \"x\"""#,
+        )
+        .unwrap();
+        let text = |key: &str| object.get(key).and_then(Value::as_str);
+        assert_eq!(text("host"), Some("10.0.0.7"));
+        assert_eq!(text("ident"), None);
+        assert_eq!(text("user"), Some("bob"));
+        assert_eq!(text("time"), Some("10/Oct/2024:13:55:36 +0000"));
+        assert_eq!(text("method"), Some("GET"));
+        assert_eq!(text("path"), Some("/api/v1?page=2"));
+        assert_eq!(text("protocol"), Some("HTTP/1.1"));
+        assert_eq!(text("msg"), Some("GET /api/v1?page=2"));
+        assert_eq!(text("referrer"), Some("https://example.com/"));
+        assert_eq!(text("user_agent"), Some("curl/8.5 \"x\""));
+        assert_eq!(text("level"), Some("ERROR"));
+        assert_eq!(object["status"], 503);
+        assert_eq!(object["bytes"], 512);
+
+        assert!(parse("not an access log line").is_none());
+        assert!(parse(r#"h - - [t] "GET / HTTP/1.1" 999 0"#).is_none());
+    }
+
+    #[test]
+    fn a_missing_request_is_still_the_message() {
+        let object = parse(r#"10.0.0.7 - - [10/Oct/2024:13:55:36 +0000] "-" 408 -"#).unwrap();
+        assert_eq!(object["msg"], "-");
+        assert_eq!(object.get("method"), None);
+        assert_eq!(object.get("request"), None);
+        assert_eq!(object["level"], "WARN");
+        assert_eq!(object["bytes"], 0);
+    }
+
+    #[test]
+    fn tallies_requests_by_path() {
+        let mut tally = AccessTally::default();
+        for line in [
+            r#"h - - [t] "GET /a?x=1 HTTP/1.1" 200 100"#,
+            r#"h - - [t] "GET /a HTTP/1.1" 502 20"#,
+        ] {
+            tally.record(&parse(line).unwrap());
+        }
+        let mut more = AccessTally::default();
+        more.record(&parse(r#"h - - [t] "POST /b HTTP/1.1" 201 5"#).unwrap());
+        tally.merge(more);
+        assert_eq!(tally.requests, 3);
+        assert_eq!(tally.statuses[&502], 1);
+        let a = &tally.paths["/a"];
+        assert_eq!((a.requests, a.server_errors, a.bytes), (2, 1, 120));
+        assert_eq!(tally.paths["/b"].requests, 1);
+    }
+}
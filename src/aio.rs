@@ -0,0 +1,223 @@
+//! --io-backend async: files are read through tokio, with several reads
+//! of each file in flight at once, for network filesystems (NFS, SMB)
+//! where every read waits on a round trip. The scan threads still split
+//! and count the lines; they read from a stream of chunks fetched ahead
+//! of them.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::sync::Arc;
+
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, Semaphore};
+
+/// Bytes asked for by one read, normally.
+const CHUNK: usize = 512 << 10;
+
+/// Reads of one file in flight at once; with the chunks waiting in its
+/// channel this bounds the memory per file being scanned.
+const AHEAD_PER_FILE: usize = 8;
+
+/// Chunks read but not yet taken by the scan thread, per file.
+const WAITING_PER_FILE: usize = 2;
+
+/// --io-depth when not given.
+pub const DEFAULT_DEPTH: usize = 32;
+
+type Chunk = io::Result<Vec<u8>>;
+
+/// The runtime the reads run on, shared by every scan thread.
+pub struct AsyncReads {
+    runtime: Runtime,
+    /// One permit per read in flight, over all files (--io-depth)
+    in_flight: Arc<Semaphore>,
+    /// Bytes asked for by one read
+    chunk: usize,
+}
+
+impl AsyncReads {
+    pub fn start(depth: usize) -> Result<AsyncReads, String> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .max_blocking_threads(depth)
+            .thread_name("logbuddy-io")
+            .build()
+            .map_err(|e| format!("could not start the async I/O backend: {}", e))?;
+        Ok(AsyncReads {
+            runtime,
+            in_flight: Arc::new(Semaphore::new(depth)),
+            chunk: CHUNK,
+        })
+    }
+
+    /// Starts reading `file` from the start; its bytes come out of the
+    /// returned reader in order. Dropping the reader stops the reads.
+    pub fn stream(&self, file: File) -> ChunkReader {
+        let (tx, rx) = mpsc::channel(WAITING_PER_FILE);
+        self.runtime.spawn(fetch(
+            Arc::new(file),
+            self.chunk,
+            self.in_flight.clone(),
+            tx,
+        ));
+        ChunkReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+/// Reads `file` chunk by chunk, `chunk` bytes at a time, into `tx`,
+/// keeping reads of the chunks after the next one going. Past the size
+/// the file had at the start it goes on one read at a time, in case it
+/// grew.
+async fn fetch(file: Arc<File>, chunk: usize, in_flight: Arc<Semaphore>, tx: mpsc::Sender<Chunk>) {
+    let size = file.metadata().map_or(0, |m| m.len());
+    let mut reads = VecDeque::new();
+    let mut offset = 0;
+    loop {
+        while reads.len() < AHEAD_PER_FILE && (offset < size || reads.is_empty()) {
+            let permit = match in_flight.clone().acquire_owned().await {
+                Ok(p) => p,
+                Err(_) => return,
+            };
+            let file = file.clone();
+            let at = offset;
+            reads.push_back(tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                read_chunk(&file, at, chunk)
+            }));
+            offset += chunk as u64;
+        }
+        let bytes = match reads.pop_front() {
+            Some(read) => read.await.unwrap_or_else(|e| Err(io::Error::other(e))),
+            None => return,
+        };
+        // A short chunk is the end of the file
+        let last = !matches!(&bytes, Ok(b) if b.len() == chunk);
+        if tx.send(bytes).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+/// Up to `len` bytes of `file` from `offset`; fewer only at the end.
+fn read_chunk(file: &File, offset: u64, len: usize) -> Chunk {
+    let mut buf = vec![0; len];
+    let mut filled = 0;
+    while filled < len {
+        match read_at(file, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    buf.truncate(filled);
+    Ok(buf)
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// A file's bytes as AsyncReads fetches them.
+pub struct ChunkReader {
+    rx: mpsc::Receiver<Chunk>,
+    chunk: Vec<u8>,
+    /// Bytes of `chunk` already consumed
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ChunkReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                None => break,
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.chunk.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("logbuddy-aio-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn lines(reader: impl BufRead) -> Vec<Vec<u8>> {
+        reader.split(b'\n').map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn chunks_read_as_the_file_does() {
+        // Few reads in flight, so later chunks wait for a permit too
+        let reads = AsyncReads {
+            chunk: 7,
+            ..AsyncReads::start(2).unwrap()
+        };
+        let long = format!("{}\n", "x".repeat(40));
+        let cases: [(&str, Vec<u8>); 4] = [
+            (
+                "lines",
+                b"one\ntwo words\r\n\nthree\nfour and more\n5".to_vec(),
+            ),
+            // Three whole chunks, so the last read comes back empty
+            ("boundary", b"abc\nde\nfghijk\nlmnopq\n".to_vec()),
+            (
+                "long",
+                [long.as_bytes(), "é\n".as_bytes(), long.as_bytes()].concat(),
+            ),
+            ("empty", Vec::new()),
+        ];
+        for (name, bytes) in cases {
+            let path = temp_file(name, &bytes);
+            let streamed = lines(reads.stream(File::open(&path).unwrap()));
+            let synced = lines(BufReader::new(File::open(&path).unwrap()));
+            assert_eq!(streamed, synced, "{}", name);
+
+            let mut all = Vec::new();
+            reads
+                .stream(File::open(&path).unwrap())
+                .read_to_end(&mut all)
+                .unwrap();
+            assert_eq!(all, bytes, "{}", name);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
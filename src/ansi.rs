@@ -0,0 +1,50 @@
+//! ANSI escape sequences in lines from terminal output (colors, cursor
+//! moves, OSC 8 links), removed before lines are searched and counted.
+
+use std::borrow::Cow;
+
+/// The escape character that starts every sequence.
+const ESC: u8 = 0x1b;
+
+/// `line` without escape sequences: CSI (`ESC [ ... m`), OSC (`ESC ] ...`
+/// ended by BEL or `ESC \`) and two-character ones like `ESC (B`.
+pub fn strip(line: &str) -> Cow<'_, str> {
+    if memchr::memchr(ESC, line.as_bytes()).is_none() {
+        return Cow::Borrowed(line);
+    }
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC as char {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters and intermediates, up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == ESC as char && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Character set selection takes one more character
+            Some('(' | ')' | '*' | '+') => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(out)
+}
@@ -0,0 +1,187 @@
+//! Archives, as support bundles arrive (`bundle.tar.gz`, `logs.zip`): the
+//! files inside are read one after another straight from the archive,
+//! without unpacking it first.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::compress::{self, Compression};
+use crate::rotation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Archive {
+    Tar,
+    Zip,
+}
+
+impl Archive {
+    /// The kind of archive `name` is, by its extension: `.tar` (compressed
+    /// too, as `.tar.gz`), `.tgz` or `.zip`.
+    pub fn of(name: &str) -> Option<Archive> {
+        match rotation::extension(name).as_str() {
+            "tar" | "tgz" => Some(Archive::Tar),
+            "zip" => Some(Archive::Zip),
+            _ => None,
+        }
+    }
+}
+
+/// Calls `file` with the path and contents of each regular file in the
+/// archive at `path`, in archive order, until it returns false. Zip
+/// entries that can't be read, such as encrypted ones, are skipped with a
+/// warning.
+pub fn for_each_file(
+    path: &Path,
+    archive: Archive,
+    mut file: impl FnMut(&str, &mut dyn Read) -> bool,
+) -> io::Result<()> {
+    let opened = File::open(path)?;
+    match archive {
+        Archive::Tar => {
+            let reader: Box<dyn Read> = match Compression::detect(path) {
+                Some(compression) => compress::decompress(opened, compression)?.0,
+                None => Box::new(opened),
+            };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                if !file(&name, &mut entry) {
+                    break;
+                }
+            }
+        }
+        Archive::Zip => {
+            let mut zip = zip::ZipArchive::new(opened).map_err(io::Error::other)?;
+            for i in 0..zip.len() {
+                let opened = zip
+                    .by_index(i)
+                    .and_then(|entry| Ok((entry.name()?.to_string(), entry)));
+                let (name, mut entry) = match opened {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        warn!("skipping file {} of {}: {}", i + 1, path.display(), e);
+                        continue;
+                    }
+                };
+                if !entry.is_file() {
+                    continue;
+                }
+                if !file(&name, &mut entry) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use flate2::write::GzEncoder;
+    use zip::write::SimpleFileOptions;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("logbuddy-archive-{}-{}", std::process::id(), name))
+    }
+
+    /// Every file in the archive at `path`, with its text.
+    fn files(path: &Path, archive: Archive, limit: usize) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        for_each_file(path, archive, |name, reader| {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            files.push((name.to_string(), text));
+            files.len() < limit
+        })
+        .unwrap();
+        files
+    }
+
+    fn owned(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|(name, text)| (name.to_string(), text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn kinds_by_name() {
+        assert_eq!(Archive::of("bundle.tar"), Some(Archive::Tar));
+        assert_eq!(Archive::of("bundle.tar.gz"), Some(Archive::Tar));
+        assert_eq!(Archive::of("bundle.TGZ"), Some(Archive::Tar));
+        assert_eq!(Archive::of("logs.zip"), Some(Archive::Zip));
+        assert_eq!(Archive::of("app.log.gz"), None);
+        assert_eq!(Archive::of("tar"), None);
+    }
+
+    #[test]
+    fn reads_compressed_tars() {
+        let path = temp_path("bundle.tar.gz");
+        let gz = GzEncoder::new(File::create(&path).unwrap(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(gz);
+        let mut add = |name: &str, text: &str, kind: tar::EntryType| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, text.as_bytes())
+                .unwrap();
+        };
+        add("logs/", "", tar::EntryType::Directory);
+        add(
+            "logs/app.log",
+            "INFO one\nERROR two\n",
+            tar::EntryType::Regular,
+        );
+        add("logs/db.log", "WARN slow\n", tar::EntryType::Regular);
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let all = files(&path, Archive::Tar, usize::MAX);
+        assert_eq!(
+            all,
+            owned(&[
+                ("logs/app.log", "INFO one\nERROR two\n"),
+                ("logs/db.log", "WARN slow\n")
+            ])
+        );
+        // Stops when told to
+        assert_eq!(files(&path, Archive::Tar, 1).len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_zips() {
+        let path = temp_path("logs.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        zip.add_directory("logs/", options).unwrap();
+        zip.start_file("logs/app.log", options).unwrap();
+        zip.write_all(b"INFO zipped\n").unwrap();
+        zip.start_file("b.txt", options).unwrap();
+        zip.write_all(b"second\n").unwrap();
+        zip.finish().unwrap();
+
+        let all = files(&path, Archive::Zip, usize::MAX);
+        assert_eq!(
+            all,
+            owned(&[("logs/app.log", "INFO zipped\n"), ("b.txt", "second\n")])
+        );
+        assert!(for_each_file(&temp_path("missing.zip"), Archive::Zip, |_, _| true).is_err());
+        std::fs::write(&path, "not a zip").unwrap();
+        assert!(for_each_file(&path, Archive::Zip, |_, _| true).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
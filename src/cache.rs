@@ -0,0 +1,399 @@
+//! --cache: per-file scan results saved on disk, keyed by the file's path,
+//! size and modification time (or version, for a source's) plus every
+//! option that changes what a scan counts, so unchanged files are not read
+//! again.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::cli::{Config, FieldKey};
+use crate::fingerprint;
+use crate::level::Level;
+use crate::scanner::FileScan;
+
+/// Bump when the saved layout changes, so old entries are ignored.
+const FORMAT: u32 = 34;
+
+pub struct ScanCache {
+    dir: PathBuf,
+    /// Options that change per-file results, as text
+    options: String,
+}
+
+/// One cache file: the full key, checked on load in case two keys share
+/// a fingerprint, the name the file was scanned as, and the scans: one for
+/// a log, one per log inside an archive.
+#[derive(Serialize, Deserialize)]
+struct Entry<S> {
+    key: String,
+    name: String,
+    scans: S,
+}
+
+impl ScanCache {
+    /// `keep_matches` and `keep_hits` say whether scans keep every match
+    /// and the hit lines; entries without them don't stand in for ones with.
+    pub fn open(
+        dir: &str,
+        config: &Config,
+        keep_matches: bool,
+        keep_hits: bool,
+    ) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("could not create cache {}: {}", dir, e))?;
+        Ok(ScanCache {
+            dir: PathBuf::from(dir),
+            options: scan_options(config, keep_matches, keep_hits),
+        })
+    }
+
+    /// The key for a file with `stamp` (see file_stamp).
+    pub fn key(&self, stamp: &str) -> String {
+        format!("{} | {}", stamp, self.options)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.json", fingerprint::fingerprint(key)))
+    }
+
+    /// The scans saved for `key`, under the file's `name` now: a file
+    /// scanned from another folder had another name relative to it.
+    pub fn load(&self, key: &str, name: &str) -> Option<Vec<FileScan>> {
+        let path = self.entry_path(key);
+        let file = File::open(&path).ok()?;
+        let loaded = serde_json::from_reader::<_, Entry<Value>>(BufReader::new(file)).and_then(
+            |mut entry| {
+                if entry.key != key {
+                    return Ok(None);
+                }
+                if entry.name != name {
+                    rename(&mut entry.scans, &entry.name, name);
+                }
+                serde_json::from_value(entry.scans).map(Some)
+            },
+        );
+        loaded.unwrap_or_else(|e| {
+            debug!("ignoring cache entry {}: {}", path.display(), e);
+            None
+        })
+    }
+
+    /// Saves the scans of the file `name`; failures only cost the next run
+    /// a re-read.
+    pub fn store(&self, key: &str, name: &str, scans: Vec<FileScan>) -> Vec<FileScan> {
+        let path = self.entry_path(key);
+        // Written aside and renamed, so a parallel or interrupted run never
+        // sees half an entry
+        let partial = path.with_extension(format!("tmp{}", std::process::id()));
+        let entry = Entry {
+            key: key.to_string(),
+            name: name.to_string(),
+            scans,
+        };
+        let written = File::create(&partial)
+            .map_err(|e| e.to_string())
+            .and_then(|f| {
+                serde_json::to_writer(BufWriter::new(f), &entry).map_err(|e| e.to_string())
+            })
+            .and_then(|()| fs::rename(&partial, &path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("could not write cache entry {}: {}", path.display(), e);
+            let _ = fs::remove_file(&partial);
+        }
+        entry.scans
+    }
+}
+
+/// Renames the file `from` to `to` wherever a scan of it names it: as the
+/// scan's and affected files' name, and before the `:line` of samples and
+/// the `!entry` of logs in an archive.
+fn rename(value: &mut Value, from: &str, to: &str) {
+    match value {
+        Value::String(text) => {
+            if let Some(rest) = text.strip_prefix(from) {
+                if rest.is_empty() || rest.starts_with([':', '!']) {
+                    *text = format!("{}{}", to, rest);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| rename(v, from, to)),
+        Value::Object(fields) => fields.values_mut().for_each(|v| rename(v, from, to)),
+        _ => {}
+    }
+}
+
+/// Every option that changes what a scan of one file counts, as text.
+/// Not --since, which --last moves on every run: a scan records which
+/// values it holds for instead.
+pub fn scan_options(config: &Config, keep_matches: bool, keep_hits: bool) -> String {
+    let checks: Vec<(&str, Option<Level>)> = config
+        .checks
+        .iter()
+        .map(|c| (c.pattern.as_str(), c.level))
+        .collect();
+    format!(
+        "v{} format {} find {:?} decode {} lines {:?} fields {:?} checks {:?} fingerprints {:?} levels {:?} min level {:?} multiline {:?} stats {:?} \
+         histogram {:?} max gap {:?} skew {:?} \
+         trace {:?} session {:?} group {:?} count {:?} distinct {:?} squeeze {} ngrams {:?} tokenizer {:?} words {} per file {} \
+         approx {} memory {:?} line {:?} encoding {:?} until {:?} record {} fields {} \
+         matches {} hits {}",
+        env!("CARGO_PKG_VERSION"),
+        FORMAT,
+        config.find,
+        config.decode_nested,
+        config.line_format,
+        config.find_fields,
+        checks,
+        config.fingerprint_levels,
+        config.level_rules.describe(),
+        config.min_level,
+        config.multiline,
+        config
+            .stats_fields
+            .iter()
+            .map(FieldKey::describe)
+            .collect::<Vec<_>>(),
+        config.bucket_width(),
+        config.max_gap,
+        config.skew_key,
+        config.trace_id.as_ref().map(Regex::as_str),
+        config.session_key,
+        config.group_by.as_ref().map(FieldKey::describe),
+        config.count_by.iter().map(Regex::as_str).collect::<Vec<_>>(),
+        config.distinct,
+        config.squeeze_whitespace,
+        config.ngrams,
+        config.tokenizer,
+        config.word_filter.describe(),
+        config.top_per_file,
+        config.approx_counts,
+        config.file_memory_budget(),
+        config.max_line_length,
+        config.encoding,
+        config.until,
+        config.max_record_bytes,
+        config.max_fields,
+        keep_matches,
+        keep_hits
+    )
+}
+
+/// Canonical path, size and modification time of `path`, which change
+/// when the file does; None if it can't be stat'ed.
+pub fn file_stamp(path: &Path) -> Option<String> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let path = fs::canonicalize(path).ok()?;
+    Some(format!(
+        "{} | {} | {}",
+        path.display(),
+        meta.len(),
+        modified.as_nanos()
+    ))
+}
+
+/// Serde helpers for maps whose keys JSON can't hold directly.
+pub mod pairs {
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Writes the map as a list of (key, value) pairs.
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let pairs: Vec<(K, V)> = Vec::deserialize(deserializer)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+/// Errno stats keyed by the static constant names; unknown names (from
+/// another version's table) are dropped on load.
+pub mod errno_stats {
+    use super::*;
+
+    use serde::{Deserializer, Serializer};
+
+    use crate::errno::{self, ErrnoStat};
+
+    pub fn serialize<S: Serializer>(
+        map: &HashMap<&'static str, ErrnoStat>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<&'static str, ErrnoStat>, D::Error> {
+        let map: HashMap<String, ErrnoStat> = HashMap::deserialize(deserializer)?;
+        Ok(map
+            .into_iter()
+            .filter_map(|(name, stat)| Some((errno::known_name(&name)?, stat)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::cli;
+    use crate::scanner::ScanTotals;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("logbuddy-cache-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scan_of(name: &str) -> FileScan {
+        let mut totals = serde_json::to_value(ScanTotals::default()).unwrap();
+        totals["total_lines"] = json!(3);
+        serde_json::from_value(json!({
+            "name": name,
+            "totals": totals,
+            "hits": [],
+            "hit_lines": [1, 2],
+            "since_range": {}
+        }))
+        .unwrap()
+    }
+
+    fn open(dir: &Path, args: &[&str]) -> ScanCache {
+        let config = cli::parse_args(args.iter().map(|a| a.to_string())).unwrap();
+        ScanCache::open(dir.to_str().unwrap(), &config, false, false).unwrap()
+    }
+
+    #[test]
+    fn stores_and_loads_scans() {
+        let dir = temp_dir("entries");
+        let cache = open(&dir, &[]);
+        let key = cache.key("/logs/a.log | 12 | 1");
+        assert!(cache.load(&key, "a.log").is_none());
+        cache.store(&key, "a.log", vec![scan_of("a.log")]);
+
+        let scans = cache.load(&key, "a.log").unwrap();
+        let saved = serde_json::to_value(&scans).unwrap();
+        assert_eq!(saved[0]["name"], "a.log");
+        assert_eq!(saved[0]["totals"]["total_lines"], 3);
+        assert_eq!(saved[0]["hit_lines"], json!([1, 2]));
+        // Written whole, nothing left aside
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(names.len(), 1);
+
+        // Another file, or the same with options that count differently
+        assert!(cache
+            .load(&cache.key("/logs/b.log | 12 | 1"), "a.log")
+            .is_none());
+        let finding = open(&dir, &["--find", "ERROR"]);
+        assert_ne!(finding.key("/logs/a.log | 12 | 1"), key);
+        assert!(finding
+            .load(&finding.key("/logs/a.log | 12 | 1"), "a.log")
+            .is_none());
+        // --last moves --since every run; scans say which ones they hold for
+        assert_eq!(
+            open(&dir, &["--last", "1h"]).key("k"),
+            open(&dir, &["--since", "2024-05-01"]).key("k")
+        );
+        assert_ne!(
+            open(&dir, &["--until", "2024-05-01"]).key("k"),
+            cache.key("k")
+        );
+
+        // A key sharing the fingerprint, and a damaged entry
+        let path = cache.entry_path(&key);
+        let mut entry: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        entry["key"] = json!("someone else's");
+        fs::write(&path, entry.to_string()).unwrap();
+        assert!(cache.load(&key, "a.log").is_none());
+        fs::write(&path, "{\"key\": ").unwrap();
+        assert!(cache.load(&key, "a.log").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stamps_change_with_the_file() {
+        let dir = temp_dir("stamps");
+        let path = dir.join("a.log");
+        assert_eq!(file_stamp(&path), None);
+        fs::write(&path, "one\n").unwrap();
+        let first = file_stamp(&path).unwrap();
+        assert!(first.contains(" | 4 | "), "{}", first);
+        assert_eq!(file_stamp(&path).as_ref(), Some(&first));
+        fs::write(&path, "one\ntwo\n").unwrap();
+        assert_ne!(file_stamp(&path).unwrap(), first);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn maps_keyed_for_json() {
+        #[derive(Serialize, Deserialize)]
+        struct Saved {
+            #[serde(with = "pairs")]
+            by_number: HashMap<u32, String>,
+            #[serde(with = "errno_stats")]
+            errnos: HashMap<&'static str, crate::errno::ErrnoStat>,
+        }
+        let json = json!({
+            "by_number": [[404, "not found"]],
+            "errnos": {
+                "ENOENT": {"count": 2, "files": ["a.log"]},
+                "ENOTANERRNO": {"count": 1, "files": ["a.log"]}
+            }
+        });
+        let saved: Saved = serde_json::from_value(json).unwrap();
+        assert_eq!(saved.by_number[&404], "not found");
+        assert_eq!(saved.errnos.len(), 1);
+        assert_eq!(saved.errnos["ENOENT"].count, 2);
+        let back = serde_json::to_value(&saved).unwrap();
+        assert_eq!(back["by_number"], json!([[404, "not found"]]));
+        assert_eq!(back["errnos"]["ENOENT"]["files"], json!(["a.log"]));
+    }
+
+    #[test]
+    fn renames_the_file_wherever_named() {
+        let mut scans = json!([{
+            "name": "sub/a.log",
+            "errno_stats": [["ENOENT", {"count": 1, "files": ["sub/a.log"]}]],
+            "hit_samples": {"count": 1, "samples": ["sub/a.log:3: ERROR sub/a.log"]},
+            "archive": "sub/a.log!logs/app.log",
+            "other": ["sub/a.log.1", "sub/a.logs", "see sub/a.log", 3]
+        }]);
+        rename(&mut scans, "sub/a.log", "a.log");
+        assert_eq!(
+            scans,
+            json!([{
+                "name": "a.log",
+                "errno_stats": [["ENOENT", {"count": 1, "files": ["a.log"]}]],
+                "hit_samples": {"count": 1, "samples": ["a.log:3: ERROR sub/a.log"]},
+                "archive": "a.log!logs/app.log",
+                "other": ["sub/a.log.1", "sub/a.logs", "see sub/a.log", 3]
+            }])
+        );
+    }
+}
@@ -0,0 +1,413 @@
+//! --cef and --leef: security events in ArcSight CEF or IBM QRadar LEEF,
+//! as SIEMs export them, read as fields (the header's vendor, product,
+//! event and severity, and every extension key), with events counted by
+//! severity, name and source address.
+//!
+//! `CEF:0|Security|threatmanager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232`
+//! `LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=192.0.2.0\tdst=172.50.123.1\tsev=5`
+//!
+//! Either may follow a syslog header, whose time is then the line's unless
+//! the event has its own (rt, devTime).
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fields::{self, Object};
+use crate::timestamp;
+
+/// Severity bands, lowest first, as CEF names them: 0-3, 4-6, 7-8, 9-10.
+pub const SEVERITIES: [&str; 4] = ["Low", "Medium", "High", "Very-High"];
+
+/// Level words for the severity bands (level::Level).
+const LEVELS: [&str; 4] = ["INFO", "WARN", "ERROR", "FATAL"];
+
+const CEF_HEADER: [&str; 7] = [
+    "cef_version",
+    "vendor",
+    "product",
+    "device_version",
+    "signature_id",
+    "name",
+    "severity",
+];
+
+const LEEF_HEADER: [&str; 5] = [
+    "leef_version",
+    "vendor",
+    "product",
+    "device_version",
+    "event_id",
+];
+
+/// Fields holding the event's own time, tried in order.
+const TIME_FIELDS: [&str; 4] = ["rt", "devTime", "end", "start"];
+
+/// The fields of a CEF or LEEF line; None if it is neither. `level` comes
+/// from the severity band and `msg`, unless an extension sets it, is the
+/// event name (LEEF event id). A syslog header's date without a year gets
+/// the one that puts it at most a day after `near`, as with --syslog.
+pub fn parse(line: &str, near: i64) -> Option<Object> {
+    let (start, leef) = find_start(line)?;
+    let mut object = if leef {
+        parse_leef(&line[start + "LEEF:".len()..])?
+    } else {
+        parse_cef(&line[start + "CEF:".len()..])?
+    };
+    if let Some(band) = band(&object) {
+        object.entry("level").or_insert_with(|| LEVELS[band].into());
+    }
+    let name = fields::field(&object, "name")
+        .or_else(|| fields::field(&object, "event_id"))
+        .map(|n| n.into_owned());
+    if let Some(name) = name {
+        object.entry("msg").or_insert(Value::String(name));
+    }
+    let header = line[..start].trim_start();
+    let millis = TIME_FIELDS
+        .iter()
+        .find_map(|name| timestamp::parse_line(&fields::field(&object, name)?, near))
+        .or_else(|| timestamp::parse_line(header, near).filter(|_| start > 0));
+    if let Some(millis) = millis {
+        object
+            .entry("time")
+            .or_insert_with(|| timestamp::format_iso(millis).into());
+    }
+    Some(object)
+}
+
+/// Where `CEF:` or `LEEF:` starts the line or a word in it, and whether
+/// it is LEEF.
+fn find_start(line: &str) -> Option<(usize, bool)> {
+    [("CEF:", false), ("LEEF:", true)]
+        .iter()
+        .filter_map(|&(tag, leef)| {
+            let start = line
+                .match_indices(tag)
+                .map(|(i, _)| i)
+                .find(|&i| i == 0 || line.as_bytes()[i - 1] == b' ')?;
+            Some((start, leef))
+        })
+        .min()
+}
+
+/// `Version|Vendor|Product|Version|SignatureID|Name|Severity|Extension`,
+/// `|` and `\` escaped with `\` in the header.
+fn parse_cef(text: &str) -> Option<Object> {
+    let (header, extension) = split_header(text, CEF_HEADER.len())?;
+    let mut object = Object::new();
+    for (name, value) in CEF_HEADER.iter().zip(header) {
+        object.insert(name.to_string(), Value::String(value));
+    }
+    for (key, value) in extension_pairs(extension) {
+        object.entry(key).or_insert(Value::String(value));
+    }
+    Some(object)
+}
+
+/// `Version|Vendor|Product|Version|EventID|Attributes`, attributes split at
+/// tabs, or with LEEF 2.0 at the character given after the event id (as
+/// itself or in hex, `x5E` or `0x5E`).
+fn parse_leef(text: &str) -> Option<Object> {
+    let version = text.split('|').next()?;
+    let two = version.starts_with('2');
+    let fields = LEEF_HEADER.len() + usize::from(two);
+    let (mut header, attributes) = split_header(text, fields)?;
+    let separator = if two {
+        separator(&header.pop()?)?
+    } else {
+        '\t'
+    };
+    let mut object = Object::new();
+    for (name, value) in LEEF_HEADER.iter().zip(header) {
+        object.insert(name.to_string(), Value::String(value));
+    }
+    for attribute in attributes.split(separator) {
+        if let Some((key, value)) = attribute.split_once('=') {
+            let key = key.trim();
+            if !key.is_empty() {
+                object.entry(key).or_insert_with(|| value.trim().into());
+            }
+        }
+    }
+    Some(object)
+}
+
+/// The LEEF 2.0 attribute separator; empty means tab.
+fn separator(text: &str) -> Option<char> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('x'))
+        .filter(|h| !h.is_empty());
+    if let Some(hex) = hex {
+        return char::from_u32(u32::from_str_radix(hex, 16).ok()?);
+    }
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Some('\t'),
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// The first `count` `|`-separated fields, unescaped, and the text after
+/// them; None if there are fewer.
+fn split_header(text: &str, count: usize) -> Option<(Vec<String>, &str)> {
+    let mut fields = Vec::with_capacity(count);
+    let mut field = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next().map(|(_, c)| c)),
+            '|' => {
+                fields.push(std::mem::take(&mut field));
+                if fields.len() == count {
+                    return Some((fields, &text[i + 1..]));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    None
+}
+
+/// The `key=value` pairs of a CEF extension. Values may hold spaces, and
+/// `\=`, `\\`, `\n` and `\r`; a value ends at the space before the next
+/// key.
+fn extension_pairs(text: &str) -> Vec<(String, String)> {
+    let bytes = text.as_bytes();
+    // (key start, `=` position) of every key
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if i == 0 || bytes[i - 1] == b' ' {
+            let end = i + bytes[i..]
+                .iter()
+                .take_while(|&&b| {
+                    b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-' | b'[' | b']')
+                })
+                .count();
+            if end > i && bytes.get(end) == Some(&b'=') {
+                keys.push((i, end));
+                i = end + 1;
+                continue;
+            }
+        }
+        if bytes[i] == b'\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    let mut pairs = Vec::with_capacity(keys.len());
+    for (n, &(start, equals)) in keys.iter().enumerate() {
+        let end = keys.get(n + 1).map_or(text.len(), |&(next, _)| next);
+        let value = text[equals + 1..end].trim_end_matches(' ');
+        pairs.push((text[start..equals].to_string(), unescape(value)));
+    }
+    pairs
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The severity band of an event: CEF's severity, or LEEF's sev.
+fn band(object: &Object) -> Option<usize> {
+    ["severity", "sev"]
+        .iter()
+        .find_map(|name| severity_band(&fields::field(object, name)?))
+}
+
+/// Index into SEVERITIES of a severity: 0 to 10, or a band name.
+fn severity_band(text: &str) -> Option<usize> {
+    if let Ok(number) = text.trim().parse::<u8>() {
+        return match number {
+            0..=3 => Some(0),
+            4..=6 => Some(1),
+            7..=8 => Some(2),
+            9..=10 => Some(3),
+            _ => None,
+        };
+    }
+    let text = text.trim().replace(' ', "-");
+    SEVERITIES
+        .iter()
+        .position(|band| band.eq_ignore_ascii_case(&text))
+}
+
+/// Events by severity band, by name and by source address.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SecurityTally {
+    pub events: usize,
+    /// Per band in SEVERITIES; events without a severity aren't in any
+    pub severities: [usize; 4],
+    /// By CEF name, or LEEF event id
+    pub names: HashMap<String, usize>,
+    /// By `src`
+    pub sources: HashMap<String, usize>,
+}
+
+impl SecurityTally {
+    /// Counts the event in `object`, as parse made it.
+    pub fn record(&mut self, object: &Object) {
+        self.events += 1;
+        if let Some(band) = band(object) {
+            self.severities[band] += 1;
+        }
+        let name = fields::field(object, "name").or_else(|| fields::field(object, "event_id"));
+        if let Some(name) = name {
+            count(&mut self.names, &name);
+        }
+        if let Some(source) = fields::field(object, "src") {
+            count(&mut self.sources, &source);
+        }
+    }
+
+    pub fn merge(&mut self, other: SecurityTally) {
+        self.events += other.events;
+        for (count, more) in self.severities.iter_mut().zip(other.severities) {
+            *count += more;
+        }
+        for (name, count) in other.names {
+            *self.names.entry(name).or_insert(0) += count;
+        }
+        for (source, count) in other.sources {
+            *self.sources.entry(source).or_insert(0) += count;
+        }
+    }
+}
+
+fn count(counts: &mut HashMap<String, usize>, key: &str) {
+    match counts.get_mut(key) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(key.to_string(), 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(object: &Object, name: &str) -> Option<String> {
+        fields::field(object, name).map(|v| v.into_owned())
+    }
+
+    #[test]
+    fn cef_events() {
+        let line = r"CEF:0|Security|threatmanager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232";
+        let event = parse(line, 0).unwrap();
+        assert_eq!(text(&event, "vendor").as_deref(), Some("Security"));
+        assert_eq!(text(&event, "signature_id").as_deref(), Some("100"));
+        assert_eq!(
+            text(&event, "msg").as_deref(),
+            Some("worm successfully stopped")
+        );
+        assert_eq!(text(&event, "level").as_deref(), Some("FATAL"));
+        assert_eq!(text(&event, "src").as_deref(), Some("10.0.0.1"));
+        assert_eq!(text(&event, "spt").as_deref(), Some("1232"));
+        assert_eq!(text(&event, "time"), None);
+
+        // Escapes in the header and the extension; values with spaces
+        let line = r"CEF:0|ACME|fire\|wall|2|7|deny|Medium|act=blocked twice msg=a\=b c\\d\nnext cs1Label=rule name";
+        let event = parse(line, 0).unwrap();
+        assert_eq!(text(&event, "product").as_deref(), Some("fire|wall"));
+        assert_eq!(text(&event, "act").as_deref(), Some("blocked twice"));
+        assert_eq!(text(&event, "msg").as_deref(), Some("a=b c\\d\nnext"));
+        assert_eq!(text(&event, "cs1Label").as_deref(), Some("rule name"));
+        assert_eq!(text(&event, "level").as_deref(), Some("WARN"));
+
+        assert!(parse("CEF:0|too|few|fields", 0).is_none());
+        assert!(parse("no event here", 0).is_none());
+        assert!(parse("XCEF:0|a|b|c|d|e|1|", 0).is_none());
+    }
+
+    #[test]
+    fn leef_events() {
+        let line =
+            "LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=192.0.2.0\tdst=172.50.123.1\tsev=5";
+        let event = parse(line, 0).unwrap();
+        assert_eq!(text(&event, "event_id").as_deref(), Some("15345"));
+        assert_eq!(text(&event, "msg").as_deref(), Some("15345"));
+        assert_eq!(text(&event, "dst").as_deref(), Some("172.50.123.1"));
+        assert_eq!(text(&event, "level").as_deref(), Some("WARN"));
+
+        // LEEF 2.0 names its separator, here as hex
+        let line = "LEEF:2.0|Lancope|StealthWatch|1.0|41|x5E|src=10.0.1.8^dst=10.0.0.5^sev=8";
+        let event = parse(line, 0).unwrap();
+        assert_eq!(text(&event, "src").as_deref(), Some("10.0.1.8"));
+        assert_eq!(text(&event, "dst").as_deref(), Some("10.0.0.5"));
+        assert_eq!(text(&event, "level").as_deref(), Some("ERROR"));
+        assert_eq!(separator(""), Some('\t'));
+        assert_eq!(separator("|"), Some('|'));
+        assert_eq!(separator("0x09"), Some('\t'));
+        assert_eq!(separator("ab"), None);
+    }
+
+    #[test]
+    fn times() {
+        // 2024-05-01 12:00:00 UTC
+        let near = 1_714_564_800_000;
+        let line = "May  1 11:00:00 fw-1 CEF:0|ACME|fw|2|7|deny|3|src=10.0.0.1";
+        let event = parse(line, near).unwrap();
+        assert_eq!(
+            text(&event, "time").as_deref(),
+            Some("2024-05-01T11:00:00.000Z")
+        );
+        // The event's own time wins over the syslog header's
+        let line = "May  1 11:00:00 fw-1 CEF:0|ACME|fw|2|7|deny|3|rt=1714564800000 src=10.0.0.1";
+        let event = parse(line, near).unwrap();
+        assert_eq!(
+            text(&event, "time").as_deref(),
+            Some("2024-05-01T12:00:00.000Z")
+        );
+    }
+
+    #[test]
+    fn severity_bands() {
+        assert_eq!(severity_band("0"), Some(0));
+        assert_eq!(severity_band("6"), Some(1));
+        assert_eq!(severity_band("8"), Some(2));
+        assert_eq!(severity_band(" 10 "), Some(3));
+        assert_eq!(severity_band("11"), None);
+        assert_eq!(severity_band("very high"), Some(3));
+        assert_eq!(severity_band("high"), Some(2));
+        assert_eq!(severity_band("urgent"), None);
+    }
+
+    #[test]
+    fn tallies() {
+        let lines = [
+            "CEF:0|a|b|1|1|login failed|8|src=10.0.0.1",
+            "CEF:0|a|b|1|1|login failed|7|src=10.0.0.2",
+            "CEF:0|a|b|1|2|port scan|2|src=10.0.0.1",
+        ];
+        let mut tally = SecurityTally::default();
+        for line in &lines[..2] {
+            tally.record(&parse(line, 0).unwrap());
+        }
+        let mut other = SecurityTally::default();
+        other.record(&parse(lines[2], 0).unwrap());
+        tally.merge(other);
+        assert_eq!(tally.events, 3);
+        assert_eq!(tally.severities, [1, 0, 2, 0]);
+        assert_eq!(tally.names["login failed"], 2);
+        assert_eq!(tally.names["port scan"], 1);
+        assert_eq!(tally.sources["10.0.0.1"], 2);
+    }
+}
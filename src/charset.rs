@@ -0,0 +1,351 @@
+//! Files that aren't UTF-8: UTF-16 (as Windows apps often write logs)
+//! and Latin-1, found by their byte order mark or a look at their first
+//! bytes, or named with --encoding, are read as UTF-8 through Transcode.
+//! Bytes that don't decode become U+FFFD, so no file is left out for them.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::compress;
+
+/// Bytes looked at to guess the charset of a file without a BOM.
+const SNIFF_BYTES: usize = 4096;
+
+/// Bytes read from the file at a time while transcoding.
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO 8859-1: every byte is the character with its value
+    Latin1,
+}
+
+impl Charset {
+    /// The charset --encoding names: utf-8, utf-16le, utf-16be or latin1
+    /// (also iso-8859-1), in any case.
+    pub fn from_name(name: &str) -> Option<Charset> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(Charset::Utf8),
+            "utf-16le" | "utf16le" | "utf-16" => Some(Charset::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Charset::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Utf16Le => "UTF-16LE",
+            Charset::Utf16Be => "UTF-16BE",
+            Charset::Latin1 => "Latin-1",
+        }
+    }
+
+    /// The charset of a file starting with `sample`: by its BOM, else
+    /// UTF-16 if every other byte is mostly NUL, else UTF-8 if the sample
+    /// is valid UTF-8, else Latin-1.
+    pub fn sniff(sample: &[u8]) -> Charset {
+        if sample.starts_with(&[0xff, 0xfe]) {
+            return Charset::Utf16Le;
+        }
+        if sample.starts_with(&[0xfe, 0xff]) {
+            return Charset::Utf16Be;
+        }
+        if sample.starts_with(&[0xef, 0xbb, 0xbf]) {
+            return Charset::Utf8;
+        }
+        let pairs = sample.len() / 2;
+        if pairs >= 2 {
+            let nul_at = |parity: usize| {
+                sample
+                    .iter()
+                    .skip(parity)
+                    .step_by(2)
+                    .filter(|&&b| b == 0)
+                    .count()
+            };
+            // ASCII text in UTF-16 has a NUL in every other byte
+            if nul_at(1) * 10 >= pairs * 7 && nul_at(0) * 10 < pairs {
+                return Charset::Utf16Le;
+            }
+            if nul_at(0) * 10 >= pairs * 7 && nul_at(1) * 10 < pairs {
+                return Charset::Utf16Be;
+            }
+        }
+        match std::str::from_utf8(sample) {
+            Ok(_) => Charset::Utf8,
+            // A character cut off at the end of the sample is fine
+            Err(e) if e.error_len().is_none() => Charset::Utf8,
+            Err(_) => Charset::Latin1,
+        }
+    }
+
+    /// The charset of the file at `path`, sniffed from its first bytes.
+    pub fn detect(path: &Path) -> io::Result<Charset> {
+        let mut sample = Vec::with_capacity(SNIFF_BYTES);
+        File::open(path)?
+            .take(SNIFF_BYTES as u64)
+            .read_to_end(&mut sample)?;
+        Ok(Charset::sniff(&sample))
+    }
+}
+
+/// The file at `path` as text, in `charset` or the one it looks like,
+/// decompressed if it is compressed.
+pub fn read_to_string(path: &Path, charset: Option<Charset>) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    compress::open(path)?.read_to_end(&mut bytes)?;
+    let charset = charset.unwrap_or_else(|| Charset::sniff(&bytes[..bytes.len().min(SNIFF_BYTES)]));
+    let mut text = String::new();
+    Transcode::new(&bytes[..], charset).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Reads `inner`, in a charset, as UTF-8, leaving out a byte order mark.
+pub struct Transcode<R> {
+    inner: R,
+    charset: Charset,
+    /// Bytes read but not decoded yet: half a UTF-16 unit or surrogate
+    /// pair, or the start of a UTF-8 character
+    pending: Vec<u8>,
+    /// Decoded, waiting to be read
+    out: Vec<u8>,
+    taken: usize,
+    /// Bytes read from `inner` so far
+    read: u64,
+    /// Whether anything has been decoded yet, so a BOM is behind
+    started: bool,
+    eof: bool,
+}
+
+impl<R: Read> Transcode<R> {
+    pub fn new(inner: R, charset: Charset) -> Transcode<R> {
+        Transcode {
+            inner,
+            charset,
+            pending: Vec::new(),
+            out: Vec::new(),
+            taken: 0,
+            read: 0,
+            started: false,
+            eof: false,
+        }
+    }
+
+    /// Bytes read from the underlying reader so far.
+    pub fn source_bytes(&self) -> u64 {
+        self.read
+    }
+
+    /// Reads and decodes the next chunk into `out`.
+    fn fill(&mut self) -> io::Result<()> {
+        let start = self.pending.len();
+        self.pending.resize(start + CHUNK, 0);
+        let n = loop {
+            match self.inner.read(&mut self.pending[start..]) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.pending.truncate(start);
+                    return Err(e);
+                }
+            }
+        };
+        self.pending.truncate(start + n);
+        self.read += n as u64;
+        self.eof = n == 0;
+        self.out.clear();
+        self.taken = 0;
+        let used = match self.charset {
+            Charset::Utf8 => decode_utf8(&self.pending, self.eof, &mut self.out),
+            Charset::Utf16Le => {
+                decode_utf16(&self.pending, self.eof, u16::from_le_bytes, &mut self.out)
+            }
+            Charset::Utf16Be => {
+                decode_utf16(&self.pending, self.eof, u16::from_be_bytes, &mut self.out)
+            }
+            Charset::Latin1 => {
+                let text: String = self.pending.iter().map(|&b| b as char).collect();
+                self.out.extend_from_slice(text.as_bytes());
+                self.pending.len()
+            }
+        };
+        self.pending.drain(..used);
+        // The BOM is the first character decoded, however the reads split it
+        if !self.started && !self.out.is_empty() {
+            self.started = true;
+            let bom = "\u{feff}".as_bytes();
+            if self.charset != Charset::Latin1 && self.out.starts_with(bom) {
+                self.taken = bom.len();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Transcode<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.taken == self.out.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.taken);
+        buf[..n].copy_from_slice(&self.out[self.taken..self.taken + n]);
+        self.taken += n;
+        Ok(n)
+    }
+}
+
+/// Decodes `bytes` into `out`, invalid bytes as U+FFFD; returns how many
+/// were used, leaving a character cut off at the end unless at `eof`.
+fn decode_utf8(bytes: &[u8], eof: bool, out: &mut Vec<u8>) -> usize {
+    let mut used = 0;
+    loop {
+        match std::str::from_utf8(&bytes[used..]) {
+            Ok(text) => {
+                out.extend_from_slice(text.as_bytes());
+                return bytes.len();
+            }
+            Err(e) => {
+                let valid = used + e.valid_up_to();
+                out.extend_from_slice(&bytes[used..valid]);
+                match e.error_len() {
+                    Some(len) => {
+                        out.extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                        used = valid + len;
+                    }
+                    None if eof => {
+                        out.extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                        return bytes.len();
+                    }
+                    None => return valid,
+                }
+            }
+        }
+    }
+}
+
+/// Like decode_utf8, for UTF-16 units made by `unit`.
+fn decode_utf16(bytes: &[u8], eof: bool, unit: fn([u8; 2]) -> u16, out: &mut Vec<u8>) -> usize {
+    let mut units: Vec<u16> = bytes.chunks_exact(2).map(|p| unit([p[0], p[1]])).collect();
+    let mut used = units.len() * 2;
+    // Keep a high surrogate at the end for the low one in the next read
+    if !eof && units.last().is_some_and(|u| (0xd800..0xdc00).contains(u)) {
+        units.pop();
+        used -= 2;
+    }
+    let mut buf = [0; 4];
+    for c in char::decode_utf16(units) {
+        let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+    if eof && used < bytes.len() {
+        out.extend_from_slice(char::REPLACEMENT_CHARACTER.encode_utf8(&mut buf).as_bytes());
+        used = bytes.len();
+    }
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one byte per read, so every character is split.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.is_empty()) {
+                (Some((&b, rest)), false) => {
+                    buf[0] = b;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8], charset: Charset) -> String {
+        let mut text = String::new();
+        Transcode::new(Trickle(bytes), charset)
+            .read_to_string(&mut text)
+            .unwrap();
+        let mut whole = String::new();
+        Transcode::new(bytes, charset)
+            .read_to_string(&mut whole)
+            .unwrap();
+        assert_eq!(text, whole);
+        text
+    }
+
+    fn utf16(text: &str, unit: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        text.encode_utf16().flat_map(unit).collect()
+    }
+
+    #[test]
+    fn sniffing() {
+        assert_eq!(Charset::sniff(b"\xff\xfeh\0i\0"), Charset::Utf16Le);
+        assert_eq!(Charset::sniff(b"\xfe\xff\0h\0i"), Charset::Utf16Be);
+        assert_eq!(Charset::sniff(b"\xef\xbb\xbfhi"), Charset::Utf8);
+        let text = "2024-05-01 ERROR boom\n";
+        assert_eq!(
+            Charset::sniff(&utf16(text, u16::to_le_bytes)),
+            Charset::Utf16Le
+        );
+        assert_eq!(
+            Charset::sniff(&utf16(text, u16::to_be_bytes)),
+            Charset::Utf16Be
+        );
+        assert_eq!(Charset::sniff("café ok".as_bytes()), Charset::Utf8);
+        // A character cut off by the end of the sample
+        assert_eq!(Charset::sniff(&"café".as_bytes()[..4]), Charset::Utf8);
+        assert_eq!(Charset::sniff(b"caf\xe9 ok"), Charset::Latin1);
+        assert_eq!(Charset::sniff(b""), Charset::Utf8);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Charset::from_name("UTF_16LE"), Some(Charset::Utf16Le));
+        assert_eq!(Charset::from_name("iso-8859-1"), Some(Charset::Latin1));
+        assert_eq!(Charset::from_name("utf8"), Some(Charset::Utf8));
+        assert_eq!(Charset::from_name("ebcdic"), None);
+        assert_eq!(Charset::Utf16Be.name(), "UTF-16BE");
+    }
+
+    #[test]
+    fn transcoding() {
+        let text = "naïve 日本 😀 ok\n";
+        let mut le = vec![0xff, 0xfe];
+        le.extend(utf16(text, u16::to_le_bytes));
+        assert_eq!(decode(&le, Charset::Utf16Le), text);
+        let mut be = vec![0xfe, 0xff];
+        be.extend(utf16(text, u16::to_be_bytes));
+        assert_eq!(decode(&be, Charset::Utf16Be), text);
+        let mut utf8 = b"\xef\xbb\xbf".to_vec();
+        utf8.extend_from_slice(text.as_bytes());
+        assert_eq!(decode(&utf8, Charset::Utf8), text);
+        assert_eq!(decode(b"caf\xe9", Charset::Latin1), "café");
+
+        // Bytes that don't decode become U+FFFD
+        assert_eq!(decode(b"a\xffb", Charset::Utf8), "a\u{fffd}b");
+        assert_eq!(decode(b"ab\xe6\x97", Charset::Utf8), "ab\u{fffd}");
+        // A lone surrogate, and an odd byte at the end
+        assert_eq!(
+            decode(&[0x00, 0xd8, b'a', 0], Charset::Utf16Le),
+            "\u{fffd}a"
+        );
+        assert_eq!(decode(&[b'a', 0, b'b'], Charset::Utf16Le), "a\u{fffd}");
+
+        let mut reader = Transcode::new(&le[..], Charset::Utf16Le);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.source_bytes(), le.len() as u64);
+    }
+}
@@ -0,0 +1,67 @@
+//! Bars and sparklines for the text summary: counts drawn in proportion
+//! to the largest, with Unicode blocks (eighths of a character wide or
+//! tall) or plain ASCII for terminals and files that can't show them.
+
+/// Partial block characters for bars, an eighth wider each.
+const EIGHTHS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+/// Sparkline heights, lowest first, above a blank for nothing.
+const UNICODE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const ASCII_LEVELS: [char; 8] = ['.', ',', ':', '-', '=', '+', '*', '#'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChartStyle {
+    #[default]
+    Unicode,
+    Ascii,
+    /// No bars or sparklines, just the numbers
+    Off,
+}
+
+impl ChartStyle {
+    pub fn from_name(name: &str) -> Option<ChartStyle> {
+        match name {
+            "unicode" => Some(ChartStyle::Unicode),
+            "ascii" => Some(ChartStyle::Ascii),
+            "off" | "none" => Some(ChartStyle::Off),
+            _ => None,
+        }
+    }
+
+    /// A bar for `value` out of `most`, `width` characters at `most`. A
+    /// value above 0 always gets some bar.
+    pub fn bar(self, value: usize, most: usize, width: usize) -> String {
+        let most = most.max(1);
+        match self {
+            ChartStyle::Off => String::new(),
+            ChartStyle::Ascii => "#".repeat((value * width).div_ceil(most)),
+            ChartStyle::Unicode => {
+                let eighths = (value * width * 8).div_ceil(most);
+                let mut bar = "█".repeat(eighths / 8);
+                let rest = eighths % 8;
+                if rest > 0 {
+                    bar.push(EIGHTHS[rest - 1]);
+                }
+                bar
+            }
+        }
+    }
+
+    /// One character per count, as tall as it is next to the largest;
+    /// zero is a blank. Empty when Off.
+    pub fn sparkline(self, counts: &[usize]) -> String {
+        let levels = match self {
+            ChartStyle::Off => return String::new(),
+            ChartStyle::Ascii => ASCII_LEVELS,
+            ChartStyle::Unicode => UNICODE_LEVELS,
+        };
+        let most = counts.iter().copied().max().unwrap_or(0).max(1);
+        counts
+            .iter()
+            .map(|&n| match n {
+                0 => ' ',
+                n => levels[((n * levels.len()).div_ceil(most) - 1).min(levels.len() - 1)],
+            })
+            .collect()
+    }
+}
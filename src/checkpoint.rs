@@ -0,0 +1,164 @@
+//! --checkpoint and --resume: the totals of the files scanned so far are
+//! saved every now and then, so a crashed or interrupted scan of a large
+//! folder can go on from there instead of starting over.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::scanner::ScanTotals;
+
+/// Time between saves while scanning.
+const SAVE_EVERY: Duration = Duration::from_secs(30);
+
+/// Bump when the saved layout changes.
+const FORMAT: u32 = 29;
+
+#[derive(Serialize)]
+struct Saved<'c> {
+    format: u32,
+    options: &'c str,
+    done: &'c [String],
+    totals: &'c ScanTotals,
+}
+
+#[derive(Deserialize)]
+struct Loaded {
+    format: u32,
+    options: String,
+    done: Vec<String>,
+    totals: ScanTotals,
+}
+
+pub struct Checkpoint {
+    path: PathBuf,
+    /// Scan options (see cache::scan_options); a resumed scan must match
+    options: String,
+    /// File stamps (see cache::file_stamp) of the files in the totals
+    done: Vec<String>,
+    last_saved: Instant,
+    /// Set once a file was cut short: totals from then on include lines
+    /// that a resumed scan would read again
+    frozen: bool,
+}
+
+impl Checkpoint {
+    pub fn new(path: &str, options: String) -> Checkpoint {
+        Checkpoint {
+            path: PathBuf::from(path),
+            options,
+            done: Vec::new(),
+            last_saved: Instant::now(),
+            frozen: false,
+        }
+    }
+
+    /// Loads the checkpoint at `path` for --resume, with the totals saved
+    /// in it.
+    pub fn resume(path: &str, options: String) -> Result<(Checkpoint, ScanTotals), String> {
+        let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+        let loaded: Loaded = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("could not read checkpoint {}: {}", path, e))?;
+        if loaded.format != FORMAT || loaded.options != options {
+            return Err(format!(
+                "{} was saved by another version or with other options; \
+                 run again without --resume",
+                path
+            ));
+        }
+        info!("resuming after {} files from {}", loaded.done.len(), path);
+        let checkpoint = Checkpoint {
+            path: PathBuf::from(path),
+            options,
+            done: loaded.done,
+            last_saved: Instant::now(),
+            frozen: false,
+        };
+        Ok((checkpoint, loaded.totals))
+    }
+
+    /// Which of `stamps` (current file stamps) are already in the totals.
+    /// Fails if a file in them has changed or gone, since its counts can't
+    /// be taken back out.
+    pub fn done_files(&self, stamps: &[Option<String>]) -> Result<Vec<bool>, String> {
+        let now: HashSet<&str> = stamps.iter().flatten().map(String::as_str).collect();
+        if let Some(gone) = self.done.iter().find(|s| !now.contains(s.as_str())) {
+            let name = gone.split(" | ").next().unwrap_or(gone);
+            return Err(format!(
+                "{} changed or went away since the checkpoint; run again without --resume",
+                name
+            ));
+        }
+        let done: HashSet<&str> = self.done.iter().map(String::as_str).collect();
+        Ok(stamps
+            .iter()
+            .map(|s| s.as_deref().is_some_and(|s| done.contains(s)))
+            .collect())
+    }
+
+    /// Notes a file merged into `totals`, saving them when it's time.
+    pub fn file_done(&mut self, stamp: String, totals: &ScanTotals) {
+        if self.frozen {
+            return;
+        }
+        self.done.push(stamp);
+        if self.last_saved.elapsed() >= SAVE_EVERY {
+            self.save(totals);
+        }
+    }
+
+    /// Saves `totals` for good, before a file that was only partly read is
+    /// merged into them.
+    pub fn freeze(&mut self, totals: &ScanTotals) {
+        if !self.frozen {
+            self.save(totals);
+            self.frozen = true;
+        }
+    }
+
+    /// Saves the totals if the scan stopped early, and removes the
+    /// checkpoint once every file is done.
+    pub fn finish(mut self, totals: &ScanTotals, stopped: bool) {
+        if stopped {
+            if !self.frozen {
+                self.save(totals);
+            }
+            eprintln!(
+                "Saved a checkpoint to {}; add --resume to go on",
+                self.path.display()
+            );
+        } else if let Err(e) = fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("could not remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    fn save(&mut self, totals: &ScanTotals) {
+        let saved = Saved {
+            format: FORMAT,
+            options: &self.options,
+            done: &self.done,
+            totals,
+        };
+        // Written aside and renamed, so a crash while saving keeps the
+        // last checkpoint
+        let partial = self.path.with_extension("tmp");
+        let written = File::create(&partial)
+            .map_err(|e| e.to_string())
+            .and_then(|f| {
+                serde_json::to_writer(BufWriter::new(f), &saved).map_err(|e| e.to_string())
+            })
+            .and_then(|()| fs::rename(&partial, &self.path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("could not save checkpoint {}: {}", self.path.display(), e);
+            let _ = fs::remove_file(&partial);
+        }
+        self.last_saved = Instant::now();
+    }
+}
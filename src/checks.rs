@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use crate::level::Level;
+use crate::search::Needle;
+
+/// How many offending lines are kept per check for failure messages.
+const MAX_SAMPLES: usize = 5;
+
+/// A pass/fail rule given with --check: at most `max` lines may contain
+/// `pattern` (case-insensitive). `logbuddy gate` budgets may also count
+/// only lines at one level, or every line at it.
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Empty for a budget on a level alone
+    pub pattern: String,
+    needle: Option<Needle>,
+    pub level: Option<Level>,
+    pub max: usize,
+}
+
+impl Check {
+    /// Parses "FATAL" (must not appear) or "timeout<=10" (at most 10 lines).
+    pub fn parse(spec: &str) -> Result<Check, String> {
+        let (pattern, max) = match spec.rsplit_once("<=") {
+            Some((pattern, max)) => {
+                let max = max
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("--check '{}': '{}' is not a number", spec, max))?;
+                (pattern, max)
+            }
+            None => (spec, 0),
+        };
+
+        if pattern.is_empty() {
+            return Err("--check needs some text to look for".to_string());
+        }
+
+        Ok(Check::budget(Some(pattern), None, max))
+    }
+
+    /// At most `max` lines at `level` containing `pattern`, either of
+    /// which may be left out.
+    pub fn budget(pattern: Option<&str>, level: Option<Level>, max: usize) -> Check {
+        Check {
+            pattern: pattern.unwrap_or_default().to_string(),
+            needle: pattern.map(Needle::new),
+            level,
+            max,
+        }
+    }
+
+    /// Human-readable test name, e.g. "no lines containing 'FATAL'".
+    pub fn name(&self) -> String {
+        if self.max == 0 {
+            format!("no lines containing '{}'", self.pattern)
+        } else {
+            format!("at most {} lines containing '{}'", self.max, self.pattern)
+        }
+    }
+
+    /// Whether `line`, at `level` if it has one, counts against the rule.
+    pub fn matches(&self, line: &str, level: Option<Level>) -> bool {
+        if self.level.is_some() && self.level != level {
+            return false;
+        }
+        self.needle
+            .as_ref()
+            .is_none_or(|needle| needle.is_in(line.as_bytes()))
+    }
+}
+
+/// Matching lines seen so far for one check.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CheckTally {
+    pub count: usize,
+    /// "file:line: text" of the first few matches
+    pub samples: Vec<String>,
+}
+
+impl CheckTally {
+    /// Adds a tally from a file scanned later, keeping sample order.
+    pub fn merge(&mut self, other: CheckTally) {
+        self.count += other.count;
+        let room = MAX_SAMPLES.saturating_sub(self.samples.len());
+        self.samples.extend(other.samples.into_iter().take(room));
+    }
+
+    pub fn record(&mut self, file_name: &str, line_number: usize, line: &str) {
+        self.count += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples
+                .push(format!("{}:{}: {}", file_name, line_number, line));
+        }
+    }
+}
@@ -0,0 +1,1152 @@
+use std::io::{self, IsTerminal};
+use std::thread;
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::charset::Charset;
+use crate::chart::ChartStyle;
+use crate::checks::Check;
+use crate::level::{Level, LevelRules};
+use crate::report::Section;
+use crate::sink::SinkSpec;
+use crate::source;
+use crate::stopwords::WordFilter;
+use crate::style::{ColorChoice, Theme};
+use crate::timestamp;
+use crate::tokenize::Tokenizer;
+use crate::units::{self, UnitKind};
+
+/// Default --max-record-bytes: 1 MiB.
+const DEFAULT_MAX_RECORD_BYTES: usize = 1 << 20;
+const DEFAULT_MAX_FIELDS: usize = 1000;
+
+/// Words listed unless --top says otherwise.
+const DEFAULT_TOP_WORDS: usize = 10;
+
+/// Values listed per --count-by regex unless --count-top says otherwise.
+const DEFAULT_COUNT_TOP: usize = 20;
+
+/// Bucket width for --spikes, and the --find sparkline, without
+/// --histogram.
+const DEFAULT_SPIKE_BUCKET: Duration = Duration::from_secs(60);
+
+/// Value of --format: how the end-of-scan report is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Junit,
+    Sarif,
+    GhAnnotations,
+    Json,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "junit" => Some(OutputFormat::Junit),
+            "sarif" => Some(OutputFormat::Sarif),
+            "gh-annotations" => Some(OutputFormat::GhAnnotations),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// How lines are read: as text, or as fields with --json-logs, --logfmt,
+/// --access-log, --syslog, --delimited or --cef.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineFormat {
+    #[default]
+    Text,
+    Json,
+    Logfmt,
+    Access,
+    Syslog,
+    /// CEF or LEEF, --cef or --leef
+    Cef,
+    /// Columns split at this character
+    Delimited(char),
+}
+
+impl LineFormat {
+    fn from_flag(flag: &str) -> Option<LineFormat> {
+        match flag {
+            "--json-logs" => Some(LineFormat::Json),
+            "--logfmt" => Some(LineFormat::Logfmt),
+            "--access-log" => Some(LineFormat::Access),
+            "--syslog" => Some(LineFormat::Syslog),
+            "--cef" | "--leef" => Some(LineFormat::Cef),
+            _ => None,
+        }
+    }
+
+    /// What the summary calls lines in this format.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineFormat::Text => "text",
+            LineFormat::Json => "JSON",
+            LineFormat::Logfmt => "logfmt",
+            LineFormat::Access => "access log",
+            LineFormat::Syslog => "syslog",
+            LineFormat::Cef => "CEF/LEEF",
+            LineFormat::Delimited(_) => "delimited",
+        }
+    }
+}
+
+/// --multiline: what starts a log entry; lines that don't continue the
+/// entry before them.
+#[derive(Debug, Clone)]
+pub enum EntryStart {
+    /// A line with a timestamp (see timestamp::parse_line)
+    Timestamp,
+    /// A line matching --entry-start
+    Pattern(Regex),
+}
+
+/// A value looked up on every line, as --group-by and --stats-field name
+/// it, or as --group-regex and --stats-regex capture it.
+#[derive(Debug, Clone)]
+pub enum FieldKey {
+    /// The value of a field (see fields::Record)
+    Field(String),
+    /// The first capture group of a regex, or its whole match
+    Pattern(Regex),
+}
+
+impl FieldKey {
+    /// How the summary names the key.
+    pub fn describe(&self) -> String {
+        match self {
+            FieldKey::Field(name) => name.clone(),
+            FieldKey::Pattern(pattern) => format!("/{}/", pattern.as_str()),
+        }
+    }
+}
+
+/// Value of --io-backend: how files are read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoBackend {
+    /// Plain blocking reads, one at a time per file
+    #[default]
+    Sync,
+    /// Reads through tokio, many in flight at once (see aio)
+    Async,
+}
+
+impl IoBackend {
+    fn from_name(name: &str) -> Option<IoBackend> {
+        match name {
+            "sync" => Some(IoBackend::Sync),
+            "async" => Some(IoBackend::Async),
+            _ => None,
+        }
+    }
+}
+
+/// Options collected from the command line.
+/// Anything not given on the command line keeps its default here.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Folder to scan. When missing, main asks for it interactively.
+    pub path: Option<String>,
+    /// s3://bucket/folder whose objects are scanned instead of `path`.
+    pub s3: Option<String>,
+    /// `user@host:/var/log` whose files are scanned over SSH instead.
+    pub remote: Option<String>,
+    /// Scan the files in subfolders of `path` too.
+    pub recursive: bool,
+    /// Case-insensitive text to search for on every line.
+    pub find: Option<String>,
+    /// Also search inside base64 / URL-encoded / escaped JSON payloads.
+    pub decode_nested: bool,
+    /// --json-logs, --logfmt, --access-log, --syslog, --delimited or --cef:
+    /// read each line as fields.
+    pub line_format: LineFormat,
+    /// --field: with a line format, --find looks only in these fields.
+    pub find_fields: Vec<String>,
+    /// Handlebars template used instead of the built-in summary.
+    pub template: Option<String>,
+    /// Summary sections to print, after applying --show / --hide.
+    pub sections: Vec<Section>,
+    pub color: ColorChoice,
+    pub theme: Theme,
+    /// How bars and sparklines are drawn in the text summary.
+    pub charts: ChartStyle,
+    /// Make hit locations clickable with OSC 8 terminal hyperlinks.
+    pub hyperlinks: ColorChoice,
+    /// URL a hit location links to; see links::Linker for placeholders.
+    pub link_template: Option<String>,
+    /// Print counts and sizes as "2.3M" / "1.4 GiB" in the text summary.
+    pub human: bool,
+    pub format: OutputFormat,
+    /// Pass/fail rules reported in the checks section and as JUnit test cases.
+    pub checks: Vec<Check>,
+    /// --level-rules: custom level detection.
+    pub level_rules: LevelRules,
+    /// Gaps between timestamps longer than this are flagged.
+    pub max_gap: Option<Duration>,
+    /// Width of the --histogram time buckets.
+    pub histogram: Option<Duration>,
+    /// --spikes: buckets with this many times the median hits are flagged.
+    pub spike_factor: Option<f64>,
+    /// --rare N: how many of the rarest templates to list.
+    pub rare: Option<usize>,
+    /// Only lines at this level or above.
+    pub min_level: Option<Level>,
+    /// Levels whose lines' fingerprints the scan keeps, for `logbuddy
+    /// gate`'s new_fingerprints; no command-line option sets them.
+    pub fingerprint_levels: Vec<Level>,
+    /// --multiline or --entry-start: lines are grouped into log entries.
+    pub multiline: Option<EntryStart>,
+    /// Fail when --find matches more than this many lines.
+    pub max_hits: Option<usize>,
+    /// Fields whose numeric values (with units like 15ms or 512KiB) are summarized.
+    pub stats_fields: Vec<FieldKey>,
+    /// Field that correlates events across files, for clock-skew estimates.
+    pub skew_key: Option<String>,
+    /// Folder for per-file results reused by later scans.
+    pub cache: Option<String>,
+    /// File to record the scanned files, options and version in.
+    pub write_manifest: Option<String>,
+    /// Recorded scan to verify and repeat.
+    pub manifest: Option<String>,
+    /// Read files that change while being scanned again.
+    pub reread_unstable: bool,
+    /// Count words in fixed memory, approximately.
+    pub approx_counts: bool,
+    /// --top: most frequent words (and phrases) listed.
+    pub top_words: usize,
+    /// --bottom: least frequent words listed; 0 lists none.
+    pub bottom_words: usize,
+    /// --top-per-file: most frequent words listed per file; 0 lists none.
+    pub top_per_file: usize,
+    /// How messages are split into words.
+    pub tokenizer: Tokenizer,
+    /// --stopwords, --min-word-len and --max-word-len.
+    pub word_filter: WordFilter,
+    /// --ngrams: phrase lengths (2, 3) whose counts are listed too.
+    pub ngrams: Vec<usize>,
+    /// Bytes of a line that field lookups and payload decoding look at.
+    pub max_record_bytes: usize,
+    /// Fields of a line that field lookups and payload decoding look at.
+    pub max_fields: usize,
+    /// Files larger than this are left out of the scan.
+    pub max_file_size: Option<usize>,
+    /// Lines longer than this are cut to it before anything counts them.
+    pub max_line_length: Option<usize>,
+    /// --encoding of every file; None sniffs each one.
+    pub encoding: Option<Charset>,
+    /// --trace-id: lines matching it are listed from all files by time.
+    pub trace_id: Option<Regex>,
+    /// Field whose value ties lines into one session (user, session id).
+    pub session_key: Option<String>,
+    /// --group-by or --group-regex: lines are counted per value of this.
+    pub group_by: Option<FieldKey>,
+    /// --count-by: matches are counted per captured value of each.
+    pub count_by: Vec<Regex>,
+    /// Values listed per --count-by regex.
+    pub count_top: usize,
+    /// --distinct: fields whose values are counted.
+    pub distinct: Vec<String>,
+    /// Repeated lines are compared with whitespace runs as one space.
+    pub squeeze_whitespace: bool,
+    /// -q: only the summary, without the banner or per-hit lines.
+    pub quiet: bool,
+    /// File the totals so far are saved in, for --resume.
+    pub checkpoint: Option<String>,
+    /// Go on from the --checkpoint file instead of starting over.
+    pub resume: bool,
+    /// Rough limit on the memory of word counts and buffered hits.
+    pub max_memory: Option<usize>,
+    /// Only lines from this point in time on (Unix millis).
+    pub since: Option<i64>,
+    /// Only lines until this point in time (Unix millis).
+    pub until: Option<i64>,
+    /// Stop scanning after this long and report what was read.
+    pub timeout: Option<Duration>,
+    /// --no-progress: never draw the progress bar.
+    pub no_progress: bool,
+    /// Number of -v flags: 1 logs info, 2 debug, 3 or more trace.
+    pub verbosity: u8,
+    /// Repository browser URL for the source locations section.
+    pub source_link_template: Option<String>,
+    /// Read files through a memory map instead of buffered reads.
+    pub mmap: bool,
+    /// How files are read.
+    pub io_backend: IoBackend,
+    /// Reads in flight at once with --io-backend async.
+    pub io_depth: Option<usize>,
+    /// Files scanned at once; None means one per CPU.
+    pub jobs: Option<usize>,
+    /// Where hits and totals go; empty means the console.
+    pub sinks: Vec<SinkSpec>,
+    /// Print the JSON Schema of --format json and exit.
+    pub schema: bool,
+    pub help: bool,
+}
+
+pub const USAGE: &str = "\
+Usage: logbuddy [OPTIONS]
+       logbuddy gate --config <FILE>      (see logbuddy gate --help)
+       logbuddy generate [OPTIONS]        (see logbuddy generate --help)
+       logbuddy replay <FILE> [OPTIONS]   (see logbuddy replay --help)
+       logbuddy index [--path DIR]        (see logbuddy index --help)
+       logbuddy query <TEXT>              (see logbuddy query --help)
+       logbuddy merge [--path DIR]...     (see logbuddy merge --help)
+
+Options:
+  --path <DIR>       Folder to scan (asked for interactively if omitted):
+                     its .txt and .log files, and Windows event logs
+                     (.evtx), read as one line per event. Rotated files
+                     (app.log.1, app-20240110.log) are read with the
+                     rest of their log, oldest first, and compressed
+                     ones (.gz, .zst, .xz and .bz2, or any file starting
+                     with their magic bytes) as they decompress. The logs
+                     in .tar, .tar.gz and .zip archives are read too,
+                     named like bundle.zip!var/log/app.log. DIR can be
+                     a URL instead, its files streamed as they are
+                     scanned: an http(s):// file, directory index page
+                     or logbuddy manifest (.json) on a web server, or a
+                     folder in a bucket: s3://bucket/folder
+                     (as --s3), gs://bucket/folder for Google Cloud
+                     Storage (credentials from GOOGLE_OAUTH_ACCESS_TOKEN,
+                     gcloud's application default credentials or the
+                     metadata server) or az://account/container/folder
+                     or its https://account.blob.core.windows.net/...
+                     URL for Azure Blob Storage (a SAS token in the URL
+                     or AZURE_STORAGE_SAS_TOKEN, AZURE_STORAGE_KEY or
+                     AZURE_STORAGE_CONNECTION_STRING)
+  --s3 <URL>         Scan the .txt and .log objects in an S3 folder
+                     (s3://bucket/folder) instead, streaming each one;
+                     credentials and region come from the AWS_*
+                     variables or ~/.aws/credentials, AWS_ENDPOINT_URL
+                     points at another S3-compatible store
+  --remote <[USER@]HOST:DIR>
+                     Scan the .txt and .log files in DIR on HOST over
+                     SSH instead (also ssh://user@host:port/dir),
+                     streaming each one: ssh runs with your
+                     ~/.ssh/config and agent, over one shared
+                     connection, and DIR is read with GNU find and cat
+                     (LOGBUDDY_SSH names another ssh program)
+  -r, --recursive    Also scan the files in DIR's subfolders, named by
+                     their path from DIR
+  --find <TEXT>      Print lines containing TEXT (case-insensitive)
+  --trace-id <ID>    List every line mentioning ID (a request or
+                     correlation id, or a regex matching one), from all
+                     files, merged in timestamp order with the file and
+                     line each came from, to follow one request across
+                     services (same as --show trace-id, with the id)
+  --decode-nested    Let --find match inside base64, URL-encoded and
+                     escaped JSON payloads embedded in lines
+  --json-logs        Read lines as JSON objects, one per line: the level
+                     and time come from fields like level and ts, words
+                     and templates from msg or message, and --stats-field,
+                     --skew-key and --session-key name fields (http.status
+                     for nested ones). Other lines are read as text and
+                     counted in the summary
+  --logfmt           Likewise for logfmt lines of key=value pairs
+                     (level=info msg=\"request done\" duration_ms=12), as
+                     Heroku and many Go services write them
+  --access-log       Likewise for Apache and Nginx access logs (common or
+                     combined format): fields host, user, method, path,
+                     status, bytes, referrer and user_agent; 5xx is ERROR,
+                     4xx WARN. The summary counts requests by status and
+                     by path
+  --syslog           Likewise for syslog lines, RFC 3164 (with or without
+                     the <PRI>) or RFC 5424: fields priority, facility,
+                     severity, host, app, pid and msg. Dates without a year
+                     are put in the year the file was last written. The
+                     summary counts lines by facility, severity and app
+  --cef, --leef      Likewise for CEF or LEEF security events, after a
+                     syslog header or not (either flag reads both): the
+                     header as fields vendor, product, signature_id (LEEF
+                     event_id), name and severity, and every extension
+                     key (src, dst, act, ...). Severity 0-3 is INFO, 4-6
+                     WARN, 7-8 ERROR and 9-10 FATAL. The summary counts
+                     events by severity, name and source address
+  --delimited <SEP>  Likewise for CSV and the like, columns split at SEP
+                     (\",\", \";\", \"|\" or tab); values may be quoted.
+                     Columns are named by the file's first line if it
+                     looks like a header, or c1, c2, ... if not
+  --field <NAME>     With a line format above, let --find look only in
+                     field NAME of the lines that parsed; repeatable
+  --since <TIME>     Only look at lines from TIME on (2024-05-01,
+                     \"2024-05-01 12:00\", ISO 8601 with a zone, epoch
+                     seconds, or now; times without a zone are UTC).
+                     Lines without a timestamp go with the line before
+                     them; files last modified before TIME are skipped
+  --until <TIME>     Only look at lines up to TIME
+  --last <DURATION>  Same as --since DURATION before now, e.g. 24h or 7d
+  --template <FILE>  Render the summary with a Handlebars template instead
+                     of the built-in text (hit lines are not printed)
+  --show <LIST>      Comma-separated sections to add to the summary
+  --hide <LIST>      Comma-separated sections to leave out
+                     (summary, levels, hits, hit-files, trace-id, histogram,
+                     spikes, payloads, files, rollups, words, distinctive,
+                     templates, repeats, rare, traces, syscalls, checks,
+                     stats, groups, counts, distinct, skew, sessions,
+                     access, syslog, security, order, gaps, sources,
+                     timings, or all;
+                     files, rare, histogram, spikes, gaps, sources and
+                     timings are hidden by default)
+  --min-level <LEVEL>
+                     Only look at lines of LEVEL (trace, debug, info,
+                     warn, error, fatal) or above: hits, words, templates
+                     and the rest skip the others. Lines without a level
+                     go with the line before them, so stack traces stay
+                     with the error they belong to
+  --multiline        Group lines into log entries: a line without a
+                     timestamp continues the entry before it, so a stack
+                     trace is one entry with its error. Entries, not
+                     lines, are searched and counted, and the summary
+                     ranks Java and Python stack traces by exception and
+                     top frames
+  --entry-start <REGEX>
+                     Likewise, but entries start at lines matching REGEX
+  --level-rules <FILE>
+                     Custom level detection for nonstandard severities:
+                     a YAML list of regexes and levels, e.g.
+                       - match: '^\\[E\\d+\\]'
+                         level: ERROR
+                       - match: '(?i)\\boops\\b'
+                         level: WARN
+                     The first rule that matches a line gives its level;
+                     other lines get theirs from level words (ERROR,
+                     WARN, ...) or a syslog priority as usual
+  --rare [N]         List the rarest message templates (seen at most 3
+                     times, or the N rarest whatever their counts, with
+                     their first line) instead of the most common ones
+  --max-gap <TIME>   Flag silences: gaps between consecutive timestamps in
+                     a file longer than TIME (e.g. 10m), which often mean
+                     the service was down or stuck, among the longest
+                     gaps listed (same as --show gaps, with the limit)
+  --histogram <WIDTH>
+                     Add a chart of lines (and --find hits) per WIDTH of
+                     time, e.g. 1h or 5m, by their timestamps (UTC), to
+                     see when something started (same as --show
+                     histogram, with the bucket width)
+  --spikes <FACTOR>  Flag bursts: time buckets with more than FACTOR
+                     times the median number of --find hits (or of lines,
+                     without --find), e.g. 5, for a quick look at when an
+                     incident happened. Buckets are --histogram wide, or
+                     a minute (same as --show spikes, with the factor)
+  --timings          Add wall time, MB/s, lines/s, the time spent
+                     reading, tokenizing, searching and mining templates,
+                     and logbuddy's peak memory, CPU time and I/O wait to
+                     the summary (same as --show timings)
+  --color <WHEN>     Color output: auto (default), always or never
+  --theme <LIST>     Override colors, e.g. hit=yellow,header=bold
+                     (keys: hit, header, location, error, warn, info,
+                     debug; colors: red, green, yellow, blue, magenta,
+                     cyan, white, gray, bold, none)
+  --charts <STYLE>   Draw the histogram, top words and the --find hits
+                     over time with unicode blocks (default), ascii
+                     characters, or not at all (off)
+  --hyperlinks <WHEN>
+                     Make hit locations clickable (OSC 8): auto (default,
+                     when stdout is a terminal), always or never
+  --link-template <URL>
+                     Link target for hit locations, e.g.
+                     https://viewer/open?path={path}&line={line};
+                     placeholders {path} (absolute), {file} and {line}
+                     (default file://{path})
+  --source-link-template <URL>
+                     Link file.rs:123 locations named in log lines to a
+                     repo browser, e.g. https://git.example.com/app/blob/
+                     main/{path}#L{line} (see --show sources)
+  --human            Show counts and sizes as 2.3M / 1.4 GiB in the summary
+  --format <FORMAT>  Report format: text (default), json, junit, sarif or
+                     gh-annotations (GitHub Actions workflow commands)
+  --sink <SPEC>      Send hits somewhere other than the terminal;
+                     repeatable, and replaces the terminal unless
+                     `--sink console` is also given. Specs: console,
+                     file:PATH (file:line: text), jsonl:PATH (a JSON
+                     object per hit), webhook:URL (JSON batches, then a
+                     summary), elasticsearch:URL/INDEX (bulk API),
+                     prometheus:PATH (totals for the textfile collector)
+                     and words-csv:PATH (every word with its count)
+  --schema           Print the JSON Schema of --format json and exit
+  --check <RULE>     Fail unless a rule holds; repeatable. FATAL means no
+                     line may contain FATAL, timeout<=10 allows up to 10
+  --max-hits <N>     Fail when --find matches more than N lines
+  --stats-field <NAME>
+                     Summarize the numbers in NAME=value / \"NAME\": value
+                     fields (count, min, max, mean, p50, p95, p99); units
+                     such as 15ms, 2.3s or 512KiB are normalized.
+                     Repeatable
+  --stats-regex <REGEX>
+                     Likewise for the numbers REGEX's first capture group
+                     (or its whole match) finds, e.g. 'took (\\S+)'
+  --skew-key <NAME>  Estimate clock skew between files from events that
+                     share the same value of field NAME (e.g. request_id)
+  --session-key <NAME>
+                     Rebuild sessions from lines sharing the value of
+                     field NAME (e.g. session or user): common event
+                     sequences, sessions that ended in an error, lines
+                     per session, and how long sessions lasted (p50,
+                     p90, p99 and the longest, by their timestamps)
+  --group-by <NAME>  Count lines per value of field NAME (e.g. service or
+                     status), with their warnings and errors
+  --group-regex <REGEX>
+                     Likewise per value of REGEX's first capture group
+                     (or its whole match), e.g. 'user=(\\w+)'
+  --count-by <REGEX> Count every match of REGEX per value of its first
+                     capture group (or its whole match), e.g.
+                     'user=(\\w+)', and list the top values. Repeatable
+  --count-top <N>    Values listed per --count-by (default 20)
+  --distinct <NAME>  Count the distinct values of field NAME (exactly up
+                     to 10000, estimated beyond) and list the most and
+                     least common ones. Repeatable
+  --squeeze-whitespace
+                     Count lines as repeats of each other when they differ
+                     only in spacing (runs of spaces and tabs)
+  --cache <DIR>      Keep each file's results in DIR and reuse them while
+                     the file's size and modification time stay the same
+                     (and the options that affect counting too); old
+                     entries are never removed, delete DIR to clear it
+  --reread-unstable  Read files that grow, shrink or get rotated while
+                     being scanned again (up to 3 reads); without it
+                     they are only flagged as unstable in the summary
+  --write-manifest <FILE>
+                     Record the files scanned (name, size, SHA-256), the
+                     options and the logbuddy version in FILE (JSON)
+  --manifest <FILE>  Repeat a recorded scan: check that the folder holds
+                     exactly the recorded files, unchanged, then scan it
+                     with the recorded options (and any given here)
+  --approx-counts    Count words in fixed memory (a few MB per file being
+                     scanned) for corpora with huge numbers of distinct
+                     tokens; top word counts may overshoot slightly, and
+                     --sink words-csv lists only the likely top words
+  --top <N>          List the N most frequent words (default 10)
+  --bottom <N>       Also list the N least frequent words, where typos,
+                     one-off error codes and ids turn up (not with
+                     approximate counts)
+  --top-per-file <N> Also list the N most frequent words of each file
+  --tokenizer <unicode|whitespace>
+                     How messages are split into counted words (default
+                     unicode): at Unicode word boundaries, so path parts,
+                     key=value pairs and Chinese characters count
+                     apart; or at whitespace only, which is faster and
+                     keeps such tokens whole
+  --stopwords <FILE> Leave the words in FILE (whitespace-separated, # for
+                     comments) and a built-in list of English and log
+                     words (the, to, info, error, ...) out of the word
+                     counts; 'builtin' for the built-in list alone
+  --min-word-len <N>, --max-word-len <N>
+                     Likewise leave out words shorter or longer than N
+                     characters, such as single hex digits
+  --ngrams <N>       Also count phrases of N words (2, 3 or 2,3), like
+                     \"connection reset\", and list the top ones; they are
+                     counted in fixed memory, so counts may overshoot
+                     slightly
+  --max-record-bytes <SIZE>
+                     Let field lookups (--stats-field, --skew-key,
+                     --session-key) and --decode-nested read only the
+                     first SIZE of a line (default 1MiB); longer lines
+                     are counted as truncated in the summary
+  --max-fields <N>   Likewise, stop after the first N name=value or
+                     \"name\": fields of a line (default 1000)
+  --max-filesize <SIZE>
+                     Leave files larger than SIZE (e.g. 2GiB) out of the
+                     scan; the summary lists them
+  --max-line-length <SIZE>
+                     Cut lines longer than SIZE (e.g. 64KiB) to SIZE
+                     before counting words or searching them, so minified
+                     JSON or base64 dumps don't swamp the results; the
+                     summary counts them
+  --encoding <NAME>  Read files as utf-8, utf-16le, utf-16be or latin1
+                     instead of auto (the default): UTF-16 is found by its
+                     byte order mark or NUL bytes, and files that aren't
+                     valid UTF-8 are read as Latin-1
+  -j, --jobs <N>     Scan up to N files at once (default: one per CPU);
+                     output is the same for any N
+  --mmap             Memory-map files instead of reading them; faster on
+                     large local files, but don't use it on files that
+                     may be truncated while scanning
+  --io-backend <sync|async>
+                     How files are read (default sync). async keeps many
+                     reads in flight at once, which keeps throughput up
+                     on NFS or SMB mounts where each read waits on the
+                     network
+  --io-depth <N>     Reads in flight at once with --io-backend async, over
+                     all files being scanned (default 32)
+  -q, --quiet        Print only the summary: no banner, no hit lines
+  --timeout <TIME>   Stop scanning after TIME (e.g. 90s, 5m) and report
+                     what was read so far, marked as partial; exits 124
+                     (Ctrl-C does the same and exits 130)
+  --max-memory <SIZE>
+                     Keep word counts and buffered --find hits to about
+                     SIZE (e.g. 2GiB): past it, words are counted
+                     approximately (see --approx-counts) and hits wait in
+                     a temporary file. Which files switch depends on
+                     --jobs, so counts may then differ between job counts
+  --checkpoint <FILE>
+                     Save the totals so far to FILE every 30 seconds and
+                     when the scan is stopped early; removed once the scan
+                     completes
+  --resume           Go on from the --checkpoint FILE of a scan with the
+                     same options that crashed or was stopped, skipping
+                     the files it covers (their hits are not output again)
+  --no-progress      Don't draw the progress bar shown on stderr when it
+                     is a terminal (files, bytes and time left)
+  -v, --verbose      Log what the scan is doing to stderr, and list each
+                     rotated log's files in the files table; -vv adds
+                     which files were opened or skipped, -vvv everything
+  -h, --help         Show this help";
+
+/// Parses the arguments that follow the program name.
+pub fn parse_args<I>(args: I) -> Result<Config, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut config = Config {
+        sections: Section::DEFAULT.to_vec(),
+        max_record_bytes: DEFAULT_MAX_RECORD_BYTES,
+        max_fields: DEFAULT_MAX_FIELDS,
+        count_top: DEFAULT_COUNT_TOP,
+        top_words: DEFAULT_TOP_WORDS,
+        ..Config::default()
+    };
+    let mut args = args.into_iter().peekable();
+    // --last, turned into --since once every flag is in
+    let mut last = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => config.path = Some(value_for(&arg, &mut args)?),
+            "--s3" => config.s3 = Some(value_for(&arg, &mut args)?),
+            "--remote" => config.remote = Some(value_for(&arg, &mut args)?),
+            "-r" | "--recursive" => config.recursive = true,
+            "--find" => config.find = Some(value_for(&arg, &mut args)?),
+            "--decode-nested" => config.decode_nested = true,
+            "--json-logs" | "--logfmt" | "--access-log" | "--syslog" | "--cef" | "--leef"
+            | "--delimited" => {
+                let format = match LineFormat::from_flag(&arg) {
+                    Some(format) => format,
+                    None => LineFormat::Delimited(parse_separator(&value_for(&arg, &mut args)?)?),
+                };
+                if config.line_format != LineFormat::Text && config.line_format != format {
+                    return Err(
+                        "--json-logs, --logfmt, --access-log, --syslog, --cef and --delimited \
+                         are different line formats; pick one"
+                            .to_string(),
+                    );
+                }
+                config.line_format = format;
+            }
+            "--field" => config.find_fields.push(value_for(&arg, &mut args)?),
+            "--template" => config.template = Some(value_for(&arg, &mut args)?),
+            "--min-level" => {
+                let value = value_for(&arg, &mut args)?;
+                config.min_level =
+                    Some(Level::from_word(&value.to_uppercase()).ok_or_else(|| {
+                        format!(
+                            "--min-level needs trace, debug, info, warn, error or fatal, not '{}'",
+                            value
+                        )
+                    })?);
+            }
+            "--multiline" => {
+                if config.multiline.is_none() {
+                    config.multiline = Some(EntryStart::Timestamp);
+                }
+            }
+            "--entry-start" => {
+                let value = value_for(&arg, &mut args)?;
+                let pattern = Regex::new(&value)
+                    .map_err(|e| format!("--entry-start needs a regex, not '{}': {}", value, e))?;
+                config.multiline = Some(EntryStart::Pattern(pattern));
+            }
+            "--level-rules" => config.level_rules = LevelRules::load(&value_for(&arg, &mut args)?)?,
+            // Applied in order, so "--hide all --show files" prints only the file table
+            "--show" => {
+                for section in parse_sections(&value_for(&arg, &mut args)?)? {
+                    if !config.sections.contains(&section) {
+                        config.sections.push(section);
+                    }
+                }
+            }
+            "--hide" => {
+                let hidden = parse_sections(&value_for(&arg, &mut args)?)?;
+                config.sections.retain(|s| !hidden.contains(s));
+            }
+            "--timings" => {
+                if !config.sections.contains(&Section::Timings) {
+                    config.sections.push(Section::Timings);
+                }
+            }
+            "--max-gap" => {
+                let value = value_for(&arg, &mut args)?;
+                config.max_gap = Some(parse_duration(&arg, &value)?);
+                if !config.sections.contains(&Section::Gaps) {
+                    config.sections.push(Section::Gaps);
+                }
+            }
+            "--histogram" => {
+                let value = value_for(&arg, &mut args)?;
+                config.histogram = Some(parse_duration(&arg, &value)?);
+                if !config.sections.contains(&Section::Histogram) {
+                    config.sections.push(Section::Histogram);
+                }
+            }
+            "--spikes" => {
+                let value = value_for(&arg, &mut args)?;
+                let factor = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|f| f.is_finite() && *f > 1.0)
+                    .ok_or_else(|| format!("--spikes needs a number above 1, not '{}'", value))?;
+                config.spike_factor = Some(factor);
+                if !config.sections.contains(&Section::Spikes) {
+                    config.sections.push(Section::Spikes);
+                }
+            }
+            "--rare" => {
+                // The count is optional, so a path or flag after --rare
+                // is left alone
+                if let Some(n) = args.next_if(|a| a.parse::<usize>().is_ok_and(|n| n > 0)) {
+                    config.rare = n.parse().ok();
+                }
+                config.sections.retain(|s| *s != Section::Templates);
+                if !config.sections.contains(&Section::Rare) {
+                    config.sections.push(Section::Rare);
+                }
+            }
+            "--color" => {
+                let value = value_for(&arg, &mut args)?;
+                config.color = ColorChoice::from_name(&value).ok_or_else(|| {
+                    format!("--color must be auto, always or never, not '{}'", value)
+                })?;
+            }
+            "--charts" => {
+                let value = value_for(&arg, &mut args)?;
+                config.charts = ChartStyle::from_name(&value).ok_or_else(|| {
+                    format!("--charts must be unicode, ascii or off, not '{}'", value)
+                })?;
+            }
+            "--hyperlinks" => {
+                let value = value_for(&arg, &mut args)?;
+                config.hyperlinks = ColorChoice::from_name(&value).ok_or_else(|| {
+                    format!(
+                        "--hyperlinks must be auto, always or never, not '{}'",
+                        value
+                    )
+                })?;
+            }
+            "--source-link-template" => {
+                config.source_link_template = Some(value_for(&arg, &mut args)?)
+            }
+            "--link-template" => config.link_template = Some(value_for(&arg, &mut args)?),
+            "--theme" => config.theme.apply(&value_for(&arg, &mut args)?)?,
+            "--human" => config.human = true,
+            "--format" => {
+                let value = value_for(&arg, &mut args)?;
+                config.format = OutputFormat::from_name(&value)
+                    .ok_or_else(|| format!("unknown format '{}'", value))?;
+            }
+            "--check" => config
+                .checks
+                .push(Check::parse(&value_for(&arg, &mut args)?)?),
+            "--max-hits" => {
+                let value = value_for(&arg, &mut args)?;
+                let max = value
+                    .parse()
+                    .map_err(|_| format!("--max-hits needs a number, not '{}'", value))?;
+                config.max_hits = Some(max);
+            }
+            "--stats-field" => config
+                .stats_fields
+                .push(FieldKey::Field(value_for(&arg, &mut args)?)),
+            "--stats-regex" => {
+                let value = value_for(&arg, &mut args)?;
+                let pattern = Regex::new(&value)
+                    .map_err(|e| format!("--stats-regex needs a regex, not '{}': {}", value, e))?;
+                config.stats_fields.push(FieldKey::Pattern(pattern));
+            }
+            "--skew-key" => config.skew_key = Some(value_for(&arg, &mut args)?),
+            "--trace-id" => {
+                let value = value_for(&arg, &mut args)?;
+                let pattern = Regex::new(&value).map_err(|e| {
+                    format!("--trace-id needs an id or a regex, not '{}': {}", value, e)
+                })?;
+                config.trace_id = Some(pattern);
+            }
+            "--session-key" => config.session_key = Some(value_for(&arg, &mut args)?),
+            "--group-by" => config.group_by = Some(FieldKey::Field(value_for(&arg, &mut args)?)),
+            "--group-regex" => {
+                let value = value_for(&arg, &mut args)?;
+                let pattern = Regex::new(&value)
+                    .map_err(|e| format!("--group-regex needs a regex, not '{}': {}", value, e))?;
+                config.group_by = Some(FieldKey::Pattern(pattern));
+            }
+            "--count-by" => {
+                let value = value_for(&arg, &mut args)?;
+                let pattern = Regex::new(&value)
+                    .map_err(|e| format!("--count-by needs a regex, not '{}': {}", value, e))?;
+                config.count_by.push(pattern);
+            }
+            "--distinct" => config.distinct.push(value_for(&arg, &mut args)?),
+            "--squeeze-whitespace" => config.squeeze_whitespace = true,
+            "--top" | "--bottom" | "--top-per-file" => {
+                let value = value_for(&arg, &mut args)?;
+                let n =
+                    value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        format!("{} needs a number above 0, not '{}'", arg, value)
+                    })?;
+                match arg.as_str() {
+                    "--top" => config.top_words = n,
+                    "--bottom" => config.bottom_words = n,
+                    _ => config.top_per_file = n,
+                }
+            }
+            "--count-top" => {
+                let value = value_for(&arg, &mut args)?;
+                config.count_top = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!("--count-top needs a number above 0, not '{}'", value)
+                })?;
+            }
+            "--mmap" => config.mmap = true,
+            "--io-backend" => {
+                let value = value_for(&arg, &mut args)?;
+                config.io_backend = IoBackend::from_name(&value)
+                    .ok_or_else(|| format!("unknown I/O backend '{}'", value))?;
+            }
+            "--io-depth" => {
+                let value = value_for(&arg, &mut args)?;
+                let depth = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("--io-depth needs a number above 0, not '{}'", value))?;
+                config.io_depth = Some(depth);
+            }
+            "--approx-counts" => config.approx_counts = true,
+            "--tokenizer" => {
+                let value = value_for(&arg, &mut args)?;
+                config.tokenizer = Tokenizer::from_name(&value).ok_or_else(|| {
+                    format!("--tokenizer must be unicode or whitespace, not '{}'", value)
+                })?;
+            }
+            "--stopwords" => config
+                .word_filter
+                .load_stopwords(&value_for(&arg, &mut args)?)?,
+            "--min-word-len" | "--max-word-len" => {
+                let value = value_for(&arg, &mut args)?;
+                let len: usize =
+                    value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        format!("{} needs a number above 0, not '{}'", arg, value)
+                    })?;
+                if arg == "--min-word-len" {
+                    config.word_filter.min_len = len;
+                } else {
+                    config.word_filter.max_len = Some(len);
+                }
+            }
+            "--ngrams" => {
+                let value = value_for(&arg, &mut args)?;
+                let sizes: Option<Vec<usize>> = value
+                    .split(',')
+                    .map(|n| n.trim().parse().ok().filter(|n| (2..=3).contains(n)))
+                    .collect();
+                config.ngrams =
+                    sizes.ok_or_else(|| format!("--ngrams needs 2, 3 or 2,3, not '{}'", value))?;
+                config.ngrams.sort_unstable();
+                config.ngrams.dedup();
+            }
+            "--reread-unstable" => config.reread_unstable = true,
+            "--cache" => config.cache = Some(value_for(&arg, &mut args)?),
+            "--write-manifest" => config.write_manifest = Some(value_for(&arg, &mut args)?),
+            "--manifest" => config.manifest = Some(value_for(&arg, &mut args)?),
+            "--max-record-bytes" => {
+                config.max_record_bytes = parse_size(&arg, &value_for(&arg, &mut args)?)?
+            }
+            "--max-filesize" => {
+                let value = value_for(&arg, &mut args)?;
+                config.max_file_size = Some(parse_size(&arg, &value)?);
+            }
+            "--max-line-length" => {
+                let value = value_for(&arg, &mut args)?;
+                config.max_line_length = Some(parse_size(&arg, &value)?);
+            }
+            "--encoding" => {
+                let value = value_for(&arg, &mut args)?;
+                config.encoding = match value.as_str() {
+                    "auto" => None,
+                    name => Some(Charset::from_name(name).ok_or_else(|| {
+                        format!(
+                            "--encoding needs auto, utf-8, utf-16le, utf-16be or latin1, not '{}'",
+                            value
+                        )
+                    })?),
+                };
+            }
+            "--max-fields" => {
+                let value = value_for(&arg, &mut args)?;
+                config.max_fields = value
+                    .parse()
+                    .map_err(|_| format!("--max-fields needs a number, not '{}'", value))?;
+            }
+            "-j" | "--jobs" => {
+                let value = value_for(&arg, &mut args)?;
+                let jobs = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("--jobs needs a number above 0, not '{}'", value))?;
+                config.jobs = Some(jobs);
+            }
+            "-q" | "--quiet" => config.quiet = true,
+            "--no-progress" => config.no_progress = true,
+            "--since" => config.since = Some(parse_time(&arg, &value_for(&arg, &mut args)?)?),
+            "--until" => config.until = Some(parse_time(&arg, &value_for(&arg, &mut args)?)?),
+            "--last" => {
+                let value = value_for(&arg, &mut args)?;
+                let window = parse_duration(&arg, &value)?;
+                last = Some(timestamp::now_millis() - window.as_millis() as i64);
+            }
+            "--max-memory" => {
+                let value = value_for(&arg, &mut args)?;
+                config.max_memory = Some(parse_size(&arg, &value)?);
+            }
+            "--checkpoint" => config.checkpoint = Some(value_for(&arg, &mut args)?),
+            "--resume" => config.resume = true,
+            "--timeout" => {
+                let value = value_for(&arg, &mut args)?;
+                config.timeout = Some(parse_duration(&arg, &value)?);
+            }
+            "-v" | "--verbose" => config.verbosity = config.verbosity.saturating_add(1),
+            "-vv" => config.verbosity = config.verbosity.saturating_add(2),
+            "-vvv" => config.verbosity = config.verbosity.saturating_add(3),
+            "--sink" => config
+                .sinks
+                .push(SinkSpec::parse(&value_for(&arg, &mut args)?)?),
+            "--schema" => config.schema = true,
+            "-h" | "--help" => config.help = true,
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    if let Some(since) = last {
+        if config.since.is_some() {
+            return Err(
+                "--last and --since both set where the window starts; pick one".to_string(),
+            );
+        }
+        config.since = Some(since);
+    }
+    if let (Some(since), Some(until)) = (config.since, config.until) {
+        if since > until {
+            return Err("--since is later than --until".to_string());
+        }
+    }
+
+    if config.max_hits.is_some() && config.find.is_none() {
+        return Err("--max-hits only makes sense together with --find".to_string());
+    }
+
+    if config.decode_nested && config.find.is_none() {
+        return Err("--decode-nested only makes sense together with --find".to_string());
+    }
+
+    let searches_fields = config.line_format != LineFormat::Text && config.find.is_some();
+    if !config.find_fields.is_empty() && !searches_fields {
+        return Err(
+            "--field only makes sense together with --find and a line format \
+             (--json-logs, --logfmt, --access-log, --syslog, --cef or --delimited)"
+                .to_string(),
+        );
+    }
+
+    if config.io_backend == IoBackend::Async && config.mmap {
+        return Err("--mmap and --io-backend async are two ways of reading; pick one".to_string());
+    }
+    if config.io_depth.is_some() && config.io_backend != IoBackend::Async {
+        return Err("--io-depth only makes sense together with --io-backend async".to_string());
+    }
+
+    if config
+        .word_filter
+        .max_len
+        .is_some_and(|max| max < config.word_filter.min_len)
+    {
+        return Err(
+            "--max-word-len is below --min-word-len, so no word would be counted".to_string(),
+        );
+    }
+
+    let targets = [
+        ("--path", config.path.is_some()),
+        ("--s3", config.s3.is_some()),
+        ("--remote", config.remote.is_some()),
+    ];
+    let given: Vec<&str> = targets
+        .iter()
+        .filter(|(_, given)| *given)
+        .map(|(option, _)| *option)
+        .collect();
+    if given.len() > 1 {
+        return Err(format!(
+            "{} and {} both say what to scan; pick one",
+            given[0], given[1]
+        ));
+    }
+    let remote = config.s3.is_some()
+        || config.remote.is_some()
+        || config.path.as_deref().is_some_and(source::is_url);
+    if remote && config.write_manifest.is_some() {
+        return Err("--write-manifest records local files, not remote ones".to_string());
+    }
+
+    if config.resume && config.checkpoint.is_none() {
+        return Err("--resume needs the --checkpoint file to resume from".to_string());
+    }
+
+    if config.find.is_none() {
+        if let Some(spec) = config.sinks.iter().find(|s| s.hits_only()) {
+            return Err(format!(
+                "--sink {} only makes sense together with --find",
+                spec.kind()
+            ));
+        }
+    }
+    if config.sinks.contains(&SinkSpec::Console) && !config.text_output() {
+        return Err("--sink console only works with the text format".to_string());
+    }
+
+    Ok(config)
+}
+
+impl Config {
+    /// True when the plain text summary goes to stdout, so hit lines and
+    /// the banner can be printed too.
+    pub fn text_output(&self) -> bool {
+        self.template.is_none() && self.format == OutputFormat::Text
+    }
+
+    /// True when the banner and hit lines are printed: text output
+    /// without --quiet.
+    pub fn chatty(&self) -> bool {
+        self.text_output() && !self.quiet
+    }
+
+    /// Files scanned at once: --jobs, or one per CPU.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    /// Width of the time buckets lines are counted in: --histogram, or a
+    /// minute for --spikes or --find alone.
+    pub fn bucket_width(&self) -> Option<Duration> {
+        self.histogram.or_else(|| {
+            (self.spike_factor.is_some() || self.find.is_some()).then_some(DEFAULT_SPIKE_BUCKET)
+        })
+    }
+
+    /// The share of --max-memory each file being scanned gets; the merged
+    /// totals get the other half.
+    pub fn file_memory_budget(&self) -> Option<usize> {
+        self.max_memory.map(|m| m / 2 / self.jobs())
+    }
+
+    /// True when a progress bar goes to stderr: it is a terminal, and
+    /// neither --no-progress nor --quiet was given.
+    pub fn shows_progress(&self) -> bool {
+        !self.no_progress && !self.quiet && io::stderr().is_terminal()
+    }
+
+    /// Formats that list every match rather than just counts.
+    pub fn lists_matches(&self) -> bool {
+        matches!(
+            self.format,
+            OutputFormat::Sarif | OutputFormat::GhAnnotations
+        )
+    }
+
+    /// Whether a summary section should be printed.
+    pub fn shows(&self, section: Section) -> bool {
+        self.sections.contains(&section)
+    }
+}
+
+/// Parses a list like "files,hits"; "all" stands for every section.
+fn parse_sections(list: &str) -> Result<Vec<Section>, String> {
+    let mut sections = Vec::new();
+    for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if name == "all" {
+            sections.extend(Section::ALL);
+            continue;
+        }
+        match Section::from_name(name) {
+            Some(s) => sections.push(s),
+            None => return Err(format!("unknown section '{}'", name)),
+        }
+    }
+    Ok(sections)
+}
+
+/// Parses the column separator for --delimited: one character, or "tab"
+/// or "\\t" for a tab, which is awkward to type.
+fn parse_separator(value: &str) -> Result<char, String> {
+    if value == "tab" || value == "\\t" {
+        return Ok('\t');
+    }
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '"' && !c.is_alphanumeric() => Ok(c),
+        _ => Err(format!(
+            "--delimited needs one separator character such as , or ; or tab, not '{}'",
+            value
+        )),
+    }
+}
+
+/// Parses a byte size such as "4096", "512KiB" or "2MB" for `flag`.
+fn parse_size(flag: &str, value: &str) -> Result<usize, String> {
+    match units::parse_quantity(value) {
+        Some(q) if matches!(q.kind, UnitKind::Size | UnitKind::Plain) && q.value >= 0.0 => {
+            Ok(q.value as usize)
+        }
+        _ => Err(format!(
+            "{} needs a size such as 4096 or 512KiB, not '{}'",
+            flag, value
+        )),
+    }
+}
+
+fn parse_time(flag: &str, value: &str) -> Result<i64, String> {
+    timestamp::parse_arg(value, timestamp::now_millis()).ok_or_else(|| {
+        format!(
+            "{} needs a time such as 2024-05-01, \"2024-05-01 12:00\", \
+             2024-05-01T12:00:00+02:00 or now, not '{}'",
+            flag, value
+        )
+    })
+}
+
+fn parse_duration(flag: &str, value: &str) -> Result<Duration, String> {
+    match units::parse_quantity(value) {
+        Some(q) if q.kind == UnitKind::Duration && q.value > 0.0 => {
+            Ok(Duration::from_secs_f64(q.value / 1000.0))
+        }
+        _ => Err(format!(
+            "{} needs a duration such as 90s, 5m or 1h, not '{}'",
+            flag, value
+        )),
+    }
+}
+
+/// Takes the value that must follow a flag like `--path`.
+pub fn value_for<I>(flag: &str, args: &mut I) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    match args.next() {
+        Some(v) if !v.starts_with("--") => Ok(v),
+        _ => Err(format!("{} needs a value", flag)),
+    }
+}
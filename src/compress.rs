@@ -0,0 +1,244 @@
+//! Compressed logs, as log rotation leaves them (`app.log.2.gz`,
+//! `app.log.2.zst`): found by their magic bytes whatever they are named,
+//! and read decompressed as they stream in. Gzip is always built in; zstd,
+//! xz and bzip2 come with the cargo features of those names (all on by
+//! default).
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+use flate2::read::MultiGzDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+/// Extensions of compressed files.
+const EXTENSIONS: [&str; 5] = ["gz", "zst", "zstd", "xz", "bz2"];
+
+/// Bytes looked at to tell how a file is compressed.
+const MAGIC_BYTES: usize = 6;
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    /// The cargo feature that builds this decompression in.
+    fn feature(self) -> &'static str {
+        match self {
+            Compression::Gzip => "default",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    /// The compression `sample`, a file's first bytes, starts with.
+    pub fn sniff(sample: &[u8]) -> Option<Compression> {
+        if sample.starts_with(&[0x1f, 0x8b]) {
+            return Some(Compression::Gzip);
+        }
+        if sample.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Some(Compression::Zstd);
+        }
+        if sample.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            return Some(Compression::Xz);
+        }
+        // "BZh" and the block size, 1 to 9
+        if sample.starts_with(b"BZh") && sample.get(3).is_some_and(|b| (b'1'..=b'9').contains(b)) {
+            return Some(Compression::Bzip2);
+        }
+        None
+    }
+
+    /// The compression of the file at `path`, if it is compressed.
+    pub fn detect(path: &Path) -> Option<Compression> {
+        let mut sample = Vec::with_capacity(MAGIC_BYTES);
+        File::open(path)
+            .ok()?
+            .take(MAGIC_BYTES as u64)
+            .read_to_end(&mut sample)
+            .ok()?;
+        Compression::sniff(&sample)
+    }
+}
+
+/// `name` without the extension of a compressed file, which files are
+/// listed by (`app.log` for `app.log.gz`).
+pub fn strip_extension(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((rest, ext)) if EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)) => rest,
+        _ => name,
+    }
+}
+
+/// Bytes read so far by a decompressor or a Counted reader, kept after it
+/// is wrapped in other readers.
+#[derive(Clone, Default)]
+pub struct SourceBytes(Rc<Cell<u64>>);
+
+impl SourceBytes {
+    pub fn get(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+pub struct Counted<R> {
+    inner: R,
+    read: SourceBytes,
+}
+
+/// `inner`, counting the bytes read from it.
+pub fn counted<R: Read>(inner: R) -> (Counted<R>, SourceBytes) {
+    let read = SourceBytes::default();
+    let counted = Counted {
+        inner,
+        read: read.clone(),
+    };
+    (counted, read)
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.0.set(self.read.0.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Reads `inner`, compressed with `compression`, decompressed. Files of
+/// several members or frames (as `cat a.gz b.gz` makes) are read to the
+/// end. Fails for a compression this build leaves out.
+pub fn decompress<'a, R: Read + 'a>(
+    inner: R,
+    compression: Compression,
+) -> io::Result<(Box<dyn Read + 'a>, SourceBytes)> {
+    let (counted, read) = counted(inner);
+    let reader: Box<dyn Read + 'a> = match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(counted)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(counted)?),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(counted)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(counted)),
+        #[allow(unreachable_patterns)]
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{} files need logbuddy built with the {} feature",
+                    compression.name(),
+                    compression.feature()
+                ),
+            ))
+        }
+    };
+    Ok((reader, read))
+}
+
+/// The file at `path`, decompressed if it is compressed.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    Ok(match Compression::detect(path) {
+        Some(compression) => decompress(file, compression)?.0,
+        None => Box::new(file),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_all(mut reader: impl Read) -> String {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        assert_eq!(Compression::sniff(&gzip("x")), Some(Compression::Gzip));
+        assert_eq!(
+            Compression::sniff(&[0x28, 0xb5, 0x2f, 0xfd, 0, 0]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::sniff(b"\xfd7zXZ\x00"), Some(Compression::Xz));
+        assert_eq!(Compression::sniff(b"BZh91AY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::sniff(b"BZh0"), None);
+        assert_eq!(Compression::sniff(b"BZ"), None);
+        assert_eq!(Compression::sniff(b"INFO ok\n"), None);
+        assert_eq!(Compression::sniff(b""), None);
+    }
+
+    #[test]
+    fn strips_compressed_extensions() {
+        assert_eq!(strip_extension("app.log.2.gz"), "app.log.2");
+        assert_eq!(strip_extension("app.log.ZST"), "app.log");
+        assert_eq!(strip_extension("app.log.bz2"), "app.log");
+        assert_eq!(strip_extension("app.log"), "app.log");
+        assert_eq!(strip_extension("gz"), "gz");
+    }
+
+    #[test]
+    fn reads_every_gzip_member() {
+        // As `cat a.gz b.gz` makes
+        let bytes = [gzip("first\n"), gzip("second\n")].concat();
+        let (reader, read) = decompress(&bytes[..], Compression::Gzip).unwrap();
+        assert_eq!(read_all(reader), "first\nsecond\n");
+        assert_eq!(read.get(), bytes.len() as u64);
+
+        let (counted, read) = counted(&b"12345"[..]);
+        assert_eq!(read_all(counted), "12345");
+        assert_eq!(read.get(), 5);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn reads_zstd() {
+        let bytes = zstd::encode_all(&b"INFO zstd\n"[..], 3).unwrap();
+        assert_eq!(Compression::sniff(&bytes), Some(Compression::Zstd));
+        let (reader, _) = decompress(&bytes[..], Compression::Zstd).unwrap();
+        assert_eq!(read_all(reader), "INFO zstd\n");
+    }
+
+    #[test]
+    fn opens_by_content_not_name() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let packed = dir.join(format!("logbuddy-compress-{}-packed.log", id));
+        let plain = dir.join(format!("logbuddy-compress-{}-plain.gz", id));
+        std::fs::write(&packed, gzip("INFO packed\n")).unwrap();
+        std::fs::write(&plain, "INFO plain\n").unwrap();
+        assert_eq!(Compression::detect(&packed), Some(Compression::Gzip));
+        assert_eq!(Compression::detect(&plain), None);
+        assert_eq!(read_all(open(&packed).unwrap()), "INFO packed\n");
+        assert_eq!(read_all(open(&plain).unwrap()), "INFO plain\n");
+        assert!(open(&dir.join(format!("logbuddy-compress-{}-missing", id))).is_err());
+        std::fs::remove_file(packed).unwrap();
+        std::fs::remove_file(plain).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+//! --trace-id: every line that mentions one request or correlation id, from
+//! all the files scanned, to put back together in time order and follow
+//! the request from service to service.
+
+use serde::{Deserialize, Serialize};
+
+/// Lines kept at most; an id that common is more of a search than a trace.
+const MAX_LINES: usize = 100_000;
+
+/// One line mentioning the id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracedLine {
+    /// Unix millis of the line, or of the line before it
+    pub time_ms: Option<i64>,
+    pub file: String,
+    pub line_number: usize,
+    pub line: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TraceIdLines {
+    /// In file order, then line order
+    pub lines: Vec<TracedLine>,
+    /// Lines that came after MAX_LINES others
+    pub dropped: usize,
+}
+
+impl TraceIdLines {
+    pub fn record(&mut self, time_ms: Option<i64>, file: &str, line_number: usize, line: &str) {
+        if self.lines.len() >= MAX_LINES {
+            self.dropped += 1;
+            return;
+        }
+        self.lines.push(TracedLine {
+            time_ms,
+            file: file.to_string(),
+            line_number,
+            line: line.to_string(),
+        });
+    }
+
+    pub fn merge(&mut self, other: TraceIdLines) {
+        let room = MAX_LINES.saturating_sub(self.lines.len());
+        self.dropped += other.dropped + other.lines.len().saturating_sub(room);
+        self.lines.extend(other.lines.into_iter().take(room));
+    }
+
+    /// The lines by time, lines without one after the rest; lines at the
+    /// same time keep their file and line order.
+    pub fn by_time(&self) -> Vec<&TracedLine> {
+        let mut lines: Vec<&TracedLine> = self.lines.iter().collect();
+        lines.sort_by_key(|l| (l.time_ms.is_none(), l.time_ms));
+        lines
+    }
+}
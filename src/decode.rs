@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+
+/// Payloads nested inside payloads are followed this many levels deep.
+const MAX_DEPTH: usize = 3;
+
+/// Shortest run of base64 characters that is worth trying to decode.
+const MIN_BASE64_LEN: usize = 16;
+
+/// The kind of encoding a nested payload was wrapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    Base64,
+    Url,
+    EscapedJson,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Base64 => "base64",
+            Encoding::Url => "url",
+            Encoding::EscapedJson => "escaped-json",
+        }
+    }
+}
+
+/// One payload that was found inside a line and decoded.
+#[derive(Serialize, Deserialize)]
+pub struct Decoded {
+    pub encoding: Encoding,
+    pub text: String,
+}
+
+/// Finds encoded payloads in a line and decodes them, including payloads
+/// that turn up inside an already decoded payload.
+pub fn decode_nested(line: &str) -> Vec<Decoded> {
+    let mut out = Vec::new();
+    decode_level(line, 0, &mut out);
+    out
+}
+
+fn decode_level(text: &str, depth: usize, out: &mut Vec<Decoded>) {
+    if depth >= MAX_DEPTH {
+        return;
+    }
+
+    let mut found = Vec::new();
+
+    // base64 blobs that decode to JSON
+    for run in text.split(|c: char| !is_base64_char(c)) {
+        if run.len() < MIN_BASE64_LEN {
+            continue;
+        }
+        if let Some(decoded) = decode_base64(run) {
+            if looks_like_json(&decoded) {
+                found.push(Decoded {
+                    encoding: Encoding::Base64,
+                    text: decoded,
+                });
+            }
+        }
+    }
+
+    // URL-encoded values such as query strings
+    for token in text.split_whitespace() {
+        if let Some(decoded) = decode_url(token) {
+            found.push(Decoded {
+                encoding: Encoding::Url,
+                text: decoded,
+            });
+        }
+    }
+
+    // JSON that was serialized again into a string: {\"user\":\"bob\"}
+    if text.contains("\\\"") {
+        if let Some(decoded) = unescape_json_string(text) {
+            if decoded.contains('{') || decoded.contains('[') {
+                found.push(Decoded {
+                    encoding: Encoding::EscapedJson,
+                    text: decoded,
+                });
+            }
+        }
+    }
+
+    for decoded in found {
+        decode_level(&decoded.text, depth + 1, out);
+        out.push(decoded);
+    }
+}
+
+fn is_base64_char(c: char) -> bool {
+    // '=' padding is left out so "key=blob" splits; padding is optional anyway
+    c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '-' || c == '_'
+}
+
+fn looks_like_json(text: &str) -> bool {
+    let t = text.trim();
+    (t.starts_with('{') && t.ends_with('}')) || (t.starts_with('[') && t.ends_with(']'))
+}
+
+/// Decodes standard or URL-safe base64, with or without padding.
+/// Returns None unless the result is printable UTF-8 text.
+fn decode_base64(input: &str) -> Option<String> {
+    let text = String::from_utf8(base64(input)?).ok()?;
+    if text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        return None;
+    }
+    Some(text)
+}
+
+/// The bytes standard or URL-safe base64 encodes, with or without
+/// padding.
+pub fn base64(input: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' | b'-' => Some(62),
+            b'/' | b'_' => Some(63),
+            _ => None,
+        }
+    };
+
+    let data = input.trim_end_matches('=').as_bytes();
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut acc = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            acc |= value(c)? << (18 - 6 * i);
+        }
+        bytes.push((acc >> 16) as u8);
+        if chunk.len() > 2 {
+            bytes.push((acc >> 8) as u8);
+        }
+        if chunk.len() > 3 {
+            bytes.push(acc as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Decodes `%XX` escapes (and `+` as space). Returns None when the token
+/// has no valid escapes, so plain words are left alone.
+fn decode_url(token: &str) -> Option<String> {
+    let bytes = token.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut escapes = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        escapes += 1;
+                        i += 3;
+                        continue;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+
+    if escapes == 0 {
+        return None;
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Undoes one level of JSON string escaping (`\"`, `\\`, `\n`, `\uXXXX`, ...).
+fn unescape_json_string(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '"' => out.push('"'),
+            '\\' => out.push('\\'),
+            '/' => out.push('/'),
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).ok()?;
+                out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            other => {
+                out.push('\\');
+                out.push(other);
+            }
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_bytes() {
+        assert_eq!(base64("Zm9vYmFy").unwrap(), b"foobar");
+        assert_eq!(base64("Zm9vYg==").unwrap(), b"foob");
+        assert_eq!(base64("Zm9vYg").unwrap(), b"foob");
+        // Not text, so only base64 gives it back
+        assert_eq!(base64("/w==").unwrap(), [0xff]);
+        assert_eq!(decode_base64("/w=="), None);
+        assert_eq!(base64("Zm9vY"), None);
+        assert_eq!(base64("Zm9v!"), None);
+    }
+}
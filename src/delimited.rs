@@ -0,0 +1,124 @@
+//! --delimited: CSV, TSV and the like, one record per line, read as
+//! fields named after the file's header line, or c1, c2, ... when it has
+//! none. Fields may be quoted, with `""` for a quote inside.
+
+use serde_json::Value;
+
+use crate::fields::Object;
+use crate::timestamp;
+
+/// The fields of `line`, unquoted.
+pub fn split(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    let mut at_start = true;
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+        } else if c == separator {
+            fields.push(std::mem::take(&mut field));
+            at_start = true;
+            continue;
+        } else if c == '"' && at_start {
+            quoted = true;
+        } else {
+            field.push(c);
+        }
+        at_start = false;
+    }
+    fields.push(field);
+    fields
+}
+
+/// Whether `fields`, the first line of a file, look like column names:
+/// none empty, numeric or a timestamp, and no two the same.
+pub fn is_header(fields: &[String]) -> bool {
+    fields.iter().enumerate().all(|(i, name)| {
+        let name = name.trim();
+        !name.is_empty()
+            && name.parse::<f64>().is_err()
+            && timestamp::parse_line(name, timestamp::now_millis()).is_none()
+            && !fields[..i].iter().any(|other| other.trim() == name)
+    })
+}
+
+/// `fields` by column name; fields past the header's get c<N> names.
+pub fn record(fields: Vec<String>, columns: Option<&[String]>) -> Object {
+    let mut object = Object::new();
+    for (i, value) in fields.into_iter().enumerate() {
+        let name = match columns.and_then(|c| c.get(i)) {
+            Some(name) => name.trim().to_string(),
+            None => format!("c{}", i + 1),
+        };
+        object.entry(name).or_insert(Value::String(value));
+    }
+    object
+}
+
+/// The values of a record, for word counts and templates when it has no
+/// message column.
+pub fn text(fields: &[String]) -> String {
+    let values: Vec<&str> = fields
+        .iter()
+        .map(String::as_str)
+        .filter(|v| !v.is_empty())
+        .collect();
+    values.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn splits() {
+        assert_eq!(split("a,b,,c", ','), fields(&["a", "b", "", "c"]));
+        assert_eq!(
+            split(r#""x, y",2,"say ""hi""""#, ','),
+            fields(&["x, y", "2", r#"say "hi""#])
+        );
+        // A quote inside a field is just a character
+        assert_eq!(split(r#"5" disk,ok"#, ','), fields(&[r#"5" disk"#, "ok"]));
+        assert_eq!(split("a\tb c", '\t'), fields(&["a", "b c"]));
+        assert_eq!(split("", ','), fields(&[""]));
+    }
+
+    #[test]
+    fn headers() {
+        assert!(is_header(&fields(&["time", "level", "message"])));
+        assert!(!is_header(&fields(&[
+            "2024-05-01T12:00:00Z",
+            "ERROR",
+            "boom"
+        ])));
+        assert!(!is_header(&fields(&["id", "42"])));
+        assert!(!is_header(&fields(&["name", ""])));
+        assert!(!is_header(&fields(&["a", " a"])));
+    }
+
+    #[test]
+    fn records() {
+        let columns = fields(&["time", " level"]);
+        let object = record(fields(&["t0", "ERROR", "extra"]), Some(&columns));
+        assert_eq!(object["time"], "t0");
+        assert_eq!(object["level"], "ERROR");
+        assert_eq!(object["c3"], "extra");
+        let object = record(fields(&["x", "y"]), None);
+        assert_eq!(object["c1"], "x");
+        assert_eq!(object["c2"], "y");
+        assert_eq!(text(&fields(&["t0", "", "boom"])), "t0 boom");
+    }
+}
@@ -0,0 +1,71 @@
+//! --distinct: how many values a field takes, counted exactly while there
+//! are few and estimated with HyperLogLog once there are many, with how
+//! often each of them came.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hll::HyperLogLog;
+
+/// Values counted one by one at most; past this the number of values is
+/// estimated, and only the ones already counted go on being counted.
+const MAX_VALUES: usize = 10_000;
+
+/// The values of one --distinct field.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DistinctTally {
+    /// Lines per value, for the first MAX_VALUES values
+    pub values: HashMap<String, usize>,
+    /// Whether values were left out of `values`
+    pub overflowed: bool,
+    /// Every value, for the estimate once `values` overflows
+    pub sketch: HyperLogLog,
+    /// Lines that had the field
+    pub lines: usize,
+    /// Lines that didn't
+    pub missing: usize,
+}
+
+impl DistinctTally {
+    pub fn record(&mut self, value: Option<&str>) {
+        let Some(value) = value else {
+            self.missing += 1;
+            return;
+        };
+        self.lines += 1;
+        self.sketch.add(value);
+        self.add(value, 1);
+    }
+
+    fn add(&mut self, value: &str, count: usize) {
+        let full = self.values.len() >= MAX_VALUES;
+        match self.values.get_mut(value) {
+            Some(total) => *total += count,
+            None if full => self.overflowed = true,
+            None => {
+                self.values.insert(value.to_string(), count);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: DistinctTally) {
+        for (value, count) in other.values {
+            self.add(&value, count);
+        }
+        self.overflowed |= other.overflowed;
+        self.sketch.merge(&other.sketch);
+        self.lines += other.lines;
+        self.missing += other.missing;
+    }
+
+    /// The number of values, and whether it is exact.
+    pub fn count(&self) -> (u64, bool) {
+        if self.overflowed {
+            let estimate = self.sketch.estimate().max(self.values.len() as u64 + 1);
+            (estimate, false)
+        } else {
+            (self.values.len() as u64, true)
+        }
+    }
+}
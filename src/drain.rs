@@ -0,0 +1,527 @@
+//! Online log-template mining after Drain (He et al., ICWS 2017): lines
+//! are routed through a fixed-depth tree by token count and leading
+//! tokens, then joined to the most similar template in that leaf, with
+//! the tokens that differ turned into `<*>`. Numbers, UUIDs, IP addresses
+//! and hex ids are placeholders from the start, keeping the text around
+//! them (`user=<*>`, `<*>ms`).
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Placeholder for a variable token.
+pub const WILDCARD: &str = "<*>";
+
+/// How many leading tokens pick the leaf, after the token count.
+const PREFIX_DEPTH: usize = 2;
+
+/// Share of tokens that must match for a line to join a template.
+const SIMILARITY: f64 = 0.4;
+
+/// Children per tree node before further tokens share the wildcard child.
+const MAX_CHILDREN: usize = 100;
+
+/// Distinct values kept per placeholder; rarer ones past this are only
+/// counted in ValueCounts::other.
+const MAX_VALUES: usize = 1000;
+
+/// One mined template and how many lines it covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cluster {
+    pub tokens: Vec<String>,
+    pub count: usize,
+    /// Values seen at each position; only filled where `tokens` has `<*>`
+    pub values: Vec<ValueCounts>,
+    /// "file:line: text" of the first line of this template
+    pub first_seen: String,
+}
+
+/// How often each value filled one placeholder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValueCounts {
+    pub counts: HashMap<String, usize>,
+    /// Lines whose value was not kept because MAX_VALUES was reached
+    pub other: usize,
+}
+
+impl ValueCounts {
+    fn add(&mut self, value: &str, count: usize) {
+        if let Some(n) = self.counts.get_mut(value) {
+            *n += count;
+        } else if self.counts.len() < MAX_VALUES {
+            self.counts.insert(value.to_string(), count);
+        } else {
+            self.other += count;
+        }
+    }
+
+    fn merge(&mut self, other: ValueCounts) {
+        // Most frequent first, so that the values kept under MAX_VALUES
+        // don't depend on hash order
+        for (value, count) in other.ranked() {
+            self.add(value, count);
+        }
+        self.other += other.other;
+    }
+
+    /// Values by count, most frequent first (ties alphabetically).
+    pub fn ranked(&self) -> Vec<(&str, usize)> {
+        let mut ranked: Vec<(&str, usize)> =
+            self.counts.iter().map(|(v, c)| (v.as_str(), *c)).collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked
+    }
+}
+
+impl Cluster {
+    pub fn template(&self) -> String {
+        self.tokens.join(" ")
+    }
+
+    /// What filled position `i`, as whole tokens with counts: the kept
+    /// values of a `<*>`, those of a masked token with its text put back
+    /// around them (`20` of `<*>ms` as `20ms`), or the constant token for
+    /// all lines.
+    fn take_values(&mut self, i: usize) -> ValueCounts {
+        let token = &self.tokens[i];
+        if token == WILDCARD {
+            return std::mem::take(&mut self.values[i]);
+        }
+        let mut values = ValueCounts::default();
+        if is_variable(token) {
+            let masked = std::mem::take(&mut self.values[i]);
+            for (value, count) in masked.ranked() {
+                values.add(&token.replacen(WILDCARD, value, 1), count);
+            }
+            values.other = masked.other;
+        } else {
+            values.add(token, self.count);
+        }
+        values
+    }
+
+    /// Joins `other` into this template; positions that differ become
+    /// placeholders and start counting values.
+    fn absorb(&mut self, mut other: Cluster) {
+        for i in 0..self.tokens.len() {
+            if self.tokens[i] == other.tokens[i] {
+                if is_variable(&self.tokens[i]) {
+                    let more = std::mem::take(&mut other.values[i]);
+                    self.values[i].merge(more);
+                }
+                continue;
+            }
+            if self.tokens[i] != WILDCARD {
+                self.values[i] = self.take_values(i);
+                self.tokens[i] = WILDCARD.to_string();
+            }
+            let more = other.take_values(i);
+            self.values[i].merge(more);
+        }
+        self.count += other.count;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: HashMap<String, Node>,
+    /// Indexes into Drain::clusters, at leaf depth only
+    clusters: Vec<usize>,
+}
+
+/// Only the clusters are saved (for --cache and --checkpoint); the tree
+/// is rebuilt from them on load.
+#[derive(Debug, Default, Serialize)]
+pub struct Drain {
+    /// First level: token count
+    #[serde(skip)]
+    root: HashMap<usize, Node>,
+    pub clusters: Vec<Cluster>,
+}
+
+impl Drain {
+    /// Adds a line and returns the index of its template in `clusters`;
+    /// blank lines have none.
+    pub fn add_line(&mut self, line: &str, file_name: &str, line_number: usize) -> Option<usize> {
+        let mut tokens = Vec::new();
+        let mut values = Vec::new();
+        for token in line.split_whitespace() {
+            let mut value = ValueCounts::default();
+            match mask(token) {
+                Some((masked, variable)) => {
+                    value.add(variable, 1);
+                    tokens.push(masked);
+                }
+                None => tokens.push(token.to_string()),
+            }
+            values.push(value);
+        }
+        if tokens.is_empty() {
+            return None;
+        }
+        let known = self.clusters.len();
+        let index = self.add(Cluster {
+            tokens,
+            count: 1,
+            values,
+            first_seen: String::new(),
+        });
+        // Only new templates keep their line, so most lines cost no copy
+        if self.clusters.len() > known {
+            self.clusters[known].first_seen = format!("{}:{}: {}", file_name, line_number, line);
+        }
+        Some(index)
+    }
+
+    /// Folds in the templates mined from a later file, returning where
+    /// each of its clusters ended up in this one.
+    pub fn merge(&mut self, other: Drain) -> Vec<usize> {
+        other.clusters.into_iter().map(|c| self.add(c)).collect()
+    }
+
+    /// Adds a line (a cluster of one) or a template mined elsewhere and
+    /// returns the index of the cluster it joined. `incoming` has at least
+    /// one token.
+    fn add(&mut self, incoming: Cluster) -> usize {
+        let tokens: Vec<&str> = incoming.tokens.iter().map(String::as_str).collect();
+        let node = leaf(&mut self.root, &tokens);
+
+        let best = node
+            .clusters
+            .iter()
+            .map(|&i| (i, similarity(&self.clusters[i].tokens, &tokens)))
+            .filter(|&(_, sim)| sim >= SIMILARITY)
+            // The first of equally similar templates wins, so results are stable
+            .fold(None, |best: Option<(usize, f64)>, (i, sim)| match best {
+                Some((_, best_sim)) if best_sim >= sim => best,
+                _ => Some((i, sim)),
+            });
+
+        match best {
+            Some((i, _)) => {
+                self.clusters[i].absorb(incoming);
+                i
+            }
+            None => {
+                let i = self.clusters.len();
+                node.clusters.push(i);
+                self.clusters.push(incoming);
+                i
+            }
+        }
+    }
+}
+
+/// The leaf of the tree where templates like `tokens` live, created as
+/// needed.
+fn leaf<'r>(root: &'r mut HashMap<usize, Node>, tokens: &[&str]) -> &'r mut Node {
+    let mut node = root.entry(tokens.len()).or_default();
+    for token in tokens.iter().take(PREFIX_DEPTH) {
+        let key = if node.children.contains_key(*token) {
+            *token
+        } else if *token != WILDCARD && node.children.len() < MAX_CHILDREN {
+            node.children.insert(token.to_string(), Node::default());
+            *token
+        } else {
+            WILDCARD
+        };
+        node = node.children.entry(key.to_string()).or_default();
+    }
+    node
+}
+
+impl<'de> Deserialize<'de> for Drain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Saved {
+            clusters: Vec<Cluster>,
+        }
+        let saved = Saved::deserialize(deserializer)?;
+        // Each cluster goes back under its current tokens, keeping its
+        // index (sessions refer to templates by index)
+        let mut root = HashMap::new();
+        for (i, cluster) in saved.clusters.iter().enumerate() {
+            let tokens: Vec<&str> = cluster.tokens.iter().map(String::as_str).collect();
+            leaf(&mut root, &tokens).clusters.push(i);
+        }
+        Ok(Drain {
+            root,
+            clusters: saved.clusters,
+        })
+    }
+}
+
+/// Whether a template token holds a placeholder: is `<*>` or has one in it.
+pub fn is_variable(token: &str) -> bool {
+    token.contains(WILDCARD)
+}
+
+/// `token` with its variable part as `<*>`, and that part; None if it has
+/// none. A number, UUID, IP address (with its port) or hex id with text
+/// around it keeps the text; any other token with digits, like a date or
+/// `7f3a-x9`, is a placeholder as a whole.
+fn mask(token: &str) -> Option<(String, &str)> {
+    if !token.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let bytes = token.as_bytes();
+    let span = (0..bytes.len())
+        .filter(|&i| bytes[i].is_ascii_hexdigit())
+        .filter(|&i| i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+        .find_map(|start| {
+            [uuid_len, ip_len, hex_len, number_len]
+                .iter()
+                .find_map(|len| len(&token[start..]))
+                .map(|len| (start, start + len))
+        });
+    match span {
+        Some((start, end))
+            if !token[..start].bytes().any(|b| b.is_ascii_digit())
+                && !token[end..].bytes().any(|b| b.is_ascii_digit()) =>
+        {
+            let masked = format!("{}{}{}", &token[..start], WILDCARD, &token[end..]);
+            Some((masked, &token[start..end]))
+        }
+        _ => Some((WILDCARD.to_string(), token)),
+    }
+}
+
+/// Length of `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` at the start of `text`.
+fn uuid_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let groups = [8, 4, 4, 4, 12];
+    let mut at = 0;
+    for (n, &len) in groups.iter().enumerate() {
+        let run = bytes[at..]
+            .iter()
+            .take_while(|b| b.is_ascii_hexdigit())
+            .count();
+        if run != len {
+            return None;
+        }
+        at += len;
+        if n + 1 < groups.len() {
+            if bytes.get(at) != Some(&b'-') {
+                return None;
+            }
+            at += 1;
+        }
+    }
+    Some(at)
+}
+
+/// Length of a dotted IPv4 address, with a `:port`, at the start of `text`.
+fn ip_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut at = 0;
+    for part in 0..4 {
+        let run = bytes[at..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if run == 0 || run > 3 {
+            return None;
+        }
+        at += run;
+        if part < 3 {
+            if bytes.get(at) != Some(&b'.') {
+                return None;
+            }
+            at += 1;
+        }
+    }
+    if bytes.get(at) == Some(&b':') {
+        let port = bytes[at + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if port > 0 {
+            at += port + 1;
+        }
+    }
+    Some(at)
+}
+
+/// Length of `0x1f`, or of a run of at least 8 hex digits (with a letter
+/// and a digit, so neither plain words nor plain numbers), at the start of
+/// `text`.
+fn hex_len(text: &str) -> Option<usize> {
+    let hex_run = |t: &str| t.bytes().take_while(u8::is_ascii_hexdigit).count();
+    if let Some(rest) = text.strip_prefix("0x") {
+        let run = hex_run(rest);
+        return (run > 0).then_some(run + 2);
+    }
+    let run = hex_run(text);
+    let digits = &text.as_bytes()[..run];
+    let mixed = digits.iter().any(u8::is_ascii_digit) && digits.iter().any(u8::is_ascii_alphabetic);
+    let ends = !text
+        .as_bytes()
+        .get(run)
+        .is_some_and(u8::is_ascii_alphanumeric);
+    (run >= 8 && mixed && ends).then_some(run)
+}
+
+/// Length of a number like `42`, `1.5` or `1,024` at the start of `text`.
+fn number_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut at = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    if at == 0 {
+        return None;
+    }
+    while matches!(bytes.get(at), Some(b'.' | b','))
+        && bytes.get(at + 1).is_some_and(u8::is_ascii_digit)
+    {
+        at += 1;
+        at += bytes[at..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+    }
+    Some(at)
+}
+
+/// Share of positions where the template and the line agree; wildcards
+/// in the template don't count as agreement.
+fn similarity(template: &[String], tokens: &[&str]) -> f64 {
+    let same = template
+        .iter()
+        .zip(tokens)
+        .filter(|(t, l)| t.as_str() != WILDCARD && t.as_str() == **l)
+        .count();
+    same as f64 / template.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mine(lines: &[&str]) -> Drain {
+        let mut drain = Drain::default();
+        for (i, line) in lines.iter().enumerate() {
+            drain.add_line(line, "app.log", i + 1);
+        }
+        drain
+    }
+
+    fn templates(drain: &Drain) -> Vec<String> {
+        drain.clusters.iter().map(Cluster::template).collect()
+    }
+
+    #[test]
+    fn masking() {
+        let masked = |token| mask(token).map(|(t, v)| (t, v.to_string()));
+        let pair = |t: &str, v: &str| Some((t.to_string(), v.to_string()));
+        assert_eq!(masked("timeout"), None);
+        assert_eq!(masked("42"), pair("<*>", "42"));
+        assert_eq!(masked("user=42"), pair("user=<*>", "42"));
+        assert_eq!(masked("1,024ms"), pair("<*>ms", "1,024"));
+        assert_eq!(masked("(2.5s)"), pair("(<*>s)", "2.5"));
+        assert_eq!(masked("10.0.0.1:8080"), pair("<*>", "10.0.0.1:8080"));
+        assert_eq!(masked("from=10.0.0.1,"), pair("from=<*>,", "10.0.0.1"));
+        assert_eq!(
+            masked("id=0b3e9a2c-6d1f-4e8a-9c7b-2f5d8e1a4b6c"),
+            pair("id=<*>", "0b3e9a2c-6d1f-4e8a-9c7b-2f5d8e1a4b6c")
+        );
+        assert_eq!(masked("ptr=0x7ffd"), pair("ptr=<*>", "0x7ffd"));
+        assert_eq!(
+            masked("commit=deadbeef42"),
+            pair("commit=<*>", "deadbeef42")
+        );
+        // Digits left over around the span: the whole token varies
+        assert_eq!(masked("2024-05-01"), pair("<*>", "2024-05-01"));
+        assert_eq!(masked("7f3a-x9"), pair("<*>", "7f3a-x9"));
+        assert_eq!(masked("v2"), pair("<*>", "v2"));
+    }
+
+    #[test]
+    fn similar_lines_share_a_template() {
+        let drain = mine(&[
+            "connected to db in 12ms",
+            "connected to db in 340ms",
+            "connected to cache in 7ms",
+            "disk full on /var",
+        ]);
+        assert_eq!(
+            templates(&drain),
+            ["connected to <*> in <*>ms", "disk full on /var"]
+        );
+        let connected = &drain.clusters[0];
+        assert_eq!(connected.count, 3);
+        assert_eq!(connected.values[2].ranked(), [("db", 2), ("cache", 1)]);
+        assert_eq!(
+            connected.values[4].ranked(),
+            [("12", 1), ("340", 1), ("7", 1)]
+        );
+        assert_eq!(connected.first_seen, "app.log:1: connected to db in 12ms");
+        // Only lines with as many tokens, and leading tokens alike, meet
+        assert!(mine(&["a b c", "a b c d"]).clusters.len() == 2);
+        assert_eq!(mine(&["   "]).clusters.len(), 0);
+    }
+
+    #[test]
+    fn similarity_threshold() {
+        assert_eq!(
+            similarity(
+                &["a", "b", "c", "d", "e"].map(String::from),
+                &["a", "b", "x", "y", "z"]
+            ),
+            0.4
+        );
+        assert_eq!(
+            similarity(&["<*>", "b"].map(String::from), &["<*>", "b"]),
+            0.5
+        );
+        // Two of five tokens in common is enough to join
+        let drain = mine(&["job a b c d", "job a x y z"]);
+        assert_eq!(templates(&drain), ["job a <*> <*> <*>"]);
+        // Two of six is not
+        let drain = mine(&["job a b c d e", "job a x y z w"]);
+        assert_eq!(drain.clusters.len(), 2);
+    }
+
+    #[test]
+    fn differing_masked_tokens_keep_whole_values() {
+        let drain = mine(&[
+            "request took duration=2.3s",
+            "request took duration=20ms",
+            "request took duration=4ms",
+        ]);
+        assert_eq!(templates(&drain), ["request took <*>"]);
+        assert_eq!(
+            drain.clusters[0].values[2].ranked(),
+            [
+                ("duration=2.3s", 1),
+                ("duration=20ms", 1),
+                ("duration=4ms", 1)
+            ]
+        );
+        // A constant token turned placeholder counts every line it had
+        let drain = mine(&[
+            "login ok for alice",
+            "login ok for alice",
+            "login ok for bob",
+        ]);
+        assert_eq!(
+            drain.clusters[0].values[3].ranked(),
+            [("alice", 2), ("bob", 1)]
+        );
+    }
+
+    #[test]
+    fn merges_and_reloads() {
+        let mut first = mine(&["connected to db in 12ms"]);
+        let second = mine(&["disk full on /var", "connected to db in 40ms"]);
+        assert_eq!(first.merge(second), [1, 0]);
+        assert_eq!(first.clusters[0].count, 2);
+        assert_eq!(first.clusters[0].values[4].ranked(), [("12", 1), ("40", 1)]);
+
+        let saved = serde_json::to_string(&first).unwrap();
+        let mut loaded: Drain = serde_json::from_str(&saved).unwrap();
+        assert_eq!(
+            loaded.add_line("connected to db in 9ms", "b.log", 1),
+            Some(0)
+        );
+        assert_eq!(loaded.add_line("disk full on /home", "b.log", 2), Some(1));
+        assert_eq!(templates(&loaded)[1], "disk full on <*>");
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Linux errno constants and their numeric values, used to recognise both
+/// symbolic names (`ECONNREFUSED`) and numeric forms (`errno=111`).
+const ERRNO_NAMES: &[(&str, u32)] = &[
+    ("EPERM", 1),
+    ("ENOENT", 2),
+    ("ESRCH", 3),
+    ("EINTR", 4),
+    ("EIO", 5),
+    ("ENXIO", 6),
+    ("E2BIG", 7),
+    ("ENOEXEC", 8),
+    ("EBADF", 9),
+    ("ECHILD", 10),
+    ("EAGAIN", 11),
+    ("ENOMEM", 12),
+    ("EACCES", 13),
+    ("EFAULT", 14),
+    ("EBUSY", 16),
+    ("EEXIST", 17),
+    ("EXDEV", 18),
+    ("ENODEV", 19),
+    ("ENOTDIR", 20),
+    ("EISDIR", 21),
+    ("EINVAL", 22),
+    ("ENFILE", 23),
+    ("EMFILE", 24),
+    ("ENOTTY", 25),
+    ("ETXTBSY", 26),
+    ("EFBIG", 27),
+    ("ENOSPC", 28),
+    ("ESPIPE", 29),
+    ("EROFS", 30),
+    ("EMLINK", 31),
+    ("EPIPE", 32),
+    ("ERANGE", 34),
+    ("EDEADLK", 35),
+    ("ENAMETOOLONG", 36),
+    ("ENOLCK", 37),
+    ("ENOSYS", 38),
+    ("ENOTEMPTY", 39),
+    ("ELOOP", 40),
+    ("ENOTSOCK", 88),
+    ("EMSGSIZE", 90),
+    ("ENOPROTOOPT", 92),
+    ("EOPNOTSUPP", 95),
+    ("EAFNOSUPPORT", 97),
+    ("EADDRINUSE", 98),
+    ("EADDRNOTAVAIL", 99),
+    ("ENETDOWN", 100),
+    ("ENETUNREACH", 101),
+    ("ENETRESET", 102),
+    ("ECONNABORTED", 103),
+    ("ECONNRESET", 104),
+    ("ENOBUFS", 105),
+    ("EISCONN", 106),
+    ("ENOTCONN", 107),
+    ("ESHUTDOWN", 108),
+    ("ETIMEDOUT", 110),
+    ("ECONNREFUSED", 111),
+    ("EHOSTDOWN", 112),
+    ("EHOSTUNREACH", 113),
+    ("EALREADY", 114),
+    ("EINPROGRESS", 115),
+    ("ESTALE", 116),
+    ("EDQUOT", 122),
+    ("ECANCELED", 125),
+];
+
+/// How many lines mentioned one errno constant, and which files they were in.
+#[derive(Serialize, Deserialize)]
+pub struct ErrnoStat {
+    pub count: usize,
+    pub files: Vec<String>,
+}
+
+impl ErrnoStat {
+    pub fn merge(&mut self, other: ErrnoStat) {
+        self.count += other.count;
+        for file in other.files {
+            if !self.files.contains(&file) {
+                self.files.push(file);
+            }
+        }
+    }
+}
+
+/// The static name for a constant read back from elsewhere, e.g. a cache.
+pub fn known_name(name: &str) -> Option<&'static str> {
+    ERRNO_NAMES.iter().map(|(n, _)| *n).find(|n| *n == name)
+}
+
+/// Finds errno constants on each line, either by name (`EACCES`) or by
+/// number (`errno=13`, `[Errno 13]`), and records them per file.
+/// A constant is counted at most once per line.
+pub fn count_errnos_in_text(
+    text: &str,
+    file_name: &str,
+    stats: &mut HashMap<&'static str, ErrnoStat>,
+) {
+    for line in text.lines() {
+        let mut found: Vec<&'static str> = Vec::new();
+
+        // Symbolic names: whole tokens only, so "ERROR" or "EIOx" don't match
+        for token in line.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if let Some((name, _)) = ERRNO_NAMES.iter().find(|(name, _)| *name == token) {
+                if !found.contains(name) {
+                    found.push(name);
+                }
+            }
+        }
+
+        // Numeric forms: "errno" followed by separators and a number
+        let lower = line.to_lowercase();
+        let mut rest = lower.as_str();
+        while let Some(pos) = rest.find("errno") {
+            rest = &rest[pos + "errno".len()..];
+            let digits: String = rest
+                .trim_start_matches(['=', ':', ' ', '-'])
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+
+            let number = match digits.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            if let Some((name, _)) = ERRNO_NAMES.iter().find(|(_, n)| *n == number) {
+                if !found.contains(name) {
+                    found.push(name);
+                }
+            }
+        }
+
+        for name in found {
+            let stat = stats.entry(name).or_insert(ErrnoStat {
+                count: 0,
+                files: Vec::new(),
+            });
+            stat.count += 1;
+            if !stat.files.iter().any(|f| f == file_name) {
+                stat.files.push(file_name.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(text: &str) -> HashMap<&'static str, ErrnoStat> {
+        let mut stats = HashMap::new();
+        count_errnos_in_text(text, "app.log", &mut stats);
+        stats
+    }
+
+    #[test]
+    fn names_and_numbers() {
+        let stats = count(
+            "connect failed: ECONNREFUSED\n\
+             open(/etc/x): errno=13\n\
+             [Errno 2] No such file; ENOENT again\n\
+             write: Errno: 28\n\
+             ok",
+        );
+        let mut names: Vec<(&str, usize)> = stats.iter().map(|(n, s)| (*n, s.count)).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                ("EACCES", 1),
+                ("ECONNREFUSED", 1),
+                ("ENOENT", 1),
+                ("ENOSPC", 1)
+            ]
+        );
+        assert_eq!(stats["ENOENT"].files, ["app.log"]);
+    }
+
+    #[test]
+    fn whole_tokens_and_known_numbers_only() {
+        let stats = count("ERROR EIOx xEIO errno=9999 errno=abc errno_count=5 EPERM_DENIED");
+        assert_eq!(stats.len(), 1, "{:?}", stats.keys().collect::<Vec<_>>());
+        // "EPERM_DENIED" splits at the underscore
+        assert_eq!(stats["EPERM"].count, 1);
+    }
+
+    #[test]
+    fn merges_files_once() {
+        let mut stats = HashMap::new();
+        count_errnos_in_text("EIO\nEIO\n", "a.log", &mut stats);
+        let mut more = HashMap::new();
+        count_errnos_in_text("EIO\n", "b.log", &mut more);
+        count_errnos_in_text("EIO\n", "a.log", &mut more);
+        let eio = stats.get_mut("EIO").unwrap();
+        eio.merge(more.remove("EIO").unwrap());
+        assert_eq!(eio.count, 4);
+        assert_eq!(eio.files, ["a.log", "b.log"]);
+
+        assert_eq!(known_name("ETIMEDOUT"), Some("ETIMEDOUT"));
+        assert_eq!(known_name("ENOTANERRNO"), None);
+    }
+}
@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+
+use serde_json::{Map, Value};
+
+use crate::cli::FieldKey;
+use crate::level::Level;
+use crate::timestamp;
+
+/// Fields holding the message, tried in order.
+const MESSAGE_FIELDS: [&str; 3] = ["msg", "message", "@message"];
+
+/// Fields holding the level; `at` is Heroku's.
+const LEVEL_FIELDS: [&str; 5] = ["level", "severity", "lvl", "log.level", "at"];
+
+const TIME_FIELDS: [&str; 5] = ["time", "timestamp", "ts", "@timestamp", "t"];
+
+/// The fields of a line read with a line format (--json-logs, --logfmt,
+/// --access-log, --syslog, --delimited, --cef).
+pub type Object = Map<String, Value>;
+
+/// Where a line's fields are looked up: in its parsed fields with a line
+/// format, else in its text.
+pub struct Record<'l> {
+    /// The line as cut for field parsing (--max-record-bytes, --max-fields)
+    pub text: &'l str,
+    pub object: Option<&'l Object>,
+}
+
+impl<'l> Record<'l> {
+    pub fn value(&self, name: &str) -> Option<Cow<'l, str>> {
+        match self.object {
+            Some(object) => field(object, name),
+            None => find_value(self.text, name).map(Cow::Borrowed),
+        }
+    }
+
+    /// The value of `key`: a field's, or a regex's first capture group
+    /// (the whole match if it has none).
+    pub fn lookup(&self, key: &FieldKey) -> Option<Cow<'l, str>> {
+        match key {
+            FieldKey::Field(name) => self.value(name),
+            FieldKey::Pattern(pattern) => {
+                let captures = pattern.captures(self.text)?;
+                let found = captures.get(1).or_else(|| captures.get(0))?;
+                Some(Cow::Borrowed(found.as_str()))
+            }
+        }
+    }
+}
+
+/// The value of field `name` as text: strings as they are, numbers and
+/// booleans as written, objects and arrays as JSON. A dotted name like
+/// `http.status` looks into nested objects, unless a field is named that
+/// way itself.
+pub fn field<'o>(object: &'o Object, name: &str) -> Option<Cow<'o, str>> {
+    let value = match object.get(name) {
+        Some(value) => value,
+        None => {
+            let mut parts = name.split('.');
+            let mut value = object.get(parts.next()?)?;
+            for part in parts {
+                value = value.as_object()?.get(part)?;
+            }
+            value
+        }
+    };
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        other => Some(Cow::Owned(other.to_string())),
+    }
+}
+
+pub fn message(object: &Object) -> Option<Cow<'_, str>> {
+    MESSAGE_FIELDS.iter().find_map(|name| field(object, name))
+}
+
+/// The level in a level field: a level word in any case, or a number as
+/// bunyan and pino write them (30 info, 50 error, ...).
+pub fn level(object: &Object) -> Option<Level> {
+    LEVEL_FIELDS.iter().find_map(|name| {
+        let value = field(object, name)?;
+        if let Ok(number) = value.parse::<u32>() {
+            return match number {
+                0..=10 => Some(Level::Trace),
+                11..=20 => Some(Level::Debug),
+                21..=30 => Some(Level::Info),
+                31..=40 => Some(Level::Warn),
+                41..=50 => Some(Level::Error),
+                _ => Some(Level::Fatal),
+            };
+        }
+        Level::from_word(&value.to_ascii_uppercase())
+    })
+}
+
+/// The time in a time field, in any format timestamp::parse_line knows,
+/// a syslog date in the year that puts it at most a day after `near`.
+pub fn time(object: &Object, near: i64) -> Option<i64> {
+    TIME_FIELDS
+        .iter()
+        .find_map(|name| timestamp::parse_line(&field(object, name)?, near))
+}
+
+/// Finds the value of a `name=value`, `name: value` or `"name": "value"`
+/// field in a line. The name must match exactly and start a word.
+pub fn find_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let mut search_from = 0;
+
+    while let Some(found) = line[search_from..].find(name) {
+        let start = search_from + found;
+        search_from = start + name.len();
+
+        let before = line[..start].chars().next_back();
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+
+        if let Some(value) = value_after_name(&line[search_from..]) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Given the text right after a field name, returns the value if the name
+/// is followed by `=` or `:`.
+fn value_after_name(rest: &str) -> Option<&str> {
+    // A closing quote belongs to a JSON key: "name": value
+    let rest = rest.strip_prefix('"').unwrap_or(rest);
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').or_else(|| rest.strip_prefix(':'))?;
+    let rest = rest.trim_start();
+
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        return Some(&quoted[..end]);
+    }
+
+    let end = rest
+        .find(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '}' | ']' | ')' | '"'))
+        .unwrap_or(rest.len());
+    if end == 0 {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+/// `record` cut before its field number `max_fields + 1`, and whether it
+/// was cut. A field is a name (starting with a letter, `_` or `"`) followed
+/// by `=` or `:`, so timestamps like 12:00:01 don't count.
+pub fn limit_fields(record: &str, max_fields: usize) -> (&str, bool) {
+    let bytes = record.as_bytes();
+    let mut fields = 0;
+    let mut token_start = 0;
+    let mut counted = false;
+    for (i, &b) in bytes.iter().enumerate() {
+        if b.is_ascii_whitespace() || matches!(b, b',' | b'{' | b'[') {
+            token_start = i + 1;
+            counted = false;
+            continue;
+        }
+        if counted || !matches!(b, b'=' | b':') || i == token_start {
+            continue;
+        }
+        let first = bytes[token_start];
+        if first.is_ascii_alphabetic() || matches!(first, b'_' | b'"') {
+            counted = true;
+            fields += 1;
+            if fields > max_fields {
+                return (&record[..token_start], true);
+            }
+        }
+    }
+    (record, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use regex::Regex;
+    use serde_json::json;
+
+    fn object(value: Value) -> Object {
+        match value {
+            Value::Object(object) => object,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn values_in_text() {
+        let line = r#"ts=1 user_id=7 id=42 "name": "bob smith", took: 15ms; path=/a}"#;
+        assert_eq!(find_value(line, "id"), Some("42"));
+        assert_eq!(find_value(line, "name"), Some("bob smith"));
+        assert_eq!(find_value(line, "took"), Some("15ms"));
+        assert_eq!(find_value(line, "path"), Some("/a"));
+        assert_eq!(find_value(line, "user"), None);
+        assert_eq!(find_value("id= x", "id"), Some("x"));
+        assert_eq!(find_value("id=", "id"), None);
+        assert_eq!(find_value("valid=1", "id"), None);
+    }
+
+    #[test]
+    fn values_in_objects() {
+        let nested = object(json!({
+            "msg": "hi",
+            "http": {"status": 503, "ok": false},
+            "http.method": "GET",
+            "tags": ["a"],
+            "none": null
+        }));
+        let value = |name| field(&nested, name).map(Cow::into_owned);
+        assert_eq!(value("http.status").as_deref(), Some("503"));
+        assert_eq!(value("http.ok").as_deref(), Some("false"));
+        // A field named with a dot wins over the nested lookup
+        assert_eq!(value("http.method").as_deref(), Some("GET"));
+        assert_eq!(value("tags").as_deref(), Some(r#"["a"]"#));
+        assert_eq!(value("none"), None);
+        assert_eq!(value("msg.x"), None);
+        assert_eq!(message(&nested).as_deref(), Some("hi"));
+        let later = object(json!({"@message": "x", "message": "m"}));
+        assert_eq!(message(&later).as_deref(), Some("m"));
+    }
+
+    #[test]
+    fn levels_and_times() {
+        let level_of = |value: Value| level(&object(value));
+        assert_eq!(level_of(json!({"level": "warning"})), Some(Level::Warn));
+        assert_eq!(level_of(json!({"severity": "ERROR"})), Some(Level::Error));
+        assert_eq!(level_of(json!({"at": "info"})), Some(Level::Info));
+        assert_eq!(
+            level_of(json!({"log": {"level": "debug"}})),
+            Some(Level::Debug)
+        );
+        // bunyan and pino numbers
+        assert_eq!(level_of(json!({"level": 30})), Some(Level::Info));
+        assert_eq!(level_of(json!({"level": 50})), Some(Level::Error));
+        assert_eq!(level_of(json!({"level": 60})), Some(Level::Fatal));
+        assert_eq!(level_of(json!({"level": "loud"})), None);
+
+        let noon = 1_714_564_800_000;
+        let time_of = |value: Value| time(&object(value), 0);
+        assert_eq!(time_of(json!({"time": "2024-05-01T12:00:00Z"})), Some(noon));
+        assert_eq!(
+            time_of(json!({"@timestamp": "2024-05-01 12:00:00"})),
+            Some(noon)
+        );
+        assert_eq!(time_of(json!({"ts": 1714564800})), Some(noon));
+        assert_eq!(time_of(json!({"time": "soon"})), None);
+    }
+
+    #[test]
+    fn records() {
+        let fields = object(json!({"user": "bob"}));
+        let parsed = Record {
+            text: "user=eve",
+            object: Some(&fields),
+        };
+        let text = Record {
+            text: "user=eve took 15ms",
+            object: None,
+        };
+        let key = |name: &str| FieldKey::Field(name.to_string());
+        assert_eq!(parsed.lookup(&key("user")).as_deref(), Some("bob"));
+        assert_eq!(text.lookup(&key("user")).as_deref(), Some("eve"));
+        let took = FieldKey::Pattern(Regex::new(r"took (\d+)ms").unwrap());
+        assert_eq!(text.lookup(&took).as_deref(), Some("15"));
+        let whole = FieldKey::Pattern(Regex::new(r"\d+ms").unwrap());
+        assert_eq!(parsed.lookup(&whole), None);
+        assert_eq!(text.lookup(&whole).as_deref(), Some("15ms"));
+    }
+
+    #[test]
+    fn limits_fields() {
+        let line = r#"12:00:01 a=1 b: 2 {"c": 3, d=4} e=5"#;
+        assert_eq!(limit_fields(line, 5), (line, false));
+        assert_eq!(limit_fields(line, 2), (r#"12:00:01 a=1 b: 2 {"#, true));
+        assert_eq!(limit_fields("a=b=c d=1", 1), ("a=b=c ", true));
+        assert_eq!(limit_fields("=1 :2", 0), ("=1 :2", false));
+    }
+}
@@ -0,0 +1,72 @@
+//! Groups log lines that differ only in their variable parts (numbers,
+//! ids, hashes) under one stable fingerprint.
+
+use serde::{Deserialize, Serialize};
+
+/// The first line of a fingerprint, as a scan with
+/// Config::fingerprint_levels keeps it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Seen {
+    pub template: String,
+    pub file: String,
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// The line with numbers and hex ids masked, e.g.
+/// "2024-05-01 12:00:01 ERROR db timeout after 30s" ->
+/// "<n>-<n>-<n> <n>:<n>:<n> ERROR db timeout after <n>s".
+pub fn template(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for (i, token) in line.split(' ').enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        if is_hex_id(token) {
+            out.push_str("<hex>");
+        } else {
+            mask_numbers(token, &mut out);
+        }
+    }
+    out
+}
+
+/// FNV-1a hash of the template, as 16 hex digits. Stable across runs and
+/// builds, so it can be stored in a baseline file.
+pub fn fingerprint(template: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in template.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Tokens of 8+ hex digits (and dashes, for UUIDs) mixing digits and
+/// letters; pure numbers and dates are left to mask_numbers.
+fn is_hex_id(token: &str) -> bool {
+    let token = token.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    token.len() >= 8
+        && token.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Replaces each run of digits (with any decimal point) by "<n>".
+fn mask_numbers(token: &str, out: &mut String) {
+    let mut in_number = false;
+    let mut chars = token.chars().peekable();
+    while let Some(c) = chars.next() {
+        let decimal_point =
+            in_number && c == '.' && chars.peek().is_some_and(|n| n.is_ascii_digit());
+        if c.is_ascii_digit() || decimal_point {
+            if !in_number {
+                out.push_str("<n>");
+                in_number = true;
+            }
+        } else {
+            in_number = false;
+            out.push(c);
+        }
+    }
+}
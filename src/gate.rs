@@ -0,0 +1,389 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use serde::Deserialize;
+
+use crate::checks::{Check, CheckTally};
+use crate::cli::{self, value_for};
+use crate::fingerprint::Seen;
+use crate::level::{Level, LevelRule, LevelRules};
+use crate::scanner::Scanner;
+
+pub const USAGE: &str = "\
+Usage: logbuddy gate --config <FILE> [DIR] [OPTIONS]
+
+Checks the logs in DIR (default: the config's path, or .) against the
+budgets in a YAML config, prints a pass/fail table and exits with 1 when
+any budget is exceeded. Meant for CI jobs.
+
+Options:
+  --config <FILE>     Gate config (YAML), see below
+  --update-baseline   Write every fingerprint seen to the baseline file
+                      instead of checking against it
+  -h, --help          Show this help
+
+Config:
+  path: logs/                  # folder to scan, if DIR is not given
+  levels:                      # custom level rules (see --level-rules)
+    - match: '^\\[E\\d+\\]'
+      level: ERROR
+  budgets:
+    - level: ERROR             # lines at exactly this level
+      max: 0                   # allowed lines (default 0)
+    - level: WARN
+      max: 20
+    - match: timeout           # lines containing this text
+      max: 3
+      name: few timeouts       # optional label in the table
+  new_fingerprints:
+    baseline: gate-baseline.txt
+    levels: [FATAL, ERROR]     # default FATAL and ERROR
+    max: 0";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GateConfig {
+    path: Option<String>,
+    /// Custom level detection, as with --level-rules
+    #[serde(default)]
+    levels: Vec<LevelRule>,
+    #[serde(default)]
+    budgets: Vec<Budget>,
+    new_fingerprints: Option<FingerprintGate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Budget {
+    name: Option<String>,
+    level: Option<Level>,
+    #[serde(rename = "match")]
+    pattern: Option<String>,
+    #[serde(default)]
+    max: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FingerprintGate {
+    baseline: String,
+    #[serde(default = "default_fingerprint_levels")]
+    levels: Vec<Level>,
+    #[serde(default)]
+    max: usize,
+}
+
+fn default_fingerprint_levels() -> Vec<Level> {
+    vec![Level::Fatal, Level::Error]
+}
+
+impl Budget {
+    fn label(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let what = match (&self.level, &self.pattern) {
+            (Some(level), _) => format!("{} lines", level.name()),
+            (None, Some(pattern)) => format!("lines containing '{}'", pattern),
+            (None, None) => "lines".to_string(),
+        };
+        if self.max == 0 {
+            format!("no {}", what)
+        } else {
+            format!("at most {} {}", self.max, what)
+        }
+    }
+}
+
+/// Entry point for `logbuddy gate ...`; `args` excludes "gate".
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let mut config_path = None;
+    let mut dir = None;
+    let mut update_baseline = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(value_for(&arg, &mut args)?),
+            "--update-baseline" => update_baseline = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("unknown gate argument '{}'", other))
+            }
+            other if dir.is_none() => dir = Some(other.to_string()),
+            other => return Err(format!("gate takes one folder, got '{}' as well", other)),
+        }
+    }
+
+    let config_path = config_path.ok_or("gate needs --config <FILE>")?;
+    let text = fs::read_to_string(&config_path)
+        .map_err(|e| format!("could not read {}: {}", config_path, e))?;
+    let config: GateConfig =
+        serde_yaml::from_str(&text).map_err(|e| format!("{}: {}", config_path, e))?;
+    if let Some(b) = config
+        .budgets
+        .iter()
+        .find(|b| b.level.is_none() && b.pattern.is_none())
+    {
+        return Err(format!(
+            "{}: budget '{}' needs a level or a match",
+            config_path,
+            b.label()
+        ));
+    }
+
+    let dir = dir
+        .or(config.path.clone())
+        .unwrap_or_else(|| ".".to_string());
+    let outcome = scan(Path::new(&dir), &config)?;
+
+    if update_baseline {
+        let gate = config
+            .new_fingerprints
+            .as_ref()
+            .ok_or("--update-baseline needs new_fingerprints in the config")?;
+        write_baseline(&gate.baseline, &outcome.templates)?;
+        println!(
+            "Wrote {} fingerprints to {}",
+            outcome.templates.len(),
+            gate.baseline
+        );
+        return Ok(());
+    }
+
+    let mut rows: Vec<(String, usize, usize, &CheckTally)> = config
+        .budgets
+        .iter()
+        .zip(&outcome.budgets)
+        .map(|(b, tally)| (b.label(), tally.count, b.max, tally))
+        .collect();
+
+    let new_fingerprints;
+    if let Some(gate) = &config.new_fingerprints {
+        let baseline = read_baseline(&gate.baseline)?;
+        new_fingerprints = new_fingerprint_tally(&outcome, &baseline);
+        rows.push((
+            "new fingerprints".to_string(),
+            new_fingerprints.count,
+            gate.max,
+            &new_fingerprints,
+        ));
+    }
+
+    let failed = print_table(&rows);
+    if failed > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+struct Outcome {
+    /// One tally per budget, in config order
+    budgets: Vec<CheckTally>,
+    /// First line of each fingerprint, for the levels new_fingerprints
+    /// looks at
+    templates: BTreeMap<String, Seen>,
+}
+
+/// Scans `dir` as `logbuddy --path DIR` would, each budget a --check, so
+/// levels and lines are found the same way in both.
+fn scan(dir: &Path, config: &GateConfig) -> Result<Outcome, String> {
+    let mut scan_config = cli::parse_args(["--no-progress".to_string()])?;
+    scan_config.level_rules = LevelRules::new(config.levels.clone())?;
+    scan_config.checks = config
+        .budgets
+        .iter()
+        .map(|b| Check::budget(b.pattern.as_deref(), b.level, b.max))
+        .collect();
+    if let Some(gate) = &config.new_fingerprints {
+        scan_config.fingerprint_levels = gate.levels.clone();
+    }
+    let mut scanner = Scanner::new(&scan_config, Vec::new());
+    scanner.scan_folder(dir)?;
+    Ok(Outcome {
+        budgets: scanner.totals.checks,
+        templates: scanner.totals.fingerprints,
+    })
+}
+
+/// Fingerprints read from a baseline; a missing file is an empty baseline.
+fn read_baseline(path: &str) -> Result<HashSet<String>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(format!("could not read {}: {}", path, e)),
+    };
+    Ok(text
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_whitespace().next())
+        .map(str::to_string)
+        .collect())
+}
+
+/// One "fingerprint template" line per fingerprint; only the first word
+/// is read back, the template is there for reviewers.
+fn write_baseline(path: &str, templates: &BTreeMap<String, Seen>) -> Result<(), String> {
+    let mut text = String::from("# logbuddy gate baseline: fingerprint, then the line template\n");
+    for (fingerprint, seen) in templates {
+        text.push_str(&format!("{} {}\n", fingerprint, seen.template));
+    }
+    fs::write(path, text).map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+fn new_fingerprint_tally(outcome: &Outcome, baseline: &HashSet<String>) -> CheckTally {
+    let mut tally = CheckTally::default();
+    for (fingerprint, seen) in &outcome.templates {
+        if !baseline.contains(fingerprint) {
+            tally.record(&seen.file, seen.line_number, &seen.line);
+        }
+    }
+    tally
+}
+
+/// Prints one row per budget and returns how many failed.
+fn print_table(rows: &[(String, usize, usize, &CheckTally)]) -> usize {
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max(6);
+    println!("{:<width$}  {:>7}  {:>7}  Result", "Budget", "Found", "Max");
+
+    let mut failed = 0;
+    for (label, found, max, tally) in rows {
+        let passed = found <= max;
+        println!(
+            "{:<width$}  {:>7}  {:>7}  {}",
+            label,
+            found,
+            max,
+            if passed { "PASS" } else { "FAIL" }
+        );
+        if !passed {
+            failed += 1;
+            for sample in &tally.samples {
+                println!("    {}", sample);
+            }
+        }
+    }
+
+    println!();
+    if failed == 0 {
+        println!("Gate passed ({} budgets)", rows.len());
+    } else {
+        println!("Gate failed: {} of {} budgets exceeded", failed, rows.len());
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("logbuddy-gate-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(yaml: &str) -> GateConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn configs_and_labels() {
+        let config = config(
+            "
+budgets:
+  - level: ERROR
+  - level: WARN
+    max: 20
+  - match: timeout
+    max: 3
+  - match: oom
+    name: no OOM kills
+new_fingerprints:
+  baseline: base.txt
+",
+        );
+        let labels: Vec<String> = config.budgets.iter().map(Budget::label).collect();
+        assert_eq!(
+            labels,
+            [
+                "no ERROR lines",
+                "at most 20 WARN lines",
+                "at most 3 lines containing 'timeout'",
+                "no OOM kills"
+            ]
+        );
+        let gate = config.new_fingerprints.unwrap();
+        assert_eq!(
+            (gate.levels, gate.max),
+            (vec![Level::Fatal, Level::Error], 0)
+        );
+
+        // Typos are errors rather than budgets that never fail
+        assert!(serde_yaml::from_str::<GateConfig>("budget: []").is_err());
+        assert!(serde_yaml::from_str::<GateConfig>("budgets: [{level: ERROR, mx: 1}]").is_err());
+        assert!(serde_yaml::from_str::<GateConfig>("budgets: [{level: BAD}]").is_err());
+    }
+
+    #[test]
+    fn scans_budgets_and_new_fingerprints() {
+        let dir = temp_dir("scan");
+        fs::write(
+            dir.join("app.log"),
+            "INFO start\n[E42] disk gone\nERROR timeout after 30s\nWARN timeout after 5s\nERROR timeout after 31s\n",
+        )
+        .unwrap();
+        let config = config(
+            r"
+levels:
+  - match: '^\[E\d+\]'
+    level: FATAL
+budgets:
+  - level: ERROR
+  - match: timeout
+    max: 3
+new_fingerprints:
+  baseline: unused
+",
+        );
+        let outcome = scan(&dir, &config).unwrap();
+        let counts: Vec<usize> = outcome.budgets.iter().map(|t| t.count).collect();
+        assert_eq!(counts, [2, 3]);
+        assert_eq!(
+            outcome.budgets[0].samples[0],
+            "app.log:3: ERROR timeout after 30s"
+        );
+        // The [E42] line by the config's level rule, the two ERROR lines
+        // as one fingerprint
+        assert_eq!(outcome.templates.len(), 2);
+
+        let baseline = dir.join("baseline.txt");
+        let baseline = baseline.to_str().unwrap();
+        assert!(read_baseline(baseline).unwrap().is_empty());
+        write_baseline(baseline, &outcome.templates).unwrap();
+        let known = read_baseline(baseline).unwrap();
+        assert_eq!(known.len(), 2);
+        assert_eq!(new_fingerprint_tally(&outcome, &known).count, 0);
+
+        let mut fewer = known.clone();
+        let error = outcome
+            .templates
+            .iter()
+            .find(|(_, seen)| seen.line.starts_with("ERROR"))
+            .unwrap();
+        fewer.remove(error.0);
+        let new = new_fingerprint_tally(&outcome, &fewer);
+        assert_eq!(new.count, 1);
+        assert_eq!(new.samples, ["app.log:3: ERROR timeout after 30s"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+//! `--path gs://bucket/folder`: the objects in a Google Cloud Storage
+//! bucket as files to scan, through its JSON API. Requests carry the
+//! access token in GOOGLE_OAUTH_ACCESS_TOKEN, or else one got for the
+//! application default credentials (`gcloud auth application-default
+//! login`), or else from the metadata server of the Google Cloud machine
+//! logbuddy runs on; without any they go unauthenticated, as public
+//! buckets allow. STORAGE_EMULATOR_HOST points at an emulator instead.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::debug;
+use ureq::http::Response;
+use ureq::Body;
+
+use crate::source::{self, env_var, uri_encode, RemoteFile, Source};
+use crate::timestamp;
+
+const ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Where access tokens for user credentials come from.
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+/// Host of the metadata server on Google Cloud machines.
+const METADATA_HOST: &str = "metadata.google.internal";
+
+/// How long to wait for a metadata server, which isn't there off Google
+/// Cloud.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Gcs {
+    /// As given on the command line
+    location: String,
+    bucket: String,
+    /// Folder listed: empty for the whole bucket, else ending in '/'
+    prefix: String,
+    /// Scheme and host requests go to
+    endpoint: String,
+    /// OAuth access token sent as a bearer token
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+/// A page of a bucket listing.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Listing {
+    #[serde(default)]
+    items: Vec<Object>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    name: String,
+    /// Bytes, as a decimal string
+    #[serde(default)]
+    size: String,
+    /// RFC 3339
+    updated: Option<String>,
+    /// Changes whenever the object is rewritten
+    #[serde(default)]
+    generation: String,
+}
+
+/// A credentials file for the application default credentials.
+#[derive(Deserialize)]
+struct CredentialsFile {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    client_id: String,
+    #[serde(default)]
+    client_secret: String,
+    #[serde(default)]
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+impl Gcs {
+    /// The bucket and folder of `url`, `gs://bucket/folder` (or just
+    /// `gs://bucket`), with an access token from the environment.
+    pub fn new(url: &str) -> Result<Gcs, String> {
+        let invalid = || format!("'{}' is not a gs://bucket/folder URL", url);
+        let rest = url.strip_prefix("gs://").ok_or_else(invalid)?;
+        let (bucket, folder) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(invalid());
+        }
+        let prefix = match folder.trim_matches('/') {
+            "" => String::new(),
+            folder => format!("{}/", folder),
+        };
+        let agent = source::agent();
+        let (endpoint, token) = match env_var("STORAGE_EMULATOR_HOST") {
+            // Emulators take no credentials
+            Some(host) if host.contains("://") => (host.trim_end_matches('/').to_string(), None),
+            Some(host) => (format!("http://{}", host.trim_end_matches('/')), None),
+            None => (ENDPOINT.to_string(), find_token(&agent)?),
+        };
+        if token.is_none() {
+            debug!("no Google Cloud credentials found; sending unauthenticated requests");
+        }
+        Ok(Gcs {
+            location: url.to_string(),
+            bucket: bucket.to_string(),
+            prefix,
+            endpoint,
+            token,
+            agent,
+        })
+    }
+
+    /// GETs `path` (below the endpoint) with the `query` parameters.
+    /// Answers other than 2xx are errors.
+    fn get(&self, path: &str, query: &[(&str, &str)]) -> io::Result<Response<Body>> {
+        let params: Vec<String> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, uri_encode(value, true)))
+            .collect();
+        let url = format!("{}{}?{}", self.endpoint, path, params.join("&"));
+        let mut request = self.agent.get(&url);
+        if let Some(token) = &self.token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        debug!("GET {}", url);
+        let mut response = request
+            .call()
+            .map_err(|e| io::Error::other(format!("{}: {}", url, e)))?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        // {"error": {"code": 404, "message": "..."}}
+        let status = response.status();
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v["error"]["message"].as_str().map(str::to_string));
+        Err(io::Error::other(match message {
+            Some(message) => format!("{}: {}", status, message),
+            None => status.to_string(),
+        }))
+    }
+}
+
+impl Source for Gcs {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn locate(&self, file: &RemoteFile) -> String {
+        format!("gs://{}/{}", self.bucket, file.key)
+    }
+
+    /// Objects whose name starts with the folder, a page at a time;
+    /// without `recursive`, those with no further '/'.
+    fn list(&self, recursive: bool) -> Result<Vec<RemoteFile>, String> {
+        let path = format!("/storage/v1/b/{}/o", uri_encode(&self.bucket, true));
+        let mut files = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("prefix", self.prefix.as_str())];
+            if !recursive {
+                query.push(("delimiter", "/"));
+            }
+            if let Some(token) = &token {
+                query.push(("pageToken", token));
+            }
+            let listed = self
+                .get(&path, &query)
+                .and_then(|mut response| {
+                    response
+                        .body_mut()
+                        .read_to_string()
+                        .map_err(io::Error::other)
+                })
+                .and_then(|json| parse_listing(&json, &self.prefix).map_err(io::Error::other));
+            let (page, next) =
+                listed.map_err(|e| format!("could not list {}: {}", self.location, e))?;
+            files.extend(page);
+            token = next;
+            if token.is_none() {
+                return Ok(files);
+            }
+        }
+    }
+
+    /// The generation listed, so an object rewritten since is read as it
+    /// was then or not at all.
+    fn open(&self, file: &RemoteFile) -> io::Result<Box<dyn Read>> {
+        let path = format!(
+            "/storage/v1/b/{}/o/{}",
+            uri_encode(&self.bucket, true),
+            uri_encode(&file.key, true)
+        );
+        let mut query = vec![("alt", "media")];
+        if !file.version.is_empty() {
+            query.push(("generation", file.version.as_str()));
+        }
+        let response = self.get(&path, &query)?;
+        Ok(Box::new(response.into_body().into_reader()))
+    }
+}
+
+/// The objects in a page of a bucket listing, named from the folder
+/// `prefix`, and the token of the next page if there is one.
+fn parse_listing(
+    json: &str,
+    prefix: &str,
+) -> Result<(Vec<RemoteFile>, Option<String>), serde_json::Error> {
+    let listing: Listing = serde_json::from_str(json)?;
+    let files = listing
+        .items
+        .into_iter()
+        // Consoles make "folders" as empty objects named like one
+        .filter(|object| !object.name.ends_with('/'))
+        .map(|object| RemoteFile {
+            name: object
+                .name
+                .strip_prefix(prefix)
+                .unwrap_or(&object.name)
+                .to_string(),
+            size: object.size.parse().unwrap_or(0),
+            modified: object.updated.and_then(|t| timestamp::parse_iso(&t)),
+            version: object.generation,
+            key: object.name,
+        })
+        .collect();
+    Ok((files, listing.next_page_token))
+}
+
+/// An access token: GOOGLE_OAUTH_ACCESS_TOKEN, or one for the user in the
+/// application default credentials, or the metadata server's; None when
+/// there are none of those. Service account keys would need RS256
+/// signing, so those are refused with what to do instead.
+fn find_token(agent: &ureq::Agent) -> Result<Option<String>, String> {
+    if let Some(token) = env_var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+        return Ok(Some(token));
+    }
+    if let Some(path) = credentials_file() {
+        if let Ok(text) = fs::read_to_string(&path) {
+            let file: CredentialsFile = serde_json::from_str(&text)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            if file.kind != "authorized_user" {
+                return Err(format!(
+                    "{} holds {} credentials, which logbuddy can't use; set \
+                     GOOGLE_OAUTH_ACCESS_TOKEN=$(gcloud auth print-access-token) instead",
+                    path.display(),
+                    file.kind
+                ));
+            }
+            let response = agent.post(TOKEN_URL).send_form([
+                ("client_id", file.client_id.as_str()),
+                ("client_secret", file.client_secret.as_str()),
+                ("refresh_token", file.refresh_token.as_str()),
+                ("grant_type", "refresh_token"),
+            ]);
+            return read_token(response).map(Some).map_err(|e| {
+                format!(
+                    "could not get an access token for {}: {}",
+                    path.display(),
+                    e
+                )
+            });
+        }
+    }
+    let host = env_var("GCE_METADATA_HOST").unwrap_or_else(|| METADATA_HOST.to_string());
+    let response = agent
+        .get(format!(
+            "http://{}/computeMetadata/v1/instance/service-accounts/default/token",
+            host
+        ))
+        .header("metadata-flavor", "Google")
+        .config()
+        .timeout_global(Some(METADATA_TIMEOUT))
+        .build()
+        .call();
+    match read_token(response) {
+        Ok(token) => Ok(Some(token)),
+        Err(e) => {
+            debug!("no token from the metadata server: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// The application default credentials file: GOOGLE_APPLICATION_CREDENTIALS,
+/// or the one `gcloud auth application-default login` writes.
+fn credentials_file() -> Option<PathBuf> {
+    if let Some(path) = env_var("GOOGLE_APPLICATION_CREDENTIALS") {
+        return Some(PathBuf::from(path));
+    }
+    let config =
+        env_var("CLOUDSDK_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| match env_var("APPDATA") {
+                Some(appdata) => Some(PathBuf::from(appdata).join("gcloud")),
+                None => Some(
+                    PathBuf::from(env_var("HOME")?)
+                        .join(".config")
+                        .join("gcloud"),
+                ),
+            })?;
+    Some(config.join("application_default_credentials.json"))
+}
+
+/// The access token in a token endpoint's answer.
+fn read_token(response: Result<Response<Body>, ureq::Error>) -> Result<String, String> {
+    let mut response = response.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("{} {}", status, body.trim()));
+    }
+    serde_json::from_str::<Token>(&body)
+        .map(|t| t.access_token)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_pages() {
+        let first = r#"{
+            "kind": "storage#objects",
+            "nextPageToken": "CgRsb2dz",
+            "prefixes": ["logs/sub/"],
+            "items": [
+                {"name": "logs/app.log", "size": "68", "generation": "1704891600123456",
+                 "updated": "2024-01-10T13:00:00.000Z"},
+                {"name": "logs/folder/", "size": "0", "generation": "1"},
+                {"name": "logs/app.log.1.gz", "size": "51", "generation": "7"}
+            ]
+        }"#;
+        let (files, token) = parse_listing(first, "logs/").unwrap();
+        assert_eq!(token.as_deref(), Some("CgRsb2dz"));
+        let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["app.log", "app.log.1.gz"]);
+        assert_eq!(files[0].key, "logs/app.log");
+        assert_eq!(files[0].size, 68);
+        assert_eq!(files[0].modified, Some(1_704_891_600_000));
+        assert_eq!(files[0].version, "1704891600123456");
+        assert_eq!(files[1].modified, None);
+
+        // The last page has no token, and an empty folder no items
+        let (files, token) = parse_listing(r#"{"kind": "storage#objects"}"#, "logs/").unwrap();
+        assert!(files.is_empty());
+        assert_eq!(token, None);
+    }
+
+    #[test]
+    fn listing_must_be_json() {
+        assert!(parse_listing("<html>", "").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::value_for;
+use crate::timestamp::{civil_time, month_name};
+
+pub const USAGE: &str = "\
+Usage: logbuddy generate [OPTIONS]
+
+Writes a synthetic log file for benchmarks and test fixtures. The same
+options and seed always produce the same file.
+
+Options:
+  --lines <N>        Number of lines, e.g. 5000, 10k, 1M (default 10k)
+  --format <FORMAT>  clf (Apache common log) or app (default app)
+  --error-rate <R>   Share of error lines, e.g. 2% or 0.02 (default 2%)
+  --seed <N>         Random seed (default 42)
+  --out <DIR>        Folder to write into, created if needed (default .)
+  -h, --help         Show this help";
+
+/// 2024-05-01T00:00:00Z, so fixtures don't change from day to day.
+const START_EPOCH: u64 = 1_714_521_600;
+
+/// Share of errors that arrive in bursts rather than spread out evenly.
+const BURST_SHARE: f64 = 0.4;
+
+/// Chance that a line inside a burst is an error.
+const BURST_ERROR_CHANCE: f64 = 0.7;
+
+/// Burst length is uniform in 20..200 lines.
+const BURST_MIN: u64 = 20;
+const BURST_SPREAD: u64 = 180;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Clf,
+    App,
+}
+
+struct Options {
+    lines: u64,
+    format: Format,
+    error_rate: f64,
+    seed: u64,
+    out: PathBuf,
+}
+
+/// Entry point for `logbuddy generate ...`; `args` excludes "generate".
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = match parse_args(args)? {
+        Some(o) => o,
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    fs::create_dir_all(&options.out)
+        .map_err(|e| format!("could not create {}: {}", options.out.display(), e))?;
+    let file_name = match options.format {
+        Format::Clf => "access.log",
+        Format::App => "app.log",
+    };
+    let path = options.out.join(file_name);
+
+    write_log(&path, &options).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    println!("Wrote {} lines to {}", options.lines, path.display());
+    Ok(())
+}
+
+/// Returns None when --help was asked for.
+fn parse_args<I>(args: I) -> Result<Option<Options>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options {
+        lines: 10_000,
+        format: Format::App,
+        error_rate: 0.02,
+        seed: 42,
+        out: PathBuf::from("."),
+    };
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lines" => options.lines = parse_count(&value_for(&arg, &mut args)?)?,
+            "--format" => {
+                options.format = match value_for(&arg, &mut args)?.as_str() {
+                    "clf" => Format::Clf,
+                    "app" => Format::App,
+                    other => return Err(format!("unknown generate format '{}'", other)),
+                }
+            }
+            "--error-rate" => options.error_rate = parse_rate(&value_for(&arg, &mut args)?)?,
+            "--seed" => {
+                let value = value_for(&arg, &mut args)?;
+                options.seed = value
+                    .parse()
+                    .map_err(|_| format!("--seed needs a number, not '{}'", value))?;
+            }
+            "--out" => options.out = PathBuf::from(value_for(&arg, &mut args)?),
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unknown generate argument '{}'", other)),
+        }
+    }
+
+    Ok(Some(options))
+}
+
+/// "5000", "10k", "1M", "2.5M" -> a line count.
+fn parse_count(text: &str) -> Result<u64, String> {
+    let (number, scale) = match text.chars().last() {
+        Some('k') | Some('K') => (&text[..text.len() - 1], 1e3),
+        Some('m') | Some('M') => (&text[..text.len() - 1], 1e6),
+        Some('g') | Some('G') => (&text[..text.len() - 1], 1e9),
+        _ => (text, 1.0),
+    };
+    let value: f64 = number
+        .replace('_', "")
+        .parse()
+        .map_err(|_| format!("'{}' is not a line count", text))?;
+    if value < 0.0 {
+        return Err(format!("'{}' is not a line count", text));
+    }
+    Ok((value * scale).round() as u64)
+}
+
+/// "2%" or "0.02" -> 0.02.
+fn parse_rate(text: &str) -> Result<f64, String> {
+    let rate = match text.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|p| p / 100.0),
+        None => text.parse::<f64>(),
+    }
+    .map_err(|_| format!("'{}' is not an error rate", text))?;
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("error rate '{}' must be between 0% and 100%", text));
+    }
+    Ok(rate)
+}
+
+fn write_log(path: &Path, options: &Options) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut rng = Rng::new(options.seed);
+    let mut now = START_EPOCH;
+    // Lines left in the current error burst
+    let mut burst_left = 0u64;
+
+    // Split the error budget so bursts plus background errors add up to
+    // roughly --error-rate overall
+    let mean_burst_errors = (BURST_MIN as f64 + BURST_SPREAD as f64 / 2.0) * BURST_ERROR_CHANCE;
+    let burst_chance = options.error_rate * BURST_SHARE / mean_burst_errors;
+    let background_rate = options.error_rate * (1.0 - BURST_SHARE);
+
+    for _ in 0..options.lines {
+        let is_error = if burst_left > 0 {
+            burst_left -= 1;
+            rng.chance(BURST_ERROR_CHANCE)
+        } else {
+            if rng.chance(burst_chance) {
+                burst_left = BURST_MIN + rng.below(BURST_SPREAD);
+            }
+            rng.chance(background_rate)
+        };
+
+        // Bursts are fast; normal traffic has a few seconds between lines
+        now += if burst_left > 0 { 0 } else { rng.below(4) };
+
+        match options.format {
+            Format::Clf => write_clf_line(&mut out, &mut rng, now, is_error)?,
+            Format::App => write_app_line(&mut out, &mut rng, now, is_error)?,
+        }
+    }
+
+    out.flush()
+}
+
+const USERS: &[&str] = &["alice", "bob", "carol", "dave", "erin", "mallory", "-"];
+const PATHS: &[&str] = &[
+    "/",
+    "/index.html",
+    "/api/users",
+    "/api/orders",
+    "/api/orders/checkout",
+    "/static/app.js",
+    "/static/style.css",
+    "/login",
+];
+const METHODS: &[&str] = &["GET", "GET", "GET", "POST", "PUT", "DELETE"];
+
+fn write_clf_line(
+    out: &mut impl Write,
+    rng: &mut Rng,
+    now: u64,
+    is_error: bool,
+) -> std::io::Result<()> {
+    let status = if is_error {
+        *rng.pick(&[500, 502, 503, 504])
+    } else {
+        *rng.pick(&[200, 200, 200, 200, 201, 204, 301, 304, 404])
+    };
+    let (y, mo, d, h, mi, s) = civil_time(now as i64);
+
+    writeln!(
+        out,
+        "{} - {} [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} HTTP/1.1\" {} {}",
+        ip(rng),
+        rng.pick(USERS),
+        d,
+        month_name(mo),
+        y,
+        h,
+        mi,
+        s,
+        rng.pick(METHODS),
+        rng.pick(PATHS),
+        status,
+        rng.below(50_000) + 100
+    )
+}
+
+const INFO_MESSAGES: &[&str] = &[
+    "Request handled successfully",
+    "User login: {user}",
+    "Cache hit for key session:{id}",
+    "Listening on port 8080",
+    "Job {id} finished in {ms}ms",
+];
+const WARN_MESSAGES: &[&str] = &[
+    "Slow database query detected ({ms}ms)",
+    "High memory usage detected",
+    "Retrying connection to {ip}",
+];
+const ERROR_MESSAGES: &[&str] = &[
+    "Connection failed for user {user}",
+    "connect() to {ip} failed: ECONNREFUSED",
+    "Timeout after {ms}ms waiting for upstream {ip}",
+    "Missing permissions for user {user}",
+];
+
+fn write_app_line(
+    out: &mut impl Write,
+    rng: &mut Rng,
+    now: u64,
+    is_error: bool,
+) -> std::io::Result<()> {
+    let (level, template) = if is_error {
+        ("ERROR", *rng.pick(ERROR_MESSAGES))
+    } else if rng.chance(0.05) {
+        ("WARNING", *rng.pick(WARN_MESSAGES))
+    } else {
+        ("INFO", *rng.pick(INFO_MESSAGES))
+    };
+
+    let user = *rng.pick(&USERS[..USERS.len() - 1]);
+    let message = template
+        .replace("{user}", user)
+        .replace("{id}", &format!("{:08x}", rng.next() as u32))
+        .replace("{ms}", &(rng.below(5000) + 1).to_string())
+        .replace("{ip}", &ip(rng));
+
+    let (y, mo, d, h, mi, s) = civil_time(now as i64);
+    writeln!(
+        out,
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z {} {}",
+        y, mo, d, h, mi, s, level, message
+    )
+}
+
+fn ip(rng: &mut Rng) -> String {
+    format!(
+        "10.{}.{}.{}",
+        rng.below(4),
+        rng.below(256),
+        rng.below(254) + 1
+    )
+}
+
+/// Small deterministic PRNG (SplitMix64); good enough for fixtures.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
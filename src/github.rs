@@ -0,0 +1,46 @@
+use crate::cli::Config;
+use crate::report::{self, Report};
+
+/// Renders every match as a GitHub Actions workflow command, e.g.
+/// `::error file=logs/app.log,line=12,title=...::message`, so the Actions
+/// UI annotates the log file. --find hits are warnings, --check matches errors.
+pub fn render(report: &Report, config: &Config) -> String {
+    let mut out = String::new();
+
+    for m in &report.matches {
+        let (command, title) = match m.rule_id.strip_prefix("check-") {
+            Some(n) => {
+                let index = n.parse::<usize>().unwrap_or(1) - 1;
+                let name = config.checks.get(index).map(|c| c.name());
+                ("error", name.unwrap_or_else(|| m.rule_id.clone()))
+            }
+            None => {
+                let find = config.find.as_deref().unwrap_or_default();
+                ("warning", format!("lines containing '{}'", find))
+            }
+        };
+
+        out.push_str(&format!(
+            "::{} file={},line={},title={}::{}\n",
+            command,
+            escape_property(&report::file_uri(&report.folder, &m.file)),
+            m.line,
+            escape_property(&format!("logbuddy: {}", title)),
+            escape_data(&m.text)
+        ));
+    }
+
+    out
+}
+
+/// Escaping for the message part of a workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escaping for `key=value` properties, which also may not contain `:` or `,`.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
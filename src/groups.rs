@@ -0,0 +1,115 @@
+//! --group-by and --group-regex: lines counted per value of a field, or of
+//! a regex capture, with how many of them were warnings and errors; and
+//! --count-by: every match of a regex counted per captured value.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::level::Level;
+
+/// Distinct values counted at most; lines (or matches) with other values go
+/// in `other`, so a key like a request id doesn't hold every one in memory.
+const MAX_GROUPS: usize = 100_000;
+
+/// Lines with one value.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GroupStat {
+    pub lines: usize,
+    pub warnings: usize,
+    /// ERROR and FATAL
+    pub errors: usize,
+}
+
+impl GroupStat {
+    fn add(&mut self, more: &GroupStat) {
+        self.lines += more.lines;
+        self.warnings += more.warnings;
+        self.errors += more.errors;
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GroupTally {
+    pub groups: HashMap<String, GroupStat>,
+    /// Lines whose value came after MAX_GROUPS others
+    pub other: GroupStat,
+    /// Lines without a value
+    pub ungrouped: usize,
+}
+
+impl GroupTally {
+    pub fn record(&mut self, value: Option<&str>, level: Option<Level>) {
+        let Some(value) = value else {
+            self.ungrouped += 1;
+            return;
+        };
+        let full = self.groups.len() >= MAX_GROUPS;
+        let stat = match self.groups.get_mut(value) {
+            Some(stat) => stat,
+            None if full => &mut self.other,
+            None => self.groups.entry(value.to_string()).or_default(),
+        };
+        stat.lines += 1;
+        match level {
+            Some(Level::Warn) => stat.warnings += 1,
+            Some(Level::Error | Level::Fatal) => stat.errors += 1,
+            _ => {}
+        }
+    }
+
+    pub fn merge(&mut self, other: GroupTally) {
+        for (value, more) in other.groups {
+            let full = self.groups.len() >= MAX_GROUPS;
+            match self.groups.get_mut(&value) {
+                Some(stat) => stat.add(&more),
+                None if full => self.other.add(&more),
+                None => {
+                    self.groups.insert(value, more);
+                }
+            }
+        }
+        self.other.add(&other.other);
+        self.ungrouped += other.ungrouped;
+    }
+}
+
+/// Matches of one --count-by regex per captured value.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CountTally {
+    pub values: HashMap<String, usize>,
+    /// Matches whose value came after MAX_GROUPS others
+    pub other: usize,
+}
+
+impl CountTally {
+    /// Counts every match of `pattern` in `text` by its first capture
+    /// group (the whole match if it has none).
+    pub fn record(&mut self, pattern: &Regex, text: &str) {
+        for captures in pattern.captures_iter(text) {
+            let Some(found) = captures.get(1).or_else(|| captures.get(0)) else {
+                continue;
+            };
+            self.add(found.as_str(), 1);
+        }
+    }
+
+    fn add(&mut self, value: &str, count: usize) {
+        let full = self.values.len() >= MAX_GROUPS;
+        match self.values.get_mut(value) {
+            Some(total) => *total += count,
+            None if full => self.other += count,
+            None => {
+                self.values.insert(value.to_string(), count);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: CountTally) {
+        for (value, count) in other.values {
+            self.add(&value, count);
+        }
+        self.other += other.other;
+    }
+}
@@ -0,0 +1,62 @@
+/// Formats a count for people: thousands separators below a million
+/// ("123,456"), then one decimal with a suffix ("2.3M", "1.1B").
+pub fn count(n: usize) -> String {
+    const SUFFIXES: [(f64, &str); 3] = [(1e12, "T"), (1e9, "B"), (1e6, "M")];
+
+    for (scale, suffix) in SUFFIXES {
+        if n as f64 >= scale {
+            return format!("{:.1}{}", n as f64 / scale, suffix);
+        }
+    }
+    separated(n)
+}
+
+/// Formats a byte size with binary units ("512 B", "1.4 GiB").
+pub fn bytes(n: usize) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut value = n as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Formats a span of time the way it is given on the command line ("1h",
+/// "1h30m", "90s", "250ms").
+pub fn duration_ms(ms: u64) -> String {
+    if !ms.is_multiple_of(1000) {
+        return format!("{}ms", ms);
+    }
+    let parts = [(86_400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let mut secs = ms / 1000;
+    let mut out = String::new();
+    for (size, unit) in parts {
+        if secs >= size {
+            out.push_str(&format!("{}{}", secs / size, unit));
+            secs %= size;
+        }
+    }
+    if out.is_empty() {
+        out.push_str("0s");
+    }
+    out
+}
+
+/// "1234567" -> "1,234,567".
+fn separated(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
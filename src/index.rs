@@ -0,0 +1,469 @@
+//! `logbuddy index` and `logbuddy query`: an on-disk word index of a log
+//! folder, so repeated searches read only the lines that can match
+//! instead of every file.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::ansi;
+use crate::cli::value_for;
+use crate::compress::Compression;
+use crate::scanner::{self, normalize_word};
+use crate::search::Needle;
+
+pub const USAGE: &str = "\
+Usage: logbuddy index [--path DIR] [OPTIONS]
+
+Builds a word index of the .txt and .log files in DIR (default .) for
+`logbuddy query`. Run it again to update the index: files whose size and
+modification time are unchanged keep their entries, the others are read
+again and deleted files are dropped.
+
+Options:
+  --path <DIR>       Folder to index (default .)
+  --index <FILE>     Where to write the index (default DIR/.logbuddy-index)
+  -h, --help         Show this help";
+
+pub const QUERY_USAGE: &str = "\
+Usage: logbuddy query <TEXT> [--path DIR] [OPTIONS]
+
+Prints the lines containing TEXT (case-insensitive, like --find) using the
+index built by `logbuddy index`; only the lines whose words contain every
+word of TEXT are read. Files changed since they were indexed are skipped with a warning.
+
+Options:
+  --path <DIR>       Indexed folder (default .)
+  --index <FILE>     Index to read (default DIR/.logbuddy-index)
+  -h, --help         Show this help";
+
+/// Bump when the layout changes; older indexes are rebuilt.
+const FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    format: u32,
+    files: Vec<IndexedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    name: String,
+    size: u64,
+    /// Modification time, nanoseconds since the epoch
+    modified: u128,
+    /// Byte offset of each line; line N starts at lines[N - 1]
+    lines: Vec<u64>,
+    /// Normalized word → the line numbers it is on, ascending
+    words: HashMap<String, Vec<u32>>,
+}
+
+/// Size and modification time of `path`, to tell whether it changed.
+fn stamp(path: &Path) -> Option<(u64, u128)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((meta.len(), modified.as_nanos()))
+}
+
+fn index_path(dir: &str, index: Option<String>) -> PathBuf {
+    index.map_or_else(|| Path::new(dir).join(".logbuddy-index"), PathBuf::from)
+}
+
+fn load(path: &Path) -> Result<Index, String> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+/// Entry point for `logbuddy index ...`; `args` excludes "index".
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let mut dir = ".".to_string();
+    let mut index = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => dir = value_for(&arg, &mut args)?,
+            "--index" => index = Some(value_for(&arg, &mut args)?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other => return Err(format!("unknown index argument '{}'", other)),
+        }
+    }
+
+    let started = Instant::now();
+    let index_path = index_path(&dir, index);
+    let mut previous: HashMap<String, IndexedFile> = match load(&index_path) {
+        Ok(old) if old.format == FORMAT => {
+            old.files.into_iter().map(|f| (f.name.clone(), f)).collect()
+        }
+        Ok(_) => {
+            info!(
+                "{} is from another version; rebuilding it",
+                index_path.display()
+            );
+            HashMap::new()
+        }
+        Err(e) => {
+            debug!("starting a new index: {}", e);
+            HashMap::new()
+        }
+    };
+
+    let mut files = scanner::log_files(Path::new(&dir), false)?;
+    // Lines of a compressed file can't be read from an offset
+    files.retain(|(path, name)| match Compression::detect(path) {
+        Some(compression) => {
+            debug!("skipping {}: {} compressed", name, compression.name());
+            false
+        }
+        None => true,
+    });
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut kept = Vec::new();
+    let mut stale = Vec::new();
+    for (path, name) in files {
+        match previous.remove(&name) {
+            Some(old) if stamp(&path) == Some((old.size, old.modified)) => kept.push(old),
+            _ => stale.push((path, name)),
+        }
+    }
+    let reused = kept.len();
+
+    let built: Vec<IndexedFile> = stale
+        .par_iter()
+        .filter_map(|(path, name)| match index_file(path, name) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                warn!("skipping {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    let read = built.len();
+
+    let mut files = kept;
+    files.extend(built);
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    let index = Index {
+        format: FORMAT,
+        files,
+    };
+
+    // Written aside and renamed, so a query never reads half an index
+    let partial = index_path.with_extension("tmp");
+    let written = File::create(&partial)
+        .map_err(|e| e.to_string())
+        .and_then(|f| serde_json::to_writer(BufWriter::new(f), &index).map_err(|e| e.to_string()))
+        .and_then(|()| fs::rename(&partial, &index_path).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("could not write {}: {}", index_path.display(), e));
+    }
+
+    eprintln!(
+        "Indexed {} files ({} read, {} unchanged) into {} in {:.1}s",
+        read + reused,
+        read,
+        reused,
+        index_path.display(),
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn index_file(path: &Path, name: &str) -> Result<IndexedFile, String> {
+    // Stamped before reading, so lines appended meanwhile make the entry
+    // stale rather than silently missing
+    let (size, modified) = stamp(path).ok_or("could not stat the file")?;
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::new();
+    let mut words: HashMap<String, Vec<u32>> = HashMap::new();
+    let mut buf = Vec::new();
+    let mut scratch = String::new();
+    let mut offset = 0u64;
+
+    loop {
+        buf.clear();
+        let n = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        lines.push(offset);
+        offset += n as u64;
+        let line_number = lines.len() as u32;
+        let text = String::from_utf8_lossy(&buf);
+        for word in ansi::strip(&text).split_whitespace() {
+            let w = normalize_word(word, &mut scratch);
+            if w.is_empty() {
+                continue;
+            }
+            let on = match words.get_mut(w) {
+                Some(on) => on,
+                None => words.entry(w.to_string()).or_default(),
+            };
+            if on.last() != Some(&line_number) {
+                on.push(line_number);
+            }
+        }
+    }
+
+    Ok(IndexedFile {
+        name: name.to_string(),
+        size,
+        modified,
+        lines,
+        words,
+    })
+}
+
+/// Entry point for `logbuddy query ...`; `args` excludes "query".
+pub fn query(args: Vec<String>) -> Result<(), String> {
+    let mut text = None;
+    let mut dir = ".".to_string();
+    let mut index = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => dir = value_for(&arg, &mut args)?,
+            "--index" => index = Some(value_for(&arg, &mut args)?),
+            "-h" | "--help" => {
+                println!("{}", QUERY_USAGE);
+                return Ok(());
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("unknown query argument '{}'", other))
+            }
+            other if text.is_none() => text = Some(other.to_string()),
+            other => return Err(format!("query takes one text, got '{}' as well", other)),
+        }
+    }
+
+    let text = text.ok_or("query needs the text to look for")?;
+    let mut scratch = String::new();
+    let mut terms: Vec<String> = text
+        .split_whitespace()
+        .map(|w| normalize_word(w, &mut scratch).to_string())
+        .filter(|w| !w.is_empty())
+        .collect();
+    terms.dedup();
+    if terms.is_empty() {
+        return Err("query text needs at least one letter or digit".to_string());
+    }
+
+    let started = Instant::now();
+    let index_path = index_path(&dir, index);
+    let index = load(&index_path).map_err(|e| format!("{} (run logbuddy index first)", e))?;
+    if index.format != FORMAT {
+        return Err(format!(
+            "{} is from another version; run logbuddy index again",
+            index_path.display()
+        ));
+    }
+
+    let needle = Needle::new(&text);
+    let mut out = io::stdout().lock();
+    let mut hits = 0usize;
+    for file in &index.files {
+        let path = Path::new(&dir).join(&file.name);
+        let candidates = candidate_lines(file, &terms);
+        if candidates.is_empty() {
+            continue;
+        }
+        if stamp(&path) != Some((file.size, file.modified)) {
+            warn!(
+                "{} changed since it was indexed; run logbuddy index again",
+                file.name
+            );
+            continue;
+        }
+        match print_matches(&mut out, &path, file, &candidates, &needle) {
+            Ok(printed) => hits += printed,
+            // A reader that stops early, like `| head -1`, has what it wanted
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    eprintln!(
+        "{} matching lines in {} files, {:.0}ms",
+        hits,
+        index.files.len(),
+        started.elapsed().as_secs_f64() * 1000.0
+    );
+    Ok(())
+}
+
+/// Lines of `file` that may contain the text, ascending: for each term,
+/// the lines of every indexed word containing it (the text is matched as a
+/// substring, so "fail" must find "failed"), intersected across terms.
+fn candidate_lines(file: &IndexedFile, terms: &[String]) -> Vec<u32> {
+    let mut lines: Option<Vec<u32>> = None;
+    for term in terms {
+        let mut on: Vec<u32> = file
+            .words
+            .iter()
+            .filter(|(word, _)| word.contains(term.as_str()))
+            .flat_map(|(_, lines)| lines.iter().copied())
+            .collect();
+        on.sort_unstable();
+        on.dedup();
+        lines = Some(match lines {
+            None => on,
+            Some(mut lines) => {
+                lines.retain(|l| on.binary_search(l).is_ok());
+                lines
+            }
+        });
+        if lines.as_ref().is_some_and(Vec::is_empty) {
+            break;
+        }
+    }
+    lines.unwrap_or_default()
+}
+
+/// Writes the candidates that really contain the text to `out`; returns
+/// how many.
+fn print_matches(
+    out: &mut impl Write,
+    path: &Path,
+    file: &IndexedFile,
+    candidates: &[u32],
+    needle: &Needle,
+) -> std::io::Result<usize> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = Vec::new();
+    let mut printed = 0;
+    for &line_number in candidates {
+        reader.seek(SeekFrom::Start(file.lines[line_number as usize - 1]))?;
+        buf.clear();
+        reader.read_until(b'\n', &mut buf)?;
+        if !needle.is_in(&buf) {
+            continue;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        writeln!(
+            out,
+            "{}:{}: {}",
+            file.name,
+            line_number,
+            line.trim_end_matches(['\r', '\n'])
+        )?;
+        printed += 1;
+    }
+    Ok(printed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed(words: &[(&str, &[u32])]) -> IndexedFile {
+        IndexedFile {
+            name: "app.log".to_string(),
+            size: 0,
+            modified: 0,
+            lines: Vec::new(),
+            words: words
+                .iter()
+                .map(|(word, lines)| (word.to_string(), lines.to_vec()))
+                .collect(),
+        }
+    }
+
+    fn terms(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("logbuddy-index-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn candidates_contain_every_term() {
+        let file = indexed(&[
+            ("failed", &[1, 3]),
+            ("failure", &[3, 5]),
+            ("db", &[3, 5, 7]),
+            ("timeout", &[2]),
+        ]);
+        // A term matches every word containing it, each line once
+        assert_eq!(candidate_lines(&file, &terms("fail")), [1, 3, 5]);
+        assert_eq!(candidate_lines(&file, &terms("ail")), [1, 3, 5]);
+        assert_eq!(candidate_lines(&file, &terms("fail db")), [3, 5]);
+        assert_eq!(candidate_lines(&file, &terms("db timeout")), [] as [u32; 0]);
+        assert_eq!(candidate_lines(&file, &terms("nothing db")), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn prints_lines_that_really_match() {
+        let dir = temp_dir("print");
+        let path = dir.join("app.log");
+        fs::write(&path, "db failed\r\nfail db twice\nok\n").unwrap();
+        let file = index_file(&path, "app.log").unwrap();
+        let candidates = candidate_lines(&file, &terms("fail db"));
+        assert_eq!(candidates, [1, 2]);
+
+        let mut out = Vec::new();
+        let printed = print_matches(&mut out, &path, &file, &candidates, &Needle::new("FAIL DB"));
+        assert_eq!(printed.unwrap(), 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "app.log:2: fail db twice\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn updates_keep_unchanged_files() {
+        let dir = temp_dir("update");
+        for (name, text) in [
+            ("a.log", "alpha one\n"),
+            ("b.log", "beta\n"),
+            ("c.log", "gamma\n"),
+        ] {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let index_path = dir.join(".logbuddy-index");
+        let index_again = || {
+            run(vec![
+                "--path".to_string(),
+                dir.to_str().unwrap().to_string(),
+            ])
+        };
+        index_again().unwrap();
+
+        // Marks a.log's entry, to tell whether the next run reads it again
+        let mut index = load(&index_path).unwrap();
+        assert_eq!(index.files.len(), 3);
+        index.files[0].words.insert("marker".to_string(), vec![1]);
+        fs::write(&index_path, serde_json::to_string(&index).unwrap()).unwrap();
+        fs::write(dir.join("b.log"), "beta changed\n").unwrap();
+        fs::remove_file(dir.join("c.log")).unwrap();
+        index_again().unwrap();
+
+        let index = load(&index_path).unwrap();
+        let names: Vec<&str> = index.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["a.log", "b.log"]);
+        assert!(index.files[0].words.contains_key("marker"));
+        assert!(index.files[1].words.contains_key("changed"));
+        assert_eq!(index.files[1].size, 13);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! Stopping a scan early, on Ctrl-C or at the --timeout deadline: the
+//! scan stops where it is, so the summary can still cover what was read.
+//! A second Ctrl-C exits at once. A reader of the hit lines that goes
+//! away (`| head`) stops it too, quietly.
+
+use std::process;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use tracing::warn;
+
+/// Why the scan stopped early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Interrupted,
+    TimedOut,
+    /// Stdout was closed under the hit lines
+    OutputClosed,
+}
+
+impl Stop {
+    /// Exit status of a run that stopped this way: what shells use for
+    /// SIGINT, and what timeout(1) uses. A reader that had enough is no
+    /// failure.
+    pub fn exit_status(self) -> i32 {
+        match self {
+            Stop::Interrupted => 130,
+            Stop::TimedOut => 124,
+            Stop::OutputClosed => 0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stop::Interrupted => "interrupted",
+            Stop::TimedOut => "timeout",
+            Stop::OutputClosed => "output closed",
+        }
+    }
+}
+
+/// 0 while running, else 1 + the Stop variant
+static STOPPED: AtomicU8 = AtomicU8::new(0);
+
+/// Stops the scan, unless it was already stopped; returns whether this
+/// call stopped it.
+fn stop(reason: Stop) -> bool {
+    let code = match reason {
+        Stop::Interrupted => 1,
+        Stop::TimedOut => 2,
+        Stop::OutputClosed => 3,
+    };
+    STOPPED
+        .compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+}
+
+pub fn install() {
+    let installed = ctrlc::set_handler(|| {
+        if !stop(Stop::Interrupted) {
+            process::exit(Stop::Interrupted.exit_status());
+        }
+        eprintln!("\nInterrupted; finishing with what was scanned (Ctrl-C again to quit)");
+    });
+    if let Err(e) = installed {
+        warn!("could not install the Ctrl-C handler: {}", e);
+    }
+}
+
+/// Stops the scan once `timeout` has passed from now.
+pub fn set_deadline(timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        if stop(Stop::TimedOut) {
+            eprintln!(
+                "\nScan ran past --timeout ({:?}); finishing with what was scanned",
+                timeout
+            );
+        }
+    });
+}
+
+/// Stops the scan because nothing reads its output any more.
+pub fn output_closed() {
+    stop(Stop::OutputClosed);
+}
+
+/// Whether the scan should stop.
+pub fn requested() -> bool {
+    STOPPED.load(Ordering::Relaxed) != 0
+}
+
+pub fn reason() -> Option<Stop> {
+    match STOPPED.load(Ordering::Relaxed) {
+        1 => Some(Stop::Interrupted),
+        2 => Some(Stop::TimedOut),
+        3 => Some(Stop::OutputClosed),
+        _ => None,
+    }
+}
@@ -0,0 +1,41 @@
+//! --json-logs: lines holding one JSON object each are read as fields
+//! rather than as text (see fields::Record).
+
+use serde_json::Value;
+
+use crate::fields::Object;
+
+/// The line as a JSON object, if it is one.
+pub fn parse(line: &str) -> Option<Object> {
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return None;
+    }
+    match serde_json::from_str(line) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_only() {
+        let object = parse("  {\"level\":\"info\",\"msg\":\"ok\",\"n\":3}\r").unwrap();
+        assert_eq!(object["msg"], "ok");
+        assert_eq!(object["n"], 3);
+        for line in [
+            "[1, 2]",
+            "\"text\"",
+            "INFO {\"a\":1}",
+            "{\"msg\": \"cut",
+            "{} trailing",
+            "",
+        ] {
+            assert!(parse(line).is_none(), "{:?}", line);
+        }
+        assert!(parse("{}").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::report::Report;
+
+/// Renders every check as a JUnit test case, for CI test-report views.
+pub fn render(report: &Report) -> String {
+    let tests = report.checks.len();
+    let failures = report.failed_checks();
+    let suite = format!("logbuddy: {}", report.folder);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites name=\"logbuddy\" tests=\"{}\" failures=\"{}\">\n",
+        tests, failures
+    ));
+    out.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        escape(&suite),
+        tests,
+        failures
+    ));
+
+    for check in &report.checks {
+        let open = format!(
+            "    <testcase classname=\"logbuddy.checks\" name=\"{}\"",
+            escape(&check.name)
+        );
+        if check.passed {
+            out.push_str(&open);
+            out.push_str("/>\n");
+            continue;
+        }
+
+        out.push_str(&open);
+        out.push_str(">\n");
+        out.push_str(&format!(
+            "      <failure message=\"{} matching lines, at most {} allowed\">",
+            check.count, check.max
+        ));
+        out.push_str(&escape(&check.samples.join("\n")));
+        out.push_str("</failure>\n");
+        out.push_str("    </testcase>\n");
+    }
+
+    out.push_str("  </testsuite>\n");
+    out.push_str("</testsuites>\n");
+    out
+}
+
+/// Escapes text for use in XML attributes and element content.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than tab/newline are not allowed in XML 1.0
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,224 @@
+use std::fs;
+
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+
+/// Severity of a log line, from the level word it contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    /// Every level, least severe first.
+    pub const ALL: [Level; 6] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+        Level::Fatal,
+    ];
+
+    /// Recognizes the upper-case level words, including common aliases.
+    pub fn from_word(word: &str) -> Option<Level> {
+        match word {
+            "FATAL" | "CRITICAL" | "CRIT" | "ALERT" | "EMERG" | "PANIC" => Some(Level::Fatal),
+            "ERROR" | "ERR" | "SEVERE" => Some(Level::Error),
+            "WARN" | "WARNING" => Some(Level::Warn),
+            "INFO" | "NOTICE" => Some(Level::Info),
+            "DEBUG" => Some(Level::Debug),
+            "TRACE" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// The level of a line: from a syslog priority (`<13>`) it starts
+    /// with, else its first level word.
+    pub fn detect(line: &str) -> Option<Level> {
+        Level::from_syslog_priority(line).or_else(|| Level::find_in(line))
+    }
+
+    /// The severity in a `<PRI>` prefix (RFC 3164 and 5424), where PRI is
+    /// facility × 8 + severity.
+    fn from_syslog_priority(line: &str) -> Option<Level> {
+        let rest = line.strip_prefix('<')?;
+        let end = rest.find('>')?;
+        if end == 0 || end > 3 || !rest[..end].bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let priority: u32 = rest[..end].parse().ok()?;
+        match priority % 8 {
+            0..=2 => Some(Level::Fatal),
+            3 => Some(Level::Error),
+            4 => Some(Level::Warn),
+            5 | 6 => Some(Level::Info),
+            _ => Some(Level::Debug),
+        }
+    }
+
+    /// Position in ALL.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The first whole-word level in `line`, if any.
+    pub fn find_in(line: &str) -> Option<Level> {
+        line.split(|c: char| !c.is_ascii_alphabetic())
+            .find_map(Level::from_word)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+            Level::Fatal => "FATAL",
+        }
+    }
+}
+
+/// One entry of --level-rules or a gate config's `levels`: lines matching
+/// the regex have the level.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelRule {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub level: Level,
+}
+
+/// Custom level detection, for formats the level words don't cover
+/// ("SEVERE", "oops", "[E42]"): the first rule whose regex matches gives
+/// the level, and lines no rule matches fall back to Level::detect.
+#[derive(Debug, Clone, Default)]
+pub struct LevelRules {
+    rules: Vec<LevelRule>,
+    /// The rules' regexes, tried all at once
+    set: Option<RegexSet>,
+}
+
+impl LevelRules {
+    pub fn new(rules: Vec<LevelRule>) -> Result<LevelRules, String> {
+        if rules.is_empty() {
+            return Ok(LevelRules::default());
+        }
+        for rule in &rules {
+            Regex::new(&rule.pattern)
+                .map_err(|e| format!("bad level rule '{}': {}", rule.pattern, e))?;
+        }
+        let set = RegexSet::new(rules.iter().map(|r| &r.pattern)).map_err(|e| e.to_string())?;
+        Ok(LevelRules {
+            rules,
+            set: Some(set),
+        })
+    }
+
+    /// Reads --level-rules: a YAML list of `match` and `level` pairs.
+    pub fn load(path: &str) -> Result<LevelRules, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let rules: Vec<LevelRule> =
+            serde_yaml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        LevelRules::new(rules)
+    }
+
+    pub fn detect(&self, line: &str) -> Option<Level> {
+        if let Some(set) = &self.set {
+            if let Some(first) = set.matches(line).iter().next() {
+                return Some(self.rules[first].level);
+            }
+        }
+        Level::detect(line)
+    }
+
+    /// The rules as text, for cache keys.
+    pub fn describe(&self) -> Vec<(&str, &'static str)> {
+        self.rules
+            .iter()
+            .map(|r| (r.pattern.as_str(), r.level.name()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_words() {
+        assert_eq!(
+            Level::detect("2024-05-01 ERROR db down"),
+            Some(Level::Error)
+        );
+        assert_eq!(Level::detect("[WARNING] disk 91%"), Some(Level::Warn));
+        assert_eq!(Level::detect("CRIT: out of memory"), Some(Level::Fatal));
+        assert_eq!(Level::detect("level=INFO msg=ok"), Some(Level::Info));
+        // The first one wins, whole words only, upper case only
+        assert_eq!(Level::detect("DEBUG retry after ERROR"), Some(Level::Debug));
+        assert_eq!(Level::detect("ERRORS: none, INFORMATION"), None);
+        assert_eq!(Level::detect("error: lower case"), None);
+        assert_eq!(Level::find_in("x_TRACE_y"), Some(Level::Trace));
+    }
+
+    #[test]
+    fn syslog_priorities() {
+        // facility × 8 + severity
+        assert_eq!(Level::detect("<0>kernel panic"), Some(Level::Fatal));
+        assert_eq!(Level::detect("<11>sshd: INFO x"), Some(Level::Error));
+        assert_eq!(
+            Level::detect("<12>1 2024-05-01T12:00:00Z"),
+            Some(Level::Warn)
+        );
+        assert_eq!(Level::detect("<14>ok"), Some(Level::Info));
+        assert_eq!(Level::detect("<191>verbose"), Some(Level::Debug));
+        // Not a priority, so the words decide
+        assert_eq!(Level::detect("<1234>ERROR x"), Some(Level::Error));
+        assert_eq!(Level::detect("<>ERROR x"), Some(Level::Error));
+        assert_eq!(Level::detect("<a1>ok"), None);
+    }
+
+    #[test]
+    fn ordered_by_severity() {
+        assert!(Level::Trace < Level::Debug && Level::Error < Level::Fatal);
+        for (i, level) in Level::ALL.iter().enumerate() {
+            assert_eq!(level.index(), i);
+            assert_eq!(Level::from_word(level.name()), Some(*level));
+        }
+    }
+
+    #[test]
+    fn rules_come_before_level_words() {
+        let yaml = r#"
+- match: '\[E\d+\]'
+  level: ERROR
+- match: oops
+  level: WARN
+"#;
+        let rules = LevelRules::new(serde_yaml::from_str(yaml).unwrap()).unwrap();
+        assert_eq!(rules.detect("INFO [E42] failed"), Some(Level::Error));
+        assert_eq!(rules.detect("oops [E7]"), Some(Level::Error));
+        assert_eq!(rules.detect("INFO oops"), Some(Level::Warn));
+        assert_eq!(rules.detect("DEBUG fine"), Some(Level::Debug));
+        assert_eq!(rules.describe(), [(r"\[E\d+\]", "ERROR"), ("oops", "WARN")]);
+
+        assert!(LevelRules::new(Vec::new()).unwrap().describe().is_empty());
+        let bad = vec![LevelRule {
+            pattern: "(".to_string(),
+            level: Level::Info,
+        }];
+        assert!(LevelRules::new(bad)
+            .unwrap_err()
+            .starts_with("bad level rule '('"));
+        assert!(serde_yaml::from_str::<Vec<LevelRule>>("- {match: x, level: LOUD}").is_err());
+        assert!(serde_yaml::from_str::<Vec<LevelRule>>("- {match: x, level: INFO, y: 1}").is_err());
+    }
+}
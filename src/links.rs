@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+
+/// Used when --link-template is not given.
+pub const DEFAULT_TEMPLATE: &str = "file://{path}";
+
+/// Builds the URL a hit location links to from a --link-template such as
+/// "https://logs.example.com/view?file={path}&line={line}".
+///
+/// Placeholders: `{path}` is the file's absolute path, `{file}` its name
+/// and `{line}` the 1-based line number. Paths and names are
+/// percent-encoded.
+pub struct Linker {
+    template: String,
+}
+
+impl Linker {
+    pub fn new(template: &str) -> Self {
+        Linker {
+            template: template.to_string(),
+        }
+    }
+
+    /// Absolute, percent-encoded form of `path`, computed once per file.
+    pub fn encoded_path(path: &Path) -> String {
+        let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let text = absolute.to_string_lossy().replace('\\', "/");
+        // Windows paths ("C:/logs") still need a leading slash in a URL
+        if text.starts_with('/') {
+            percent_encode(&text)
+        } else {
+            format!("/{}", percent_encode(&text))
+        }
+    }
+
+    /// URL for a source location from a log line, e.g. with a template
+    /// like "https://git.example.com/repo/blob/main/{path}#L{line}".
+    /// `{path}` is the path as logged.
+    pub fn source_url(&self, path: &str, line: u32) -> String {
+        let file = path.rsplit('/').next().unwrap_or(path);
+        self.template
+            .replace("{path}", &percent_encode(path.trim_start_matches("./")))
+            .replace("{file}", &percent_encode(file))
+            .replace("{line}", &line.to_string())
+    }
+
+    pub fn url(&self, encoded_path: &str, file_name: &str, line: usize) -> String {
+        self.template
+            .replace("{path}", encoded_path)
+            .replace("{file}", &percent_encode(file_name))
+            .replace("{line}", &line.to_string())
+    }
+}
+
+/// Percent-encodes everything except unreserved characters, '/' and ':'.
+fn percent_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~' | b'/' | b':') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
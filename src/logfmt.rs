@@ -0,0 +1,99 @@
+//! --logfmt: lines of `key=value` pairs, as Heroku and many Go services
+//! write them (`level=info msg="request done" duration_ms=12`), read as
+//! fields rather than as text (see fields::Record).
+
+use serde_json::Value;
+
+use crate::fields::Object;
+
+/// The pairs of `line`, values as strings; a key without `=` is a flag
+/// and gets `true`. None when the line has no `key=value` pair at all.
+/// The first of repeated keys wins.
+pub fn parse(line: &str) -> Option<Object> {
+    let mut object = Object::new();
+    let mut pairs = 0;
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = &rest[key_end..];
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let (value, after) = parse_value(after);
+                rest = after;
+                pairs += 1;
+                Value::String(value)
+            }
+            None => Value::Bool(true),
+        };
+        if !key.is_empty() {
+            object.entry(key).or_insert(value);
+        }
+        rest = rest.trim_start();
+    }
+    (pairs > 0).then_some(object)
+}
+
+/// A value and the text after it: a quoted string with `\"`, `\\`, `\n`
+/// and `\t` escapes, or everything up to the next space.
+fn parse_value(text: &str) -> (String, &str) {
+    let Some(quoted) = text.strip_prefix('"') else {
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        return (text[..end].to_string(), &text[end..]);
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[i + 1..]),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, escaped)) => value.push(escaped),
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    // No closing quote: the rest of the line
+    (value, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs() {
+        let object =
+            parse(r#"level=info msg="request done" duration_ms=12 cached path=/a=b"#).unwrap();
+        assert_eq!(object["level"], "info");
+        assert_eq!(object["msg"], "request done");
+        assert_eq!(object["duration_ms"], "12");
+        assert_eq!(object["cached"], true);
+        assert_eq!(object["path"], "/a=b");
+
+        let object =
+            parse(r#"msg="say \"hi\"\n\tback\\slash" empty= level=warn level=error"#).unwrap();
+        assert_eq!(object["msg"], "say \"hi\"\n\tback\\slash");
+        assert_eq!(object["empty"], "");
+        // The first of repeated keys wins
+        assert_eq!(object["level"], "warn");
+
+        // An unclosed quote runs to the end of the line
+        assert_eq!(
+            parse(r#"msg="cut off here"#).unwrap()["msg"],
+            "cut off here"
+        );
+        assert_eq!(parse(r#"=orphan k=v"#).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn not_logfmt() {
+        assert!(parse("just some words").is_none());
+        assert!(parse("").is_none());
+        assert!(parse("   ").is_none());
+    }
+}
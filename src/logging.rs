@@ -0,0 +1,23 @@
+use std::io::{self, IsTerminal};
+
+use tracing::Level;
+
+/// Sends internal diagnostics to stderr. Warnings always show (unless
+/// --quiet); each -v adds a level: info, debug, trace.
+pub fn init(verbosity: u8, quiet: bool) {
+    let level = match (quiet, verbosity) {
+        (true, 0) => Level::ERROR,
+        (_, 0) => Level::WARN,
+        (_, 1) => Level::INFO,
+        (_, 2) => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_max_level(level)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .init();
+}
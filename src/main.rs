@@ -1,132 +1,265 @@
-use std::collections::HashMap;
-use std::fs;
+mod access;
+mod aio;
+mod ansi;
+mod archive;
+mod azure;
+mod cache;
+mod cef;
+mod charset;
+mod chart;
+mod checkpoint;
+mod checks;
+mod cli;
+mod compress;
+mod correlate;
+mod decode;
+mod delimited;
+mod distinct;
+mod drain;
+mod errno;
+mod eventlog;
+mod fields;
+mod fingerprint;
+mod gate;
+mod gcs;
+mod generate;
+mod github;
+mod groups;
+mod hll;
+mod http;
+mod human;
+mod index;
+mod interrupt;
+mod jsonlog;
+mod junit;
+mod level;
+mod links;
+mod logfmt;
+mod logging;
+mod manifest;
+mod memory;
+mod merge;
+mod origins;
+mod progress;
+mod repeats;
+mod replay;
+mod report;
+mod rotation;
+mod s3;
+mod sarif;
+mod scanner;
+mod search;
+mod sessions;
+mod sink;
+mod sketch;
+mod skew;
+mod source;
+mod ssh;
+mod stats;
+mod stopwords;
+mod style;
+mod syslog;
+mod timestamp;
+mod tokenize;
+mod traces;
+mod units;
+mod usage;
+mod words;
+
+use std::env;
 use std::io::{self, Write};
 use std::path::Path;
+use std::process;
 
-fn main() {
-    // Immutable variable
-    let title = "LogBuddy (simple Rust version)";
-    println!("=== {} ===", title);
+use cli::{Config, OutputFormat};
+use manifest::Manifest;
+use report::Report;
+use scanner::Scanner;
+use style::Style;
 
-    // Mutable variable
-    let mut folder = String::new();
+/// A subcommand's entry point, given the arguments after its name.
+type Subcommand = fn(Vec<String>) -> Result<(), String>;
 
-    print!("Enter a folder path to scan (e.g. ./logs or .): ");
-    io::stdout().flush().unwrap();
-    io::stdin()
-        .read_line(&mut folder)
-        .expect("Failed to read input");
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let folder = folder.trim();
-    if folder.is_empty() {
-        println!("No folder provided. Exiting.");
+    // Subcommands have their own options and usage text
+    let subcommand = match args.first().map(String::as_str) {
+        Some("gate") => Some((gate::run as Subcommand, gate::USAGE)),
+        Some("generate") => Some((generate::run as Subcommand, generate::USAGE)),
+        Some("index") => Some((index::run as Subcommand, index::USAGE)),
+        Some("merge") => Some((merge::run as Subcommand, merge::USAGE)),
+        Some("query") => Some((index::query as Subcommand, index::QUERY_USAGE)),
+        Some("replay") => Some((replay::run as Subcommand, replay::USAGE)),
+        _ => None,
+    };
+    if let Some((run, usage)) = subcommand {
+        if let Err(msg) = run(args.into_iter().skip(1).collect()) {
+            eprintln!("error: {}", msg);
+            eprintln!("{}", usage);
+            process::exit(2);
+        }
         return;
     }
 
-    let path = Path::new(folder);
-    if !path.is_dir() {
-        println!("'{}' is not a folder.", folder);
+    let mut args = args;
+    let mut config = parse_or_exit(args.clone());
+    if let Some(file) = &config.manifest {
+        let recorded = match Manifest::load(file).and_then(|m| m.verify().map(|()| m)) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(2);
+            }
+        };
+        // Options given now come last, so they win over recorded ones
+        let mut replay = recorded.replay_args();
+        replay.extend(manifest::recorded_args(&args));
+        args = replay;
+        config = parse_or_exit(args.clone());
+    }
+
+    if config.help {
+        write_out(&format!("{}\n", cli::USAGE));
+        return;
+    }
+    if config.schema {
+        write_out(&format!("{}\n", report::json_schema()));
         return;
     }
 
-    // HashMap for word counts (stretch: data structure)
-    let mut word_counts: HashMap<String, usize> = HashMap::new();
-    let mut files_scanned = 0usize;
-    let mut total_lines = 0usize;
+    logging::init(config.verbosity, config.quiet);
 
-    // Loop over entries in the folder (non-recursive, simple)
-    let entries = match fs::read_dir(path) {
-        Ok(e) => e,
+    // Immutable variable
+    let title = "LogBuddy (simple Rust version)";
+    if config.chatty() {
+        let style = Style::new(config.color, &config.theme);
+        write_out(&format!(
+            "{}\n",
+            style.header(&format!("=== {} ===", title))
+        ));
+    }
+
+    let source = match source::from_config(&config) {
+        Ok(source) => source,
         Err(e) => {
-            println!("Could not read folder: {}", e);
-            return;
+            eprintln!("error: {}", e);
+            process::exit(2);
         }
     };
+    let folder = match (&source, &config.path) {
+        (Some(source), _) => source.location().to_string(),
+        (None, Some(p)) => p.clone(),
+        (None, None) => {
+            // Mutable variable
+            let mut folder = String::new();
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(e) => {
-                println!("Skipping an entry: {}", e);
-                continue;
-            }
-        };
-
-        let file_path = entry.path();
-        // Only handle regular files with a .txt or .log extension
-        if !file_path.is_file() {
-            continue;
-        }
-
-        let ext = file_path
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-
-        if ext != "txt" && ext != "log" {
-            continue;
+            print!("Enter a folder path to scan (e.g. ./logs or .): ");
+            io::stdout().flush().unwrap();
+            io::stdin()
+                .read_line(&mut folder)
+                .expect("Failed to read input");
+            folder.trim().to_string()
         }
+    };
 
-        let contents = match fs::read_to_string(&file_path) {
-            Ok(c) => c,
-            Err(e) => {
-                println!("Could not read {}: {}", file_path.display(), e);
-                continue;
-            }
-        };
-
-        files_scanned += 1;
-        total_lines += contents.lines().count();
+    if folder.is_empty() {
+        write_out("No folder provided. Exiting.\n");
+        return;
+    }
 
-        // Function that borrows &str and &mut HashMap (references)
-        count_words_in_text(&contents, &mut word_counts);
+    let path = Path::new(&folder);
+    if source.is_none() && !path.is_dir() {
+        write_out(&format!("'{}' is not a folder.\n", folder));
+        return;
     }
 
-    println!();
-    println!("Scanned folder   : {}", folder);
-    println!("Files processed  : {}", files_scanned);
-    println!("Total lines read : {}", total_lines);
+    // Only now, so Ctrl-C at the folder prompt still quits
+    interrupt::install();
+    if let Some(timeout) = config.timeout {
+        interrupt::set_deadline(timeout);
+    }
 
-    if files_scanned == 0 {
-        println!("No .txt or .log files found. Nothing to report.");
+    let sinks = match sink::open_all(&config) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+    let mut scanner = Scanner::new(&config, sinks);
+    let scanned = match &source {
+        Some(source) => scanner.scan_source(source.as_ref()),
+        None => scanner.scan_folder(path),
+    };
+    if let Err(e) = scanned {
+        write_out(&format!("{}\n", e));
         return;
     }
+    scanner.finish();
 
-    println!("\nTop words:");
-    let mut pairs: Vec<(String, usize)> = word_counts
-        .into_iter()
-        .collect();
+    if let Some(out) = &config.write_manifest {
+        let written =
+            Manifest::new(&folder, manifest::recorded_args(&args)).and_then(|m| m.save(out));
+        if let Err(e) = written {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
 
-    // Sort by count descending
-    pairs.sort_by(|a, b| b.1.cmp(&a.1));
+    let report = Report::new(&folder, scanner.totals, &config);
+    let out = match (&config.template, config.format) {
+        (Some(template), _) => match report::render_template(&report, template) {
+            Ok(out) => out,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        },
+        (None, OutputFormat::Junit) => junit::render(&report),
+        (None, OutputFormat::Sarif) => format!("{}\n", sarif::render(&report, &config)),
+        (None, OutputFormat::GhAnnotations) => github::render(&report, &config),
+        (None, OutputFormat::Json) => format!(
+            "{}\n",
+            serde_json::to_string_pretty(&report).expect("report serializes")
+        ),
+        (None, OutputFormat::Text) => report::render_summary(&report, &config),
+    };
+    write_out(&out);
 
-    // Show up to 10 most common words
-    for (i, (word, count)) in pairs.into_iter().take(10).enumerate() {
-        println!("{:>2}. {:<20} {}", i + 1, word, count);
+    if let Some(stop) = interrupt::reason() {
+        process::exit(stop.exit_status());
+    }
+    // Failed checks fail the run, so CI jobs go red
+    if report.failed_checks() > 0 {
+        process::exit(1);
     }
 }
 
-/// Function that borrows a &str and &mut HashMap.
-/// Demonstrates loops, conditionals, references, and expressions.
-fn count_words_in_text(text: &str, counts: &mut HashMap<String, usize>) {
-    for line in text.lines() {
-        // split_whitespace is an expression that returns an iterator
-        for word in line.split_whitespace() {
-            let w = word
-                .trim_matches(|c: char| !c.is_alphanumeric())
-                .to_lowercase();
-
-            if w.is_empty() {
-                continue;
-            }
+/// Writes `text` to stdout. A reader that stops early, like `| head`,
+/// isn't an error: the run ends as it would have anyway.
+fn write_out(text: &str) {
+    let mut stdout = io::stdout().lock();
+    match stdout
+        .write_all(text.as_bytes())
+        .and_then(|()| stdout.flush())
+    {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("error: could not write to stdout: {}", e);
+            process::exit(2);
+        }
+    }
+}
 
-            // Conditional + mutation
-            if let Some(count) = counts.get_mut(&w) {
-                *count += 1;
-            } else {
-                counts.insert(w, 1);
-            }
+fn parse_or_exit(args: Vec<String>) -> Config {
+    match cli::parse_args(args) {
+        Ok(c) => c,
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            eprintln!("{}", cli::USAGE);
+            process::exit(2);
         }
     }
 }
@@ -0,0 +1,165 @@
+//! Scan manifests: the exact files (with sizes and SHA-256 hashes), options
+//! and logbuddy version behind a report, so the scan can be re-run on the
+//! same inputs later and any difference in them is caught first.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::scanner;
+
+/// Options that say where the manifest goes rather than what is scanned.
+const NOT_RECORDED: &[&str] = &["--path", "--write-manifest", "--manifest"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub logbuddy_version: String,
+    /// Folder scanned, as given
+    pub path: String,
+    /// Command-line options, without --path and the manifest options
+    pub args: Vec<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The options worth recording from a command line.
+pub fn recorded_args(args: &[String]) -> Vec<String> {
+    let mut recorded = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if NOT_RECORDED.contains(&arg.as_str()) {
+            args.next();
+        } else {
+            recorded.push(arg.clone());
+        }
+    }
+    recorded
+}
+
+impl Manifest {
+    /// Describes the files in `folder` as they are now.
+    pub fn new(folder: &str, args: Vec<String>) -> Result<Manifest, String> {
+        let files = describe_files(Path::new(folder), recursive(&args))?;
+        Ok(Manifest {
+            logbuddy_version: env!("CARGO_PKG_VERSION").to_string(),
+            path: folder.to_string(),
+            args,
+            files,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Manifest, String> {
+        let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("could not read manifest {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).expect("manifest serializes");
+        fs::write(path, text + "\n").map_err(|e| format!("could not write {}: {}", path, e))
+    }
+
+    /// Checks that the folder still holds exactly the recorded files;
+    /// the error lists every difference.
+    pub fn verify(&self) -> Result<(), String> {
+        if self.logbuddy_version != env!("CARGO_PKG_VERSION") {
+            warn!(
+                "manifest was written by logbuddy {}, this is {}; results may differ",
+                self.logbuddy_version,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+        let now = describe_files(Path::new(&self.path), recursive(&self.args))?;
+        let mut problems = Vec::new();
+        for file in &self.files {
+            match now.iter().find(|f| f.name == file.name) {
+                None => problems.push(format!("{} is missing", file.name)),
+                Some(f) if f.size != file.size => problems.push(format!(
+                    "{} is {} bytes, was {}",
+                    file.name, f.size, file.size
+                )),
+                Some(f) if f.sha256 != file.sha256 => {
+                    problems.push(format!("{} has different contents", file.name))
+                }
+                Some(_) => {}
+            }
+        }
+        for file in &now {
+            if !self.files.iter().any(|f| f.name == file.name) {
+                problems.push(format!("{} is new", file.name));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} no longer matches the manifest:\n  {}",
+                self.path,
+                problems.join("\n  ")
+            ))
+        }
+    }
+
+    /// The command line that repeats the recorded scan.
+    pub fn replay_args(&self) -> Vec<String> {
+        let mut args = self.args.clone();
+        args.push("--path".to_string());
+        args.push(self.path.clone());
+        args
+    }
+}
+
+/// Whether the recorded options scan subfolders too.
+fn recursive(args: &[String]) -> bool {
+    args.iter().any(|a| a == "-r" || a == "--recursive")
+}
+
+/// The scanned files of `folder`, by name.
+fn describe_files(folder: &Path, recursive: bool) -> Result<Vec<ManifestFile>, String> {
+    let mut files = scanner::scan_files(folder, recursive)?;
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    files
+        .par_iter()
+        .map(|(path, name)| {
+            let (size, sha256) =
+                hash_file(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            Ok(ManifestFile {
+                name: name.clone(),
+                size,
+                sha256,
+            })
+        })
+        .collect()
+}
+
+fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    let hex = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((size, hex))
+}
@@ -0,0 +1,67 @@
+//! --max-memory: when the tables that grow with the input outgrow their
+//! share, word counts switch to approximate counting and buffered hits
+//! move to a temporary file.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Tells the temporary files of one run apart.
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Records written to a temporary file, one JSON object per line, to be
+/// read back in order. The file is removed when the spill is dropped.
+pub struct Spill {
+    path: PathBuf,
+    out: BufWriter<File>,
+    pub count: usize,
+}
+
+impl Spill {
+    pub fn create() -> io::Result<Spill> {
+        let n = SPILLS.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("logbuddy-{}-{}.jsonl", process::id(), n));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Spill {
+            path,
+            out: BufWriter::new(file),
+            count: 0,
+        })
+    }
+
+    pub fn write<T: Serialize>(&mut self, records: impl IntoIterator<Item = T>) -> io::Result<()> {
+        for record in records {
+            serde_json::to_writer(&mut self.out, &record)?;
+            self.out.write_all(b"\n")?;
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with every record written, in order, reading them back
+    /// one at a time.
+    pub fn for_each<T: DeserializeOwned>(&mut self, mut f: impl FnMut(T)) -> io::Result<()> {
+        self.out.flush()?;
+        let file = self.out.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        for line in BufReader::new(&*file).lines() {
+            f(serde_json::from_str(&line?)?);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,284 @@
+//! `logbuddy merge`: the lines of every log file in one or more folders as
+//! one timeline, by timestamp. Files are read side by side, a line at a
+//! time, so merging takes memory for one line per file however big they
+//! are.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::charset::{Charset, Transcode};
+use crate::cli::value_for;
+use crate::compress;
+use crate::fields;
+use crate::scanner;
+use crate::skew::SkewTracker;
+use crate::timestamp;
+use crate::units::{self, UnitKind};
+
+pub const USAGE: &str = "\
+Usage: logbuddy merge [--path DIR]... [OPTIONS]
+
+Prints the lines of the .txt and .log files in DIR (default .),
+compressed or not, merged in timestamp order, as one timeline. Lines
+without a timestamp stay right after the line before them, so stack
+traces keep together; lines at the same time keep the order of their
+files. Each file should be in time order itself, as logs usually are.
+
+Options:
+  --path <DIR>       Folder whose files are merged; repeatable, to merge
+                     the logs of several services
+  -r, --recursive    Also merge the files in DIR's subfolders
+  --prefix           Start every line with the file it came from
+  --compensate-skew  Line up files whose clocks are off: estimate each
+                     one's skew from events sharing a --skew-key value
+                     and merge its lines by its corrected times
+  --skew-key <NAME>  Field that correlates events across files (a request
+                     or trace id), for --compensate-skew
+  --offset <FILE=DURATION>
+                     FILE's clock is DURATION ahead (+37s) or behind
+                     (-2m); merge its lines as if it weren't. Repeatable;
+                     wins over --compensate-skew's estimate for FILE
+  -h, --help         Show this help
+
+Corrected times only order the lines; they are printed as logged.";
+
+/// One file being merged, with the line it will print next.
+struct Source {
+    name: String,
+    lines: io::Lines<Box<dyn BufRead>>,
+    /// The next line and its timestamp, or that of the last timestamped
+    /// line before it
+    next: Option<(i64, String)>,
+    /// Timestamp of the last timestamped line read, corrected for skew
+    last_time: i64,
+    /// How far ahead the file's clock is, in millis
+    skew: i64,
+    /// When the file was last modified, which syslog dates go by
+    near: i64,
+}
+
+impl Source {
+    /// `path` as `name`, its clock `skew` millis ahead.
+    fn open(path: &Path, name: String, skew: i64) -> Result<Source, String> {
+        let file = compress::open(path).map_err(|e| format!("could not open {}: {}", name, e))?;
+        // The charset is sniffed from the text, the file may be compressed
+        let mut decompressed = BufReader::new(file);
+        let sample = decompressed
+            .fill_buf()
+            .map_err(|e| format!("could not read {}: {}", name, e))?;
+        let charset = Charset::sniff(sample);
+        let text: Box<dyn BufRead> =
+            Box::new(BufReader::new(Transcode::new(decompressed, charset)));
+        let mut source = Source {
+            name,
+            lines: text.lines(),
+            next: None,
+            // Lines before the first timestamp go first
+            last_time: i64::MIN,
+            skew,
+            near: fs::metadata(path)
+                .ok()
+                .as_ref()
+                .and_then(scanner::modified_millis)
+                .unwrap_or_else(timestamp::now_millis),
+        };
+        source.advance()?;
+        Ok(source)
+    }
+
+    /// Reads the next line into `next`.
+    fn advance(&mut self) -> Result<(), String> {
+        self.next = match self.lines.next() {
+            Some(line) => {
+                let line = line.map_err(|e| format!("could not read {}: {}", self.name, e))?;
+                if let Some(millis) = timestamp::parse_line(&line, self.near) {
+                    self.last_time = millis - self.skew;
+                }
+                Some((self.last_time, line))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+/// Entry point for `logbuddy merge ...`; `args` excludes "merge".
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let mut dirs = Vec::new();
+    let mut recursive = false;
+    let mut prefix = false;
+    let mut compensate = false;
+    let mut skew_key = None;
+    let mut offsets = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => dirs.push(value_for(&arg, &mut args)?),
+            "-r" | "--recursive" => recursive = true,
+            "--prefix" => prefix = true,
+            "--compensate-skew" => compensate = true,
+            "--skew-key" => skew_key = Some(value_for(&arg, &mut args)?),
+            "--offset" => offsets.push(parse_offset(&value_for(&arg, &mut args)?)?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other => return Err(format!("unknown merge argument '{}'", other)),
+        }
+    }
+    if dirs.is_empty() {
+        dirs.push(".".to_string());
+    }
+    let skew_key = match (compensate, skew_key) {
+        (true, None) => return Err("--compensate-skew needs a --skew-key".to_string()),
+        (false, Some(_)) => return Err("--skew-key is for --compensate-skew".to_string()),
+        (_, key) => key,
+    };
+
+    let mut files = Vec::new();
+    for dir in &dirs {
+        for (path, name) in scanner::log_files(Path::new(dir), recursive)? {
+            // Names from several folders could clash without theirs
+            let name = if dirs.len() > 1 {
+                Path::new(dir).join(&name).display().to_string()
+            } else {
+                name
+            };
+            files.push((path, name));
+        }
+    }
+    let mut skews = match &skew_key {
+        Some(key) => estimate_skews(&files, key)?,
+        None => vec![0; files.len()],
+    };
+    for (file, skew) in offsets {
+        let i = files
+            .iter()
+            .position(|(_, name)| *name == file)
+            .ok_or_else(|| format!("--offset names {}, which isn't merged", file))?;
+        skews[i] = skew;
+    }
+    let mut sources = Vec::new();
+    for ((path, name), skew) in files.into_iter().zip(skews) {
+        sources.push(Source::open(&path, name, skew)?);
+    }
+
+    // Earliest line first; between equal times, the earlier file
+    let mut heap: BinaryHeap<Reverse<(i64, usize)>> = sources
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| s.next.as_ref().map(|(time, _)| Reverse((*time, i))))
+        .collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+    while let Some(Reverse((_, i))) = heap.pop() {
+        let source = &mut sources[i];
+        let Some((_, line)) = source.next.take() else {
+            continue;
+        };
+        let written = if prefix {
+            writeln!(out, "{}: {}", source.name, line)
+        } else {
+            writeln!(out, "{}", line)
+        };
+        match written {
+            Ok(()) => {}
+            // The reader went away, as with `| head`
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(format!("could not write: {}", e)),
+        }
+        source.advance()?;
+        if let Some((time, _)) = &source.next {
+            heap.push(Reverse((*time, i)));
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(format!("could not write: {}", e)),
+        _ => Ok(()),
+    }
+}
+
+/// How far ahead each of `files` has its clock, from the events they share
+/// `key` values with (see skew::SkewTracker), as the report's clock skew
+/// section puts it. Files sharing none are taken to be right; the
+/// estimates go to stderr.
+fn estimate_skews(files: &[(PathBuf, String)], key: &str) -> Result<Vec<i64>, String> {
+    let mut tracker = SkewTracker::default();
+    for (i, (path, name)) in files.iter().enumerate() {
+        let mut source = Source::open(path, name.clone(), 0)?;
+        while let Some((millis, line)) = source.next.take() {
+            if let Some(value) = fields::find_value(&line, key) {
+                // Lines before the first timestamp have no time of their own
+                if millis != i64::MIN {
+                    tracker.record(value, i, millis);
+                }
+            }
+            source.advance()?;
+        }
+    }
+    let mut skews = vec![0; files.len()];
+    let Some((reference, estimates)) = tracker.estimate() else {
+        eprintln!(
+            "no two files share a {} value; merging without compensating",
+            key
+        );
+        return Ok(skews);
+    };
+    for estimate in estimates {
+        eprintln!(
+            "{}: {:+.1}s against {} ({} shared events)",
+            files[estimate.file].1,
+            estimate.offset_ms as f64 / 1000.0,
+            files[reference].1,
+            estimate.samples
+        );
+        skews[estimate.file] = estimate.offset_ms;
+    }
+    Ok(skews)
+}
+
+/// `FILE=DURATION` (`app.log=+37s`, `db.log=-1.5m`): the file and how far
+/// ahead its clock is, in millis.
+fn parse_offset(value: &str) -> Result<(String, i64), String> {
+    let invalid = || {
+        format!(
+            "--offset takes FILE=DURATION, such as app.log=+37s, not '{}'",
+            value
+        )
+    };
+    let (file, duration) = value.rsplit_once('=').ok_or_else(invalid)?;
+    match units::parse_quantity(duration) {
+        Some(q) if q.kind == UnitKind::Duration && !file.is_empty() => {
+            Ok((file.to_string(), q.value.round() as i64))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets() {
+        assert_eq!(
+            parse_offset("app.log=+37s"),
+            Ok(("app.log".to_string(), 37_000))
+        );
+        assert_eq!(
+            parse_offset("a=b.log=-1.5m"),
+            Ok(("a=b.log".to_string(), -90_000))
+        );
+        assert_eq!(
+            parse_offset("db.log=250ms"),
+            Ok(("db.log".to_string(), 250))
+        );
+        assert!(parse_offset("app.log").is_err());
+        assert!(parse_offset("=37s").is_err());
+        assert!(parse_offset("app.log=37").is_err());
+        assert!(parse_offset("app.log=37KB").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Finds the source location a log line says it came from, such as
+//! `src/db/pool.rs:123`, `(Main.java:88)` or `handler.go:45:12`.
+
+use serde::{Deserialize, Serialize};
+
+/// File extensions that count as source code.
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "go", "py", "java", "kt", "scala", "js", "jsx", "ts", "tsx", "c", "cc", "cpp", "h",
+    "hpp", "cs", "rb", "php", "swift", "ex", "exs", "erl", "lua",
+];
+
+/// The first `path.ext:line` token in `line` whose extension is a known
+/// source extension. A trailing `:column` is ignored.
+pub fn find_origin(line: &str) -> Option<(&str, u32)> {
+    line.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | '"' | '\'' | ','))
+        .find_map(parse_origin)
+}
+
+fn parse_origin(token: &str) -> Option<(&str, u32)> {
+    let (path, rest) = token.split_once(':')?;
+    let digits = rest
+        .split(':')
+        .next()?
+        .trim_end_matches(|c: char| !c.is_ascii_digit());
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (_, ext) = path.rsplit_once('.')?;
+    if !SOURCE_EXTENSIONS.contains(&ext) {
+        return None;
+    }
+    Some((path, digits.parse().ok()?))
+}
+
+/// Lines seen from one source location.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct OriginTally {
+    pub lines: usize,
+    /// Of those, lines at ERROR or FATAL level
+    pub errors: usize,
+}
+
+impl OriginTally {
+    pub fn merge(&mut self, other: OriginTally) {
+        self.lines += other.lines;
+        self.errors += other.errors;
+    }
+}
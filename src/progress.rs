@@ -0,0 +1,159 @@
+//! A progress bar on stderr for long scans: files done, bytes read and an
+//! estimate of the time left, redrawn a few times a second.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::human;
+
+/// Time between redraws.
+const TICK: Duration = Duration::from_millis(200);
+
+/// Width of the bar itself, in characters.
+const BAR_WIDTH: usize = 24;
+
+/// Bytes a scan thread reads before telling the bar.
+pub const REPORT_EVERY: usize = 1 << 20;
+
+struct Counters {
+    files_total: usize,
+    bytes_total: usize,
+    files_done: AtomicUsize,
+    bytes_done: AtomicUsize,
+    started: Instant,
+    stop: AtomicBool,
+    /// Held while drawing; true while a bar is on screen
+    drawn: Mutex<bool>,
+}
+
+/// Shared handle for the scan threads to report progress through.
+#[derive(Clone)]
+pub struct Progress(Arc<Counters>);
+
+impl Progress {
+    pub fn add_bytes(&self, bytes: usize) {
+        self.0.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn file_done(&self) {
+        self.0.files_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Runs `f` with the bar cleared, for writing other output to the
+    /// terminal; the next tick draws it again.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        let mut drawn = self.0.drawn.lock().unwrap_or_else(|e| e.into_inner());
+        if *drawn {
+            clear_line();
+            *drawn = false;
+        }
+        f()
+    }
+
+    fn draw(&self) {
+        let c = &self.0;
+        let files = c.files_done.load(Ordering::Relaxed);
+        // Re-read files can push this past the total
+        let bytes = c.bytes_done.load(Ordering::Relaxed).min(c.bytes_total);
+        let done = if c.bytes_total > 0 {
+            bytes as f64 / c.bytes_total as f64
+        } else {
+            files as f64 / c.files_total.max(1) as f64
+        };
+        let filled = (done * BAR_WIDTH as f64) as usize;
+        let bar = format!("{}{}", "=".repeat(filled), " ".repeat(BAR_WIDTH - filled));
+
+        let elapsed = c.started.elapsed().as_secs_f64();
+        let eta = if done > 0.0 && elapsed >= 1.0 {
+            format_eta(elapsed / done - elapsed)
+        } else {
+            "--:--".to_string()
+        };
+
+        let mut drawn = c.drawn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut err = io::stderr().lock();
+        let _ = write!(
+            err,
+            "\r\x1b[2K[{}] {}/{} files  {} / {}  ETA {}",
+            bar,
+            files,
+            c.files_total,
+            human::bytes(bytes),
+            human::bytes(c.bytes_total),
+            eta
+        );
+        let _ = err.flush();
+        *drawn = true;
+    }
+}
+
+/// Draws a Progress until dropped or finished.
+pub struct ProgressBar {
+    progress: Progress,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl ProgressBar {
+    /// Starts drawing for a scan of `files_total` files of `bytes_total`
+    /// bytes in all.
+    pub fn start(files_total: usize, bytes_total: usize) -> ProgressBar {
+        let progress = Progress(Arc::new(Counters {
+            files_total,
+            bytes_total,
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicUsize::new(0),
+            started: Instant::now(),
+            stop: AtomicBool::new(false),
+            drawn: Mutex::new(false),
+        }));
+        let shown = progress.clone();
+        let ticker = thread::spawn(move || {
+            // The first draw waits a tick, so quick scans never show a bar
+            while !shown.0.stop.load(Ordering::Relaxed) {
+                thread::park_timeout(TICK);
+                if !shown.0.stop.load(Ordering::Relaxed) {
+                    shown.draw();
+                }
+            }
+        });
+        ProgressBar {
+            progress,
+            ticker: Some(ticker),
+        }
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+}
+
+impl Drop for ProgressBar {
+    /// Stops the ticker and clears the bar.
+    fn drop(&mut self) {
+        self.progress.0.stop.store(true, Ordering::Relaxed);
+        if let Some(ticker) = self.ticker.take() {
+            ticker.thread().unpark();
+            let _ = ticker.join();
+        }
+        self.progress.suspend(|| ());
+    }
+}
+
+fn clear_line() {
+    let mut err = io::stderr().lock();
+    let _ = write!(err, "\r\x1b[2K");
+    let _ = err.flush();
+}
+
+/// "1:05" or "2:03:10".
+fn format_eta(seconds: f64) -> String {
+    let s = seconds.max(0.0).round() as u64;
+    if s >= 3600 {
+        format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+    } else {
+        format!("{}:{:02}", s / 60, s % 60)
+    }
+}
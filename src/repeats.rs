@@ -0,0 +1,66 @@
+//! Lines that repeat word for word, like a retry loop printing the same
+//! message millions of times, counted in bounded memory with the
+//! Misra-Gries summary: any line making up more than 1 / CAPACITY of all
+//! lines is sure to be kept, with a count at most `undercount` low.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Lines kept after pruning; the summary grows to twice this before it
+/// prunes again.
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RepeatTally {
+    pub lines: HashMap<String, u64>,
+    /// How far below the true count any kept count may be
+    pub undercount: u64,
+}
+
+impl RepeatTally {
+    pub fn record(&mut self, line: &str, squeeze: bool) {
+        let squeezed;
+        let line = if squeeze {
+            squeezed = squeeze_whitespace(line);
+            squeezed.as_str()
+        } else {
+            line
+        };
+        match self.lines.get_mut(line) {
+            Some(count) => *count += 1,
+            None => {
+                self.lines.insert(line.to_string(), 1);
+                self.prune();
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: RepeatTally) {
+        for (line, count) in other.lines {
+            *self.lines.entry(line).or_insert(0) += count;
+        }
+        self.undercount += other.undercount;
+        self.prune();
+    }
+
+    /// Once there are 2 * CAPACITY lines, takes the count of the one just
+    /// below the top CAPACITY off every line, dropping those left at zero.
+    fn prune(&mut self) {
+        if self.lines.len() < 2 * CAPACITY {
+            return;
+        }
+        let mut counts: Vec<u64> = self.lines.values().copied().collect();
+        let (_, &mut cut, _) = counts.select_nth_unstable_by(CAPACITY, |a, b| b.cmp(a));
+        self.lines.retain(|_, count| {
+            *count = count.saturating_sub(cut);
+            *count > 0
+        });
+        self.undercount += cut;
+    }
+}
+
+/// `line` trimmed, with every run of whitespace as one space.
+fn squeeze_whitespace(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::charset::{Charset, Transcode};
+use crate::cli::value_for;
+use crate::compress;
+use crate::scanner;
+use crate::timestamp;
+
+pub const USAGE: &str = "\
+Usage: logbuddy replay <FILE> [OPTIONS]
+
+Re-emits the lines of a log file, compressed or not, waiting between
+lines as long as the original timestamps say (divided by --speed).
+Lines without a timestamp are sent right after the line before them.
+
+Options:
+  --speed <N>        Speed-up factor, e.g. 10x, 0.5x or max (default 1x)
+  --to <TARGET>      Where to send lines: udp://host:port (one datagram
+                     per line), tcp://host:port, or - for stdout (default)
+  -h, --help         Show this help";
+
+enum Target {
+    Stdout(io::Stdout),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Target {
+    fn connect(spec: &str) -> Result<Target, String> {
+        if spec == "-" {
+            return Ok(Target::Stdout(io::stdout()));
+        }
+        if let Some(addr) = spec.strip_prefix("udp://") {
+            let socket = UdpSocket::bind("0.0.0.0:0")
+                .map_err(|e| format!("could not open UDP socket: {}", e))?;
+            socket
+                .connect(addr)
+                .map_err(|e| format!("could not reach {}: {}", spec, e))?;
+            return Ok(Target::Udp(socket));
+        }
+        if let Some(addr) = spec.strip_prefix("tcp://") {
+            let stream = TcpStream::connect(addr)
+                .map_err(|e| format!("could not connect to {}: {}", spec, e))?;
+            return Ok(Target::Tcp(stream));
+        }
+        Err(format!(
+            "--to must be udp://host:port, tcp://host:port or -, not '{}'",
+            spec
+        ))
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Target::Stdout(out) => writeln!(out, "{}", line),
+            Target::Udp(socket) => socket.send(line.as_bytes()).map(|_| ()),
+            Target::Tcp(stream) => {
+                stream.write_all(line.as_bytes())?;
+                stream.write_all(b"\n")
+            }
+        }
+    }
+}
+
+/// Entry point for `logbuddy replay ...`; `args` excludes "replay".
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let mut file = None;
+    // None means "as fast as possible"
+    let mut speed = Some(1.0);
+    let mut to = "-".to_string();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => speed = parse_speed(&value_for(&arg, &mut args)?)?,
+            "--to" => to = value_for(&arg, &mut args)?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("unknown replay argument '{}'", other))
+            }
+            other if file.is_none() => file = Some(other.to_string()),
+            other => return Err(format!("replay takes one file, got '{}' as well", other)),
+        }
+    }
+
+    let file = file.ok_or("replay needs a file to read")?;
+    let near = fs::metadata(&file)
+        .ok()
+        .as_ref()
+        .and_then(scanner::modified_millis)
+        .unwrap_or_else(timestamp::now_millis);
+    let reader =
+        compress::open(file.as_ref()).map_err(|e| format!("could not open {}: {}", file, e))?;
+    // Sniffed from the text, as the file may be compressed
+    let mut reader = BufReader::new(reader);
+    let charset = Charset::sniff(reader.fill_buf().unwrap_or(&[]));
+    let mut target = Target::connect(&to)?;
+
+    let started = Instant::now();
+    // Timestamp of the first timestamped line, which plays at `started`
+    let mut first_ts: Option<i64> = None;
+    let mut sent = 0usize;
+
+    for line in BufReader::new(Transcode::new(reader, charset)).lines() {
+        let line = line.map_err(|e| format!("could not read {}: {}", file, e))?;
+
+        if let (Some(speed), Some(ts)) = (speed, timestamp::parse_line(&line, near)) {
+            let first = *first_ts.get_or_insert(ts);
+            // Lines that go back in time are sent right away
+            let offset_ms = (ts - first).max(0) as f64 / speed;
+            let due = started + Duration::from_secs_f64(offset_ms / 1000.0);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+
+        target
+            .send(&line)
+            .map_err(|e| format!("could not send to {}: {}", to, e))?;
+        sent += 1;
+    }
+
+    eprintln!(
+        "Replayed {} lines from {} in {:.1}s",
+        sent,
+        file,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// "10x", "0.5x", "10" -> factor; "max" -> None (no waiting).
+fn parse_speed(text: &str) -> Result<Option<f64>, String> {
+    if text == "max" {
+        return Ok(None);
+    }
+    let factor: f64 = text
+        .trim_end_matches(['x', 'X'])
+        .parse()
+        .map_err(|_| format!("'{}' is not a speed like 10x", text))?;
+    if factor <= 0.0 || !factor.is_finite() {
+        return Err(format!("speed '{}' must be above zero", text));
+    }
+    Ok(Some(factor))
+}
@@ -0,0 +1,3366 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use handlebars::Handlebars;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::access::{AccessTally, PathStat};
+use crate::cef::{self, SecurityTally};
+use crate::cli::{Config, FieldKey, LineFormat};
+use crate::correlate::TraceIdLines;
+use crate::distinct::DistinctTally;
+use crate::drain::{self, Cluster};
+use crate::groups::{CountTally, GroupStat, GroupTally};
+use crate::human;
+use crate::interrupt::Stop;
+use crate::level::Level;
+use crate::links::Linker;
+use crate::rotation;
+use crate::scanner::{Bucket, FileStats, Rule, ScanTotals};
+use crate::sessions::{Ending, Session, SessionTracker};
+use crate::stats::FieldStats;
+use crate::style::{self, Style};
+use crate::syslog::{self, SyslogTally};
+use crate::timestamp;
+use crate::traces::TraceStat;
+use crate::units::UnitKind;
+use crate::usage::ResourceUsage;
+
+/// Version of the Report shape in --format json, templates and --schema.
+/// Bump it when a field is removed, renamed or changes type; new fields
+/// don't need a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Files left out by --max-filesize that the text summary names.
+const SKIPPED_SHOWN: usize = 5;
+
+/// How many entries the top-templates list keeps.
+const TOP_TEMPLATES: usize = 10;
+
+/// Words listed per file, and files listed, in the distinctive section.
+const DISTINCTIVE_WORDS: usize = 5;
+const DISTINCTIVE_FILES: usize = 20;
+
+/// Repeated lines the repeats section lists.
+const TOP_REPEATS: usize = 10;
+
+/// Templates seen at most this often are listed as rare.
+const RARE_MAX_COUNT: usize = 3;
+const TOP_RARE: usize = 20;
+
+const TOP_SEQUENCES: usize = 10;
+const TOP_FAILED_SESSIONS: usize = 10;
+const TOP_LONGEST_SESSIONS: usize = 10;
+
+/// Most frequent values listed per template placeholder.
+const TOP_VALUES: usize = 5;
+
+/// Gaps the text summary lists, over all files.
+const TOP_GAPS: usize = 5;
+
+/// How many source locations the sources list keeps.
+const TOP_SOURCES: usize = 20;
+
+/// --histogram fills in empty buckets only up to this many in all.
+const MAX_HISTOGRAM_BUCKETS: i64 = 10_000;
+
+/// Width of the longest histogram bar, in characters.
+const HISTOGRAM_BAR: usize = 40;
+
+/// Width of the longest top-word bar, in characters.
+const WORD_BAR: usize = 20;
+
+/// Columns in the --find hits-over-time sparkline.
+const TIMELINE_COLUMNS: i64 = 60;
+
+/// Paths the access log section lists.
+const TOP_PATHS: usize = 10;
+
+/// Apps the syslog section lists.
+const TOP_APPS: usize = 10;
+
+/// Values the groups section lists, most lines first.
+const TOP_GROUPS: usize = 20;
+
+/// Most and least common values listed per --distinct field.
+const TOP_DISTINCT: usize = 5;
+
+/// Stack trace signatures the traces section lists.
+const TOP_TRACES: usize = 10;
+
+/// Event names and source addresses the security section lists.
+const TOP_EVENTS: usize = 10;
+
+/// Spikes the text summary lists, biggest first.
+const TOP_SPIKES: usize = 10;
+
+/// The summary of a scan in a shape that can be printed or fed to a
+/// user template. Field names are part of the --template interface.
+#[derive(Serialize, JsonSchema)]
+pub struct Report {
+    /// Always SCHEMA_VERSION
+    pub schema_version: u32,
+    pub folder: String,
+    /// True when the scan was stopped with Ctrl-C or by --timeout;
+    /// everything below covers only what was read until then
+    pub partial: bool,
+    /// "interrupted", "timeout" or "output closed", for partial reports
+    pub stopped_by: Option<&'static str>,
+    pub files_scanned: usize,
+    pub total_lines: usize,
+    pub total_bytes: usize,
+    /// Of files_scanned, files whose results came from --cache
+    pub files_cached: usize,
+    /// Lines cut short for field parsing by --max-record-bytes / --max-fields
+    pub truncated_records: usize,
+    /// "file:line: text…" of the first few
+    pub truncated_record_samples: Vec<String>,
+    /// Lines cut to --max-line-length before they were counted
+    pub long_lines: usize,
+    /// "file:line: text… (N bytes)" of the first few
+    pub long_line_samples: Vec<String>,
+    /// Files left out by --max-filesize
+    pub skipped_files: Vec<SkippedFile>,
+    /// Lines per severity, most severe first, from level words (ERROR,
+    /// WARN, ...) or syslog priorities
+    pub levels: Vec<LevelCount>,
+    /// Lines in which no level was found
+    pub lines_without_level: usize,
+    /// Lines left out by --min-level; the level counts include them
+    pub lines_below_min_level: usize,
+    /// With a line format (--json-logs, --logfmt, --access-log, ...), lines
+    /// not in it, which were read as text
+    pub lines_unparsed: usize,
+    /// With --multiline, lines that continued a log entry; total_lines
+    /// less these is the number of entries
+    pub continuation_lines: usize,
+    /// Files read as UTF-16 or Latin-1 (see --encoding)
+    pub files_transcoded: usize,
+    /// Lines read but left out by --since, --until or --last
+    pub lines_outside_window: usize,
+    /// Files left out for being last modified before --since
+    pub files_before_window: usize,
+    /// --find text and the number of matching lines, if a search was run
+    pub find: Option<String>,
+    pub hits: usize,
+    pub files: Vec<FileSummary>,
+    /// Files, lines, bytes and hits per subfolder and per extension
+    pub rollups: Rollups,
+    pub nested_payloads: Vec<PayloadCount>,
+    pub top_words: Vec<WordCount>,
+    /// The --bottom N least frequent words, fewest first; empty with
+    /// approximate counts
+    pub bottom_words: Vec<WordCount>,
+    /// One per --ngrams size: the most frequent phrases, estimated
+    pub top_ngrams: Vec<NgramCounts>,
+    /// True with --approx-counts, or once --max-memory made word counting
+    /// switch over: word counts are estimates, possibly high
+    pub words_approximate: bool,
+    /// Distinct words, estimated with HyperLogLog (within about 1%)
+    pub distinct_words_estimate: u64,
+    /// Per file, the words most characteristic of it by TF-IDF; only with
+    /// several files
+    pub distinctive_words: Vec<FileWords>,
+    /// Most frequent message templates, variable parts shown as <*>
+    pub top_templates: Vec<TemplateCount>,
+    /// Messages seen word for word most often (more than once)
+    pub repeated_lines: Vec<LineCount>,
+    /// How far below the true count the repeated line counts may be
+    pub repeats_undercount: u64,
+    /// Templates seen at most three times, or the --rare N rarest, rarest
+    /// first
+    pub rare_templates: Vec<RareTemplate>,
+    /// With --multiline, the stack traces seen most, by signature
+    pub top_traces: Vec<TraceCount>,
+    /// Distinct stack trace signatures
+    pub distinct_traces: usize,
+    pub syscall_errors: Vec<SyscallError>,
+    pub checks: Vec<CheckResult>,
+    pub field_stats: Vec<FieldReport>,
+    /// Source locations named in log lines, most errors first
+    pub source_locations: Vec<SourceLocation>,
+    /// Only set with --skew-key and at least two files sharing key values
+    pub clock_skew: Option<ClockSkew>,
+    /// Only set with --session-key
+    pub sessions: Option<SessionReport>,
+    /// Only set with --group-by or --group-regex
+    pub groups: Option<GroupReport>,
+    /// One per --count-by, in order
+    pub counts: Vec<CountReport>,
+    /// One per --distinct, in order
+    pub distinct: Vec<DistinctReport>,
+    /// Only set with --access-log
+    pub access: Option<AccessReport>,
+    /// Only set with --syslog
+    pub syslog: Option<SyslogReport>,
+    /// Only set with --cef or --leef
+    pub security: Option<SecurityReport>,
+    /// --max-gap, if given
+    pub max_gap_ms: Option<i64>,
+    /// Only set with --histogram
+    pub histogram: Option<Histogram>,
+    /// Only set with --spikes
+    pub spikes: Option<Spikes>,
+    /// Only set with --trace-id
+    pub trace_id: Option<TraceIdReport>,
+    /// Only set with --find, when lines had timestamps
+    pub hit_timeline: Option<Timeline>,
+    /// Only set with --timings
+    pub timings: Option<Timings>,
+    /// Every match, only collected for --format sarif
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<MatchRecord>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Histogram {
+    /// Width of each bucket
+    pub bucket_ms: i64,
+    /// From the first bucket with a line to the last; the buckets in
+    /// between are all there, empty ones too, unless that would make more
+    /// than 10000
+    pub buckets: Vec<HistogramBucket>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct HistogramBucket {
+    /// Unix millis, a multiple of bucket_ms
+    pub start_ms: i64,
+    /// Lines whose timestamp (or that of the line before them) falls in
+    /// the bucket
+    pub lines: usize,
+    /// Of those, lines matching --find
+    pub hits: usize,
+}
+
+/// --find hits from the first timestamp to the last, in equal spans.
+#[derive(Serialize, JsonSchema)]
+pub struct Timeline {
+    /// Unix millis where the first span starts
+    pub start_ms: i64,
+    /// Width of each span
+    pub span_ms: i64,
+    /// At most 60, one per span
+    pub hits: Vec<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Spikes {
+    pub bucket_ms: i64,
+    /// --spikes
+    pub factor: f64,
+    /// "hits" with --find, else "lines": what was counted per bucket
+    pub counted: &'static str,
+    /// Median count over the buckets from the first timestamp to the last,
+    /// empty ones included
+    pub median: f64,
+    /// Buckets counting more than factor times the median (or than factor,
+    /// when the median is below 1), in time order
+    pub buckets: Vec<Spike>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Spike {
+    pub start_ms: i64,
+    pub count: usize,
+    /// count over the median (over 1, when the median is below 1)
+    pub ratio: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Timings {
+    pub wall_ms: f64,
+    /// Bytes read per second of wall time, in MB (10^6 bytes)
+    pub mb_per_second: f64,
+    pub lines_per_second: f64,
+    /// Summed over the scan threads, so with --jobs they add up to more
+    /// than wall_ms
+    pub phases: Vec<PhaseTime>,
+    /// Of the whole run up to the report; None off Linux
+    pub resources: Option<ResourceUsage>,
+    /// Largest estimate of the word tables and buffered hits, the part
+    /// --max-memory limits
+    pub peak_estimated_bytes: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PhaseTime {
+    /// listing, reading, tokenizing, searching, templates or other
+    pub phase: &'static str,
+    pub ms: f64,
+    /// Part of the time of all phases, 0 to 1
+    pub share: f64,
+}
+
+/// Numeric summary of one --stats-field, in the canonical unit of the
+/// kind most of its values had.
+#[derive(Serialize, JsonSchema)]
+pub struct FieldReport {
+    pub name: String,
+    /// "duration", "size" or "number"; None when no value parsed
+    pub kind: Option<&'static str>,
+    /// "ms", "B" or "" (bare numbers)
+    pub unit: &'static str,
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Percentiles, within 1% of the true values
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    /// Values of other kinds, left out of the numbers above
+    pub mismatched: Vec<KindCount>,
+    pub unparsed: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct KindCount {
+    pub kind: &'static str,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SourceLocation {
+    pub path: String,
+    pub line: u32,
+    pub lines: usize,
+    /// Lines at ERROR or FATAL level
+    pub errors: usize,
+    /// From --source-link-template
+    pub url: Option<String>,
+}
+
+/// Estimated clock offsets of each file against a reference file.
+#[derive(Serialize, JsonSchema)]
+pub struct ClockSkew {
+    pub key: String,
+    pub reference: String,
+    pub files: Vec<FileSkew>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileSkew {
+    pub file: String,
+    /// Positive when the file's clock is ahead of the reference
+    pub offset_ms: i64,
+    /// Number of key values seen in both files
+    pub samples: usize,
+}
+
+/// Lines per value of --group-by or --group-regex.
+#[derive(Serialize, JsonSchema)]
+pub struct GroupReport {
+    /// The field name, or /regex/
+    pub by: String,
+    /// The values with the most lines
+    pub groups: Vec<GroupCount>,
+    /// Distinct values counted
+    pub distinct: usize,
+    /// Lines with a value not listed
+    pub other: usize,
+    /// Lines without a value
+    pub ungrouped: usize,
+}
+
+/// Matches of one --count-by regex per captured value.
+#[derive(Serialize, JsonSchema)]
+pub struct CountReport {
+    pub pattern: String,
+    /// Every match
+    pub matches: usize,
+    /// Distinct values counted
+    pub distinct: usize,
+    /// The values with the most matches, --count-top of them
+    pub top: Vec<EventCount>,
+    /// Matches with a value not listed
+    pub other: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Rollups {
+    /// Per subfolder of the scanned folder (`.` for the files directly in
+    /// it), most bytes first
+    pub by_directory: Vec<Rollup>,
+    /// Per file extension, a rotation number or date like `.1` skipped
+    pub by_extension: Vec<Rollup>,
+    /// Sets of rotated files (app.log, app.log.1, ...), as one log each
+    pub rotated: Vec<RotatedLog>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RotatedLog {
+    /// The name without the rotation number or date
+    pub name: String,
+    /// Oldest first, the order they were read in
+    pub files: Vec<String>,
+    pub lines: usize,
+    pub bytes: usize,
+    pub hits: usize,
+    /// Timestamps of its first and last lines that have one (Unix millis)
+    pub first_timestamp_ms: Option<i64>,
+    pub last_timestamp_ms: Option<i64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Rollup {
+    pub name: String,
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: usize,
+    pub hits: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileWords {
+    pub file: String,
+    /// Highest score first
+    pub words: Vec<WordScore>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct WordScore {
+    pub word: String,
+    /// Times in the file
+    pub count: usize,
+    /// Share of the file's words, times the log of how few files have it
+    pub tf_idf: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LineCount {
+    pub line: String,
+    pub count: u64,
+}
+
+/// The values of one --distinct field.
+#[derive(Serialize, JsonSchema)]
+pub struct DistinctReport {
+    pub field: String,
+    /// Distinct values, estimated (within about 1%) unless `exact`
+    pub values: u64,
+    pub exact: bool,
+    /// Lines with the field
+    pub lines: usize,
+    /// Lines without it
+    pub missing: usize,
+    /// The most common values, most lines first
+    pub most_common: Vec<EventCount>,
+    /// The least common values not among the most common, fewest lines
+    /// first; empty unless `exact`
+    pub least_common: Vec<EventCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GroupCount {
+    pub value: String,
+    pub lines: usize,
+    pub warnings: usize,
+    /// ERROR and FATAL lines
+    pub errors: usize,
+}
+
+/// Sessions rebuilt from --session-key, with message templates as events.
+#[derive(Serialize, JsonSchema)]
+pub struct SessionReport {
+    pub key: String,
+    pub sessions: usize,
+    /// Last event looked like a logout or disconnect
+    pub ended: usize,
+    /// Last line was at ERROR or FATAL
+    pub failed: usize,
+    /// Neither ended nor failed
+    pub open: usize,
+    /// Most common event sequences
+    pub sequences: Vec<SessionSequence>,
+    /// First few failed sessions by id
+    pub failures: Vec<SessionFailure>,
+    /// Sessions with at least one timestamped line
+    pub timed: usize,
+    /// Of the timed sessions, from their first timestamp to their last;
+    /// None without any
+    pub durations_ms: Option<Percentiles>,
+    /// Lines per session
+    pub lines: Option<Percentiles>,
+    /// The timed sessions that lasted longest
+    pub longest: Vec<SessionSpan>,
+}
+
+/// Nearest-rank percentiles of a set of whole numbers.
+#[derive(Serialize, JsonSchema)]
+pub struct Percentiles {
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+impl Percentiles {
+    /// None for no values.
+    fn of(mut values: Vec<i64>) -> Option<Percentiles> {
+        values.sort_unstable();
+        let at = |q: f64| values[((q * values.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Percentiles {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max: *values.last()?,
+        })
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionSpan {
+    pub session: String,
+    pub lines: usize,
+    /// Unix millis of its first and last timestamps
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub duration_ms: i64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionSequence {
+    /// Templates in order, repeats in a row collapsed
+    pub events: Vec<String>,
+    pub sessions: usize,
+    /// Of those, sessions that ended in an error
+    pub failed: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionFailure {
+    pub session: String,
+    pub lines: usize,
+    pub events: Vec<String>,
+    /// "file:line: text" of the error that ended it
+    pub last_line: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TraceIdReport {
+    /// --trace-id, as given
+    pub pattern: String,
+    /// Files with at least one of the lines
+    pub files: usize,
+    /// Lines left out for there being more than 100000
+    pub dropped: usize,
+    /// By time; lines without a timestamp (or one before them in their
+    /// file) come last
+    pub lines: Vec<TracedLineRecord>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TracedLineRecord {
+    /// Unix millis
+    pub time_ms: Option<i64>,
+    pub file: String,
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MatchRecord {
+    /// "find" or "check-N" (1-based, in --check order)
+    pub rule_id: String,
+    pub file: String,
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CheckResult {
+    pub name: String,
+    pub count: usize,
+    pub max: usize,
+    pub passed: bool,
+    /// "file:line: text" of the first few offending lines
+    pub samples: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileSummary {
+    pub name: String,
+    pub lines: usize,
+    pub bytes: usize,
+    pub hits: usize,
+    /// Lines with a timestamp earlier than one before them in the file
+    pub out_of_order: usize,
+    pub max_regression_ms: i64,
+    /// "file:line: text" of the first few out-of-order lines
+    pub out_of_order_samples: Vec<String>,
+    /// How the file changed while it was read (grew, shrank, replaced,
+    /// removed, was modified), if it did; its counts may not add up
+    pub changed_while_read: Option<String>,
+    /// The longest gaps between consecutive timestamps, longest first
+    pub longest_gaps: Vec<GapSummary>,
+    /// Gaps longer than --max-gap
+    pub long_gaps: usize,
+    /// Timestamps of the first and last lines that have one (Unix millis)
+    pub first_timestamp_ms: Option<i64>,
+    pub last_timestamp_ms: Option<i64>,
+    /// Line numbers of its first and last --find hit
+    pub first_hit: Option<usize>,
+    pub last_hit: Option<usize>,
+    /// Its --top-per-file most frequent words; empty without it
+    pub top_words: Vec<WordCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GapSummary {
+    /// Timestamp of the line before the gap (Unix millis)
+    pub from_ms: i64,
+    /// Timestamp of the line that ended it
+    pub to_ms: i64,
+    pub duration_ms: i64,
+    pub line: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LevelCount {
+    pub level: &'static str,
+    pub count: usize,
+    /// Of all lines scanned
+    pub share: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AccessReport {
+    pub requests: usize,
+    /// Requests per status code, most first
+    pub statuses: Vec<StatusCount>,
+    /// Paths, without query strings, with the most requests
+    pub top_paths: Vec<PathCount>,
+    /// Distinct paths, without query strings
+    pub distinct_paths: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct StatusCount {
+    pub status: u16,
+    pub count: usize,
+    /// Of all requests
+    pub share: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PathCount {
+    pub path: String,
+    pub requests: usize,
+    /// Of those, answered with a 5xx status
+    pub server_errors: usize,
+    /// Response bytes sent
+    pub bytes: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SyslogReport {
+    /// Lines per facility, most first; only lines with a <PRI> have one
+    pub facilities: Vec<FacilityCount>,
+    /// Apps (syslog tags) with the most lines
+    pub top_apps: Vec<AppCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FacilityCount {
+    /// kern, user, mail, daemon, auth, ..., local7
+    pub facility: &'static str,
+    pub count: usize,
+    /// Lines per severity, emerg first, leaving out those with none
+    pub severities: Vec<SeverityCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SeverityCount {
+    /// emerg, alert, crit, err, warning, notice, info or debug
+    pub severity: &'static str,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AppCount {
+    pub app: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SecurityReport {
+    pub events: usize,
+    /// Events per severity band, Low first, leaving out those with none
+    pub severities: Vec<BandCount>,
+    /// Event names (LEEF event ids) with the most events
+    pub top_events: Vec<EventCount>,
+    /// Source addresses (src) with the most events
+    pub top_sources: Vec<EventCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BandCount {
+    /// Low, Medium, High or Very-High
+    pub severity: &'static str,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct EventCount {
+    /// An event name, a source address or a --count-by value
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SkippedFile {
+    pub name: String,
+    pub bytes: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PayloadCount {
+    pub encoding: &'static str,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TemplateCount {
+    pub template: String,
+    pub count: usize,
+    /// "file:line: text" of its first line
+    pub example: String,
+    /// One entry per `<*>`, left to right
+    pub variables: Vec<TemplateVariable>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TraceCount {
+    /// `java.lang.NullPointerException`, `ValueError`, ...
+    pub exception: String,
+    /// Without line numbers, the frame nearest the throw first
+    pub frames: Vec<String>,
+    pub count: usize,
+    /// "file:line" of the first entry with it
+    pub example: String,
+    /// The exception message there
+    pub message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RareTemplate {
+    pub template: String,
+    pub count: usize,
+    /// "file:line: text" of its first line
+    pub first_seen: String,
+}
+
+/// The values that filled one placeholder of a template.
+#[derive(Serialize, JsonSchema)]
+pub struct TemplateVariable {
+    /// 1-based among the template's placeholders
+    pub position: usize,
+    /// Distinct values kept (at most 1000)
+    pub distinct: usize,
+    /// Lines whose value was past the distinct limit
+    pub other: usize,
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NgramCounts {
+    /// Words per phrase
+    pub words: usize,
+    pub top: Vec<WordCount>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct WordCount {
+    pub word: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SyscallError {
+    pub name: &'static str,
+    pub count: usize,
+    pub files: Vec<String>,
+}
+
+impl Report {
+    pub fn new(folder: &str, totals: ScanTotals, config: &Config) -> Self {
+        let timings = config
+            .shows(Section::Timings)
+            .then(|| timings_report(&totals));
+        let histogram = config
+            .histogram
+            .map(|width| histogram_report(&totals, width));
+        let spikes = match (config.spike_factor, config.bucket_width()) {
+            (Some(factor), Some(width)) => Some(spikes_report(
+                &histogram_report(&totals, width),
+                factor,
+                config.find.is_some(),
+            )),
+            _ => None,
+        };
+        let hit_timeline = match (&config.find, config.bucket_width()) {
+            (Some(_), Some(width)) => timeline_report(&totals, width),
+            _ => None,
+        };
+        let trace_id = config
+            .trace_id
+            .as_ref()
+            .map(|pattern| trace_id_report(pattern.as_str(), &totals.trace_id));
+        let distinct_words_estimate = totals.distinct_words.estimate();
+        let top_words = top_counts(totals.words(), config.top_words)
+            .into_iter()
+            .map(|(word, count)| WordCount {
+                word: word.to_string(),
+                count,
+            })
+            .collect();
+        // Approximate counts keep only the likely top words by name
+        let bottom_words = if totals.approx_words.is_some() {
+            Vec::new()
+        } else {
+            bottom_counts(totals.words(), config.bottom_words)
+                .into_iter()
+                .map(|(word, count)| WordCount {
+                    word: word.to_string(),
+                    count,
+                })
+                .collect()
+        };
+        let top_ngrams = config
+            .ngrams
+            .iter()
+            .zip(&totals.ngrams)
+            .map(|(&words, counts)| NgramCounts {
+                words,
+                top: top_counts(counts.counts(), config.top_words)
+                    .into_iter()
+                    .map(|(word, count)| WordCount {
+                        word: word.to_string(),
+                        count,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut syscall_errors: Vec<SyscallError> = totals
+            .errno_stats
+            .into_iter()
+            .map(|(name, stat)| SyscallError {
+                name,
+                count: stat.count,
+                files: stat.files,
+            })
+            .collect();
+        syscall_errors.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(b.name)));
+
+        let mut nested_payloads: Vec<PayloadCount> = totals
+            .decoded
+            .into_iter()
+            .map(|(enc, count)| PayloadCount {
+                encoding: enc.name(),
+                count,
+            })
+            .collect();
+        nested_payloads.sort_by_key(|p| p.encoding);
+
+        let access =
+            (config.line_format == LineFormat::Access).then(|| access_report(&totals.access));
+        let syslog =
+            (config.line_format == LineFormat::Syslog).then(|| syslog_report(&totals.syslog));
+        let security =
+            (config.line_format == LineFormat::Cef).then(|| security_report(&totals.security));
+        let groups = config
+            .group_by
+            .as_ref()
+            .map(|key| group_report(key, totals.groups));
+        let counts = config
+            .count_by
+            .iter()
+            .zip(&totals.counts)
+            .map(|(pattern, tally)| count_report(pattern, tally, config.count_top))
+            .collect();
+        let distinct = config
+            .distinct
+            .iter()
+            .zip(&totals.distinct)
+            .map(|(field, tally)| distinct_report(field, tally))
+            .collect();
+        let mut repeated_lines: Vec<LineCount> = totals
+            .repeats
+            .lines
+            .iter()
+            .filter(|&(_, &count)| count > 1)
+            .map(|(line, &count)| LineCount {
+                line: line.clone(),
+                count,
+            })
+            .collect();
+        repeated_lines.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.line.cmp(&b.line)));
+        repeated_lines.truncate(TOP_REPEATS);
+        let sessions = config
+            .session_key
+            .as_ref()
+            .map(|key| session_report(key, &totals.sessions, &totals.templates.clusters));
+
+        let mut clusters = totals.templates.clusters;
+        clusters.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tokens.cmp(&b.tokens)));
+        let top_templates = clusters
+            .iter()
+            .take(TOP_TEMPLATES)
+            .map(|c| TemplateCount {
+                template: c.template(),
+                count: c.count,
+                example: c.first_seen.clone(),
+                variables: template_variables(c),
+            })
+            .collect();
+        // --rare N lists the N rarest however common they are
+        let (rare_max, rare_listed) = match config.rare {
+            Some(n) => (usize::MAX, n),
+            None => (RARE_MAX_COUNT, TOP_RARE),
+        };
+        let mut rare_templates: Vec<RareTemplate> = clusters
+            .iter()
+            .rev()
+            .take_while(|c| c.count <= rare_max)
+            .map(|c| RareTemplate {
+                template: c.template(),
+                count: c.count,
+                first_seen: c.first_seen.clone(),
+            })
+            .collect();
+        rare_templates.sort_by(|a, b| a.count.cmp(&b.count).then(a.template.cmp(&b.template)));
+        rare_templates.truncate(rare_listed);
+
+        let distinct_traces = totals.traces.traces.len();
+        let mut traces: Vec<TraceStat> = totals.traces.traces.into_values().collect();
+        traces.sort_by(|a, b| b.count.cmp(&a.count).then(a.example.cmp(&b.example)));
+        let top_traces = traces
+            .into_iter()
+            .take(TOP_TRACES)
+            .map(|t| TraceCount {
+                exception: t.exception,
+                frames: t.frames,
+                count: t.count,
+                example: t.example,
+                message: t.message,
+            })
+            .collect();
+
+        let source_linker = config.source_link_template.as_deref().map(Linker::new);
+        let mut source_locations: Vec<SourceLocation> = totals
+            .origins
+            .into_iter()
+            .map(|((path, line), tally)| SourceLocation {
+                url: source_linker.as_ref().map(|l| l.source_url(&path, line)),
+                path,
+                line,
+                lines: tally.lines,
+                errors: tally.errors,
+            })
+            .collect();
+        source_locations.sort_by(|a, b| {
+            (b.errors, b.lines)
+                .cmp(&(a.errors, a.lines))
+                .then_with(|| (&a.path, a.line).cmp(&(&b.path, b.line)))
+        });
+        source_locations.truncate(TOP_SOURCES);
+
+        let clock_skew = config.skew_key.as_ref().and_then(|key| {
+            let (reference, estimates) = totals.skew.estimate()?;
+            let mut files: Vec<FileSkew> = estimates
+                .into_iter()
+                .map(|e| FileSkew {
+                    file: totals.files[e.file].name.clone(),
+                    offset_ms: e.offset_ms,
+                    samples: e.samples,
+                })
+                .collect();
+            files.sort_by(|a, b| a.file.cmp(&b.file));
+            Some(ClockSkew {
+                key: key.clone(),
+                reference: totals.files[reference].name.clone(),
+                files,
+            })
+        });
+
+        let distinctive_words = distinctive_words(&totals.files);
+        let rollups = Rollups {
+            by_directory: rollup(&totals.files, |name| {
+                name.split_once('/')
+                    .map_or(".", |(folder, _)| folder)
+                    .to_string()
+            }),
+            by_extension: rollup(&totals.files, rotation::extension),
+            rotated: rotated_logs(&totals.files),
+        };
+        let mut files: Vec<FileSummary> = totals
+            .files
+            .into_iter()
+            .map(|f| FileSummary {
+                name: f.name,
+                lines: f.lines,
+                bytes: f.bytes,
+                hits: f.hits,
+                out_of_order: f.order.backwards.count,
+                max_regression_ms: f.order.max_regression_ms,
+                out_of_order_samples: f.order.backwards.samples,
+                changed_while_read: f.changed,
+                longest_gaps: f
+                    .order
+                    .longest_gaps
+                    .iter()
+                    .map(|g| GapSummary {
+                        from_ms: g.from_ms,
+                        to_ms: g.to_ms,
+                        duration_ms: g.ms(),
+                        line: g.line_number,
+                    })
+                    .collect(),
+                long_gaps: f.order.long_gaps,
+                first_hit: f.hit_lines.map(|(first, _)| first),
+                last_hit: f.hit_lines.map(|(_, last)| last),
+                top_words: f
+                    .top_words
+                    .into_iter()
+                    .take(config.top_per_file)
+                    .map(|(word, count)| WordCount { word, count })
+                    .collect(),
+                first_timestamp_ms: f.order.first,
+                last_timestamp_ms: f.order.last,
+            })
+            .collect();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut checks: Vec<CheckResult> = config
+            .checks
+            .iter()
+            .zip(totals.checks)
+            .map(|(check, tally)| CheckResult {
+                name: check.name(),
+                count: tally.count,
+                max: check.max,
+                passed: tally.count <= check.max,
+                samples: tally.samples,
+            })
+            .collect();
+        if let (Some(max), Some(find)) = (config.max_hits, &config.find) {
+            checks.push(CheckResult {
+                name: format!("at most {} hits for '{}'", max, find),
+                count: totals.hits,
+                max,
+                passed: totals.hits <= max,
+                samples: totals.hit_samples.samples,
+            });
+        }
+
+        let matches = totals
+            .matches
+            .into_iter()
+            .map(|m| MatchRecord {
+                rule_id: rule_id(m.rule),
+                file: m.file,
+                line: m.line_number,
+                text: m.text,
+            })
+            .collect();
+
+        let field_stats = config
+            .stats_fields
+            .iter()
+            .zip(totals.field_stats)
+            .map(|(key, stats)| field_report(&key.describe(), &stats))
+            .collect();
+
+        let leveled: usize = totals.levels.iter().sum::<usize>() + totals.unleveled;
+        let levels = Level::ALL
+            .iter()
+            .rev()
+            .map(|&level| {
+                let count = totals.levels[level.index()];
+                LevelCount {
+                    level: level.name(),
+                    count,
+                    share: if leveled > 0 {
+                        count as f64 / leveled as f64
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+
+        let mut skipped_files: Vec<SkippedFile> = totals
+            .skipped_files
+            .into_iter()
+            .map(|(name, bytes)| SkippedFile { name, bytes })
+            .collect();
+        skipped_files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Report {
+            schema_version: SCHEMA_VERSION,
+            folder: folder.to_string(),
+            partial: totals.stopped.is_some(),
+            stopped_by: totals.stopped.map(Stop::name),
+            files_scanned: totals.files_scanned,
+            total_lines: totals.total_lines,
+            total_bytes: totals.total_bytes,
+            files_cached: totals.files_cached,
+            truncated_records: totals.truncated_records.count,
+            truncated_record_samples: totals.truncated_records.samples,
+            long_lines: totals.long_lines.count,
+            long_line_samples: totals.long_lines.samples,
+            skipped_files,
+            levels,
+            lines_without_level: totals.unleveled,
+            lines_below_min_level: totals.below_min_level,
+            lines_unparsed: totals.unparsed,
+            continuation_lines: totals.continuation_lines,
+            files_transcoded: totals.files_transcoded,
+            lines_outside_window: totals.outside_window,
+            files_before_window: totals.files_before_window,
+            find: config.find.clone(),
+            hits: totals.hits,
+            files,
+            nested_payloads,
+            top_words,
+            bottom_words,
+            top_ngrams,
+            words_approximate: totals.approx_words.is_some(),
+            distinct_words_estimate,
+            distinctive_words,
+            rollups,
+            top_templates,
+            repeated_lines,
+            repeats_undercount: totals.repeats.undercount,
+            rare_templates,
+            top_traces,
+            distinct_traces,
+            syscall_errors,
+            checks,
+            field_stats,
+            source_locations,
+            clock_skew,
+            sessions,
+            groups,
+            counts,
+            distinct,
+            access,
+            syslog,
+            security,
+            max_gap_ms: config.max_gap.map(|gap| gap.as_millis() as i64),
+            histogram,
+            spikes,
+            trace_id,
+            hit_timeline,
+            timings,
+            matches,
+        }
+    }
+
+    pub fn failed_checks(&self) -> usize {
+        self.checks.iter().filter(|c| !c.passed).count()
+    }
+}
+
+fn access_report(tally: &AccessTally) -> AccessReport {
+    let share = |count: usize| {
+        if tally.requests > 0 {
+            count as f64 / tally.requests as f64
+        } else {
+            0.0
+        }
+    };
+    let mut statuses: Vec<StatusCount> = tally
+        .statuses
+        .iter()
+        .map(|(&status, &count)| StatusCount {
+            status,
+            count,
+            share: share(count),
+        })
+        .collect();
+    statuses.sort_by(|a, b| b.count.cmp(&a.count).then(a.status.cmp(&b.status)));
+    let mut paths: Vec<(&String, &PathStat)> = tally.paths.iter().collect();
+    paths.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then(a.0.cmp(b.0)));
+    let top_paths = paths
+        .into_iter()
+        .take(TOP_PATHS)
+        .map(|(path, stat)| PathCount {
+            path: path.clone(),
+            requests: stat.requests,
+            server_errors: stat.server_errors,
+            bytes: stat.bytes,
+        })
+        .collect();
+    AccessReport {
+        requests: tally.requests,
+        statuses,
+        top_paths,
+        distinct_paths: tally.paths.len(),
+    }
+}
+
+fn syslog_report(tally: &SyslogTally) -> SyslogReport {
+    let mut facilities: Vec<FacilityCount> = tally
+        .facilities
+        .iter()
+        .map(|(&code, counts)| FacilityCount {
+            facility: syslog::FACILITIES[code as usize],
+            count: counts.iter().sum(),
+            severities: syslog::SEVERITIES
+                .iter()
+                .zip(counts)
+                .filter(|(_, &count)| count > 0)
+                .map(|(&severity, &count)| SeverityCount { severity, count })
+                .collect(),
+        })
+        .collect();
+    facilities.sort_by(|a, b| b.count.cmp(&a.count).then(a.facility.cmp(b.facility)));
+    let top_apps = top_counts(
+        tally.apps.iter().map(|(app, &count)| (app.as_str(), count)),
+        TOP_APPS,
+    )
+    .into_iter()
+    .map(|(app, count)| AppCount {
+        app: app.to_string(),
+        count,
+    })
+    .collect();
+    SyslogReport {
+        facilities,
+        top_apps,
+    }
+}
+
+fn security_report(tally: &SecurityTally) -> SecurityReport {
+    let top = |counts: &HashMap<String, usize>| {
+        top_counts(counts.iter().map(|(v, &c)| (v.as_str(), c)), TOP_EVENTS)
+            .into_iter()
+            .map(|(value, count)| EventCount {
+                value: value.to_string(),
+                count,
+            })
+            .collect()
+    };
+    SecurityReport {
+        events: tally.events,
+        severities: cef::SEVERITIES
+            .iter()
+            .zip(tally.severities)
+            .filter(|(_, count)| *count > 0)
+            .map(|(&severity, count)| BandCount { severity, count })
+            .collect(),
+        top_events: top(&tally.names),
+        top_sources: top(&tally.sources),
+    }
+}
+
+/// The --histogram buckets from the first to the last, with the empty ones
+/// in between unless there would be too many.
+fn histogram_report(totals: &ScanTotals, width: Duration) -> Histogram {
+    let width_ms = (width.as_millis() as i64).max(1);
+    let bucket = |(&start, b): (&i64, &Bucket)| HistogramBucket {
+        start_ms: start,
+        lines: b.lines,
+        hits: b.hits,
+    };
+    let span = match (
+        totals.histogram.keys().next(),
+        totals.histogram.keys().last(),
+    ) {
+        (Some(first), Some(last)) => Some((*first, *last)),
+        _ => None,
+    };
+    let buckets = match span {
+        Some((first, last)) if (last - first) / width_ms < MAX_HISTOGRAM_BUCKETS => {
+            let empty = Bucket::default();
+            (0..=(last - first) / width_ms)
+                .map(|i| first + i * width_ms)
+                .map(|start| bucket((&start, totals.histogram.get(&start).unwrap_or(&empty))))
+                .collect()
+        }
+        _ => totals.histogram.iter().map(bucket).collect(),
+    };
+    Histogram {
+        bucket_ms: width_ms,
+        buckets,
+    }
+}
+
+fn trace_id_report(pattern: &str, traced: &TraceIdLines) -> TraceIdReport {
+    let files: HashSet<&str> = traced.lines.iter().map(|l| l.file.as_str()).collect();
+    TraceIdReport {
+        pattern: pattern.to_string(),
+        files: files.len(),
+        dropped: traced.dropped,
+        lines: traced
+            .by_time()
+            .into_iter()
+            .map(|l| TracedLineRecord {
+                time_ms: l.time_ms,
+                file: l.file.clone(),
+                line: l.line_number,
+                text: l.line.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// The hits of the time buckets, gathered into at most TIMELINE_COLUMNS
+/// spans of whole buckets; None without timestamps.
+fn timeline_report(totals: &ScanTotals, width: Duration) -> Option<Timeline> {
+    let width_ms = (width.as_millis() as i64).max(1);
+    let first = *totals.histogram.keys().next()?;
+    let last = *totals.histogram.keys().last()?;
+    let buckets = (last - first) / width_ms + 1;
+    let per_span = (buckets + TIMELINE_COLUMNS - 1) / TIMELINE_COLUMNS;
+    let span_ms = per_span * width_ms;
+    let mut hits = vec![0; ((buckets + per_span - 1) / per_span) as usize];
+    for (start, bucket) in &totals.histogram {
+        hits[((start - first) / span_ms) as usize] += bucket.hits;
+    }
+    Some(Timeline {
+        start_ms: first,
+        span_ms,
+        hits,
+    })
+}
+
+/// The buckets of `histogram` far above its median: hits with --find,
+/// lines without.
+fn spikes_report(histogram: &Histogram, factor: f64, hits: bool) -> Spikes {
+    let count = |b: &HistogramBucket| if hits { b.hits } else { b.lines };
+    let mut counts: Vec<usize> = histogram.buckets.iter().map(count).collect();
+    counts.sort_unstable();
+    let median = match counts.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => counts[n / 2] as f64,
+        n => (counts[n / 2 - 1] + counts[n / 2]) as f64 / 2.0,
+    };
+    // Mostly quiet buckets make a median of 0, which everything is above
+    let baseline = median.max(1.0);
+    let buckets = histogram
+        .buckets
+        .iter()
+        .filter(|b| count(b) as f64 > factor * baseline)
+        .map(|b| Spike {
+            start_ms: b.start_ms,
+            count: count(b),
+            ratio: count(b) as f64 / baseline,
+        })
+        .collect();
+    Spikes {
+        bucket_ms: histogram.bucket_ms,
+        factor,
+        counted: if hits { "hits" } else { "lines" },
+        median,
+        buckets,
+    }
+}
+
+fn timings_report(totals: &ScanTotals) -> Timings {
+    let t = &totals.timings;
+    let phases = [
+        ("listing", t.listing),
+        ("reading", t.reading),
+        ("tokenizing", t.tokenizing),
+        ("searching", t.searching),
+        ("templates", t.templates),
+        ("other", t.other),
+    ];
+    let all: f64 = phases.iter().map(|(_, d)| d.as_secs_f64()).sum();
+    let seconds = t.wall.as_secs_f64().max(f64::MIN_POSITIVE);
+    Timings {
+        wall_ms: seconds * 1000.0,
+        mb_per_second: totals.total_bytes as f64 / 1e6 / seconds,
+        lines_per_second: totals.total_lines as f64 / seconds,
+        phases: phases
+            .into_iter()
+            .map(|(phase, d)| PhaseTime {
+                phase,
+                ms: d.as_secs_f64() * 1000.0,
+                share: if all > 0.0 {
+                    d.as_secs_f64() / all
+                } else {
+                    0.0
+                },
+            })
+            .collect(),
+        resources: ResourceUsage::current(),
+        peak_estimated_bytes: totals.peak_memory_estimate,
+    }
+}
+
+fn template_variables(cluster: &Cluster) -> Vec<TemplateVariable> {
+    cluster
+        .tokens
+        .iter()
+        .zip(&cluster.values)
+        .filter(|(token, _)| drain::is_variable(token))
+        .enumerate()
+        .map(|(i, (_, values))| TemplateVariable {
+            position: i + 1,
+            distinct: values.counts.len(),
+            other: values.other,
+            top: top_counts(
+                values.counts.iter().map(|(v, c)| (v.as_str(), *c)),
+                TOP_VALUES,
+            )
+            .into_iter()
+            .map(|(value, count)| ValueCount {
+                value: value.to_string(),
+                count,
+            })
+            .collect(),
+        })
+        .collect()
+}
+
+fn group_report(key: &FieldKey, tally: GroupTally) -> GroupReport {
+    let distinct = tally.groups.len();
+    let mut groups: Vec<(String, GroupStat)> = tally.groups.into_iter().collect();
+    groups.sort_by(|a, b| b.1.lines.cmp(&a.1.lines).then_with(|| a.0.cmp(&b.0)));
+    let other = tally.other.lines
+        + groups
+            .iter()
+            .skip(TOP_GROUPS)
+            .map(|(_, stat)| stat.lines)
+            .sum::<usize>();
+    GroupReport {
+        by: key.describe(),
+        groups: groups
+            .into_iter()
+            .take(TOP_GROUPS)
+            .map(|(value, stat)| GroupCount {
+                value,
+                lines: stat.lines,
+                warnings: stat.warnings,
+                errors: stat.errors,
+            })
+            .collect(),
+        distinct,
+        other,
+        ungrouped: tally.ungrouped,
+    }
+}
+
+/// The files' counts added up per `key` of their names, most bytes first.
+fn rollup(files: &[FileStats], key: impl Fn(&str) -> String) -> Vec<Rollup> {
+    let mut rollups: HashMap<String, Rollup> = HashMap::new();
+    for file in files {
+        let name = key(&file.name);
+        let rollup = rollups.entry(name.clone()).or_insert_with(|| Rollup {
+            name,
+            files: 0,
+            lines: 0,
+            bytes: 0,
+            hits: 0,
+        });
+        rollup.files += 1;
+        rollup.lines += file.lines;
+        rollup.bytes += file.bytes;
+        rollup.hits += file.hits;
+    }
+    let mut rollups: Vec<Rollup> = rollups.into_values().collect();
+    rollups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    rollups
+}
+
+/// The rotated sets among `files` by name, the files of each oldest first
+/// as they were read.
+fn rotated_logs(files: &[FileStats]) -> Vec<RotatedLog> {
+    let mut logs: Vec<RotatedLog> = Vec::new();
+    let mut at: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        let (name, _) = rotation::stream(&file.name);
+        let i = *at.entry(name.clone()).or_insert_with(|| {
+            logs.push(RotatedLog {
+                name,
+                files: Vec::new(),
+                lines: 0,
+                bytes: 0,
+                hits: 0,
+                first_timestamp_ms: None,
+                last_timestamp_ms: None,
+            });
+            logs.len() - 1
+        });
+        let log = &mut logs[i];
+        log.files.push(file.name.clone());
+        log.lines += file.lines;
+        log.bytes += file.bytes;
+        log.hits += file.hits;
+        log.first_timestamp_ms = match (log.first_timestamp_ms, file.order.first) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        log.last_timestamp_ms = log.last_timestamp_ms.max(file.order.last);
+    }
+    logs.retain(|log| log.files.len() > 1);
+    logs.sort_by(|a, b| a.name.cmp(&b.name));
+    logs
+}
+
+/// Per file, the words of its top list scoring highest by TF-IDF: the
+/// share of the file's words they make up, times ln(files / files listing
+/// them), so words every file has score nothing. The files whose best word
+/// scores highest come first.
+fn distinctive_words(files: &[FileStats]) -> Vec<FileWords> {
+    if files.len() < 2 {
+        return Vec::new();
+    }
+    let mut listed_in: HashMap<&str, usize> = HashMap::new();
+    for file in files {
+        for (word, _) in &file.top_words {
+            *listed_in.entry(word.as_str()).or_insert(0) += 1;
+        }
+    }
+    let n = files.len() as f64;
+    let mut per_file: Vec<FileWords> = files
+        .iter()
+        .filter(|f| f.words > 0)
+        .map(|file| {
+            let mut words: Vec<WordScore> = file
+                .top_words
+                .iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(word, count)| WordScore {
+                    word: word.clone(),
+                    count: *count,
+                    tf_idf: *count as f64 / file.words as f64
+                        * (n / listed_in[word.as_str()] as f64).ln(),
+                })
+                .filter(|w| w.tf_idf > 0.0)
+                .collect();
+            words.sort_by(|a, b| {
+                b.tf_idf
+                    .total_cmp(&a.tf_idf)
+                    .then_with(|| a.word.cmp(&b.word))
+            });
+            words.truncate(DISTINCTIVE_WORDS);
+            FileWords {
+                file: file.name.clone(),
+                words,
+            }
+        })
+        .filter(|f| !f.words.is_empty())
+        .collect();
+    per_file.sort_by(|a, b| {
+        b.words[0]
+            .tf_idf
+            .total_cmp(&a.words[0].tf_idf)
+            .then_with(|| a.file.cmp(&b.file))
+    });
+    per_file.truncate(DISTINCTIVE_FILES);
+    per_file
+}
+
+fn count_report(pattern: &Regex, tally: &CountTally, k: usize) -> CountReport {
+    let top: Vec<EventCount> = top_counts(tally.values.iter().map(|(v, &c)| (v.as_str(), c)), k)
+        .into_iter()
+        .map(|(value, count)| EventCount {
+            value: value.to_string(),
+            count,
+        })
+        .collect();
+    let matches = tally.values.values().sum::<usize>() + tally.other;
+    CountReport {
+        pattern: pattern.as_str().to_string(),
+        matches,
+        distinct: tally.values.len(),
+        other: matches - top.iter().map(|c| c.count).sum::<usize>(),
+        top,
+    }
+}
+
+fn distinct_report(field: &str, tally: &DistinctTally) -> DistinctReport {
+    let (values, exact) = tally.count();
+    let listed = |counts: Vec<(&str, usize)>| {
+        counts
+            .into_iter()
+            .map(|(value, count)| EventCount {
+                value: value.to_string(),
+                count,
+            })
+            .collect()
+    };
+    let most_common = listed(top_counts(
+        tally.values.iter().map(|(v, &c)| (v.as_str(), c)),
+        TOP_DISTINCT,
+    ));
+    // Values that aren't among the most common, fewest lines first
+    let least_common = if exact {
+        let mut counts: Vec<(&str, usize)> =
+            tally.values.iter().map(|(v, &c)| (v.as_str(), c)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        let rest = counts.len().saturating_sub(TOP_DISTINCT).min(TOP_DISTINCT);
+        listed(counts.into_iter().rev().take(rest).collect())
+    } else {
+        Vec::new()
+    };
+    DistinctReport {
+        field: field.to_string(),
+        values,
+        exact,
+        lines: tally.lines,
+        missing: tally.missing,
+        most_common,
+        least_common,
+    }
+}
+
+fn session_report(key: &str, tracker: &SessionTracker, clusters: &[Cluster]) -> SessionReport {
+    let labels: Vec<String> = clusters.iter().map(event_label).collect();
+    let events = |session: &Session| -> Vec<String> {
+        session.events.iter().map(|&e| labels[e].clone()).collect()
+    };
+    let mut report = SessionReport {
+        key: key.to_string(),
+        sessions: tracker.sessions.len(),
+        ended: 0,
+        failed: 0,
+        open: 0,
+        sequences: Vec::new(),
+        failures: Vec::new(),
+        timed: 0,
+        durations_ms: None,
+        lines: None,
+        longest: Vec::new(),
+    };
+
+    let mut sequences: HashMap<&[usize], (usize, usize)> = HashMap::new();
+    let mut failed = Vec::new();
+    for (id, session) in &tracker.sessions {
+        let ending = session.ending(|e| labels[e].as_str());
+        let tally = sequences.entry(&session.events).or_default();
+        tally.0 += 1;
+        match ending {
+            Ending::Ended => report.ended += 1,
+            Ending::Open => report.open += 1,
+            Ending::Failed => {
+                report.failed += 1;
+                tally.1 += 1;
+                failed.push((id, session));
+            }
+        }
+    }
+
+    let mut sequences: Vec<SessionSequence> = sequences
+        .into_iter()
+        .map(|(events, (sessions, failed))| SessionSequence {
+            events: events.iter().map(|&e| labels[e].clone()).collect(),
+            sessions,
+            failed,
+        })
+        .collect();
+    sequences.sort_by(|a, b| {
+        b.sessions
+            .cmp(&a.sessions)
+            .then_with(|| a.events.cmp(&b.events))
+    });
+    sequences.truncate(TOP_SEQUENCES);
+    report.sequences = sequences;
+
+    failed.sort_by_key(|(id, _)| *id);
+    report.failures = failed
+        .into_iter()
+        .take(TOP_FAILED_SESSIONS)
+        .map(|(id, session)| SessionFailure {
+            session: id.clone(),
+            lines: session.lines,
+            events: events(session),
+            last_line: session.failed_at.clone().unwrap_or_default(),
+        })
+        .collect();
+
+    report.lines = Percentiles::of(tracker.sessions.values().map(|s| s.lines as i64).collect());
+    let mut spans: Vec<SessionSpan> = tracker
+        .sessions
+        .iter()
+        .filter_map(|(id, session)| {
+            Some(SessionSpan {
+                session: id.clone(),
+                lines: session.lines,
+                start_ms: session.first_ms?,
+                end_ms: session.last_ms?,
+                duration_ms: session.duration_ms()?,
+            })
+        })
+        .collect();
+    report.timed = spans.len();
+    report.durations_ms = Percentiles::of(spans.iter().map(|s| s.duration_ms).collect());
+    spans.sort_by(|a, b| {
+        b.duration_ms
+            .cmp(&a.duration_ms)
+            .then_with(|| a.session.cmp(&b.session))
+    });
+    spans.truncate(TOP_LONGEST_SESSIONS);
+    report.longest = spans;
+    report
+}
+
+/// A template as a session event: without the leading timestamp
+/// placeholders and level word every line has.
+fn event_label(cluster: &Cluster) -> String {
+    let mut tokens = cluster
+        .tokens
+        .iter()
+        .skip_while(|t| t.as_str() == drain::WILDCARD)
+        .peekable();
+    if tokens.peek().is_some_and(|t| Level::from_word(t).is_some()) {
+        tokens.next();
+    }
+    let label: Vec<&str> = tokens.map(String::as_str).collect();
+    if label.is_empty() {
+        cluster.template()
+    } else {
+        label.join(" ")
+    }
+}
+
+/// The `k` highest counts, ties alphabetically so runs agree. Keeps only
+/// `k` entries at a time instead of sorting every distinct value.
+fn top_counts<'a>(
+    counts: impl Iterator<Item = (&'a str, usize)>,
+    k: usize,
+) -> Vec<(&'a str, usize)> {
+    // Max-heap on (fewest, last alphabetically): the top is the entry to drop
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (value, count) in counts {
+        heap.push((Reverse(count), value));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|(Reverse(count), value)| (value, count))
+        .collect()
+}
+
+/// The `k` entries with the lowest counts, fewest first (ties
+/// alphabetically).
+fn bottom_counts<'a>(
+    counts: impl Iterator<Item = (&'a str, usize)>,
+    k: usize,
+) -> Vec<(&'a str, usize)> {
+    // Max-heap on (most, last alphabetically): the top is the entry to drop
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (value, count) in counts {
+        heap.push((count, value));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|(count, value)| (value, count))
+        .collect()
+}
+
+fn field_report(name: &str, stats: &FieldStats) -> FieldReport {
+    let main = stats.main_kind();
+    let summary = main.and_then(|k| stats.by_kind.get(&k));
+
+    FieldReport {
+        name: name.to_string(),
+        kind: main.map(UnitKind::name),
+        unit: main.map(UnitKind::canonical_unit).unwrap_or(""),
+        count: summary.map(|s| s.count).unwrap_or(0),
+        min: summary.map(|s| s.min).unwrap_or(0.0),
+        max: summary.map(|s| s.max).unwrap_or(0.0),
+        mean: summary.map(|s| s.mean()).unwrap_or(0.0),
+        p50: summary.map(|s| s.percentile(0.5)).unwrap_or(0.0),
+        p95: summary.map(|s| s.percentile(0.95)).unwrap_or(0.0),
+        p99: summary.map(|s| s.percentile(0.99)).unwrap_or(0.0),
+        mismatched: stats
+            .by_kind
+            .iter()
+            .filter(|(kind, _)| Some(**kind) != main)
+            .map(|(kind, s)| KindCount {
+                kind: kind.name(),
+                count: s.count,
+            })
+            .collect(),
+        unparsed: stats.unparsed,
+    }
+}
+
+/// Stable identifier for a pattern, used by formats that name rules.
+pub fn rule_id(rule: Rule) -> String {
+    match rule {
+        Rule::Find => "find".to_string(),
+        Rule::Check(i) => format!("check-{}", i + 1),
+    }
+}
+
+/// Path of a scanned file relative to where logbuddy ran, with forward
+/// slashes as CI tools expect ("./logs" + "a.log" -> "logs/a.log").
+pub fn file_uri(folder: &str, file: &str) -> String {
+    let path = Path::new(folder).join(file);
+    let uri = path.to_string_lossy().replace('\\', "/");
+    uri.trim_start_matches("./").to_string()
+}
+
+/// A part of the text summary that can be turned on or off with
+/// --show / --hide. Sections always print in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Summary,
+    Levels,
+    Hits,
+    HitFiles,
+    TraceId,
+    Histogram,
+    Spikes,
+    Payloads,
+    Files,
+    Rollups,
+    Words,
+    Distinctive,
+    Syscalls,
+    Checks,
+    Stats,
+    Groups,
+    Counts,
+    Distinct,
+    Skew,
+    Access,
+    Syslog,
+    Security,
+    Order,
+    Gaps,
+    Sources,
+    Templates,
+    Repeats,
+    Rare,
+    Traces,
+    Sessions,
+    Timings,
+}
+
+impl Section {
+    pub const ALL: [Section; 31] = [
+        Section::Summary,
+        Section::Levels,
+        Section::Hits,
+        Section::HitFiles,
+        Section::TraceId,
+        Section::Histogram,
+        Section::Spikes,
+        Section::Payloads,
+        Section::Files,
+        Section::Rollups,
+        Section::Words,
+        Section::Distinctive,
+        Section::Templates,
+        Section::Repeats,
+        Section::Rare,
+        Section::Traces,
+        Section::Syscalls,
+        Section::Checks,
+        Section::Stats,
+        Section::Groups,
+        Section::Counts,
+        Section::Distinct,
+        Section::Skew,
+        Section::Sessions,
+        Section::Access,
+        Section::Syslog,
+        Section::Security,
+        Section::Order,
+        Section::Gaps,
+        Section::Sources,
+        Section::Timings,
+    ];
+
+    /// Shown unless hidden; the per-file table is opt-in because it has
+    /// one row per file, source locations because few logs name them,
+    /// rare templates because --rare asks for them, the histogram, spikes
+    /// and gaps because they need a bucket width, factor or limit to be
+    /// useful, and timings
+    /// because measuring them costs a little time itself.
+    pub const DEFAULT: [Section; 24] = [
+        Section::Summary,
+        Section::Levels,
+        Section::Hits,
+        Section::HitFiles,
+        Section::TraceId,
+        Section::Payloads,
+        Section::Rollups,
+        Section::Words,
+        Section::Distinctive,
+        Section::Templates,
+        Section::Repeats,
+        Section::Traces,
+        Section::Syscalls,
+        Section::Checks,
+        Section::Stats,
+        Section::Groups,
+        Section::Counts,
+        Section::Distinct,
+        Section::Skew,
+        Section::Sessions,
+        Section::Access,
+        Section::Syslog,
+        Section::Security,
+        Section::Order,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Summary => "summary",
+            Section::Levels => "levels",
+            Section::Histogram => "histogram",
+            Section::Spikes => "spikes",
+            Section::Hits => "hits",
+            Section::HitFiles => "hit-files",
+            Section::TraceId => "trace-id",
+            Section::Payloads => "payloads",
+            Section::Files => "files",
+            Section::Rollups => "rollups",
+            Section::Words => "words",
+            Section::Distinctive => "distinctive",
+            Section::Syscalls => "syscalls",
+            Section::Checks => "checks",
+            Section::Stats => "stats",
+            Section::Groups => "groups",
+            Section::Counts => "counts",
+            Section::Distinct => "distinct",
+            Section::Skew => "skew",
+            Section::Order => "order",
+            Section::Gaps => "gaps",
+            Section::Sources => "sources",
+            Section::Templates => "templates",
+            Section::Repeats => "repeats",
+            Section::Rare => "rare",
+            Section::Traces => "traces",
+            Section::Sessions => "sessions",
+            Section::Access => "access",
+            Section::Syslog => "syslog",
+            Section::Security => "security",
+            Section::Timings => "timings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Section> {
+        Section::ALL.iter().copied().find(|s| s.name() == name)
+    }
+}
+
+/// A line of the text summary, formatted like println!'s.
+macro_rules! outln {
+    ($out:expr) => {
+        $out.line(format_args!(""))
+    };
+    ($out:expr, $($arg:tt)*) => {
+        $out.line(format_args!($($arg)*))
+    };
+}
+
+/// Shared formatting for the text summary: colors and --human numbers.
+/// The summary builds up in `text`, to be written out in one go.
+struct Printer<'c> {
+    config: &'c Config,
+    style: Style,
+    text: RefCell<String>,
+}
+
+impl Printer<'_> {
+    fn line(&self, args: fmt::Arguments) {
+        let mut text = self.text.borrow_mut();
+        let _ = text.write_fmt(args);
+        text.push('\n');
+    }
+
+    fn count(&self, n: usize) -> String {
+        if self.config.human {
+            human::count(n)
+        } else {
+            n.to_string()
+        }
+    }
+
+    fn bytes(&self, n: usize) -> String {
+        if self.config.human {
+            human::bytes(n)
+        } else {
+            n.to_string()
+        }
+    }
+
+    fn header(&self, text: &str) -> String {
+        self.style.header(text)
+    }
+}
+
+/// The end-of-scan summary, one enabled section at a time.
+pub fn render_summary(report: &Report, config: &Config) -> String {
+    let out = Printer {
+        config,
+        style: Style::new(config.color, &config.theme),
+        text: RefCell::new(String::new()),
+    };
+    outln!(out);
+
+    if report.files_scanned == 0 {
+        if config.shows(Section::Summary) {
+            print_overview(report, &out);
+        }
+        outln!(
+            out,
+            "No .txt, .log or .evtx files found. Nothing to report."
+        );
+        return out.text.into_inner();
+    }
+
+    for section in Section::ALL {
+        if !config.shows(section) {
+            continue;
+        }
+        match section {
+            Section::Summary => print_overview(report, &out),
+            Section::Levels => print_levels(report, &out),
+            Section::Histogram => print_histogram(report, &out),
+            Section::Spikes => print_spikes(report, &out),
+            Section::Hits => print_hits(report, &out),
+            Section::HitFiles => print_hit_files(report, &out),
+            Section::TraceId => print_trace_id(report, &out),
+            Section::Payloads => print_payloads(report, &out),
+            Section::Files => print_files(report, &out),
+            Section::Rollups => print_rollups(report, &out),
+            Section::Words => print_words(report, &out),
+            Section::Distinctive => print_distinctive(report, &out),
+            Section::Syscalls => print_syscalls(report, &out),
+            Section::Checks => print_checks(report, &out),
+            Section::Stats => print_field_stats(report, &out),
+            Section::Groups => print_groups(report, &out),
+            Section::Counts => print_counts(report, &out),
+            Section::Distinct => print_distinct(report, &out),
+            Section::Skew => print_clock_skew(report, &out),
+            Section::Sessions => print_sessions(report, &out),
+            Section::Access => print_access(report, &out),
+            Section::Syslog => print_syslog(report, &out),
+            Section::Security => print_security(report, &out),
+            Section::Order => print_out_of_order(report, &out),
+            Section::Gaps => print_gaps(report, &out),
+            Section::Sources => print_sources(report, &out),
+            Section::Templates => print_templates(report, &out),
+            Section::Repeats => print_repeats(report, &out),
+            Section::Rare => print_rare(report, &out),
+            Section::Traces => print_traces(report, &out),
+            Section::Timings => print_timings(report, &out),
+        }
+    }
+    out.text.into_inner()
+}
+
+fn print_overview(report: &Report, out: &Printer) {
+    if report.partial {
+        let cause = match report.stopped_by {
+            Some("timeout") => "ran past --timeout",
+            Some("output closed") => "stopped when its output was closed",
+            _ => "was interrupted with Ctrl-C",
+        };
+        outln!(
+            out,
+            "{}",
+            out.header(&format!("Partial results: the scan {}", cause))
+        );
+    }
+    outln!(out, "Scanned folder   : {}", report.folder);
+    if report.files_cached > 0 {
+        outln!(
+            out,
+            "Files processed  : {} ({} from cache)",
+            out.count(report.files_scanned),
+            out.count(report.files_cached)
+        );
+    } else {
+        outln!(
+            out,
+            "Files processed  : {}",
+            out.count(report.files_scanned)
+        );
+    }
+    outln!(out, "Total lines read : {}", out.count(report.total_lines));
+    if out.config.multiline.is_some() {
+        outln!(
+            out,
+            "Log entries      : {} ({} lines continued one)",
+            out.count(report.total_lines - report.continuation_lines),
+            out.count(report.continuation_lines)
+        );
+    }
+    outln!(out, "Total bytes read : {}", out.bytes(report.total_bytes));
+    if report.files_transcoded > 0 {
+        outln!(
+            out,
+            "Not UTF-8        : {} files (read as UTF-16 or Latin-1, see --encoding)",
+            out.count(report.files_transcoded)
+        );
+    }
+    let first = report
+        .files
+        .iter()
+        .filter_map(|f| f.first_timestamp_ms)
+        .min();
+    let last = report
+        .files
+        .iter()
+        .filter_map(|f| f.last_timestamp_ms)
+        .max();
+    if let (Some(first), Some(last)) = (first, last) {
+        outln!(
+            out,
+            "Time range       : {} to {} (UTC)",
+            timestamp::format_millis(first),
+            timestamp::format_millis(last)
+        );
+    }
+    if report.truncated_records > 0 {
+        outln!(
+            out,
+            "Truncated records: {} (only partly parsed, see --max-record-bytes and --max-fields)",
+            out.count(report.truncated_records)
+        );
+        for sample in &report.truncated_record_samples {
+            outln!(out, "    {}", sample);
+        }
+    }
+    if report.lines_outside_window > 0 || report.files_before_window > 0 {
+        outln!(
+            out,
+            "Outside window   : {} lines, {} files (see --since, --until, --last)",
+            out.count(report.lines_outside_window),
+            out.count(report.files_before_window)
+        );
+    }
+    if report.lines_below_min_level > 0 {
+        outln!(
+            out,
+            "Below min level  : {} lines (see --min-level)",
+            out.count(report.lines_below_min_level)
+        );
+    }
+    if report.lines_unparsed > 0 {
+        outln!(
+            out,
+            "Not {:<13}: {} lines (read as text)",
+            out.config.line_format.name(),
+            out.count(report.lines_unparsed)
+        );
+    }
+    if report.long_lines > 0 {
+        outln!(
+            out,
+            "Long lines       : {} (cut to --max-line-length)",
+            out.count(report.long_lines)
+        );
+        for sample in &report.long_line_samples {
+            outln!(out, "    {}", sample);
+        }
+    }
+    if !report.skipped_files.is_empty() {
+        outln!(
+            out,
+            "Skipped files    : {} (larger than --max-filesize)",
+            out.count(report.skipped_files.len())
+        );
+        for file in report.skipped_files.iter().take(SKIPPED_SHOWN) {
+            outln!(out, "    {} ({})", file.name, out.bytes(file.bytes));
+        }
+        if report.skipped_files.len() > SKIPPED_SHOWN {
+            outln!(
+                out,
+                "    {} more",
+                report.skipped_files.len() - SKIPPED_SHOWN
+            );
+        }
+    }
+    let unstable: Vec<&FileSummary> = report
+        .files
+        .iter()
+        .filter(|f| f.changed_while_read.is_some())
+        .collect();
+    if !unstable.is_empty() {
+        outln!(
+            out,
+            "Unstable files   : {} (changed while being read, see --reread-unstable)",
+            out.count(unstable.len())
+        );
+        for f in unstable {
+            outln!(
+                out,
+                "    {} {}",
+                f.name,
+                f.changed_while_read.as_deref().unwrap_or("")
+            );
+        }
+    }
+}
+
+fn print_hits(report: &Report, out: &Printer) {
+    if let Some(find) = &report.find {
+        outln!(
+            out,
+            "Hits             : {} (for '{}')",
+            out.count(report.hits),
+            find
+        );
+    }
+    let Some(t) = &report.hit_timeline else {
+        return;
+    };
+    let sparkline = out.config.charts.sparkline(&t.hits);
+    if report.hits > 0 && !sparkline.is_empty() {
+        outln!(
+            out,
+            "Hits over time   : |{}| {} per column from {} (UTC)",
+            sparkline,
+            human::duration_ms(t.span_ms as u64),
+            timestamp::format_millis(t.start_ms)
+        );
+    }
+}
+
+/// The files with --find hits, most hits first.
+fn print_hit_files(report: &Report, out: &Printer) {
+    let mut files: Vec<&FileSummary> = report.files.iter().filter(|f| f.hits > 0).collect();
+    if report.find.is_none() || files.is_empty() {
+        return;
+    }
+    files.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.name.cmp(&b.name)));
+    let width = files
+        .iter()
+        .map(|f| f.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("file".len());
+    let line = |n: Option<usize>| n.map_or_else(|| "-".to_string(), |n| n.to_string());
+    outln!(out, "\n{}", out.header("Hits by file:"));
+    outln!(
+        out,
+        "    {:<width$} {:>8} {:>10} {:>10} {:>10}",
+        "file",
+        "hits",
+        "lines",
+        "first hit",
+        "last hit"
+    );
+    for f in files {
+        outln!(
+            out,
+            "    {:<width$} {:>8} {:>10} {:>10} {:>10}",
+            f.name,
+            out.count(f.hits),
+            out.count(f.lines),
+            line(f.first_hit),
+            line(f.last_hit)
+        );
+    }
+}
+
+/// The --trace-id lines from every file, by time.
+fn print_trace_id(report: &Report, out: &Printer) {
+    let Some(t) = &report.trace_id else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!(
+            "Trace '{}' ({} lines in {} files, by time):",
+            t.pattern,
+            out.count(t.lines.len() + t.dropped),
+            out.count(t.files)
+        ))
+    );
+    if t.lines.is_empty() {
+        outln!(out, "    No lines found");
+        return;
+    }
+    let locations: Vec<String> = t
+        .lines
+        .iter()
+        .map(|l| format!("{}:{}", l.file, l.line))
+        .collect();
+    let width = locations.iter().map(String::len).max().unwrap_or(0);
+    for (l, location) in t.lines.iter().zip(&locations) {
+        let time = l
+            .time_ms
+            .map_or_else(|| "-".to_string(), timestamp::format_millis);
+        // Padded outside the color codes, which would count as width
+        let padding = " ".repeat(width - location.len());
+        outln!(
+            out,
+            "    {:<23}  {}{}  {}",
+            time,
+            out.style.location(location),
+            padding,
+            l.text
+        );
+    }
+    if t.dropped > 0 {
+        outln!(out, "    {} more lines not kept", out.count(t.dropped));
+    }
+}
+
+fn print_payloads(report: &Report, out: &Printer) {
+    if !out.config.decode_nested {
+        return;
+    }
+    let parts: Vec<String> = report
+        .nested_payloads
+        .iter()
+        .map(|p| format!("{} {}", p.encoding, out.count(p.count)))
+        .collect();
+    if parts.is_empty() {
+        outln!(out, "Nested payloads  : none");
+    } else {
+        outln!(out, "Nested payloads  : {}", parts.join(", "));
+    }
+}
+
+/// One row per file, a rotated set's files (app.log, app.log.1, ...) as
+/// one row for the log they make up; with -v its files follow it, oldest
+/// first.
+fn print_files(report: &Report, out: &Printer) {
+    struct Row {
+        name: String,
+        lines: usize,
+        bytes: usize,
+        hits: usize,
+        first: Option<i64>,
+        last: Option<i64>,
+    }
+    let file_row = |f: &FileSummary, indent: &str| Row {
+        name: format!("{}{}", indent, f.name),
+        lines: f.lines,
+        bytes: f.bytes,
+        hits: f.hits,
+        first: f.first_timestamp_ms,
+        last: f.last_timestamp_ms,
+    };
+    let sets: HashMap<&str, &RotatedLog> = report
+        .rollups
+        .rotated
+        .iter()
+        .flat_map(|log| log.files.iter().map(move |name| (name.as_str(), log)))
+        .collect();
+    let by_name: HashMap<&str, &FileSummary> =
+        report.files.iter().map(|f| (f.name.as_str(), f)).collect();
+    let mut listed = HashSet::new();
+    let mut rows = Vec::new();
+    for f in &report.files {
+        let Some(log) = sets.get(f.name.as_str()) else {
+            rows.push(file_row(f, ""));
+            continue;
+        };
+        if !listed.insert(log.name.as_str()) {
+            continue;
+        }
+        rows.push(Row {
+            name: format!("{} ({} files)", log.name, log.files.len()),
+            lines: log.lines,
+            bytes: log.bytes,
+            hits: log.hits,
+            first: log.first_timestamp_ms,
+            last: log.last_timestamp_ms,
+        });
+        if out.config.verbosity > 0 {
+            rows.extend(
+                log.files
+                    .iter()
+                    .filter_map(|name| by_name.get(name.as_str()))
+                    .map(|member| file_row(member, "  ")),
+            );
+        }
+    }
+
+    let width = rows
+        .iter()
+        .map(|row| row.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("file".len());
+    let hits = report.find.is_some();
+    let times = rows.iter().any(|row| row.first.is_some());
+    let time = |ms: Option<i64>| ms.map_or_else(|| "-".to_string(), timestamp::format_millis);
+
+    outln!(out, "\n{}", out.header("Files:"));
+    let mut header = format!("    {:<width$} {:>10} {:>12}", "file", "lines", "bytes");
+    if hits {
+        header.push_str(&format!(" {:>8}", "hits"));
+    }
+    if times {
+        header.push_str(&format!(
+            "  {:<23}  {:<23}",
+            "first timestamp", "last timestamp"
+        ));
+    }
+    outln!(out, "{}", header.trim_end());
+    for r in &rows {
+        let mut row = format!(
+            "    {:<width$} {:>10} {:>12}",
+            r.name,
+            out.count(r.lines),
+            out.bytes(r.bytes)
+        );
+        if hits {
+            row.push_str(&format!(" {:>8}", out.count(r.hits)));
+        }
+        if times {
+            row.push_str(&format!("  {:<23}  {:<23}", time(r.first), time(r.last)));
+        }
+        outln!(out, "{}", row.trim_end());
+    }
+}
+
+/// Each rollup table, if it has more than one row.
+fn print_rollups(report: &Report, out: &Printer) {
+    let hits = report.find.is_some();
+    let tables = [
+        ("By folder:", &report.rollups.by_directory),
+        ("By extension:", &report.rollups.by_extension),
+    ];
+    for (title, rollups) in tables {
+        if rollups.len() < 2 {
+            continue;
+        }
+        let width = rollups
+            .iter()
+            .map(|r| r.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(10);
+        outln!(out, "\n{}", out.header(title));
+        let mut header = format!(
+            "    {:<width$} {:>8} {:>10} {:>12}",
+            "", "files", "lines", "bytes"
+        );
+        if hits {
+            header.push_str(&format!(" {:>8}", "hits"));
+        }
+        outln!(out, "{}", header);
+        for r in rollups {
+            let name = if r.name.is_empty() { "(none)" } else { &r.name };
+            let mut row = format!(
+                "    {:<width$} {:>8} {:>10} {:>12}",
+                name,
+                out.count(r.files),
+                out.count(r.lines),
+                out.bytes(r.bytes)
+            );
+            if hits {
+                row.push_str(&format!(" {:>8}", out.count(r.hits)));
+            }
+            outln!(out, "{}", row);
+        }
+    }
+    if report.rollups.rotated.is_empty() {
+        return;
+    }
+    let width = report
+        .rollups
+        .rotated
+        .iter()
+        .map(|log| log.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(10);
+    let time = |ms: Option<i64>| ms.map_or_else(|| "-".to_string(), timestamp::format_millis);
+    outln!(out, "\n{}", out.header("Rotated logs (oldest file first):"));
+    let mut header = format!(
+        "    {:<width$} {:>8} {:>10} {:>12}",
+        "", "files", "lines", "bytes"
+    );
+    if hits {
+        header.push_str(&format!(" {:>8}", "hits"));
+    }
+    outln!(out, "{}  {:<23}  last timestamp", header, "first timestamp");
+    for log in &report.rollups.rotated {
+        let mut row = format!(
+            "    {:<width$} {:>8} {:>10} {:>12}",
+            log.name,
+            out.count(log.files.len()),
+            out.count(log.lines),
+            out.bytes(log.bytes)
+        );
+        if hits {
+            row.push_str(&format!(" {:>8}", out.count(log.hits)));
+        }
+        outln!(
+            out,
+            "{}  {:<23}  {}",
+            row,
+            time(log.first_timestamp_ms),
+            time(log.last_timestamp_ms)
+        );
+        outln!(out, "      {}", log.files.join(", "));
+    }
+}
+
+fn print_words(report: &Report, out: &Printer) {
+    let header = if report.words_approximate {
+        "Top words (approximate):"
+    } else {
+        "Top words:"
+    };
+    outln!(out, "\n{}", out.header(header));
+    outln!(
+        out,
+        "Distinct words (approx): {}",
+        out.count(report.distinct_words_estimate as usize)
+    );
+    let most = report.top_words.first().map_or(0, |w| w.count);
+    for (i, w) in report.top_words.iter().enumerate() {
+        let bar = out.config.charts.bar(w.count, most, WORD_BAR);
+        let row = format!(
+            "{:>2}. {:<20} {:>10}  {}",
+            i + 1,
+            w.word,
+            out.count(w.count),
+            bar
+        );
+        outln!(out, "{}", row.trim_end());
+    }
+    let width = report
+        .files
+        .iter()
+        .filter(|f| !f.top_words.is_empty())
+        .map(|f| f.name.len())
+        .max();
+    if let Some(width) = width {
+        outln!(out, "\n{}", out.header("Top words per file:"));
+        for f in report.files.iter().filter(|f| !f.top_words.is_empty()) {
+            let words: Vec<String> = f
+                .top_words
+                .iter()
+                .map(|w| format!("{} ({})", w.word, out.count(w.count)))
+                .collect();
+            outln!(out, "    {:<width$}  {}", f.name, words.join(", "));
+        }
+    }
+    if !report.bottom_words.is_empty() {
+        outln!(out, "\n{}", out.header("Rarest words:"));
+        for (i, w) in report.bottom_words.iter().enumerate() {
+            outln!(out, "{:>2}. {:<20} {}", i + 1, w.word, out.count(w.count));
+        }
+    }
+    for n in &report.top_ngrams {
+        if n.top.is_empty() {
+            continue;
+        }
+        let name = if n.words == 2 { "bigrams" } else { "trigrams" };
+        outln!(out, "\n{}", out.header(&format!("Top {}:", name)));
+        let most = n.top.first().map_or(0, |w| w.count);
+        for (i, w) in n.top.iter().enumerate() {
+            let bar = out.config.charts.bar(w.count, most, WORD_BAR);
+            let row = format!(
+                "{:>2}. {:<30} {:>10}  {}",
+                i + 1,
+                w.word,
+                out.count(w.count),
+                bar
+            );
+            outln!(out, "{}", row.trim_end());
+        }
+    }
+}
+
+fn print_distinctive(report: &Report, out: &Printer) {
+    if report.distinctive_words.is_empty() {
+        return;
+    }
+    let width = report
+        .distinctive_words
+        .iter()
+        .map(|f| f.file.len())
+        .max()
+        .unwrap_or(0);
+    outln!(out, "\n{}", out.header("Distinctive words (TF-IDF):"));
+    for f in &report.distinctive_words {
+        let words: Vec<String> = f
+            .words
+            .iter()
+            .map(|w| format!("{} ({})", w.word, out.count(w.count)))
+            .collect();
+        outln!(out, "    {:<width$}  {}", f.file, words.join(", "));
+    }
+}
+
+fn print_templates(report: &Report, out: &Printer) {
+    if report.top_templates.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Top templates:"));
+    for t in &report.top_templates {
+        outln!(out, "    {:>8}  {}", out.count(t.count), t.template);
+        outln!(out, "    {:>8}    e.g. {}", "", t.example);
+        // Placeholders whose top values cover under a tenth of the lines
+        // (timestamps, ids, durations) would only be noise here
+        for v in t
+            .variables
+            .iter()
+            .filter(|v| v.top.iter().map(|c| c.count).sum::<usize>() * 10 >= t.count)
+        {
+            let mut values: Vec<String> = v
+                .top
+                .iter()
+                .map(|c| format!("{} ×{}", c.value, c.count))
+                .collect();
+            let more = v.distinct - v.top.len();
+            if v.other > 0 {
+                values.push(format!("{}+ more", more + 1));
+            } else if more > 0 {
+                values.push(format!("{} more", more));
+            }
+            outln!(
+                out,
+                "    {:>8}    ${}  {}",
+                "",
+                v.position,
+                values.join(", ")
+            );
+        }
+    }
+}
+
+fn print_repeats(report: &Report, out: &Printer) {
+    if report.repeated_lines.is_empty() {
+        return;
+    }
+    let title = if report.repeats_undercount > 0 {
+        format!(
+            "Top repeated lines (counts may be up to {} low):",
+            out.count(report.repeats_undercount as usize)
+        )
+    } else {
+        "Top repeated lines:".to_string()
+    };
+    outln!(out, "\n{}", out.header(&title));
+    for r in &report.repeated_lines {
+        outln!(out, "    {:>8}  {}", out.count(r.count as usize), r.line);
+    }
+}
+
+fn print_traces(report: &Report, out: &Printer) {
+    if report.top_traces.is_empty() {
+        return;
+    }
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!(
+            "Top exceptions ({} distinct):",
+            out.count(report.distinct_traces)
+        ))
+    );
+    for t in &report.top_traces {
+        outln!(out, "    {:>8}  {}", out.count(t.count), t.exception);
+        for frame in &t.frames {
+            outln!(out, "    {:>8}      at {}", "", frame);
+        }
+        if t.message.is_empty() {
+            outln!(out, "    {:>8}    e.g. {}", "", t.example);
+        } else {
+            outln!(out, "    {:>8}    e.g. {}: {}", "", t.example, t.message);
+        }
+    }
+}
+
+fn print_rare(report: &Report, out: &Printer) {
+    if report.rare_templates.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Rare templates:"));
+    for t in &report.rare_templates {
+        outln!(out, "    {:>8}  {}", out.count(t.count), t.template);
+        outln!(out, "    {:>8}    first: {}", "", t.first_seen);
+    }
+}
+
+fn print_levels(report: &Report, out: &Printer) {
+    if report.levels.iter().all(|l| l.count == 0) {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Levels:"));
+    for l in report.levels.iter().filter(|l| l.count > 0) {
+        let level = Level::from_word(l.level).expect("level names are level words");
+        outln!(
+            out,
+            "    {}{} {:>10} {:>5.1}%",
+            out.style.level(level, l.level),
+            " ".repeat(6 - l.level.len()),
+            out.count(l.count),
+            l.share * 100.0
+        );
+    }
+    if report.lines_without_level > 0 {
+        outln!(
+            out,
+            "    {:<6} {:>10}",
+            "(none)",
+            out.count(report.lines_without_level)
+        );
+    }
+}
+
+fn print_histogram(report: &Report, out: &Printer) {
+    let Some(h) = &report.histogram else {
+        return;
+    };
+    let width = human::duration_ms(h.bucket_ms as u64);
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!("Histogram ({} buckets, UTC):", width))
+    );
+    if h.buckets.is_empty() {
+        outln!(out, "    No timestamps found");
+        return;
+    }
+    // Down to the second only when some bucket starts between minutes
+    let time_len = if h.buckets.iter().all(|b| b.start_ms.rem_euclid(60_000) == 0) {
+        16
+    } else {
+        19
+    };
+    // With --find the bars show hits, which is what changes in an incident
+    let hits = report.find.is_some();
+    let charted = |b: &HistogramBucket| if hits { b.hits } else { b.lines };
+    let most = h.buckets.iter().map(charted).max().unwrap_or(0).max(1);
+    for b in &h.buckets {
+        let start = timestamp::format_millis(b.start_ms);
+        let bar = out.config.charts.bar(charted(b), most, HISTOGRAM_BAR);
+        let mut row = format!("    {}  {:>10}", &start[..time_len], out.count(b.lines));
+        if hits {
+            row.push_str(&format!(" {:>8} hits", out.count(b.hits)));
+        }
+        outln!(out, "{}", format!("{}  {}", row, bar).trim_end());
+    }
+}
+
+fn print_spikes(report: &Report, out: &Printer) {
+    let Some(s) = &report.spikes else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!(
+            "Spikes ({} per {} over {}x the median of {}):",
+            s.counted,
+            human::duration_ms(s.bucket_ms as u64),
+            s.factor,
+            format_number(s.median)
+        ))
+    );
+    if s.buckets.is_empty() {
+        outln!(out, "    None");
+        return;
+    }
+    let mut biggest: Vec<&Spike> = s.buckets.iter().collect();
+    biggest.sort_by(|a, b| b.count.cmp(&a.count).then(a.start_ms.cmp(&b.start_ms)));
+    for spike in biggest.iter().take(TOP_SPIKES) {
+        outln!(
+            out,
+            "    {}  {:>10} {}  {:.1}x",
+            &timestamp::format_millis(spike.start_ms)[..19],
+            out.count(spike.count),
+            s.counted,
+            spike.ratio
+        );
+    }
+    if s.buckets.len() > TOP_SPIKES {
+        outln!(out, "    {} more", s.buckets.len() - TOP_SPIKES);
+    }
+}
+
+fn print_syscalls(report: &Report, out: &Printer) {
+    if report.syscall_errors.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Syscall errors:"));
+    for e in &report.syscall_errors {
+        outln!(
+            out,
+            "    {:<16} {:>6}  {}",
+            e.name,
+            out.count(e.count),
+            e.files.join(", ")
+        );
+    }
+}
+
+fn print_checks(report: &Report, out: &Printer) {
+    if report.checks.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Checks:"));
+    for c in &report.checks {
+        let status = if c.passed { "PASS" } else { "FAIL" };
+        outln!(
+            out,
+            "    {}  {} ({} found)",
+            status,
+            c.name,
+            out.count(c.count)
+        );
+        if !c.passed {
+            for sample in &c.samples {
+                outln!(out, "          {}", sample);
+            }
+        }
+    }
+}
+
+fn print_groups(report: &Report, out: &Printer) {
+    let Some(g) = &report.groups else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!(
+            "{:<30} {:>10} {:>8} {:>8}",
+            format!("By {} ({}):", g.by, out.count(g.distinct)),
+            "lines",
+            "warn",
+            "error"
+        ))
+    );
+    for c in &g.groups {
+        outln!(
+            out,
+            "    {:<26} {:>10} {:>8} {:>8}",
+            c.value,
+            out.count(c.lines),
+            out.count(c.warnings),
+            out.count(c.errors)
+        );
+    }
+    if g.other > 0 {
+        outln!(
+            out,
+            "    {:<26} {:>10}",
+            format!("({} more)", out.count(g.distinct - g.groups.len())),
+            out.count(g.other)
+        );
+    }
+    if g.ungrouped > 0 {
+        outln!(out, "    {} lines without a value", out.count(g.ungrouped));
+    }
+}
+
+fn print_counts(report: &Report, out: &Printer) {
+    for c in &report.counts {
+        outln!(
+            out,
+            "\n{}",
+            out.header(&format!(
+                "Counted /{}/ ({} matches, {} distinct):",
+                c.pattern,
+                out.count(c.matches),
+                out.count(c.distinct)
+            ))
+        );
+        for v in &c.top {
+            outln!(out, "    {:>10}  {}", out.count(v.count), v.value);
+        }
+        if c.other > 0 {
+            outln!(
+                out,
+                "    {:>10}  ({} more)",
+                out.count(c.other),
+                out.count(c.distinct - c.top.len())
+            );
+        }
+    }
+}
+
+fn print_distinct(report: &Report, out: &Printer) {
+    for d in &report.distinct {
+        let values = if d.exact {
+            out.count(d.values as usize)
+        } else {
+            format!("~{}", out.count(d.values as usize))
+        };
+        outln!(
+            out,
+            "\n{}",
+            out.header(&format!(
+                "Distinct {}: {} values in {} lines",
+                d.field,
+                values,
+                out.count(d.lines)
+            ))
+        );
+        if d.missing > 0 {
+            outln!(out, "    {} lines without it", out.count(d.missing));
+        }
+        for (label, counts) in [
+            ("most common", &d.most_common),
+            ("least common", &d.least_common),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            let listed: Vec<String> = counts
+                .iter()
+                .map(|c| format!("{} ({})", c.value, out.count(c.count)))
+                .collect();
+            outln!(
+                out,
+                "    {:<13} {}",
+                format!("{}:", label),
+                listed.join(", ")
+            );
+        }
+    }
+}
+
+fn print_field_stats(report: &Report, out: &Printer) {
+    if report.field_stats.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Field stats:"));
+    for f in &report.field_stats {
+        let kind = match f.kind {
+            Some(kind) if f.unit.is_empty() => kind.to_string(),
+            Some(kind) => format!("{}, {}", kind, f.unit),
+            None => {
+                outln!(out, "    {:<20} no numeric values", f.name);
+                continue;
+            }
+        };
+        outln!(
+            out,
+            "    {:<20} count {}  min {}  max {}  mean {}  p50 {}  p95 {}  p99 {}  ({})",
+            f.name,
+            out.count(f.count),
+            format_number(f.min),
+            format_number(f.max),
+            format_number(f.mean),
+            format_number(f.p50),
+            format_number(f.p95),
+            format_number(f.p99),
+            kind
+        );
+
+        let mut notes: Vec<String> = f
+            .mismatched
+            .iter()
+            .map(|m| format!("{} {} values left out", out.count(m.count), m.kind))
+            .collect();
+        if f.unparsed > 0 {
+            notes.push(format!("{} values not numeric", out.count(f.unparsed)));
+        }
+        if !notes.is_empty() {
+            outln!(out, "    {:<20} {}", "", notes.join(", "));
+        }
+    }
+}
+
+fn print_clock_skew(report: &Report, out: &Printer) {
+    let Some(skew) = &report.clock_skew else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!(
+            "Clock skew (by {}, against {}):",
+            skew.key, skew.reference
+        ))
+    );
+    for f in &skew.files {
+        outln!(
+            out,
+            "    {:<20} {:+.1}s  ({} shared events)",
+            f.file,
+            f.offset_ms as f64 / 1000.0,
+            out.count(f.samples)
+        );
+    }
+}
+
+fn print_access(report: &Report, out: &Printer) {
+    let Some(a) = &report.access else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!("Requests ({}):", out.count(a.requests)))
+    );
+    if a.requests == 0 {
+        outln!(out, "    No access log lines found");
+        return;
+    }
+    for s in &a.statuses {
+        outln!(
+            out,
+            "    {}  {:>10} {:>5.1}%",
+            s.status,
+            out.count(s.count),
+            s.share * 100.0
+        );
+    }
+    outln!(
+        out,
+        "    {:<26} {:>10} {:>6} {:>11}",
+        format!("Top paths (of {}):", out.count(a.distinct_paths)),
+        "requests",
+        "5xx",
+        "bytes"
+    );
+    for p in &a.top_paths {
+        outln!(
+            out,
+            "    {:<26} {:>10} {:>6} {:>11}",
+            p.path,
+            out.count(p.requests),
+            out.count(p.server_errors),
+            out.bytes(p.bytes as usize)
+        );
+    }
+}
+
+fn print_syslog(report: &Report, out: &Printer) {
+    let Some(s) = &report.syslog else {
+        return;
+    };
+    if !s.facilities.is_empty() {
+        let mut header = format!("{:<23}", "Syslog facilities:");
+        for severity in syslog::SEVERITIES {
+            header.push_str(&format!(" {:>7}", severity));
+        }
+        outln!(out, "\n{}", out.header(&header));
+        for f in &s.facilities {
+            let mut row = format!("    {:<12} {:>6}", f.facility, out.count(f.count));
+            for severity in syslog::SEVERITIES {
+                let count = f
+                    .severities
+                    .iter()
+                    .find(|c| c.severity == severity)
+                    .map_or(0, |c| c.count);
+                row.push_str(&format!(" {:>7}", out.count(count)));
+            }
+            outln!(out, "{}", row);
+        }
+    }
+    if !s.top_apps.is_empty() {
+        outln!(out, "\n{}", out.header("Top syslog apps:"));
+        for a in &s.top_apps {
+            outln!(out, "    {:<20} {:>10}", a.app, out.count(a.count));
+        }
+    }
+}
+
+fn print_security(report: &Report, out: &Printer) {
+    let Some(s) = &report.security else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!("Security events ({}):", out.count(s.events)))
+    );
+    if s.events == 0 {
+        outln!(out, "    No CEF or LEEF events found");
+        return;
+    }
+    for b in &s.severities {
+        outln!(out, "    {:<20} {:>10}", b.severity, out.count(b.count));
+    }
+    for (title, counts) in [
+        ("Top events:", &s.top_events),
+        ("Top sources:", &s.top_sources),
+    ] {
+        if !counts.is_empty() {
+            outln!(out, "    {}", title);
+            for c in counts {
+                outln!(out, "      {:<40} {:>10}", c.value, out.count(c.count));
+            }
+        }
+    }
+}
+
+fn print_sessions(report: &Report, out: &Printer) {
+    let Some(s) = &report.sessions else {
+        return;
+    };
+    outln!(
+        out,
+        "\n{}",
+        out.header(&format!("Sessions (by {}):", s.key))
+    );
+    outln!(
+        out,
+        "    {} sessions: {} ended, {} failed, {} open",
+        out.count(s.sessions),
+        out.count(s.ended),
+        out.count(s.failed),
+        out.count(s.open)
+    );
+    if !s.sequences.is_empty() {
+        outln!(out, "    Common sequences:");
+        for seq in &s.sequences {
+            let failed = if seq.failed > 0 {
+                format!("  ({} failed)", out.count(seq.failed))
+            } else {
+                String::new()
+            };
+            outln!(
+                out,
+                "    {:>8}  {}{}",
+                out.count(seq.sessions),
+                seq.events.join(" → "),
+                failed
+            );
+        }
+    }
+    if !s.failures.is_empty() {
+        outln!(out, "    Failed sessions:");
+        for f in &s.failures {
+            outln!(
+                out,
+                "      {} ({} lines): {}",
+                f.session,
+                out.count(f.lines),
+                f.events.join(" → ")
+            );
+            outln!(out, "          {}", f.last_line);
+        }
+    }
+    if let Some(lines) = &s.lines {
+        outln!(
+            out,
+            "    Lines per session: p50 {}, p90 {}, p99 {}, max {}",
+            lines.p50,
+            lines.p90,
+            lines.p99,
+            lines.max
+        );
+    }
+    let Some(d) = &s.durations_ms else {
+        return;
+    };
+    outln!(
+        out,
+        "    Durations ({} timed): p50 {}, p90 {}, p99 {}, max {}",
+        out.count(s.timed),
+        session_duration(d.p50),
+        session_duration(d.p90),
+        session_duration(d.p99),
+        session_duration(d.max)
+    );
+    outln!(out, "    Longest sessions:");
+    let width = s.longest.iter().map(|l| l.session.len()).max().unwrap_or(0);
+    for l in &s.longest {
+        outln!(
+            out,
+            "      {:<width$}  {:>8} lines  {} to {}  {}",
+            l.session,
+            out.count(l.lines),
+            timestamp::format_millis(l.start_ms),
+            timestamp::format_millis(l.end_ms),
+            session_duration(l.duration_ms)
+        );
+    }
+}
+
+/// A session's length to the second, or in ms below one.
+fn session_duration(ms: i64) -> String {
+    let ms = ms.max(0) as u64;
+    human::duration_ms(if ms >= 1000 { ms / 1000 * 1000 } else { ms })
+}
+
+fn print_out_of_order(report: &Report, out: &Printer) {
+    let files: Vec<&FileSummary> = report.files.iter().filter(|f| f.out_of_order > 0).collect();
+    if files.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Out-of-order timestamps:"));
+    for f in files {
+        outln!(
+            out,
+            "    {:<20} {} lines go back in time, by up to {:.1}s",
+            f.name,
+            out.count(f.out_of_order),
+            f.max_regression_ms as f64 / 1000.0
+        );
+        for sample in &f.out_of_order_samples {
+            outln!(out, "          {}", sample);
+        }
+    }
+}
+
+fn print_gaps(report: &Report, out: &Printer) {
+    let mut gaps: Vec<(&str, &GapSummary)> = report
+        .files
+        .iter()
+        .flat_map(|f| f.longest_gaps.iter().map(move |g| (f.name.as_str(), g)))
+        .collect();
+    if gaps.is_empty() {
+        return;
+    }
+    gaps.sort_by_key(|(_, g)| Reverse(g.duration_ms));
+    gaps.truncate(TOP_GAPS);
+
+    outln!(out, "\n{}", out.header("Longest gaps between lines:"));
+    let width = gaps.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, g) in gaps {
+        let over = report.max_gap_ms.is_some_and(|max| g.duration_ms > max);
+        outln!(
+            out,
+            "    {:<width$} {:>10}  {} to {}, before line {}{}",
+            name,
+            human::duration_ms(g.duration_ms as u64),
+            timestamp::format_millis(g.from_ms),
+            timestamp::format_millis(g.to_ms),
+            g.line,
+            if over { "  (over --max-gap)" } else { "" }
+        );
+    }
+    if let Some(max) = report.max_gap_ms {
+        let long: Vec<&FileSummary> = report.files.iter().filter(|f| f.long_gaps > 0).collect();
+        outln!(
+            out,
+            "    {} gaps over {} in {} files",
+            out.count(long.iter().map(|f| f.long_gaps).sum()),
+            human::duration_ms(max as u64),
+            out.count(long.len())
+        );
+    }
+}
+
+fn print_sources(report: &Report, out: &Printer) {
+    if report.source_locations.is_empty() {
+        return;
+    }
+    outln!(out, "\n{}", out.header("Source locations:"));
+    let linked = style::hyperlinks_enabled(out.config.hyperlinks);
+    for s in &report.source_locations {
+        let location = format!("{}:{}", s.path, s.line);
+        let padded = format!("{:<32}", location);
+        let shown = match &s.url {
+            Some(url) if linked => style::hyperlink(url, &padded),
+            _ => padded,
+        };
+        let mut row = format!(
+            "    {} {:>6} errors  {:>6} lines",
+            shown,
+            out.count(s.errors),
+            out.count(s.lines)
+        );
+        if let (Some(url), false) = (&s.url, linked) {
+            row.push_str("  ");
+            row.push_str(url);
+        }
+        outln!(out, "{}", row);
+    }
+}
+
+/// Up to three decimals, without trailing zeros ("2300", "0.015").
+fn format_number(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    text.to_string()
+}
+
+/// JSON Schema of the Report, as printed by --schema.
+pub fn json_schema() -> String {
+    let mut schema = schemars::schema_for!(Report);
+    schema.insert(
+        "$id".to_string(),
+        format!("urn:logbuddy:report:v{}", SCHEMA_VERSION).into(),
+    );
+    if let Some(version) = schema
+        .get_mut("properties")
+        .and_then(|p| p.get_mut("schema_version"))
+        .and_then(|v| v.as_object_mut())
+    {
+        version.insert("const".to_string(), SCHEMA_VERSION.into());
+    }
+    serde_json::to_string_pretty(&schema).expect("schema serializes")
+}
+
+/// Renders the report through a user-supplied Handlebars template file.
+pub fn render_template(report: &Report, template_path: &str) -> Result<String, String> {
+    let source = fs::read_to_string(template_path)
+        .map_err(|e| format!("could not read template {}: {}", template_path, e))?;
+
+    let mut hb = Handlebars::new();
+    // Templates usually produce plain text, CSV or markdown, not HTML
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+
+    hb.render_template(&source, report)
+        .map_err(|e| format!("could not render template {}: {}", template_path, e))
+}
+
+fn print_timings(report: &Report, out: &Printer) {
+    let Some(t) = &report.timings else {
+        return;
+    };
+    outln!(out, "\n{}", out.header("Timings:"));
+    outln!(out, "    {:<12} {:.0} ms", "wall time", t.wall_ms);
+    outln!(out, "    {:<12} {:.1} MB/s", "throughput", t.mb_per_second);
+    outln!(
+        out,
+        "    {:<12} {} lines/s",
+        "",
+        out.count(t.lines_per_second as usize)
+    );
+    outln!(out, "    Time per phase (all threads):");
+    for p in &t.phases {
+        outln!(
+            out,
+            "    {:<12} {:>10.0} ms {:>5.1}%",
+            p.phase,
+            p.ms,
+            p.share * 100.0
+        );
+    }
+    outln!(out, "    Resources:");
+    outln!(
+        out,
+        "    {:<12} {} (word counts and buffered hits)",
+        "estimated",
+        out.bytes(t.peak_estimated_bytes)
+    );
+    if let Some(r) = &t.resources {
+        outln!(
+            out,
+            "    {:<12} {}",
+            "peak memory",
+            out.bytes(r.peak_rss_bytes as usize)
+        );
+        outln!(
+            out,
+            "    {:<12} {} ms user, {} ms system",
+            "CPU time",
+            r.user_cpu_ms,
+            r.system_cpu_ms
+        );
+        outln!(out, "    {:<12} {} ms", "I/O wait", r.io_wait_ms);
+    }
+}
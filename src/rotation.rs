@@ -0,0 +1,207 @@
+//! Rotated logs, found by name: `app.log` with `app.log.1`, `app.log.2`
+//! (numbered, older the higher, as logrotate does) and `app.log-20240110`
+//! or `app-2024-01-10.log` (dated), compressed (`app.log.2.gz`) or not. A
+//! set of them is one stream, read oldest file first so its lines come in
+//! time order.
+
+use std::collections::HashMap;
+
+use crate::compress;
+
+/// Where a file falls in its set, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Age {
+    /// Named with the day it was rotated, as YYYYMMDD
+    Dated(String),
+    /// `.1` is the newest rotated file, so higher numbers come first
+    Numbered(std::cmp::Reverse<u64>),
+    /// The file being written to
+    Current,
+}
+
+/// The stream `name` belongs to (its name without the rotation number or
+/// date, folders kept) and its place in it. A file that isn't rotated is
+/// the current file of its own stream.
+pub fn stream(name: &str) -> (String, Age) {
+    let name = compress::strip_extension(name);
+    let (folder, file) = match name.rfind('/') {
+        Some(i) => name.split_at(i + 1),
+        None => ("", name),
+    };
+    let (base, age) = rotated(file).unwrap_or((file.to_string(), Age::Current));
+    (format!("{}{}", folder, base), age)
+}
+
+fn rotated(file: &str) -> Option<(String, Age)> {
+    // app.log.1, or app.log.20240110
+    if let Some((base, suffix)) = file.rsplit_once('.') {
+        if base.contains('.') && !suffix.is_empty() && suffix.bytes().all(|b| b.is_ascii_digit()) {
+            let age = match date(suffix) {
+                Some(day) => Age::Dated(day),
+                None => Age::Numbered(std::cmp::Reverse(suffix.parse().ok()?)),
+            };
+            return Some((base.to_string(), age));
+        }
+    }
+    // app.log-20240110
+    if let Some((base, day)) = dated_end(file) {
+        if base.contains('.') {
+            return Some((base.to_string(), Age::Dated(day)));
+        }
+    }
+    // app-20240110.log, app_2024-01-10.txt, app.20240110.log
+    let (stem, ext) = file.rsplit_once('.')?;
+    let (base, day) = dated_end(stem)?;
+    if base.is_empty() {
+        return None;
+    }
+    Some((format!("{}.{}", base, ext), Age::Dated(day)))
+}
+
+/// `text` split before a `-`, `_` or `.` and a date at its end.
+fn dated_end(text: &str) -> Option<(&str, String)> {
+    [8, 10].iter().find_map(|&len| {
+        let at = text.len().checked_sub(len + 1)?;
+        if !text.is_char_boundary(at) {
+            return None;
+        }
+        let (base, rest) = text.split_at(at);
+        let (separator, day) = rest.split_at(1);
+        if !matches!(separator, "-" | "_" | ".") {
+            return None;
+        }
+        Some((base, date(day)?))
+    })
+}
+
+/// YYYYMMDD for a day written YYYYMMDD or YYYY-MM-DD in this century or
+/// the last.
+fn date(text: &str) -> Option<String> {
+    let digits: String = match text.len() {
+        8 => text.to_string(),
+        10 if text.as_bytes()[4] == b'-' && text.as_bytes()[7] == b'-' => text.replace('-', ""),
+        _ => return None,
+    };
+    if digits.len() != 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let month: u32 = digits[4..6].parse().ok()?;
+    let day: u32 = digits[6..].parse().ok()?;
+    let plausible = (digits.starts_with("19") || digits.starts_with("20"))
+        && (1..=12).contains(&month)
+        && (1..=31).contains(&day);
+    plausible.then_some(digits)
+}
+
+/// The extension that tells what kind of file `name` is: `log` for
+/// `app.log`, `app.log.1` and `app-20240110.log`; empty without one.
+pub fn extension(name: &str) -> String {
+    let (stream, _) = stream(name);
+    let file = stream.rsplit('/').next().unwrap_or(&stream);
+    match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => ext.to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+/// Puts the files of each rotated set together, oldest first, where the
+/// first of them was; other files keep their order.
+pub fn order<T>(files: Vec<(T, String)>) -> Vec<(T, String)> {
+    let mut first: HashMap<String, usize> = HashMap::new();
+    let mut keyed: Vec<((usize, Age), (T, String))> = files
+        .into_iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let (stream, age) = stream(&file.1);
+            let set = *first.entry(stream).or_insert(i);
+            ((set, age), file)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    keyed.into_iter().map(|(_, file)| file).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cmp::Reverse;
+
+    fn dated(day: &str) -> Age {
+        Age::Dated(day.to_string())
+    }
+
+    #[test]
+    fn streams() {
+        let cases = [
+            ("app.log", "app.log", Age::Current),
+            ("app.log.1", "app.log", Age::Numbered(Reverse(1))),
+            (
+                "logs/app.log.12.gz",
+                "logs/app.log",
+                Age::Numbered(Reverse(12)),
+            ),
+            ("app.log.20240110", "app.log", dated("20240110")),
+            ("app.log-20240110.gz", "app.log", dated("20240110")),
+            ("app-2024-01-10.log", "app.log", dated("20240110")),
+            ("app_20240110.txt", "app.txt", dated("20240110")),
+            ("app.20240110.log", "app.log", dated("20240110")),
+            // Not rotation numbers or dates
+            ("release-1.2.log", "release-1.2.log", Age::Current),
+            ("app-20241310.log", "app-20241310.log", Age::Current),
+            ("app-21240110.log", "app-21240110.log", Age::Current),
+            ("20240110.log", "20240110.log", Age::Current),
+            ("notes.1", "notes.1", Age::Current),
+        ];
+        for (name, want_stream, want_age) in cases {
+            assert_eq!(
+                stream(name),
+                (want_stream.to_string(), want_age),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(extension("app.log"), "log");
+        assert_eq!(extension("app.LOG.3.gz"), "log");
+        assert_eq!(extension("a/app-20240110.txt"), "txt");
+        assert_eq!(extension("Makefile"), "");
+        assert_eq!(extension(".hidden"), "");
+    }
+
+    #[test]
+    fn sets_are_read_oldest_first() {
+        let names = [
+            "a.log",
+            "app.log",
+            "app.log.1",
+            "app.log.2.gz",
+            "b.log",
+            "web-20240102.log",
+            "web-20240101.log",
+            "web.log",
+        ];
+        let files = names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (i, n.to_string()))
+            .collect();
+        let ordered: Vec<String> = order(files).into_iter().map(|(_, name)| name).collect();
+        assert_eq!(
+            ordered,
+            [
+                "a.log",
+                "app.log.2.gz",
+                "app.log.1",
+                "app.log",
+                "b.log",
+                "web-20240101.log",
+                "web-20240102.log",
+                "web.log"
+            ]
+        );
+    }
+}
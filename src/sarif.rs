@@ -0,0 +1,69 @@
+use serde_json::{json, Value};
+
+use crate::cli::Config;
+use crate::report::{self, Report};
+use crate::scanner::Rule;
+
+/// Renders every --find / --check match as a SARIF 2.1.0 result, one rule
+/// per pattern, for GitHub code scanning and other SARIF consumers.
+pub fn render(report: &Report, config: &Config) -> String {
+    let mut rules = Vec::new();
+    if let Some(find) = &config.find {
+        rules.push(rule(
+            Rule::Find,
+            &format!("Lines containing '{}'", find),
+            "warning",
+        ));
+    }
+    for (i, check) in config.checks.iter().enumerate() {
+        rules.push(rule(Rule::Check(i), &check.name(), "error"));
+    }
+
+    let results: Vec<Value> = report
+        .matches
+        .iter()
+        .map(|m| {
+            let level = if m.rule_id == "find" {
+                "warning"
+            } else {
+                "error"
+            };
+            json!({
+                "ruleId": m.rule_id,
+                "level": level,
+                "message": { "text": m.text },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": report::file_uri(&report.folder, &m.file) },
+                        "region": { "startLine": m.line }
+                    }
+                }]
+            })
+        })
+        .collect();
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "logbuddy",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules
+                }
+            },
+            "results": results
+        }]
+    });
+
+    serde_json::to_string_pretty(&sarif).expect("SARIF value is always serializable")
+}
+
+fn rule(rule: Rule, description: &str, level: &str) -> Value {
+    json!({
+        "id": report::rule_id(rule),
+        "shortDescription": { "text": description },
+        "defaultConfiguration": { "level": level }
+    })
+}
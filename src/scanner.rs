@@ -0,0 +1,2409 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, trace, warn};
+
+use crate::access::{self, AccessTally};
+use crate::aio::{self, AsyncReads};
+use crate::ansi;
+use crate::archive::{self, Archive};
+use crate::cache::{self, ScanCache};
+use crate::cef::{self, SecurityTally};
+use crate::charset::{Charset, Transcode};
+use crate::checkpoint::Checkpoint;
+use crate::checks::CheckTally;
+use crate::cli::{Config, EntryStart, IoBackend, LineFormat};
+use crate::compress::{self, Compression};
+use crate::correlate::TraceIdLines;
+use crate::decode::{self, Decoded, Encoding};
+use crate::delimited;
+use crate::distinct::DistinctTally;
+use crate::drain::Drain;
+use crate::errno::{self, ErrnoStat};
+use crate::eventlog;
+use crate::fields::{self, Record};
+use crate::fingerprint::{self, Seen};
+use crate::groups::{CountTally, GroupTally};
+use crate::hll::HyperLogLog;
+use crate::interrupt::{self, Stop};
+use crate::jsonlog;
+use crate::level::Level;
+use crate::logfmt;
+use crate::memory::Spill;
+use crate::origins::{self, OriginTally};
+use crate::progress::{self, Progress, ProgressBar};
+use crate::repeats::RepeatTally;
+use crate::report::Section;
+use crate::rotation;
+use crate::search::Needle;
+use crate::sessions::SessionTracker;
+use crate::sink::{Hit, OutputSink};
+use crate::sketch::ApproxCounts;
+use crate::skew::SkewTracker;
+use crate::source::{RemoteFile, Source};
+use crate::stats::FieldStats;
+use crate::stopwords::WordFilter;
+use crate::syslog::{self, SyslogTally};
+use crate::timestamp;
+use crate::tokenize::{self, Tokenizer};
+use crate::traces::{self, TraceTally};
+use crate::words::WordCounts;
+
+/// Characters of a truncated record kept as its sample.
+const PREVIEW_CHARS: usize = 80;
+
+/// Gaps kept per file, for the report.
+const LONGEST_GAPS: usize = 3;
+
+/// Words kept per file for TF-IDF; a word is counted as in another file
+/// only if it is among that file's top ones too.
+const FILE_WORDS: usize = 100;
+
+/// How many files per --jobs thread may be scanned ahead of the one
+/// being added to the totals, so a slow file doesn't leave every scan
+/// after it waiting in memory.
+const AHEAD_PER_JOB: usize = 4;
+
+/// Extensions of the files read as logs, in a folder, an archive or a
+/// source.
+const LOG_EXTENSIONS: [&str; 2] = ["txt", "log"];
+
+/// Reads of a file that keeps changing, with --reread-unstable.
+const READ_ATTEMPTS: usize = 3;
+
+/// Lines a --multiline entry grows to at most: past them, a file with no
+/// entry starts doesn't become one entry held in memory.
+const MAX_ENTRY_LINES: usize = 1000;
+
+/// Everything counted while scanning, used by the summary at the end.
+/// Serializable so per-file totals can be kept in a --cache.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ScanTotals {
+    pub files_scanned: usize,
+    /// Of those, files whose totals came from the --cache
+    #[serde(skip)]
+    pub files_cached: usize,
+    pub total_lines: usize,
+    pub total_bytes: usize,
+    pub word_counts: WordCounts,
+    /// One per --ngrams size, in the same order as Config::ngrams
+    pub ngrams: Vec<ApproxCounts>,
+    /// Replaces word_counts with --approx-counts
+    pub approx_words: Option<ApproxCounts>,
+    /// Distinct words, estimated. With exact counts it is filled in from
+    /// them once a file is read; approximate counts add every word
+    pub distinct_words: HyperLogLog,
+    // errno constant -> how often it appeared and in which files
+    #[serde(with = "cache::errno_stats")]
+    pub errno_stats: HashMap<&'static str, ErrnoStat>,
+    /// Lines matching --find
+    pub hits: usize,
+    /// "file:line: text" of the first few hits, for --max-hits failures
+    pub hit_samples: CheckTally,
+    pub files: Vec<FileStats>,
+    /// One tally per --check, in the same order as Config::checks
+    pub checks: Vec<CheckTally>,
+    /// First line of each fingerprint at Config::fingerprint_levels
+    pub fingerprints: BTreeMap<String, Seen>,
+    /// Nested payloads decoded while searching, per encoding
+    pub decoded: HashMap<Encoding, usize>,
+    /// Every --find / --check match, when the output format needs them all
+    pub matches: Vec<Match>,
+    /// One entry per --stats-field, in the same order as Config::stats_fields
+    pub field_stats: Vec<FieldStats>,
+    /// --skew-key timestamps, keyed by index into `files`
+    pub skew: SkewTracker,
+    /// Lines per `file.rs:123` source location they name
+    #[serde(with = "cache::pairs")]
+    pub origins: HashMap<(String, u32), OriginTally>,
+    /// Message templates mined from every line
+    pub templates: Drain,
+    /// Messages seen word for word more than once
+    pub repeats: RepeatTally,
+    /// --trace-id lines
+    pub trace_id: TraceIdLines,
+    /// --session-key sessions, with templates as events
+    pub sessions: SessionTracker,
+    /// --access-log requests by status and path
+    pub access: AccessTally,
+    /// --syslog lines by facility, severity and app
+    pub syslog: SyslogTally,
+    /// --cef and --leef events by severity, name and source
+    pub security: SecurityTally,
+    /// --multiline stack traces by signature
+    pub traces: TraceTally,
+    /// --group-by lines per value
+    pub groups: GroupTally,
+    /// One tally per --count-by, in the same order as Config::count_by
+    pub counts: Vec<CountTally>,
+    /// One tally per --distinct, in the same order as Config::distinct
+    pub distinct: Vec<DistinctTally>,
+    /// Lines cut short for field and payload parsing by --max-record-bytes
+    /// or --max-fields
+    pub truncated_records: CheckTally,
+    /// --histogram and --spikes: lines and hits per bucket, by bucket start
+    /// (Unix millis)
+    pub histogram: BTreeMap<i64, Bucket>,
+    /// Lines per level, by Level::index
+    pub levels: [usize; 6],
+    /// Lines in which no level was found
+    pub unleveled: usize,
+    /// Lines cut to --max-line-length
+    pub long_lines: CheckTally,
+    /// Lines read but left out for being before --since or after --until
+    pub outside_window: usize,
+    /// Lines in the window but left out for being below --min-level
+    pub below_min_level: usize,
+    /// With a line format (--json-logs, --logfmt, --access-log, ...), lines
+    /// that were read as text for not being in it
+    pub unparsed: usize,
+    /// With --multiline, lines that continued an entry rather than
+    /// starting one
+    pub continuation_lines: usize,
+    /// Files read as UTF-16 or Latin-1 rather than UTF-8
+    pub files_transcoded: usize,
+    /// Files left out for being last modified before --since
+    #[serde(skip)]
+    pub files_before_window: usize,
+    /// Files left out by --max-filesize, with their sizes
+    #[serde(skip)]
+    pub skipped_files: Vec<(String, usize)>,
+    /// Where the time went, with --timings
+    #[serde(skip)]
+    pub timings: PhaseTimes,
+    /// Set when Ctrl-C or --timeout stopped the scan before every file
+    /// was read
+    #[serde(skip)]
+    pub stopped: Option<Stop>,
+    /// Largest estimate of the word tables and hit buffers, in bytes
+    #[serde(skip)]
+    pub peak_memory_estimate: usize,
+}
+
+/// One --histogram bucket.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Bucket {
+    pub lines: usize,
+    pub hits: usize,
+}
+
+/// Time spent per phase of the scan, measured only with --timings. Phases
+/// are summed over all scan threads; `wall` is the elapsed time.
+#[derive(Debug, Default, Clone)]
+pub struct PhaseTimes {
+    /// Listing the folder
+    pub listing: Duration,
+    /// Reading files and splitting them into lines
+    pub reading: Duration,
+    /// Word and errno counting
+    pub tokenizing: Duration,
+    /// --find, --check and payload decoding
+    pub searching: Duration,
+    /// Mining message templates and sessions
+    pub templates: Duration,
+    /// Fields, source locations and timestamps
+    pub other: Duration,
+    pub wall: Duration,
+}
+
+impl PhaseTimes {
+    fn merge(&mut self, other: &PhaseTimes) {
+        self.listing += other.listing;
+        self.reading += other.reading;
+        self.tokenizing += other.tokenizing;
+        self.searching += other.searching;
+        self.templates += other.templates;
+        self.other += other.other;
+    }
+
+    /// Time spent in the lines of a file, to tell reading apart.
+    fn in_lines(&self) -> Duration {
+        self.tokenizing + self.searching + self.templates + self.other
+    }
+}
+
+/// Laps for --timings; does nothing, not even read the clock, when off.
+struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    fn start(on: bool) -> Self {
+        Stopwatch(on.then(Instant::now))
+    }
+
+    /// Adds the time since the last lap to `phase`.
+    fn lap(&mut self, phase: &mut Duration) {
+        if let Some(last) = &mut self.0 {
+            let now = Instant::now();
+            *phase += now - *last;
+            *last = now;
+        }
+    }
+}
+
+impl ScanTotals {
+    /// Empty totals with one tally per --check, --stats-field, --count-by
+    /// and --distinct.
+    fn for_config(config: &Config) -> Self {
+        ScanTotals {
+            checks: vec![CheckTally::default(); config.checks.len()],
+            approx_words: config.approx_counts.then(ApproxCounts::default),
+            field_stats: config
+                .stats_fields
+                .iter()
+                .map(|_| FieldStats::default())
+                .collect(),
+            counts: vec![CountTally::default(); config.count_by.len()],
+            ngrams: config
+                .ngrams
+                .iter()
+                .map(|_| ApproxCounts::default())
+                .collect(),
+            distinct: vec![DistinctTally::default(); config.distinct.len()],
+            ..ScanTotals::default()
+        }
+    }
+
+    /// Adds the totals of files scanned after the ones already in `self`.
+    fn merge(&mut self, other: ScanTotals) {
+        self.files_scanned += other.files_scanned;
+        self.files_cached += other.files_cached;
+        self.total_lines += other.total_lines;
+        self.total_bytes += other.total_bytes;
+        // Either side may have switched to approximate counts under
+        // --max-memory, and then the merged counts are approximate too
+        match (&mut self.approx_words, other.approx_words) {
+            (Some(approx), Some(more)) => approx.merge(more),
+            (Some(approx), None) => approx.merge(ApproxCounts::from_exact(&other.word_counts)),
+            (None, Some(more)) => {
+                self.switch_to_approx();
+                if let Some(approx) = &mut self.approx_words {
+                    approx.merge(more);
+                }
+            }
+            (None, None) => self.word_counts.merge(other.word_counts),
+        }
+        self.distinct_words.merge(&other.distinct_words);
+        for (counts, more) in self.ngrams.iter_mut().zip(other.ngrams) {
+            counts.merge(more);
+        }
+        for (name, stat) in other.errno_stats {
+            match self.errno_stats.get_mut(name) {
+                Some(existing) => existing.merge(stat),
+                None => {
+                    self.errno_stats.insert(name, stat);
+                }
+            }
+        }
+        self.hits += other.hits;
+        self.hit_samples.merge(other.hit_samples);
+        // Skew file indexes in `other` count from its first file
+        self.skew.merge(other.skew, self.files.len());
+        self.files.extend(other.files);
+        for (tally, more) in self.checks.iter_mut().zip(other.checks) {
+            tally.merge(more);
+        }
+        // Files scanned first keep their lines
+        for (fingerprint, seen) in other.fingerprints {
+            self.fingerprints.entry(fingerprint).or_insert(seen);
+        }
+        for (encoding, count) in other.decoded {
+            *self.decoded.entry(encoding).or_insert(0) += count;
+        }
+        self.matches.extend(other.matches);
+        for (stats, more) in self.field_stats.iter_mut().zip(other.field_stats) {
+            stats.merge(more);
+        }
+        for (origin, tally) in other.origins {
+            self.origins.entry(origin).or_default().merge(tally);
+        }
+        self.truncated_records.merge(other.truncated_records);
+        for (count, more) in self.levels.iter_mut().zip(other.levels) {
+            *count += more;
+        }
+        self.unleveled += other.unleveled;
+        self.long_lines.merge(other.long_lines);
+        self.outside_window += other.outside_window;
+        self.below_min_level += other.below_min_level;
+        self.unparsed += other.unparsed;
+        self.continuation_lines += other.continuation_lines;
+        self.files_transcoded += other.files_transcoded;
+        for (start, more) in other.histogram {
+            let bucket = self.histogram.entry(start).or_default();
+            bucket.lines += more.lines;
+            bucket.hits += more.hits;
+        }
+        self.timings.merge(&other.timings);
+        let events = self.templates.merge(other.templates);
+        self.sessions.merge(other.sessions, &events);
+        self.trace_id.merge(other.trace_id);
+        self.access.merge(other.access);
+        self.syslog.merge(other.syslog);
+        self.security.merge(other.security);
+        self.traces.merge(other.traces);
+        self.groups.merge(other.groups);
+        self.repeats.merge(other.repeats);
+        for (tally, more) in self.counts.iter_mut().zip(other.counts) {
+            tally.merge(more);
+        }
+        for (tally, more) in self.distinct.iter_mut().zip(other.distinct) {
+            tally.merge(more);
+        }
+    }
+
+    /// Replaces the exact word counts with approximate ones, to stay
+    /// within --max-memory.
+    fn switch_to_approx(&mut self) {
+        if self.approx_words.is_none() {
+            self.sketch_distinct_words();
+            let exact = std::mem::take(&mut self.word_counts);
+            self.approx_words = Some(ApproxCounts::from_exact(&exact));
+        }
+    }
+
+    /// Adds the exactly counted words to distinct_words.
+    fn sketch_distinct_words(&mut self) {
+        for (word, _) in self.word_counts.iter() {
+            self.distinct_words.add(word);
+        }
+    }
+
+    /// Rough size of the word tables, for --max-memory.
+    fn word_bytes(&self) -> usize {
+        self.word_counts.heap_bytes() + self.approx_words.as_ref().map_or(0, |a| a.heap_bytes())
+    }
+
+    /// Every counted word, or with --approx-counts the likely most
+    /// frequent ones, and their (estimated) counts.
+    pub fn words(&self) -> Box<dyn Iterator<Item = (&str, usize)> + '_> {
+        match &self.approx_words {
+            Some(approx) => Box::new(approx.counts()),
+            None => Box::new(self.word_counts.iter()),
+        }
+    }
+
+    /// Counts a matching line against its rule, keeping the line itself
+    /// when `keep_match` is set.
+    fn record_hit(
+        &mut self,
+        rule: Rule,
+        keep_match: bool,
+        file_name: &str,
+        line_number: usize,
+        line: &str,
+    ) {
+        match rule {
+            Rule::Find => {
+                self.hits += 1;
+                self.hit_samples.record(file_name, line_number, line);
+            }
+            Rule::Check(i) => self.checks[i].record(file_name, line_number, line),
+        }
+
+        if keep_match {
+            self.matches.push(Match {
+                rule,
+                file: file_name.to_string(),
+                line_number,
+                text: line.to_string(),
+            });
+        }
+    }
+}
+
+/// Which pattern produced a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rule {
+    /// The --find text
+    Find,
+    /// The --check rule at this index in Config::checks
+    Check(usize),
+}
+
+/// One matching line, kept only for formats that list every match.
+#[derive(Serialize, Deserialize)]
+pub struct Match {
+    pub rule: Rule,
+    pub file: String,
+    pub line_number: usize,
+    pub text: String,
+}
+
+/// Line, byte and hit counts for one scanned file.
+#[derive(Serialize, Deserialize)]
+pub struct FileStats {
+    pub name: String,
+    pub lines: usize,
+    pub bytes: usize,
+    pub hits: usize,
+    pub order: OrderTally,
+    /// How the file changed while it was read ("grew", "shrank",
+    /// "replaced", ...), when it did; its counts may not add up
+    pub changed: Option<String>,
+    /// Line numbers of its first and last --find hit
+    pub hit_lines: Option<(usize, usize)>,
+    /// Its FILE_WORDS (or --top-per-file) most frequent words, for the
+    /// distinctive words and --top-per-file
+    pub top_words: Vec<(String, usize)>,
+    /// Words counted in it
+    pub words: usize,
+}
+
+/// The timestamps of a file's lines: the first and last, and the lines
+/// whose timestamp is earlier than one already seen in the same file, and
+/// the longest silences between them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OrderTally {
+    /// Of the first line with a timestamp (Unix millis)
+    pub first: Option<i64>,
+    /// Of the last line with a timestamp
+    pub last: Option<i64>,
+    /// Latest timestamp so far (Unix millis)
+    latest: Option<i64>,
+    pub backwards: CheckTally,
+    /// Largest step back in time, in milliseconds
+    pub max_regression_ms: i64,
+    /// The LONGEST_GAPS longest gaps between consecutive timestamps,
+    /// longest first
+    pub longest_gaps: Vec<Gap>,
+    /// Gaps longer than --max-gap
+    pub long_gaps: usize,
+}
+
+/// A stretch of time without lines in a file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Gap {
+    /// Timestamp of the line before it (Unix millis)
+    pub from_ms: i64,
+    /// Timestamp of the line that ended it
+    pub to_ms: i64,
+    pub line_number: usize,
+}
+
+impl Gap {
+    pub fn ms(&self) -> i64 {
+        self.to_ms - self.from_ms
+    }
+}
+
+impl OrderTally {
+    fn record(
+        &mut self,
+        file_name: &str,
+        line_number: usize,
+        line: &str,
+        millis: i64,
+        max_gap_ms: Option<i64>,
+    ) {
+        self.first.get_or_insert(millis);
+        // From the latest time, so a line out of order doesn't open a gap
+        if let Some(latest) = self.latest.filter(|&latest| millis > latest) {
+            let gap = Gap {
+                from_ms: latest,
+                to_ms: millis,
+                line_number,
+            };
+            self.record_gap(gap, max_gap_ms);
+        }
+        self.last = Some(millis);
+        match self.latest {
+            Some(latest) if millis < latest => {
+                self.backwards.record(file_name, line_number, line);
+                self.max_regression_ms = self.max_regression_ms.max(latest - millis);
+            }
+            _ => self.latest = Some(millis),
+        }
+    }
+
+    fn record_gap(&mut self, gap: Gap, max_gap_ms: Option<i64>) {
+        if max_gap_ms.is_some_and(|max| gap.ms() > max) {
+            self.long_gaps += 1;
+        }
+        let shorter = self.longest_gaps.iter().position(|g| g.ms() < gap.ms());
+        match shorter {
+            Some(at) => self.longest_gaps.insert(at, gap),
+            None if self.longest_gaps.len() < LONGEST_GAPS => self.longest_gaps.push(gap),
+            None => return,
+        }
+        self.longest_gaps.truncate(LONGEST_GAPS);
+    }
+}
+
+/// How far scan_parallel's threads have got through the files.
+#[derive(Default)]
+struct Claims {
+    /// Files handed to a thread
+    taken: usize,
+    /// Files added to the totals
+    absorbed: usize,
+    /// Set when a thread panicked, so the others don't wait for its file
+    stopped: bool,
+}
+
+/// Sets Claims::stopped if dropped by a panic.
+struct StopOnPanic<'c>(&'c Mutex<Claims>, &'c Condvar);
+
+impl Drop for StopOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            if let Ok(mut state) = self.0.lock() {
+                state.stopped = true;
+            }
+            self.1.notify_all();
+        }
+    }
+}
+
+/// Walks a folder and feeds each log file through the counters.
+pub struct Scanner<'a> {
+    config: &'a Config,
+    /// Where hits go as they are found: the terminal and any --sink
+    sinks: Vec<Box<dyn OutputSink>>,
+    pub totals: ScanTotals,
+    /// Set while a progress bar is shown
+    progress: Option<Progress>,
+    /// With --checkpoint
+    checkpoint: Option<Checkpoint>,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(config: &'a Config, sinks: Vec<Box<dyn OutputSink>>) -> Self {
+        Scanner {
+            config,
+            sinks,
+            totals: ScanTotals::for_config(config),
+            progress: None,
+            checkpoint: None,
+        }
+    }
+
+    /// Scans every .txt, .log or .evtx file directly inside `path` (or below
+    /// it, with --recursive) on up to --jobs threads. Files are merged in
+    /// the order files_with lists them, so output and totals don't depend
+    /// on the number of jobs.
+    pub fn scan_folder(&mut self, path: &Path) -> Result<(), String> {
+        let started = Instant::now();
+        let files = scan_files(path, self.config.recursive)?
+            .into_iter()
+            .map(|(path, name)| (Input::Local(path), name))
+            .collect();
+        self.scan_inputs(files, started)
+    }
+
+    /// Scans the .txt and .log files of `source` (rotated and compressed
+    /// ones too) as scan_folder does a folder's, each streamed as it is
+    /// read.
+    pub fn scan_source(&mut self, source: &dyn Source) -> Result<(), String> {
+        let started = Instant::now();
+        let mut files = Vec::new();
+        for file in source.list(self.config.recursive)? {
+            if !LOG_EXTENSIONS.contains(&rotation::extension(&file.name).as_str()) {
+                debug!(
+                    "skipped {}: not a {} file",
+                    source.locate(&file),
+                    LOG_EXTENSIONS.join("/")
+                );
+                continue;
+            }
+            let name = file.name.clone();
+            files.push((Input::Remote(source, file), name));
+        }
+        self.scan_inputs(rotation::order(files), started)
+    }
+
+    /// Scans `files`, listed since `started`.
+    fn scan_inputs(
+        &mut self,
+        mut files: Vec<(Input, String)>,
+        started: Instant,
+    ) -> Result<(), String> {
+        let timed = self.config.shows(Section::Timings);
+        if timed {
+            self.totals.timings.listing = started.elapsed();
+        }
+        let keep_matches = self.config.lists_matches();
+        let keep_hits = !self.sinks.is_empty();
+        if let Some(file) = &self.config.checkpoint {
+            self.start_checkpoint(file, &mut files, keep_matches, keep_hits)?;
+        }
+        if let Some(limit) = self.config.max_file_size {
+            self.skip_large_files(&mut files, limit);
+        }
+        if let Some(since) = self.config.since {
+            self.skip_old_files(&mut files, since);
+        }
+        let bar = self.config.shows_progress().then(|| {
+            let bytes = files.iter().filter_map(|(input, _)| input.size()).sum();
+            ProgressBar::start(files.len(), bytes)
+        });
+        self.progress = bar.as_ref().map(|b| b.progress().clone());
+        let cache = match &self.config.cache {
+            Some(dir) => Some(ScanCache::open(dir, self.config, keep_matches, keep_hits)?),
+            None => None,
+        };
+        let worker = FileScanner {
+            config: self.config,
+            needle: self.config.find.as_deref().map(Needle::new),
+            keep_matches,
+            keep_hits,
+            cache,
+            timed,
+            progress: self.progress.clone(),
+            file_budget: self.config.file_memory_budget(),
+            max_gap_ms: self.config.max_gap.map(|gap| gap.as_millis() as i64),
+            bucket_ms: self
+                .config
+                .bucket_width()
+                .map(|width| (width.as_millis() as i64).max(1)),
+            async_reads: match self.config.io_backend {
+                IoBackend::Async => Some(AsyncReads::start(
+                    self.config.io_depth.unwrap_or(aio::DEFAULT_DEPTH),
+                )?),
+                IoBackend::Sync => None,
+            },
+        };
+        let jobs = self.config.jobs();
+
+        if jobs <= 1 || files.len() <= 1 {
+            for (input, file_name) in &files {
+                self.absorb(input, worker.scan(input, file_name));
+            }
+        } else {
+            self.scan_parallel(&worker, &files, jobs)?;
+        }
+
+        self.progress = None;
+        drop(bar);
+        self.totals.stopped = interrupt::reason();
+        if let Some(checkpoint) = self.checkpoint.take() {
+            checkpoint.finish(&self.totals, self.totals.stopped.is_some());
+        }
+        if timed {
+            self.totals.timings.wall = started.elapsed();
+        }
+        info!(
+            "scanned {} files ({} from cache), {} lines",
+            self.totals.files_scanned, self.totals.files_cached, self.totals.total_lines
+        );
+        Ok(())
+    }
+
+    /// Drops files larger than --max-filesize from `files`, noting them in
+    /// the totals.
+    fn skip_large_files(&mut self, files: &mut Vec<(Input, String)>, limit: usize) {
+        let skipped = &mut self.totals.skipped_files;
+        files.retain(|(input, name)| {
+            let Some(size) = input.size() else {
+                // Left for the scan to report
+                return true;
+            };
+            if size <= limit {
+                return true;
+            }
+            debug!("skipped {}: {} bytes, over --max-filesize", input, size);
+            skipped.push((name.clone(), size));
+            false
+        });
+    }
+
+    /// Drops files last modified before --since from `files`: none of
+    /// their lines can be in the window.
+    fn skip_old_files(&mut self, files: &mut Vec<(Input, String)>, since: i64) {
+        let before = files.len();
+        files.retain(|(input, _)| match input.modified() {
+            Some(modified) if modified < since => {
+                debug!("skipped {}: last modified before --since", input);
+                false
+            }
+            _ => true,
+        });
+        self.totals.files_before_window += before - files.len();
+    }
+
+    /// Sets up --checkpoint; with --resume, starts from the saved totals
+    /// and drops the files they already cover from `files`.
+    fn start_checkpoint(
+        &mut self,
+        file: &str,
+        files: &mut Vec<(Input, String)>,
+        keep_matches: bool,
+        keep_hits: bool,
+    ) -> Result<(), String> {
+        // Totals of lines before another --since don't add up with these
+        let options = format!(
+            "{} since {:?}",
+            cache::scan_options(self.config, keep_matches, keep_hits),
+            self.config.since
+        );
+        if !self.config.resume {
+            self.checkpoint = Some(Checkpoint::new(file, options));
+            return Ok(());
+        }
+        let (checkpoint, totals) = Checkpoint::resume(file, options)?;
+        let stamps: Vec<Option<String>> = files.iter().map(|(input, _)| input.stamp()).collect();
+        let done = checkpoint.done_files(&stamps)?;
+        let mut done = done.into_iter();
+        files.retain(|_| !done.next().unwrap_or(false));
+        self.totals = totals;
+        self.checkpoint = Some(checkpoint);
+        Ok(())
+    }
+
+    fn scan_parallel(
+        &mut self,
+        worker: &FileScanner,
+        files: &[(Input, String)],
+        jobs: usize,
+    ) -> Result<(), String> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(|e| format!("could not start {} scan threads: {}", jobs, e))?;
+        let (tx, rx) = mpsc::channel();
+        let window = jobs * AHEAD_PER_JOB;
+        // Files are taken in order, and only up to `window` past the
+        // first one not absorbed yet
+        let claims = Mutex::new(Claims::default());
+        let absorbed_more = Condvar::new();
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.scope(|threads| {
+                    for _ in 0..jobs {
+                        let tx = tx.clone();
+                        let (claims, absorbed_more) = (&claims, &absorbed_more);
+                        threads.spawn(move |_| loop {
+                            let _stop = StopOnPanic(claims, absorbed_more);
+                            let mut state = claims.lock().unwrap();
+                            while !state.stopped && state.taken >= state.absorbed + window {
+                                state = absorbed_more.wait(state).unwrap();
+                            }
+                            let i = state.taken;
+                            if state.stopped || i == files.len() {
+                                return;
+                            }
+                            state.taken += 1;
+                            drop(state);
+                            let (input, name) = &files[i];
+                            // Only fails if the receiving side has panicked
+                            let _ = tx.send((i, worker.scan(input, name)));
+                        });
+                    }
+                });
+                drop(tx);
+            });
+
+            // Files finish in any order; hold each one until its turn
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (i, scan) in rx {
+                pending.insert(i, scan);
+                while let Some(scans) = pending.remove(&next) {
+                    self.absorb(&files[next].0, scans);
+                    next += 1;
+                }
+                claims.lock().unwrap().absorbed = next;
+                absorbed_more.notify_all();
+            }
+        });
+        Ok(())
+    }
+
+    /// Hands a scanned file's hits to the sinks and adds its counts: those
+    /// of each log in it, for an archive.
+    fn absorb(&mut self, input: &Input, scans: Vec<FileScan>) {
+        if scans.is_empty() {
+            return;
+        }
+        let cut_short = scans.iter().any(|scan| scan.cut_short);
+        if let (Some(checkpoint), true) = (&mut self.checkpoint, cut_short) {
+            checkpoint.freeze(&self.totals);
+        }
+        for scan in scans {
+            self.absorb_scan(input.path(), scan);
+        }
+        if let Some(progress) = &self.progress {
+            progress.file_done();
+        }
+        if let (Some(checkpoint), false) = (&mut self.checkpoint, cut_short) {
+            if let Some(stamp) = input.stamp() {
+                checkpoint.file_done(stamp, &self.totals);
+            }
+        }
+    }
+
+    /// `file_path` is None for a file of a source.
+    fn absorb_scan(&mut self, file_path: Option<&Path>, mut scan: FileScan) {
+        let estimate = self.totals.word_bytes() + scan.totals.word_bytes() + scan.hit_bytes;
+        self.totals.peak_memory_estimate = self.totals.peak_memory_estimate.max(estimate);
+        match self.progress.clone() {
+            Some(progress) if !scan.hits.is_empty() || scan.spill.is_some() => {
+                // Hit lines would land in the middle of the bar
+                progress.suspend(|| self.write_hits(file_path, &mut scan));
+            }
+            _ => self.write_hits(file_path, &mut scan),
+        }
+        self.totals.merge(scan.totals);
+        // The merged tables get half of --max-memory, the files being
+        // scanned the other half
+        if let Some(budget) = self.config.max_memory {
+            if self.totals.approx_words.is_none() && self.totals.word_bytes() > budget / 2 {
+                info!("word counts outgrew --max-memory; counting approximately from here");
+                self.totals.switch_to_approx();
+            }
+        }
+    }
+
+    fn write_hits(&mut self, file_path: Option<&Path>, scan: &mut FileScan) {
+        let name = &scan.name;
+        self.sinks
+            .retain_mut(|sink| keep_sink(sink.file_started(file_path, name)));
+
+        // Spilled hits come from earlier lines than the ones still held
+        let mut write = |found: &FoundHit| {
+            let hit = Hit {
+                file: name,
+                line_number: found.line_number,
+                line: &found.line,
+                decoded: found.decoded.as_ref(),
+            };
+            self.sinks.retain_mut(|sink| keep_sink(sink.hit(&hit)));
+        };
+        if let Some(spill) = &mut scan.spill {
+            if let Err(e) = spill.for_each(|found: FoundHit| write(&found)) {
+                warn!("lost hits of {} kept on disk: {}", name, e);
+            }
+        }
+        for found in &scan.hits {
+            write(found);
+        }
+    }
+
+    /// Lets every sink write what it summarizes at the end of the scan. A
+    /// sink that fails (a webhook that is down) costs only its own output,
+    /// not the report.
+    pub fn finish(&mut self) {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.finish(&self.totals) {
+                warn!("could not write output: {}", e);
+            }
+        }
+    }
+}
+
+/// Counts `size` bytes, those of the file as stored, as read for `scan`
+/// in place of the bytes of its lines, keeping the progress bar from
+/// hearing of any twice.
+fn count_source_bytes(scan: &mut FileScan, size: usize) {
+    let reported = scan.totals.total_bytes - scan.unreported;
+    scan.totals.total_bytes = size;
+    scan.unreported = size.saturating_sub(reported);
+}
+
+/// Moves a file's buffered hits to a temporary file; if that fails they
+/// stay in memory.
+fn spill_hits(scan: &mut FileScan) {
+    if scan.spill.is_none() {
+        match Spill::create() {
+            Ok(spill) => {
+                debug!(
+                    "{}: keeping hits on disk to stay within --max-memory",
+                    scan.name
+                );
+                scan.spill = Some(spill);
+            }
+            Err(e) => {
+                warn!(
+                    "could not create a file for hits, keeping them in memory: {}",
+                    e
+                );
+                // Don't try again for every hit
+                scan.hit_bytes = 0;
+                return;
+            }
+        }
+    }
+    if let Some(spill) = &mut scan.spill {
+        match spill.write(scan.hits.drain(..)) {
+            Ok(()) => scan.hit_bytes = 0,
+            Err(e) => warn!("could not write hits to disk: {}", e),
+        }
+    }
+}
+
+/// A sink that fails to write is dropped so the scan can go on.
+fn keep_sink(result: io::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("could not write output, dropping it: {}", e);
+            false
+        }
+    }
+}
+
+/// A file to scan: on disk, or in a source such as an S3 bucket.
+enum Input<'s> {
+    Local(PathBuf),
+    Remote(&'s dyn Source, RemoteFile),
+}
+
+impl Input<'_> {
+    /// The file on disk, if it is on disk.
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Input::Local(path) => Some(path),
+            Input::Remote(..) => None,
+        }
+    }
+
+    /// Bytes, as listed; None if they can't be told.
+    fn size(&self) -> Option<usize> {
+        match self {
+            Input::Local(path) => fs::metadata(path).ok().map(|m| m.len() as usize),
+            Input::Remote(_, file) => Some(file.size as usize),
+        }
+    }
+
+    /// When the file was last modified, as Unix millis.
+    fn modified(&self) -> Option<i64> {
+        match self {
+            Input::Local(path) => fs::metadata(path).ok().as_ref().and_then(modified_millis),
+            Input::Remote(_, file) => file.modified,
+        }
+    }
+
+    /// What --cache and --checkpoint know the file by as it is now: its
+    /// place, size and version (see cache::file_stamp).
+    fn stamp(&self) -> Option<String> {
+        match self {
+            Input::Local(path) => cache::file_stamp(path),
+            Input::Remote(source, file) => Some(format!(
+                "{} | {} | {}",
+                source.locate(file),
+                file.size,
+                file.version
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Local(path) => write!(f, "{}", path.display()),
+            Input::Remote(source, file) => write!(f, "{}", source.locate(file)),
+        }
+    }
+}
+
+/// What one file contributed, before it is merged into the scan totals.
+#[derive(Serialize, Deserialize)]
+pub struct FileScan {
+    name: String,
+    totals: ScanTotals,
+    /// --find hits in line order, only kept when there are sinks for them
+    hits: Vec<FoundHit>,
+    /// Line numbers of the first and last --find hit
+    hit_lines: Option<(usize, usize)>,
+    /// Which --since values would have given the same scan, so a cached
+    /// one stands in for a run with another (as --last gives each time)
+    since_range: SinceRange,
+    /// Reused for each lowercased word, so counting doesn't allocate
+    #[serde(skip)]
+    word: String,
+    /// Bytes read but not yet reported to the progress bar
+    #[serde(skip)]
+    unreported: usize,
+    /// Set when Ctrl-C or --timeout stopped the read before the end
+    #[serde(skip)]
+    cut_short: bool,
+    /// Rough size of `hits`
+    #[serde(skip)]
+    hit_bytes: usize,
+    /// Hits moved out of `hits` to stay within --max-memory
+    #[serde(skip)]
+    spill: Option<Spill>,
+    /// Timestamp of the last line that had one, which the lines after it
+    /// without one share for --since and --until
+    #[serde(skip)]
+    line_time: Option<i64>,
+    /// Level of the last line that had one, likewise for --min-level
+    #[serde(skip)]
+    line_level: Option<Level>,
+    /// When the file was last modified, or else when its scan started
+    /// (Unix millis), which syslog dates without a year go by
+    #[serde(skip)]
+    near: i64,
+    /// --delimited column names, from the file's header line
+    #[serde(skip)]
+    columns: Option<Vec<String>>,
+    /// --multiline entry read so far, with its line endings
+    #[serde(skip)]
+    entry: Vec<u8>,
+    #[serde(skip)]
+    entry_lines: usize,
+}
+
+/// The --since values that leave out the same timestamped lines as the
+/// one a file was scanned with: any later than the latest line left out
+/// for being too early, up to the earliest line kept.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct SinceRange {
+    after: Option<i64>,
+    up_to: Option<i64>,
+}
+
+impl SinceRange {
+    /// Notes a line from `millis`, which `kept` says was within --since
+    /// and --until. Lines only --until left out would be left out anyway.
+    fn record(&mut self, millis: i64, since: Option<i64>, kept: bool) {
+        if since.is_some_and(|since| millis < since) {
+            self.after = Some(self.after.map_or(millis, |after| after.max(millis)));
+        } else if kept {
+            self.up_to = Some(self.up_to.map_or(millis, |up_to| up_to.min(millis)));
+        }
+    }
+
+    fn holds(&self, since: Option<i64>) -> bool {
+        match since {
+            None => self.after.is_none(),
+            Some(since) => {
+                self.after.is_none_or(|after| since > after)
+                    && self.up_to.is_none_or(|up_to| since <= up_to)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FoundHit {
+    line_number: usize,
+    line: String,
+    decoded: Option<Decoded>,
+}
+
+/// Scans single files; shared by all scan threads.
+struct FileScanner<'a> {
+    config: &'a Config,
+    /// --find text
+    needle: Option<Needle>,
+    /// Whether to collect every match into ScanTotals::matches
+    keep_matches: bool,
+    /// Whether to keep hit lines for the sinks
+    keep_hits: bool,
+    cache: Option<ScanCache>,
+    /// Whether to measure PhaseTimes
+    timed: bool,
+    progress: Option<Progress>,
+    /// Bytes of word counts plus hits one file may hold (--max-memory)
+    file_budget: Option<usize>,
+    /// Set with --io-backend async
+    async_reads: Option<AsyncReads>,
+    /// --histogram (or --spikes) bucket width
+    bucket_ms: Option<i64>,
+    max_gap_ms: Option<i64>,
+}
+
+impl FileScanner<'_> {
+    /// Scans a file, or with --cache takes its totals from the last scan
+    /// when the file hasn't changed since. An archive gives a scan per
+    /// log in it; a file that can't be read, none.
+    fn scan(&self, input: &Input, file_name: &str) -> Vec<FileScan> {
+        // Files not started before Ctrl-C or --timeout are left out
+        if interrupt::requested() {
+            return Vec::new();
+        }
+        let Some(cache) = &self.cache else {
+            return self.read(input, file_name);
+        };
+        let key = input.stamp().map(|stamp| cache.key(&stamp));
+        let cached = key
+            .as_deref()
+            .and_then(|k| cache.load(k, file_name))
+            .filter(|scans| {
+                scans
+                    .iter()
+                    .all(|scan| scan.since_range.holds(self.config.since))
+            });
+        if let Some(mut scans) = cached {
+            debug!("{}: from cache", input);
+            for scan in &mut scans {
+                scan.totals.files_cached = 1;
+                if let Some(progress) = &self.progress {
+                    progress.add_bytes(scan.totals.total_bytes);
+                }
+            }
+            return scans;
+        }
+        let scans = self.read(input, file_name);
+        // Files cut short by Ctrl-C, --timeout or changing underneath
+        // aren't worth keeping, nor hits too many to load (--max-memory);
+        // unreadable ones are tried again next time
+        let partial = interrupt::requested()
+            || scans.is_empty()
+            || scans.iter().any(|scan| {
+                scan.spill.is_some() || scan.totals.files.iter().any(|f| f.changed.is_some())
+            });
+        match key {
+            Some(key) if !partial => cache.store(&key, file_name, scans),
+            _ => scans,
+        }
+    }
+
+    /// Reads the file, again with --reread-unstable when it changed while
+    /// being read, up to READ_ATTEMPTS times in all.
+    fn read(&self, input: &Input, file_name: &str) -> Vec<FileScan> {
+        let attempts = if self.config.reread_unstable {
+            READ_ATTEMPTS
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            let scans = match input {
+                Input::Local(path) => match Archive::of(file_name) {
+                    Some(archive) => self.read_archive(path, file_name, archive),
+                    None => self.read_once(path, file_name).into_iter().collect(),
+                },
+                Input::Remote(source, file) => self
+                    .read_remote(*source, file, file_name)
+                    .into_iter()
+                    .collect(),
+            };
+            let mut files = scans.iter().flat_map(|scan| &scan.totals.files);
+            let Some(change) = files.find_map(|f| f.changed.as_deref()) else {
+                return scans;
+            };
+            if attempt == attempts {
+                warn!(
+                    "{} {} while being read; its counts may not add up",
+                    input, change
+                );
+                return scans;
+            }
+            info!("{} {} while being read; reading it again", input, change);
+            attempt += 1;
+        }
+    }
+
+    /// Reads `file_path` one line at a time, so memory use depends on the
+    /// longest line rather than the file size. With --mmap the file is
+    /// mapped instead and lines are read straight from the mapping.
+    fn read_once(&self, file_path: &Path, file_name: &str) -> Option<FileScan> {
+        let mut watch = Stopwatch::start(self.timed);
+        let file = match File::open(file_path) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("could not read {}: {}", file_path.display(), e);
+                return None;
+            }
+        };
+        debug!("opened {}", file_path.display());
+        let opened = file.metadata().ok();
+        let mut scan = self.start_scan(file_name, opened.as_ref().and_then(modified_millis));
+        let mut order = OrderTally::default();
+
+        let evtx = eventlog::is_evtx(file_path);
+        let compression = Compression::detect(file_path);
+        let charset = match self.config.encoding {
+            _ if evtx => Charset::Utf8,
+            // Sniffed once decompressing, in read_compressed
+            _ if compression.is_some() => Charset::Utf8,
+            Some(charset) => charset,
+            None => Charset::detect(file_path).unwrap_or(Charset::Utf8),
+        };
+        let transcoded = charset != Charset::Utf8;
+        let mapped = if self.config.mmap && !evtx && !transcoded && compression.is_none() {
+            map_file(&file, file_path)
+        } else {
+            None
+        };
+        match (mapped, compression) {
+            (None, _) if evtx => self.read_evtx(&mut scan, &mut order, file_path, file_name),
+            (None, Some(_)) => {
+                if !self.read_compressed(&mut scan, &mut order, &file, file_name) {
+                    return None;
+                }
+            }
+            (None, None) if transcoded => {
+                self.read_transcoded(&mut scan, &mut order, &file, file_name, charset)
+            }
+            (Some(map), _) => {
+                for line in map.split_inclusive(|&b| b == b'\n') {
+                    if interrupt::requested() {
+                        scan.cut_short = true;
+                        break;
+                    }
+                    self.feed_line(&mut scan, &mut order, file_name, line);
+                }
+            }
+            (None, None) => {
+                let mut reader: Box<dyn BufRead> = match &self.async_reads {
+                    Some(reads) => match file.try_clone() {
+                        Ok(clone) => Box::new(reads.stream(clone)),
+                        Err(e) => {
+                            warn!("could not read {}: {}", file_path.display(), e);
+                            return None;
+                        }
+                    },
+                    None => Box::new(BufReader::new(&file)),
+                };
+                let mut buf = Vec::new();
+                loop {
+                    if interrupt::requested() {
+                        scan.cut_short = true;
+                        break;
+                    }
+                    buf.clear();
+                    match reader.read_until(b'\n', &mut buf) {
+                        Ok(0) => break,
+                        Ok(_) => self.feed_line(&mut scan, &mut order, file_name, &buf),
+                        Err(e) => {
+                            warn!("stopped reading {}: {}", file_path.display(), e);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.flush_entry(&mut scan, &mut order, file_name);
+
+        // An interrupted read stops early, which isn't the file's doing
+        let changed = opened
+            .filter(|_| !interrupt::requested())
+            .and_then(|before| {
+                changed_while_read(file_path, &file, &before, scan.totals.total_bytes)
+            });
+        self.finish_scan(&mut scan, order, &mut watch, changed);
+        Some(scan)
+    }
+
+    /// An empty scan of the file `file_name`, last `modified` then (Unix
+    /// millis).
+    fn start_scan(&self, file_name: &str, modified: Option<i64>) -> FileScan {
+        let mut scan = FileScan {
+            name: file_name.to_string(),
+            totals: ScanTotals::for_config(self.config),
+            hits: Vec::new(),
+            hit_lines: None,
+            since_range: SinceRange::default(),
+            word: String::new(),
+            unreported: 0,
+            cut_short: false,
+            hit_bytes: 0,
+            spill: None,
+            line_time: None,
+            line_level: None,
+            near: modified.unwrap_or_else(timestamp::now_millis),
+            columns: None,
+            entry: Vec::new(),
+            entry_lines: 0,
+        };
+        scan.totals.files_scanned = 1;
+        scan
+    }
+
+    /// Adds the FileStats of a file read to the end into its scan.
+    fn finish_scan(
+        &self,
+        scan: &mut FileScan,
+        order: OrderTally,
+        watch: &mut Stopwatch,
+        changed: Option<&str>,
+    ) {
+        if scan.totals.approx_words.is_none() {
+            scan.totals.sketch_distinct_words();
+        }
+        let lines = scan.totals.total_lines;
+        trace!("{}: {} lines, {} hits", scan.name, lines, scan.totals.hits);
+        if let Some(progress) = &self.progress {
+            progress.add_bytes(std::mem::take(&mut scan.unreported));
+        }
+        let mut file_time = Duration::ZERO;
+        watch.lap(&mut file_time);
+        scan.totals.timings.reading = file_time.saturating_sub(scan.totals.timings.in_lines());
+        scan.totals.files.push(FileStats {
+            name: scan.name.clone(),
+            lines,
+            bytes: scan.totals.total_bytes,
+            hits: scan.totals.hits,
+            order,
+            changed: changed.map(str::to_string),
+            hit_lines: scan.hit_lines,
+            top_words: top_words(&scan.totals, FILE_WORDS.max(self.config.top_per_file)),
+            words: scan.totals.words().map(|(_, count)| count).sum(),
+        });
+    }
+
+    /// Reads each log in the archive at `file_path` as a file of its own,
+    /// named `bundle.zip!logs/app.log`; other files in it are skipped.
+    /// Bytes read are each log's own, as if the archive were unpacked.
+    fn read_archive(&self, file_path: &Path, file_name: &str, archive: Archive) -> Vec<FileScan> {
+        let opened = fs::metadata(file_path).ok();
+        let modified = opened.as_ref().and_then(modified_millis);
+        let mut scans: Vec<FileScan> = Vec::new();
+        let read = archive::for_each_file(file_path, archive, |entry, contents| {
+            if interrupt::requested() {
+                // The logs after it are left out, so the archive wasn't
+                // read to the end
+                if let Some(last) = scans.last_mut() {
+                    last.cut_short = true;
+                }
+                return false;
+            }
+            let name = format!("{}!{}", file_name, entry);
+            if !LOG_EXTENSIONS.contains(&rotation::extension(entry).as_str()) {
+                debug!("skipped {}: not a {} file", name, LOG_EXTENSIONS.join("/"));
+                return true;
+            }
+            let mut watch = Stopwatch::start(self.timed);
+            let mut scan = self.start_scan(&name, modified);
+            let mut order = OrderTally::default();
+            let (contents, stored) = compress::counted(contents);
+            if !self.read_stream(&mut scan, &mut order, &name, contents) {
+                return true;
+            }
+            count_source_bytes(&mut scan, stored.get() as usize);
+            self.finish_scan(&mut scan, order, &mut watch, None);
+            let cut_short = scan.cut_short;
+            scans.push(scan);
+            !cut_short
+        });
+        match read {
+            Err(e) if scans.is_empty() => warn!("could not read {}: {}", file_path.display(), e),
+            Err(e) => warn!("stopped reading {}: {}", file_path.display(), e),
+            Ok(()) => {}
+        }
+        // A change to the archive could have touched any log in it
+        let changed = opened
+            .filter(|_| !interrupt::requested())
+            .and_then(|before| {
+                let file = File::open(file_path).ok()?;
+                changed_while_read(file_path, &file, &before, before.len() as usize)
+            });
+        if let Some(change) = changed {
+            for stats in scans.iter_mut().flat_map(|scan| &mut scan.totals.files) {
+                stats.changed = Some(change.to_string());
+            }
+        }
+        scans
+    }
+
+    /// Streams `file` from `source`, scanning it as it arrives. Bytes read
+    /// are the file's as stored, compressed or not.
+    fn read_remote(
+        &self,
+        source: &dyn Source,
+        file: &RemoteFile,
+        file_name: &str,
+    ) -> Option<FileScan> {
+        let mut watch = Stopwatch::start(self.timed);
+        let contents = match source.open(file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("could not read {}: {}", source.locate(file), e);
+                return None;
+            }
+        };
+        debug!("opened {}", source.locate(file));
+        let mut scan = self.start_scan(file_name, file.modified);
+        let mut order = OrderTally::default();
+        let (contents, read) = compress::counted(contents);
+        if !self.read_stream(&mut scan, &mut order, file_name, contents) {
+            return None;
+        }
+        count_source_bytes(&mut scan, read.get() as usize);
+        self.finish_scan(&mut scan, order, &mut watch, None);
+        Some(scan)
+    }
+
+    /// Scans a compressed file as it decompresses. Bytes read are the
+    /// compressed file's, as with read_transcoded. False if it can't be
+    /// decompressed at all.
+    fn read_compressed(
+        &self,
+        scan: &mut FileScan,
+        order: &mut OrderTally,
+        file: &File,
+        file_name: &str,
+    ) -> bool {
+        let (counted, source) = compress::counted(file);
+        if !self.read_stream(scan, order, file_name, counted) {
+            return false;
+        }
+        count_source_bytes(scan, source.get() as usize);
+        true
+    }
+
+    /// Scans `contents`, decompressing them if they are compressed, in
+    /// --encoding or the charset their text looks like. False if they
+    /// can't be decompressed at all.
+    fn read_stream(
+        &self,
+        scan: &mut FileScan,
+        order: &mut OrderTally,
+        file_name: &str,
+        contents: impl Read,
+    ) -> bool {
+        let mut contents = BufReader::new(contents);
+        let compression = match contents.fill_buf() {
+            Ok(sample) => Compression::sniff(sample),
+            Err(e) => {
+                warn!("could not read {}: {}", file_name, e);
+                return false;
+            }
+        };
+        let text: Box<dyn Read + '_> = match compression {
+            Some(compression) => {
+                debug!("{}: reading as {}", file_name, compression.name());
+                match compress::decompress(contents, compression) {
+                    Ok((decoder, _)) => decoder,
+                    Err(e) => {
+                        warn!("could not read {}: {}", file_name, e);
+                        return false;
+                    }
+                }
+            }
+            None => Box::new(contents),
+        };
+        let mut decompressed = BufReader::new(text);
+        let charset = match self.config.encoding {
+            Some(charset) => charset,
+            None => match decompressed.fill_buf() {
+                Ok(sample) => Charset::sniff(sample),
+                Err(e) => {
+                    warn!("could not decompress {}: {}", file_name, e);
+                    return false;
+                }
+            },
+        };
+        let mut reader: Box<dyn BufRead> = if charset == Charset::Utf8 {
+            Box::new(decompressed)
+        } else {
+            debug!("{}: reading as {}", file_name, charset.name());
+            scan.totals.files_transcoded += 1;
+            Box::new(BufReader::new(Transcode::new(decompressed, charset)))
+        };
+        let mut buf = Vec::new();
+        loop {
+            if interrupt::requested() {
+                scan.cut_short = true;
+                break;
+            }
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => self.feed_line(scan, order, file_name, &buf),
+                Err(e) => {
+                    warn!("stopped reading {}: {}", file_name, e);
+                    break;
+                }
+            }
+        }
+        self.flush_entry(scan, order, file_name);
+        true
+    }
+
+    /// Scans a UTF-16 or Latin-1 file as UTF-8. Bytes read are the file's,
+    /// not the lines'.
+    fn read_transcoded(
+        &self,
+        scan: &mut FileScan,
+        order: &mut OrderTally,
+        file: &File,
+        file_name: &str,
+        charset: Charset,
+    ) {
+        debug!("{}: reading as {}", file_name, charset.name());
+        scan.totals.files_transcoded += 1;
+        let mut reader = BufReader::new(Transcode::new(file, charset));
+        let mut buf = Vec::new();
+        loop {
+            if interrupt::requested() {
+                scan.cut_short = true;
+                break;
+            }
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => self.feed_line(scan, order, file_name, &buf),
+                Err(e) => {
+                    warn!("stopped reading {}: {}", file_name, e);
+                    break;
+                }
+            }
+        }
+        // The last --multiline entry's bytes, before they are replaced
+        self.flush_entry(scan, order, file_name);
+        count_source_bytes(scan, reader.get_ref().source_bytes() as usize);
+    }
+
+    /// Scans the events of a .evtx file as lines (see eventlog). Bytes read
+    /// are the file's, not the lines'.
+    fn read_evtx(
+        &self,
+        scan: &mut FileScan,
+        order: &mut OrderTally,
+        file_path: &Path,
+        file_name: &str,
+    ) {
+        let read = eventlog::for_each_line(file_path, |line| {
+            if interrupt::requested() {
+                scan.cut_short = true;
+                return false;
+            }
+            self.scan_line(scan, order, file_name, line.as_bytes(), 1);
+            true
+        });
+        match read {
+            Ok(0) => {}
+            Ok(skipped) => warn!("{}: skipped {} unreadable events", file_name, skipped),
+            Err(e) => warn!("could not read {}: {}", file_path.display(), e),
+        }
+        count_source_bytes(
+            scan,
+            fs::metadata(file_path).map_or(0, |m| m.len() as usize),
+        );
+    }
+
+    /// Passes `raw`, one line including its line ending, to scan_line, or
+    /// with --multiline adds it to the entry it starts or continues.
+    fn feed_line(&self, scan: &mut FileScan, order: &mut OrderTally, file_name: &str, raw: &[u8]) {
+        let Some(start) = &self.config.multiline else {
+            self.scan_line(scan, order, file_name, raw, 1);
+            return;
+        };
+        if !scan.entry.is_empty()
+            && (scan.entry_lines >= MAX_ENTRY_LINES || starts_entry(start, raw, scan.near))
+        {
+            self.flush_entry(scan, order, file_name);
+        }
+        scan.entry.extend_from_slice(raw);
+        scan.entry_lines += 1;
+    }
+
+    /// Scans the --multiline entry read so far, if any.
+    fn flush_entry(&self, scan: &mut FileScan, order: &mut OrderTally, file_name: &str) {
+        if scan.entry.is_empty() {
+            return;
+        }
+        let mut entry = std::mem::take(&mut scan.entry);
+        let lines = std::mem::take(&mut scan.entry_lines);
+        scan.totals.continuation_lines += lines - 1;
+        self.scan_line(scan, order, file_name, &entry, lines);
+        // Reused for the next entry
+        entry.clear();
+        scan.entry = entry;
+    }
+
+    /// Runs every per-line counter over `raw`, one line including its
+    /// line ending, or with --multiline an entry of `lines` lines; hits
+    /// and samples give the number of its first line.
+    fn scan_line(
+        &self,
+        scan: &mut FileScan,
+        order: &mut OrderTally,
+        file_name: &str,
+        raw: &[u8],
+        lines: usize,
+    ) {
+        scan.totals.total_bytes += raw.len();
+        scan.totals.total_lines += lines;
+        if let Some(progress) = &self.progress {
+            scan.unreported += raw.len();
+            if scan.unreported >= progress::REPORT_EVERY {
+                progress.add_bytes(std::mem::take(&mut scan.unreported));
+            }
+        }
+        let line_number = scan.totals.total_lines + 1 - lines;
+
+        // Same line ending rules as str::lines: "\n" or "\r\n"
+        let mut bytes = raw.strip_suffix(b"\n").unwrap_or(raw);
+        bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if line_number == 1 {
+            bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+        }
+        let line = String::from_utf8_lossy(bytes);
+        // Colors from terminal output would end up in words and searches
+        let line = ansi::strip(&line);
+        let line = self.limit_length(&mut scan.totals, file_name, line_number, line.as_ref());
+        let mut watch = Stopwatch::start(self.timed);
+
+        let prefix = record_prefix(line, self.config.max_record_bytes);
+        if let LineFormat::Delimited(separator) = self.config.line_format {
+            if line_number == 1 {
+                let header = delimited::split(prefix, separator);
+                if delimited::is_header(&header) {
+                    scan.columns = Some(header);
+                    watch.lap(&mut scan.totals.timings.other);
+                    return;
+                }
+            }
+        }
+        // The values of a --delimited line, in column order, stand in for
+        // its message
+        let mut values = None;
+        let object = match self.config.line_format {
+            LineFormat::Text => None,
+            LineFormat::Json => jsonlog::parse(prefix),
+            LineFormat::Logfmt => logfmt::parse(prefix),
+            LineFormat::Access => access::parse(prefix),
+            LineFormat::Syslog => syslog::parse(prefix, scan.near),
+            LineFormat::Cef => cef::parse(prefix, scan.near),
+            LineFormat::Delimited(separator) => {
+                let fields = delimited::split(prefix, separator);
+                values = Some(delimited::text(&fields));
+                Some(delimited::record(fields, scan.columns.as_deref()))
+            }
+        };
+        if object.is_none() && self.config.line_format != LineFormat::Text {
+            scan.totals.unparsed += 1;
+        }
+        let millis = object
+            .as_ref()
+            .and_then(|object| fields::time(object, scan.near))
+            .or_else(|| timestamp::parse_line(line, scan.near));
+        if millis.is_some() {
+            scan.line_time = millis;
+        }
+        let kept = self.in_window(scan.line_time);
+        if let Some(millis) = scan.line_time {
+            scan.since_range.record(millis, self.config.since, kept);
+        }
+        if !kept {
+            scan.totals.outside_window += 1;
+            watch.lap(&mut scan.totals.timings.other);
+            return;
+        }
+
+        let level = object
+            .as_ref()
+            .and_then(fields::level)
+            .or_else(|| self.config.level_rules.detect(line));
+        match level {
+            Some(level) => scan.totals.levels[level.index()] += 1,
+            None => scan.totals.unleveled += 1,
+        }
+        if level.is_some() {
+            scan.line_level = level;
+        }
+        if let Some(min) = self.config.min_level {
+            if scan.line_level.is_none_or(|l| l < min) {
+                scan.totals.below_min_level += 1;
+                watch.lap(&mut scan.totals.timings.other);
+                return;
+            }
+        }
+
+        // Words and templates come from the message of a parsed line, not
+        // from its field names and punctuation
+        let message = object.as_ref().and_then(fields::message);
+        let text = message.as_deref().or(values.as_deref()).unwrap_or(line);
+
+        // Function that borrows &str and &mut WordCounts (references)
+        match &mut scan.totals.approx_words {
+            Some(approx) => count_words_approx(
+                text,
+                approx,
+                &mut scan.totals.distinct_words,
+                self.config.tokenizer,
+                &self.config.word_filter,
+                &mut scan.word,
+            ),
+            None => count_words_in_text(
+                text,
+                &mut scan.totals.word_counts,
+                self.config.tokenizer,
+                &self.config.word_filter,
+                &mut scan.word,
+            ),
+        }
+        for (&n, counts) in self.config.ngrams.iter().zip(&mut scan.totals.ngrams) {
+            count_ngrams(text, n, counts, self.config.tokenizer, &mut scan.word);
+        }
+        errno::count_errnos_in_text(text, file_name, &mut scan.totals.errno_stats);
+        if let Some(budget) = self.file_budget {
+            if scan.totals.approx_words.is_none() && scan.totals.word_bytes() > budget / 2 {
+                debug!(
+                    "{}: counting words approximately to stay within --max-memory",
+                    file_name
+                );
+                scan.totals.switch_to_approx();
+            }
+        }
+        watch.lap(&mut scan.totals.timings.tokenizing);
+
+        let record = Record {
+            text: self.structured_record(&mut scan.totals, file_name, line_number, line),
+            object: object.as_ref(),
+        };
+        let hits_before = scan.totals.hits;
+        self.search_line(scan, line_number, line, &record);
+        if let (Some(width), Some(millis)) = (self.bucket_ms, scan.line_time) {
+            let bucket = scan
+                .totals
+                .histogram
+                .entry(millis.div_euclid(width) * width)
+                .or_default();
+            bucket.lines += 1;
+            bucket.hits += scan.totals.hits - hits_before;
+        }
+        if let Some(pattern) = &self.config.trace_id {
+            if pattern.is_match(line) {
+                scan.totals
+                    .trace_id
+                    .record(scan.line_time, file_name, line_number, line);
+            }
+        }
+        self.check_line(&mut scan.totals, file_name, line_number, line, level);
+        watch.lap(&mut scan.totals.timings.searching);
+
+        self.record_fields(&mut scan.totals, &record);
+        if let Some(key) = &self.config.group_by {
+            let value = record.lookup(key);
+            scan.totals.groups.record(value.as_deref(), level);
+        }
+        for (pattern, tally) in self.config.count_by.iter().zip(&mut scan.totals.counts) {
+            tally.record(pattern, record.text);
+        }
+        for (name, tally) in self.config.distinct.iter().zip(&mut scan.totals.distinct) {
+            tally.record(record.value(name).as_deref());
+        }
+        match (self.config.line_format, record.object) {
+            (LineFormat::Access, Some(object)) => scan.totals.access.record(object),
+            (LineFormat::Syslog, Some(object)) => scan.totals.syslog.record(object),
+            (LineFormat::Cef, Some(object)) => scan.totals.security.record(object),
+            _ => {}
+        }
+        record_origin(&mut scan.totals, text, level);
+        if !text.trim().is_empty() {
+            scan.totals
+                .repeats
+                .record(text, self.config.squeeze_whitespace);
+        }
+        if lines > 1 {
+            if let Some(trace) = traces::find(line) {
+                scan.totals.traces.record(trace, file_name, line_number);
+            }
+        }
+        watch.lap(&mut scan.totals.timings.other);
+
+        let template = scan.totals.templates.add_line(text, file_name, line_number);
+        if let Some(template) = template {
+            self.track_session(scan, template, level, file_name, line_number, &record);
+        }
+        watch.lap(&mut scan.totals.timings.templates);
+
+        if let Some(millis) = millis {
+            order.record(file_name, line_number, line, millis, self.max_gap_ms);
+            self.track_skew(&mut scan.totals, &record, millis);
+        }
+        watch.lap(&mut scan.totals.timings.other);
+    }
+
+    /// Whether a line from `millis` is within --since and --until. Lines
+    /// before the first timestamp of a file are.
+    fn in_window(&self, millis: Option<i64>) -> bool {
+        let Some(millis) = millis else {
+            return true;
+        };
+        self.config.since.is_none_or(|since| millis >= since)
+            && self.config.until.is_none_or(|until| millis <= until)
+    }
+
+    /// `line` cut to --max-line-length, on a character boundary. Cut lines
+    /// are counted, with a short preview.
+    fn limit_length<'l>(
+        &self,
+        totals: &mut ScanTotals,
+        file_name: &str,
+        line_number: usize,
+        line: &'l str,
+    ) -> &'l str {
+        let limit = match self.config.max_line_length {
+            Some(limit) if line.len() > limit => limit,
+            _ => return line,
+        };
+        let mut end = limit;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        let preview: String = line.chars().take(PREVIEW_CHARS).collect();
+        totals.long_lines.record(
+            file_name,
+            line_number,
+            &format!("{}… ({} bytes)", preview, line.len()),
+        );
+        &line[..end]
+    }
+
+    /// The part of `line` that field lookups and payload decoding get to
+    /// see: at most --max-record-bytes and --max-fields. Cut lines are
+    /// counted, with a short preview.
+    fn structured_record<'l>(
+        &self,
+        totals: &mut ScanTotals,
+        file_name: &str,
+        line_number: usize,
+        line: &'l str,
+    ) -> &'l str {
+        let (record, too_many_fields) = fields::limit_fields(
+            record_prefix(line, self.config.max_record_bytes),
+            self.config.max_fields,
+        );
+        if record.len() < line.len() || too_many_fields {
+            let preview: String = line.chars().take(PREVIEW_CHARS).collect();
+            debug!(
+                "{}:{}: record of {} bytes cut to {} for parsing",
+                file_name,
+                line_number,
+                line.len(),
+                record.len()
+            );
+            totals
+                .truncated_records
+                .record(file_name, line_number, &format!("{}…", preview));
+        }
+        record
+    }
+
+    /// Records the line if it (or, with --decode-nested, a payload inside
+    /// `record`) contains the --find text. With --field, only those fields
+    /// of a parsed line are searched.
+    fn search_line(&self, scan: &mut FileScan, line_number: usize, line: &str, record: &Record) {
+        let needle = match &self.needle {
+            Some(n) => n,
+            None => return,
+        };
+
+        let found = match record.object {
+            Some(_) if !self.config.find_fields.is_empty() => {
+                self.config.find_fields.iter().any(|name| {
+                    record
+                        .value(name)
+                        .is_some_and(|value| needle.is_in(value.as_bytes()))
+                })
+            }
+            _ => needle.is_in(line.as_bytes()),
+        };
+        if found {
+            self.record_find(scan, line_number, line, None);
+            return;
+        }
+
+        if !self.config.decode_nested {
+            return;
+        }
+
+        let payloads = decode::decode_nested(record.text);
+        for payload in &payloads {
+            *scan.totals.decoded.entry(payload.encoding).or_insert(0) += 1;
+        }
+
+        if let Some(payload) = payloads
+            .into_iter()
+            .find(|p| needle.is_in(p.text.as_bytes()))
+        {
+            self.record_find(scan, line_number, line, Some(payload));
+        }
+    }
+
+    fn record_find(
+        &self,
+        scan: &mut FileScan,
+        line_number: usize,
+        line: &str,
+        decoded: Option<Decoded>,
+    ) {
+        scan.totals
+            .record_hit(Rule::Find, self.keep_matches, &scan.name, line_number, line);
+        let first = scan.hit_lines.map_or(line_number, |(first, _)| first);
+        scan.hit_lines = Some((first, line_number));
+        if self.keep_hits {
+            scan.hit_bytes += std::mem::size_of::<FoundHit>()
+                + line.len()
+                + decoded.as_ref().map_or(0, |d| d.text.len());
+            scan.hits.push(FoundHit {
+                line_number,
+                line: line.to_string(),
+                decoded,
+            });
+            if self
+                .file_budget
+                .is_some_and(|budget| scan.hit_bytes > budget / 2)
+            {
+                spill_hits(scan);
+            }
+        }
+    }
+
+    /// Feeds --stats-field values found on the line into their stats.
+    fn record_fields(&self, totals: &mut ScanTotals, record: &Record) {
+        for (key, stats) in self.config.stats_fields.iter().zip(&mut totals.field_stats) {
+            if let Some(value) = record.lookup(key) {
+                stats.record(&value);
+            }
+        }
+    }
+
+    /// Remembers when this file first logged the line's --skew-key value.
+    fn track_skew(&self, totals: &mut ScanTotals, record: &Record, millis: i64) {
+        let Some(key) = &self.config.skew_key else {
+            return;
+        };
+        if let Some(value) = record.value(key) {
+            // This file becomes index 0 of the totals once it is pushed
+            totals.skew.record(&value, 0, millis);
+        }
+    }
+
+    fn track_session(
+        &self,
+        scan: &mut FileScan,
+        template: usize,
+        level: Option<Level>,
+        file_name: &str,
+        line_number: usize,
+        record: &Record,
+    ) {
+        let Some(key) = &self.config.session_key else {
+            return;
+        };
+        if let Some(id) = record.value(key) {
+            scan.totals
+                .sessions
+                .record(&id, template, level, scan.line_time, || {
+                    format!("{}:{}: {}", file_name, line_number, record.text)
+                });
+        }
+    }
+
+    /// Counts the line, at `level` if it has one, against every --check
+    /// rule it matches, and keeps its fingerprint if it is at one of
+    /// Config::fingerprint_levels.
+    fn check_line(
+        &self,
+        totals: &mut ScanTotals,
+        file_name: &str,
+        line_number: usize,
+        line: &str,
+        level: Option<Level>,
+    ) {
+        if level.is_some_and(|l| self.config.fingerprint_levels.contains(&l)) {
+            let template = fingerprint::template(line);
+            totals
+                .fingerprints
+                .entry(fingerprint::fingerprint(&template))
+                .or_insert_with(|| Seen {
+                    template,
+                    file: file_name.to_string(),
+                    line_number,
+                    line: line.to_string(),
+                });
+        }
+        if self.config.checks.is_empty() {
+            return;
+        }
+        for (i, check) in self.config.checks.iter().enumerate() {
+            if check.matches(line, level) {
+                totals.record_hit(
+                    Rule::Check(i),
+                    self.keep_matches,
+                    file_name,
+                    line_number,
+                    line,
+                );
+            }
+        }
+    }
+}
+
+/// Whether `raw`, one line including its line ending, starts a --multiline
+/// entry; syslog dates are taken to be near `near`.
+fn starts_entry(start: &EntryStart, raw: &[u8], near: i64) -> bool {
+    let line = String::from_utf8_lossy(raw);
+    let line = ansi::strip(&line);
+    match start {
+        EntryStart::Timestamp => timestamp::parse_line(&line, near).is_some(),
+        EntryStart::Pattern(pattern) => pattern.is_match(line.trim_end_matches(['\r', '\n'])),
+    }
+}
+
+/// The first `max` bytes of `line`, or a little less to end on a character.
+fn record_prefix(line: &str, max: usize) -> &str {
+    let mut end = line.len().min(max);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// Maps `file` for --mmap; None (read normally) for empty files or when
+/// mapping fails.
+fn map_file(file: &File, path: &Path) -> Option<Mmap> {
+    if file.metadata().map_or(true, |m| m.len() == 0) {
+        return None;
+    }
+    // SAFETY: the mapping is only read, and lives no longer than this
+    // file's scan. A file truncated by another process while mapped can
+    // still raise SIGBUS, which is why --mmap is opt-in.
+    match unsafe { Mmap::map(file) } {
+        Ok(map) => Some(map),
+        Err(e) => {
+            debug!(
+                "could not map {}, reading it instead: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Counts the line against the source location it names, if any.
+fn record_origin(totals: &mut ScanTotals, line: &str, level: Option<Level>) {
+    let Some((path, line_number)) = origins::find_origin(line) else {
+        return;
+    };
+    let tally = totals
+        .origins
+        .entry((path.to_string(), line_number))
+        .or_default();
+    tally.lines += 1;
+    if level.is_some_and(|l| l >= Level::Error) {
+        tally.errors += 1;
+    }
+}
+
+/// When the file `meta` describes was last modified, as Unix millis.
+pub fn modified_millis(meta: &fs::Metadata) -> Option<i64> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(modified.as_millis() as i64)
+}
+
+/// How the file at `path`, opened as `file` when its metadata was
+/// `before`, changed while `read` bytes of it were read; None if it
+/// didn't. A rotated file shows up as removed or replaced.
+fn changed_while_read(
+    path: &Path,
+    file: &File,
+    before: &fs::Metadata,
+    read: usize,
+) -> Option<&'static str> {
+    match fs::metadata(path) {
+        Err(_) => return Some("removed"),
+        Ok(now) if file_id(&now) != file_id(before) => return Some("replaced"),
+        Ok(_) => {}
+    }
+    let now = file.metadata().ok()?;
+    let read = read as u64;
+    if now.len() < before.len() || read < before.len() {
+        Some("shrank")
+    } else if now.len() > before.len() || read > before.len() {
+        Some("grew")
+    } else if now.modified().ok() != before.modified().ok() {
+        Some("was modified")
+    } else {
+        None
+    }
+}
+
+/// Device and inode, which stay the same while a file is renamed.
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// The .txt and .log files directly inside `path` (or below it, when
+/// `recursive`), with their names.
+pub fn log_files(path: &Path, recursive: bool) -> Result<Vec<(PathBuf, String)>, String> {
+    files_with(path, &LOG_EXTENSIONS, recursive)
+}
+
+/// What a scan reads: the .txt and .log files directly inside `path` (or,
+/// with --recursive, anywhere below it), Windows event logs (.evtx), and
+/// the archives whose logs it reads too (.tar, .tar.gz, .tgz, .zip).
+pub fn scan_files(path: &Path, recursive: bool) -> Result<Vec<(PathBuf, String)>, String> {
+    files_with(
+        path,
+        &["txt", "log", "evtx", "tar", "tgz", "zip"],
+        recursive,
+    )
+}
+
+/// The files with one of `extensions` (lower case) directly inside `path`,
+/// or in its subfolders too when `recursive`; those are named by their
+/// path from `path` (`nginx/access.log`). Symlinked folders are left out,
+/// so a link back up can't loop. Folders are listed on several threads, as
+/// a tree of millions of entries on NFS takes one a long time; files come
+/// in name order whichever thread found them, rotated ones together,
+/// oldest first (see rotation::order).
+fn files_with(
+    path: &Path,
+    extensions: &[&str],
+    recursive: bool,
+) -> Result<Vec<(PathBuf, String)>, String> {
+    // The folder itself must be readable; unreadable ones below it are
+    // skipped
+    fs::read_dir(path).map_err(|e| format!("Could not read folder: {}", e))?;
+    let files = Mutex::new(Vec::new());
+    WalkBuilder::new(path)
+        .standard_filters(false)
+        .follow_links(false)
+        .max_depth((!recursive).then_some(1))
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                match entry {
+                    Ok(entry) => {
+                        if let Some(file) = listed_file(path, &entry, extensions) {
+                            files.lock().unwrap().push(file);
+                        }
+                    }
+                    Err(e) => warn!("skipping {}", e),
+                }
+                WalkState::Continue
+            })
+        });
+    let mut files = files.into_inner().unwrap();
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(rotation::order(files))
+}
+
+/// `entry`, found walking `root`, and its name from there when it is a
+/// regular file with one of `extensions`.
+fn listed_file(root: &Path, entry: &DirEntry, extensions: &[&str]) -> Option<(PathBuf, String)> {
+    let file_path = entry.path();
+    if entry.depth() == 0 || entry.file_type().is_some_and(|t| t.is_dir()) {
+        return None;
+    }
+    // Only handle regular files with one of the extensions, rotated
+    // ones (app.log.1) too. The extension is checked first and the
+    // type comes from the directory entry, so only symlinks cost a
+    // stat call (slow on NFS)
+    let ext = rotation::extension(&entry.file_name().to_string_lossy());
+    if !extensions.contains(&ext.as_str()) {
+        debug!(
+            "skipped {}: not a {} file",
+            file_path.display(),
+            extensions.join("/")
+        );
+        return None;
+    }
+
+    let is_file = match entry.file_type() {
+        Some(t) if t.is_symlink() => file_path.is_file(),
+        Some(t) => t.is_file(),
+        None => file_path.is_file(),
+    };
+    if !is_file {
+        debug!("skipped {}: not a regular file", file_path.display());
+        return None;
+    }
+
+    let file_name = file_path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((file_path.to_path_buf(), file_name))
+}
+
+/// The `k` most frequent words of one file's totals, most frequent first.
+fn top_words(totals: &ScanTotals, k: usize) -> Vec<(String, usize)> {
+    let mut words: Vec<(&str, usize)> = totals.words().collect();
+    let by_count = |a: &(&str, usize), b: &(&str, usize)| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0));
+    if words.len() > k {
+        words.select_nth_unstable_by(k, by_count);
+        words.truncate(k);
+    }
+    words.sort_by(by_count);
+    words
+        .into_iter()
+        .map(|(word, count)| (word.to_string(), count))
+        .collect()
+}
+
+/// Function that borrows a &str and &mut WordCounts.
+/// Demonstrates loops, references, and expressions. `scratch` holds each
+/// lowercased word; only words new to `counts` are copied.
+fn count_words_in_text(
+    text: &str,
+    counts: &mut WordCounts,
+    tokenizer: Tokenizer,
+    filter: &WordFilter,
+    scratch: &mut String,
+) {
+    tokenizer.for_each_word(text, |word| {
+        let w = normalize_word(word, scratch);
+        if !w.is_empty() && filter.keeps(w) {
+            counts.add(w, 1);
+        }
+    });
+}
+
+/// Same words as count_words_in_text, into fixed-size approximate counts.
+fn count_words_approx(
+    text: &str,
+    counts: &mut ApproxCounts,
+    distinct: &mut HyperLogLog,
+    tokenizer: Tokenizer,
+    filter: &WordFilter,
+    scratch: &mut String,
+) {
+    tokenizer.for_each_word(text, |word| {
+        let w = normalize_word(word, scratch);
+        if !w.is_empty() && filter.keeps(w) {
+            counts.add(w);
+            distinct.add(w);
+        }
+    });
+}
+
+/// Phrases of `n` consecutive words in each line of `text`, normalized as
+/// words are and joined by spaces. Words with digits (ids, counts, times)
+/// end a phrase instead of being in one.
+fn count_ngrams(
+    text: &str,
+    n: usize,
+    counts: &mut ApproxCounts,
+    tokenizer: Tokenizer,
+    scratch: &mut String,
+) {
+    let mut words: Vec<String> = Vec::new();
+    let mut flush = |words: &mut Vec<String>| {
+        for phrase in words.windows(n) {
+            counts.add(&phrase.join(" "));
+        }
+        words.clear();
+    };
+    for line in text.lines() {
+        tokenizer.for_each_word(line, |word| {
+            let w = normalize_word(word, scratch);
+            if w.is_empty() || w.bytes().any(|b| b.is_ascii_digit()) {
+                flush(&mut words);
+            } else {
+                words.push(w.to_string());
+            }
+        });
+        flush(&mut words);
+    }
+}
+
+/// Lowercase, without leading or trailing punctuation, written to `out`.
+/// ASCII words are lowered in place, without allocating.
+pub fn normalize_word<'o>(word: &str, out: &'o mut String) -> &'o str {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric() && !tokenize::is_mark(c));
+    out.clear();
+    if word.is_ascii() {
+        out.push_str(word);
+        out.make_ascii_lowercase();
+    } else {
+        // str::to_lowercase also handles final sigma and the like
+        out.push_str(&word.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_log_files_in_name_order() {
+        let root = std::env::temp_dir().join(format!("logbuddy-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["b", "a/deep", "c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "z.log",
+            "app.log",
+            "app.log.1",
+            "app.log.2.gz",
+            "notes.md",
+            "b/x.txt",
+            "a/deep/y.log",
+            "a/w.LOG",
+            "c/dir.log/inner.log",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "INFO ok\n").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("c/loop")).unwrap();
+
+        let names = |recursive| -> Vec<String> {
+            log_files(&root, recursive)
+                .unwrap()
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect()
+        };
+        assert_eq!(
+            names(false),
+            ["app.log.2.gz", "app.log.1", "app.log", "z.log"]
+        );
+        assert_eq!(
+            names(true),
+            [
+                "a/deep/y.log",
+                "a/w.LOG",
+                "app.log.2.gz",
+                "app.log.1",
+                "app.log",
+                "b/x.txt",
+                "c/dir.log/inner.log",
+                "z.log"
+            ]
+        );
+        assert!(log_files(&root.join("missing"), true).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn gaps_run_from_the_latest_timestamp() {
+        let mut order = OrderTally::default();
+        // Seconds: the third line is a minute behind
+        for (line_number, secs) in [(1, 0), (2, 300), (3, 240), (4, 310), (5, 1000)] {
+            order.record("app.log", line_number, "line", secs * 1000, Some(60_000));
+        }
+        assert_eq!(order.backwards.count, 1);
+        assert_eq!(order.max_regression_ms, 60_000);
+        assert_eq!((order.first, order.last), (Some(0), Some(1_000_000)));
+        let gaps: Vec<(i64, i64, usize)> = order
+            .longest_gaps
+            .iter()
+            .map(|g| (g.from_ms, g.to_ms, g.line_number))
+            .collect();
+        // 300s to 310s, not 240s to 310s
+        assert_eq!(
+            gaps,
+            [
+                (310_000, 1_000_000, 5),
+                (0, 300_000, 2),
+                (300_000, 310_000, 4)
+            ]
+        );
+        assert_eq!(order.long_gaps, 2);
+    }
+}
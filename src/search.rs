@@ -0,0 +1,44 @@
+//! Case-insensitive literal search that doesn't allocate a lowercase copy
+//! of every line.
+
+use memchr::memmem::Finder;
+
+/// Text to look for, ignoring case.
+#[derive(Debug, Clone)]
+pub struct Needle {
+    lower: String,
+    /// Only for needles with non-ASCII text, which fall back to
+    /// lowercasing the haystack
+    unicode: Option<Finder<'static>>,
+}
+
+impl Needle {
+    pub fn new(text: &str) -> Needle {
+        let lower = text.to_lowercase();
+        let unicode = (!lower.is_ascii()).then(|| Finder::new(lower.as_bytes()).into_owned());
+        Needle { lower, unicode }
+    }
+
+    /// Whether `haystack` contains the needle.
+    pub fn is_in(&self, haystack: &[u8]) -> bool {
+        if let Some(finder) = &self.unicode {
+            let lower = String::from_utf8_lossy(haystack).to_lowercase();
+            return finder.find(lower.as_bytes()).is_some();
+        }
+
+        let needle = self.lower.as_bytes();
+        let Some(&first) = needle.first() else {
+            return true;
+        };
+        // memchr finds candidates for the first byte in either case, then
+        // only those positions are compared in full
+        let upper = first.to_ascii_uppercase();
+        let last_start = match haystack.len().checked_sub(needle.len()) {
+            Some(n) => n,
+            None => return false,
+        };
+        let window = &haystack[..last_start + 1];
+        let mut candidates = memchr::memchr2_iter(first, upper, window);
+        candidates.any(|i| haystack[i..i + needle.len()].eq_ignore_ascii_case(needle))
+    }
+}
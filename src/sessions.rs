@@ -0,0 +1,218 @@
+//! Rebuilds per-session event sequences from the lines that carry the
+//! same --session-key value, with each line's message template as the
+//! event, and how long each lasted by the timestamps of its first and last
+//! lines.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::level::Level;
+
+/// Events kept per session; longer sessions only count the rest.
+pub const MAX_EVENTS: usize = 20;
+
+/// Words in a last event that mean the session ended on purpose.
+const END_WORDS: &[&str] = &[
+    "logout",
+    "logged out",
+    "log out",
+    "logoff",
+    "signed out",
+    "sign out",
+    "session closed",
+    "session ended",
+    "disconnect",
+];
+
+/// The events of one session, in the order the lines were scanned.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Template indexes into Drain::clusters, repeats in a row collapsed
+    pub events: Vec<usize>,
+    /// Template of the last line, even past MAX_EVENTS
+    pub last_event: usize,
+    /// Lines seen, including the ones past MAX_EVENTS
+    pub lines: usize,
+    /// "file:line: text" of the last line, when it was ERROR or FATAL
+    pub failed_at: Option<String>,
+    /// Unix millis of its earliest and latest timestamped lines
+    pub first_ms: Option<i64>,
+    pub last_ms: Option<i64>,
+}
+
+/// How a session's last event left it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    /// Last line at ERROR or FATAL
+    Failed,
+    /// Last event looks like a logout or disconnect
+    Ended,
+    /// Neither; the session may still be going
+    Open,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SessionTracker {
+    pub sessions: HashMap<String, Session>,
+}
+
+impl SessionTracker {
+    /// Adds a line to session `id`; `location` gives its "file:line: text",
+    /// kept if the line is an error.
+    pub fn record(
+        &mut self,
+        id: &str,
+        event: usize,
+        level: Option<Level>,
+        millis: Option<i64>,
+        location: impl FnOnce() -> String,
+    ) {
+        let session = match self.sessions.get_mut(id) {
+            Some(s) => s,
+            None => self.sessions.entry(id.to_string()).or_default(),
+        };
+        session.push(event);
+        session.lines += 1;
+        session.saw_time(millis);
+        session.failed_at = level.filter(|l| *l >= Level::Error).map(|_| location());
+    }
+
+    /// Appends the sessions of later files; `events` maps their template
+    /// indexes to the ones in this scan (see Drain::merge).
+    pub fn merge(&mut self, other: SessionTracker, events: &[usize]) {
+        for (id, more) in other.sessions {
+            let session = self.sessions.entry(id).or_default();
+            for event in more.events {
+                session.push(events[event]);
+            }
+            session.last_event = events[more.last_event];
+            session.lines += more.lines;
+            session.failed_at = more.failed_at;
+            session.saw_time(more.first_ms);
+            session.saw_time(more.last_ms);
+        }
+    }
+}
+
+impl Session {
+    fn push(&mut self, event: usize) {
+        if self.events.last() != Some(&event) && self.events.len() < MAX_EVENTS {
+            self.events.push(event);
+        }
+        self.last_event = event;
+    }
+
+    fn saw_time(&mut self, millis: Option<i64>) {
+        let Some(millis) = millis else {
+            return;
+        };
+        self.first_ms = Some(self.first_ms.map_or(millis, |first| first.min(millis)));
+        self.last_ms = Some(self.last_ms.map_or(millis, |last| last.max(millis)));
+    }
+
+    /// Time from its first timestamp to its last; None without any.
+    pub fn duration_ms(&self) -> Option<i64> {
+        Some(self.last_ms? - self.first_ms?)
+    }
+
+    /// `template` gives the text of an event's template.
+    pub fn ending<'a>(&self, template: impl Fn(usize) -> &'a str) -> Ending {
+        if self.failed_at.is_some() {
+            return Ending::Failed;
+        }
+        let last = template(self.last_event).to_lowercase();
+        if END_WORDS.iter().any(|w| last.contains(w)) {
+            Ending::Ended
+        } else {
+            Ending::Open
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATES: [&str; 4] = [
+        "user <*> logged in",
+        "opened cart <*>",
+        "payment declined <*>",
+        "user <*> logged out",
+    ];
+
+    fn template(event: usize) -> &'static str {
+        TEMPLATES[event]
+    }
+
+    fn record(tracker: &mut SessionTracker, id: &str, event: usize, level: Level, secs: i64) {
+        tracker.record(id, event, Some(level), Some(secs * 1000), || {
+            format!("app.log:{}: {}", secs, TEMPLATES[event])
+        });
+    }
+
+    #[test]
+    fn events_and_endings() {
+        let mut tracker = SessionTracker::default();
+        record(&mut tracker, "s1", 0, Level::Info, 10);
+        record(&mut tracker, "s2", 0, Level::Info, 11);
+        record(&mut tracker, "s1", 1, Level::Info, 12);
+        record(&mut tracker, "s1", 1, Level::Info, 13);
+        record(&mut tracker, "s2", 2, Level::Error, 20);
+        record(&mut tracker, "s1", 3, Level::Info, 40);
+        tracker.record("s3", 1, None, None, || unreachable!());
+
+        let s1 = &tracker.sessions["s1"];
+        // Repeats in a row collapse, but every line counts
+        assert_eq!((s1.events.as_slice(), s1.lines), (&[0, 1, 3][..], 4));
+        assert_eq!(s1.duration_ms(), Some(30_000));
+        assert_eq!(s1.ending(template), Ending::Ended);
+
+        let s2 = &tracker.sessions["s2"];
+        assert_eq!(
+            s2.failed_at.as_deref(),
+            Some("app.log:20: payment declined <*>")
+        );
+        assert_eq!(s2.ending(template), Ending::Failed);
+
+        let s3 = &tracker.sessions["s3"];
+        assert_eq!(s3.duration_ms(), None);
+        assert_eq!(s3.ending(template), Ending::Open);
+    }
+
+    #[test]
+    fn long_sessions_keep_their_last_event() {
+        let mut tracker = SessionTracker::default();
+        for i in 0..MAX_EVENTS + 5 {
+            tracker.record("s", i % 2, Some(Level::Info), None, String::new);
+        }
+        tracker.record("s", 3, Some(Level::Info), None, String::new);
+        let s = &tracker.sessions["s"];
+        assert_eq!(s.events.len(), MAX_EVENTS);
+        assert_eq!((s.lines, s.last_event), (MAX_EVENTS + 6, 3));
+        assert_eq!(s.ending(template), Ending::Ended);
+    }
+
+    #[test]
+    fn merges_later_files() {
+        let mut earlier = SessionTracker::default();
+        record(&mut earlier, "s1", 0, Level::Info, 100);
+        record(&mut earlier, "s1", 2, Level::Error, 150);
+        // The later file's templates were numbered 0 = "opened cart",
+        // 1 = "user logged in", 2 = "logged out"
+        let mut later = SessionTracker::default();
+        later.record("s1", 0, Some(Level::Info), Some(90_000), String::new);
+        later.record("s1", 2, Some(Level::Info), Some(200_000), String::new);
+        later.record("s2", 1, Some(Level::Info), None, String::new);
+        earlier.merge(later, &[1, 0, 3]);
+
+        let s1 = &earlier.sessions["s1"];
+        assert_eq!(s1.events, [0, 2, 1, 3]);
+        assert_eq!(s1.lines, 4);
+        assert_eq!((s1.first_ms, s1.last_ms), (Some(90_000), Some(200_000)));
+        // The error wasn't its last line after all
+        assert_eq!(s1.failed_at, None);
+        assert_eq!(s1.ending(template), Ending::Ended);
+        assert_eq!(earlier.sessions["s2"].events, [0]);
+    }
+}
@@ -0,0 +1,757 @@
+//! Places a scan writes to while and after it runs. The scanner hands every
+//! event to each sink instead of printing itself, so one run can show hits
+//! in the terminal and write them to files at the same time.
+
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cli::Config;
+use crate::decode::Decoded;
+use crate::interrupt;
+use crate::links::{self, Linker};
+use crate::report::Section;
+use crate::scanner::ScanTotals;
+use crate::style::{self, Style};
+use crate::usage::ResourceUsage;
+
+/// A --find match, as handed to sinks.
+pub struct Hit<'a> {
+    pub file: &'a str,
+    pub line_number: usize,
+    pub line: &'a str,
+    /// Set when only a nested payload of the line matched
+    pub decoded: Option<&'a Decoded>,
+}
+
+pub trait OutputSink {
+    /// Called before the lines of `path` are scanned; None for a file of
+    /// a source, which isn't on disk.
+    fn file_started(&mut self, _path: Option<&Path>, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn hit(&mut self, hit: &Hit) -> io::Result<()>;
+
+    /// Called once after the last file, with everything that was counted.
+    fn finish(&mut self, _totals: &ScanTotals) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Value of --sink: where hits (and for some, the totals) go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkSpec {
+    /// Hit lines on stdout, colored; the default without any --sink
+    Console,
+    /// Plain "file:line: text" hit lines
+    File(String),
+    /// One JSON object per hit
+    Jsonl(String),
+    /// Hits POSTed as JSON batches, then a summary
+    Webhook(String),
+    /// Hits indexed through the bulk API; the URL names the index
+    Elasticsearch(String),
+    /// Totals in the Prometheus text format, for the node_exporter
+    /// textfile collector
+    Prometheus(String),
+    /// Every word and its count, most frequent first
+    WordsCsv(String),
+}
+
+impl SinkSpec {
+    /// Parses "console", "jsonl:hits.jsonl", "webhook:https://..." and so on.
+    pub fn parse(spec: &str) -> Result<SinkSpec, String> {
+        if spec == "console" {
+            return Ok(SinkSpec::Console);
+        }
+        let (kind, target) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("--sink '{}' should be console or KIND:TARGET", spec))?;
+        if target.is_empty() {
+            return Err(format!("--sink '{}' is missing a target", spec));
+        }
+        let target = target.to_string();
+        match kind {
+            "file" => Ok(SinkSpec::File(target)),
+            "jsonl" => Ok(SinkSpec::Jsonl(target)),
+            "webhook" => Ok(SinkSpec::Webhook(target)),
+            "elasticsearch" => Ok(SinkSpec::Elasticsearch(target)),
+            "prometheus" => Ok(SinkSpec::Prometheus(target)),
+            "words-csv" => Ok(SinkSpec::WordsCsv(target)),
+            other => Err(format!(
+                "unknown --sink kind '{}' (expected console, file, jsonl, webhook, \
+                 elasticsearch, prometheus or words-csv)",
+                other
+            )),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SinkSpec::Console => "console",
+            SinkSpec::File(_) => "file",
+            SinkSpec::Jsonl(_) => "jsonl",
+            SinkSpec::Webhook(_) => "webhook",
+            SinkSpec::Elasticsearch(_) => "elasticsearch",
+            SinkSpec::Prometheus(_) => "prometheus",
+            SinkSpec::WordsCsv(_) => "words-csv",
+        }
+    }
+
+    /// Whether the sink only writes hits, so it needs --find.
+    pub fn hits_only(&self) -> bool {
+        matches!(
+            self,
+            SinkSpec::File(_) | SinkSpec::Jsonl(_) | SinkSpec::Elasticsearch(_)
+        )
+    }
+}
+
+/// One sink per --sink, or the terminal when hit lines are echoed and
+/// there is none.
+pub fn open_all(config: &Config) -> Result<Vec<Box<dyn OutputSink>>, String> {
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+    if config.sinks.is_empty() && config.chatty() && config.shows(Section::Hits) {
+        sinks.push(Box::new(ConsoleSink::new(config)));
+    }
+
+    for spec in &config.sinks {
+        sinks.push(match spec {
+            SinkSpec::Console => Box::new(ConsoleSink::new(config)),
+            SinkSpec::File(path) => Box::new(PlainHits { out: create(path)? }),
+            SinkSpec::Jsonl(path) => Box::new(NdjsonHits { out: create(path)? }),
+            SinkSpec::Webhook(url) => Box::new(Webhook {
+                url: url.clone(),
+                batch: Vec::new(),
+            }),
+            SinkSpec::Elasticsearch(url) => Box::new(Elasticsearch {
+                bulk_url: format!("{}/_bulk", url.trim_end_matches('/')),
+                body: String::new(),
+                pending: 0,
+            }),
+            SinkSpec::Prometheus(path) => Box::new(Prometheus {
+                path: path.clone(),
+                checks: config.checks.iter().map(|c| c.pattern.clone()).collect(),
+            }),
+            SinkSpec::WordsCsv(path) => Box::new(WordsCsv { out: create(path)? }),
+        });
+    }
+    Ok(sinks)
+}
+
+fn create(path: &str) -> Result<BufWriter<File>, String> {
+    let file = File::create(path).map_err(|e| format!("could not create {}: {}", path, e))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Echoes hit lines to stdout as they are found, colored and linked.
+struct ConsoleSink {
+    /// --find text, lowercased, for highlighting
+    needle: String,
+    style: Style,
+    /// Set when hit locations are printed as OSC 8 hyperlinks
+    linker: Option<Linker>,
+    /// Linker::encoded_path of the file being scanned, when linking; None
+    /// for a file of a source, which has no local path to link to
+    link_path: Option<String>,
+    /// Set once stdout's reader has gone away
+    closed: bool,
+}
+
+impl ConsoleSink {
+    fn new(config: &Config) -> Self {
+        ConsoleSink {
+            needle: config.find.as_deref().unwrap_or("").to_lowercase(),
+            style: Style::new(config.color, &config.theme),
+            linker: style::hyperlinks_enabled(config.hyperlinks).then(|| {
+                Linker::new(
+                    config
+                        .link_template
+                        .as_deref()
+                        .unwrap_or(links::DEFAULT_TEMPLATE),
+                )
+            }),
+            link_path: None,
+            closed: false,
+        }
+    }
+
+    fn write_hit(&self, hit: &Hit) -> io::Result<()> {
+        let location = format!("{}:{}:", hit.file, hit.line_number);
+        let mut location = self.style.location(&location);
+        if let (Some(linker), Some(path)) = (&self.linker, &self.link_path) {
+            let url = linker.url(path, hit.file, hit.line_number);
+            location = style::hyperlink(&url, &location);
+        }
+
+        let mut out = io::stdout().lock();
+        writeln!(
+            out,
+            "{} {}",
+            location,
+            self.style.hit_line(hit.line, &self.needle)
+        )?;
+        if let Some(payload) = hit.decoded {
+            let decoded = self.style.hit_line(&payload.text, &self.needle);
+            writeln!(out, "    decoded {}: {}", payload.encoding.name(), decoded)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for ConsoleSink {
+    fn file_started(&mut self, path: Option<&Path>, _name: &str) -> io::Result<()> {
+        if self.linker.is_some() {
+            self.link_path = path.map(Linker::encoded_path);
+        }
+        Ok(())
+    }
+
+    /// Hits after stdout's reader went away (`| head`) aren't errors: the
+    /// scan stops, as it would on Ctrl-C, without a word.
+    fn hit(&mut self, hit: &Hit) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        match self.write_hit(hit) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                self.closed = true;
+                interrupt::output_closed();
+                Ok(())
+            }
+            written => written,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HitRecord<'a> {
+    file: &'a str,
+    line: usize,
+    text: &'a str,
+    /// Encoding of the nested payload that matched, if not the line itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded_text: Option<&'a str>,
+}
+
+impl<'a> HitRecord<'a> {
+    fn new(hit: &Hit<'a>) -> Self {
+        HitRecord {
+            file: hit.file,
+            line: hit.line_number,
+            text: hit.line,
+            decoded: hit.decoded.map(|p| p.encoding.name()),
+            decoded_text: hit.decoded.map(|p| p.text.as_str()),
+        }
+    }
+}
+
+struct NdjsonHits {
+    out: BufWriter<File>,
+}
+
+impl OutputSink for NdjsonHits {
+    fn hit(&mut self, hit: &Hit) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &HitRecord::new(hit))?;
+        self.out.write_all(b"\n")
+    }
+
+    fn finish(&mut self, _totals: &ScanTotals) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+struct PlainHits {
+    out: BufWriter<File>,
+}
+
+impl OutputSink for PlainHits {
+    fn hit(&mut self, hit: &Hit) -> io::Result<()> {
+        writeln!(self.out, "{}:{}: {}", hit.file, hit.line_number, hit.line)
+    }
+
+    fn finish(&mut self, _totals: &ScanTotals) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Hits per request to a webhook or Elasticsearch.
+const BATCH: usize = 500;
+
+/// POSTs `{"hits": [...]}` every BATCH hits, and `{"summary": {...}}` at
+/// the end.
+struct Webhook {
+    url: String,
+    /// Hits not sent yet, as JSON
+    batch: Vec<String>,
+}
+
+impl Webhook {
+    fn send_batch(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let body = format!("{{\"hits\":[{}]}}", self.batch.join(","));
+        self.batch.clear();
+        post(&self.url, "application/json", body).map(|_| ())
+    }
+}
+
+impl OutputSink for Webhook {
+    fn hit(&mut self, hit: &Hit) -> io::Result<()> {
+        self.batch
+            .push(serde_json::to_string(&HitRecord::new(hit))?);
+        if self.batch.len() >= BATCH {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, totals: &ScanTotals) -> io::Result<()> {
+        self.send_batch()?;
+        let summary = serde_json::json!({
+            "summary": {
+                "files": totals.files_scanned,
+                "lines": totals.total_lines,
+                "bytes": totals.total_bytes,
+                "hits": totals.hits,
+            }
+        });
+        post(&self.url, "application/json", summary.to_string()).map(|_| ())
+    }
+}
+
+/// Indexes every hit as a document through the bulk API.
+struct Elasticsearch {
+    /// The index URL plus /_bulk
+    bulk_url: String,
+    /// Action and document lines not sent yet
+    body: String,
+    pending: usize,
+}
+
+impl Elasticsearch {
+    fn send_batch(&mut self) -> io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let body = std::mem::take(&mut self.body);
+        self.pending = 0;
+        let response = post(&self.bulk_url, "application/x-ndjson", body)?;
+        // The bulk API answers 200 even when documents were rejected
+        let reply: serde_json::Value = serde_json::from_str(&response)?;
+        if reply["errors"].as_bool() == Some(true) {
+            return Err(io::Error::other("elasticsearch rejected some hits"));
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for Elasticsearch {
+    fn hit(&mut self, hit: &Hit) -> io::Result<()> {
+        self.body.push_str("{\"index\":{}}\n");
+        self.body
+            .push_str(&serde_json::to_string(&HitRecord::new(hit))?);
+        self.body.push('\n');
+        self.pending += 1;
+        if self.pending >= BATCH {
+            self.send_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, _totals: &ScanTotals) -> io::Result<()> {
+        self.send_batch()
+    }
+}
+
+/// POSTs `body` and returns the response text; failed requests and
+/// non-2xx answers are errors.
+fn post(url: &str, content_type: &str, body: String) -> io::Result<String> {
+    let mut response = ureq::post(url)
+        .header("Content-Type", content_type)
+        .send(body)
+        .map_err(|e| io::Error::other(format!("{}: {}", url, e)))?;
+    response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| io::Error::other(format!("{}: {}", url, e)))
+}
+
+/// Writes the totals as gauges at the end of the scan. The file is
+/// replaced in one rename so a collector never reads half of it.
+struct Prometheus {
+    path: String,
+    /// --check patterns, for labels
+    checks: Vec<String>,
+}
+
+impl OutputSink for Prometheus {
+    fn hit(&mut self, _hit: &Hit) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, totals: &ScanTotals) -> io::Result<()> {
+        let mut text = String::new();
+        let mut gauge = |name: &str, help: &str, samples: &[(String, usize)]| {
+            text.push_str(&format!("# HELP logbuddy_{} {}\n", name, help));
+            text.push_str(&format!("# TYPE logbuddy_{} gauge\n", name));
+            for (labels, value) in samples {
+                text.push_str(&format!("logbuddy_{}{} {}\n", name, labels, value));
+            }
+        };
+        let plain = |value: usize| vec![(String::new(), value)];
+        gauge(
+            "files_scanned",
+            "Files scanned by the last run.",
+            &plain(totals.files_scanned),
+        );
+        gauge(
+            "lines",
+            "Lines read by the last run.",
+            &plain(totals.total_lines),
+        );
+        gauge(
+            "bytes",
+            "Bytes read by the last run.",
+            &plain(totals.total_bytes),
+        );
+        gauge("hits", "Lines matching --find.", &plain(totals.hits));
+        let checks: Vec<(String, usize)> = self
+            .checks
+            .iter()
+            .zip(&totals.checks)
+            .map(|(pattern, tally)| {
+                (
+                    format!("{{check=\"{}\"}}", label_value(pattern)),
+                    tally.count,
+                )
+            })
+            .collect();
+        gauge(
+            "check_lines",
+            "Lines matching each --check pattern.",
+            &checks,
+        );
+        let mut errnos: Vec<(String, usize)> = totals
+            .errno_stats
+            .iter()
+            .map(|(name, stat)| (format!("{{errno=\"{}\"}}", name), stat.count))
+            .collect();
+        errnos.sort();
+        gauge(
+            "errno_lines",
+            "Lines mentioning each errno constant.",
+            &errnos,
+        );
+        if let Some(usage) = ResourceUsage::current() {
+            let ms = |ms: u64| plain(ms as usize);
+            gauge(
+                "peak_rss_bytes",
+                "Peak resident memory of the last run.",
+                &plain(usage.peak_rss_bytes as usize),
+            );
+            gauge(
+                "user_cpu_ms",
+                "User CPU time of the last run.",
+                &ms(usage.user_cpu_ms),
+            );
+            gauge(
+                "system_cpu_ms",
+                "System CPU time of the last run.",
+                &ms(usage.system_cpu_ms),
+            );
+            gauge(
+                "io_wait_ms",
+                "Block I/O wait of the last run.",
+                &ms(usage.io_wait_ms),
+            );
+        }
+
+        let partial = format!("{}.tmp", self.path);
+        std::fs::write(&partial, text)?;
+        std::fs::rename(&partial, &self.path)
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn label_value(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+struct WordsCsv {
+    out: BufWriter<File>,
+}
+
+impl OutputSink for WordsCsv {
+    fn hit(&mut self, _hit: &Hit) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, totals: &ScanTotals) -> io::Result<()> {
+        let mut words: Vec<(&str, usize)> = totals.words().collect();
+        // Most frequent first, ties alphabetically so reruns diff cleanly
+        words.sort_by_key(|&(word, count)| (Reverse(count), word));
+
+        writeln!(self.out, "word,count")?;
+        for (word, count) in words {
+            writeln!(self.out, "{},{}", csv_field(word), count)?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Quotes a CSV field when it contains a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread::{self, JoinHandle};
+
+    use serde_json::{json, Value};
+
+    use crate::checks::CheckTally;
+
+    /// A request as the server got it.
+    struct Request {
+        line: String,
+        content_type: String,
+        body: String,
+    }
+
+    /// Answers one request per reply with `200 OK` and the reply, or with
+    /// a 503 for an empty reply; returns its URL and what it was sent.
+    fn serve(replies: &[&str]) -> (String, JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let replies: Vec<String> = replies.iter().map(|r| r.to_string()).collect();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let (mut length, mut content_type) = (0, String::new());
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => length = value.trim().parse().unwrap(),
+                        "content-type" => content_type = value.trim().to_string(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let status = if reply.is_empty() {
+                    "503 Service Unavailable"
+                } else {
+                    "200 OK"
+                };
+                write!(
+                    &stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                )
+                .unwrap();
+                requests.push(Request {
+                    line: line.trim_end().to_string(),
+                    content_type,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn hit<'a>(file: &'a str, line_number: usize, line: &'a str) -> Hit<'a> {
+        Hit {
+            file,
+            line_number,
+            line,
+            decoded: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("logbuddy-sink-{}-{}", std::process::id(), name))
+    }
+
+    fn totals() -> ScanTotals {
+        let mut totals = ScanTotals {
+            files_scanned: 2,
+            total_lines: 40,
+            total_bytes: 1234,
+            hits: 3,
+            ..ScanTotals::default()
+        };
+        totals.word_counts.add("timeout", 3);
+        totals.word_counts.add("a,b", 1);
+        totals.word_counts.add("say \"hi\"", 3);
+        totals
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(SinkSpec::parse("console"), Ok(SinkSpec::Console));
+        assert_eq!(
+            SinkSpec::parse("webhook:https://hooks.example/x?a=1"),
+            Ok(SinkSpec::Webhook("https://hooks.example/x?a=1".to_string()))
+        );
+        assert_eq!(
+            SinkSpec::parse("words-csv:out/words.csv"),
+            Ok(SinkSpec::WordsCsv("out/words.csv".to_string()))
+        );
+        assert!(SinkSpec::parse("jsonl:").is_err());
+        assert!(SinkSpec::parse("hits.jsonl").is_err());
+        assert!(SinkSpec::parse("kafka:topic").is_err());
+        assert!(SinkSpec::Jsonl(String::new()).hits_only());
+        assert!(!SinkSpec::WordsCsv(String::new()).hits_only());
+    }
+
+    #[test]
+    fn webhook_batches_then_a_summary() {
+        let (url, server) = serve(&["ok", "ok"]);
+        let mut sink = Webhook {
+            url: format!("{}/hook", url),
+            batch: Vec::new(),
+        };
+        sink.hit(&hit("app.log", 3, "ERROR boom")).unwrap();
+        sink.hit(&hit("db.log", 9, "ERROR \"quoted\"")).unwrap();
+        sink.finish(&totals()).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].line, "POST /hook HTTP/1.1");
+        assert_eq!(requests[0].content_type, "application/json");
+        let hits: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(
+            hits,
+            json!({"hits": [
+                {"file": "app.log", "line": 3, "text": "ERROR boom"},
+                {"file": "db.log", "line": 9, "text": "ERROR \"quoted\""}
+            ]})
+        );
+        let summary: Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(
+            summary,
+            json!({"summary": {"files": 2, "lines": 40, "bytes": 1234, "hits": 3}})
+        );
+    }
+
+    #[test]
+    fn webhook_failures_are_errors() {
+        let (url, server) = serve(&[""]);
+        let mut sink = Webhook {
+            url,
+            batch: Vec::new(),
+        };
+        // No hits: only the summary goes out
+        assert!(sink.finish(&totals()).is_err());
+        assert!(server.join().unwrap()[0].body.contains("summary"));
+    }
+
+    #[test]
+    fn elasticsearch_bulk_requests() {
+        let (url, server) = serve(&[r#"{"errors":false,"items":[]}"#, r#"{"errors":true}"#]);
+        let mut sink = Elasticsearch {
+            bulk_url: format!("{}/logs/_bulk", url),
+            body: String::new(),
+            pending: 0,
+        };
+        sink.hit(&hit("app.log", 3, "ERROR boom")).unwrap();
+        sink.hit(&hit("app.log", 4, "ERROR again")).unwrap();
+        sink.finish(&totals()).unwrap();
+        // Nothing pending sends nothing
+        sink.finish(&totals()).unwrap();
+        sink.hit(&hit("app.log", 5, "ERROR rejected")).unwrap();
+        assert!(sink.finish(&totals()).is_err());
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].line, "POST /logs/_bulk HTTP/1.1");
+        assert_eq!(requests[0].content_type, "application/x-ndjson");
+        assert_eq!(
+            requests[0].body,
+            "{\"index\":{}}\n{\"file\":\"app.log\",\"line\":3,\"text\":\"ERROR boom\"}\n\
+             {\"index\":{}}\n{\"file\":\"app.log\",\"line\":4,\"text\":\"ERROR again\"}\n"
+        );
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn prometheus_gauges() {
+        let path = temp_path("metrics.prom");
+        let mut totals = totals();
+        totals.checks = vec![CheckTally {
+            count: 2,
+            samples: Vec::new(),
+        }];
+        let mut sink = Prometheus {
+            path: path.to_str().unwrap().to_string(),
+            checks: vec!["say \"hi\"\\".to_string()],
+        };
+        sink.finish(&totals).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        for line in [
+            "# HELP logbuddy_files_scanned Files scanned by the last run.",
+            "# TYPE logbuddy_files_scanned gauge",
+            "logbuddy_files_scanned 2",
+            "logbuddy_lines 40",
+            "logbuddy_bytes 1234",
+            "logbuddy_hits 3",
+            r#"logbuddy_check_lines{check="say \"hi\"\\"} 2"#,
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "no {:?} in\n{}",
+                line,
+                text
+            );
+        }
+        // Replaced in one rename, nothing left aside
+        assert!(!Path::new(&format!("{}.tmp", path.display())).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn words_csv() {
+        let path = temp_path("words.csv");
+        let mut sink = WordsCsv {
+            out: create(path.to_str().unwrap()).unwrap(),
+        };
+        sink.finish(&totals()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "word,count\n\"say \"\"hi\"\"\",3\ntimeout,3\n\"a,b\",1\n"
+        );
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+        std::fs::remove_file(path).unwrap();
+    }
+}
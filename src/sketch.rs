@@ -0,0 +1,214 @@
+//! Fixed-memory approximate counting for --approx-counts: a Count-Min
+//! Sketch estimates every word's count, and only the words whose estimate
+//! could put them near the top keep a name.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::words::WordCounts;
+
+/// Rows of the sketch; each row is an independent hash.
+const DEPTH: usize = 4;
+
+/// Counters per row. Estimates overshoot by at most about
+/// e / WIDTH of all counted words (0.002% here), with 98% certainty.
+const WIDTH: usize = 1 << 17;
+
+/// Words kept by name once pruned; they are candidates for the top list.
+pub const CANDIDATES: usize = 1024;
+
+/// Counts per hashed word, never under the true count.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CountMinSketch {
+    /// DEPTH rows of WIDTH counters; empty until the first add
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    /// Adds `count` to `word` and returns its new estimate.
+    pub fn add(&mut self, word: &str, count: u32) -> u32 {
+        if self.counters.is_empty() {
+            self.counters = vec![0; DEPTH * WIDTH];
+        }
+        let mut estimate = u32::MAX;
+        for slot in slots(word) {
+            let counter = &mut self.counters[slot];
+            *counter = counter.saturating_add(count);
+            estimate = estimate.min(*counter);
+        }
+        estimate
+    }
+
+    pub fn estimate(&self, word: &str) -> u32 {
+        if self.counters.is_empty() {
+            return 0;
+        }
+        slots(word)
+            .map(|slot| self.counters[slot])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: CountMinSketch) {
+        if self.counters.is_empty() {
+            self.counters = other.counters;
+            return;
+        }
+        for (counter, more) in self.counters.iter_mut().zip(other.counters) {
+            *counter = counter.saturating_add(more);
+        }
+    }
+}
+
+/// One counter index per row, from two halves of an FNV-1a hash
+/// (Kirsch-Mitzenmacher), so runs and builds agree.
+fn slots(word: &str) -> impl Iterator<Item = usize> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in word.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+    (0..DEPTH).map(move |row| row * WIDTH + h1.wrapping_add(row.wrapping_mul(h2)) % WIDTH)
+}
+
+/// Approximate word counts: the sketch, plus the likely top words by name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApproxCounts {
+    sketch: CountMinSketch,
+    /// Up to 2 × CANDIDATES words with their last estimate
+    candidates: HashMap<String, u32>,
+    /// Estimate a new word needs to become a candidate, from the last prune
+    threshold: u32,
+}
+
+impl ApproxCounts {
+    pub fn add(&mut self, word: &str) {
+        self.add_count(word, 1);
+    }
+
+    pub fn add_count(&mut self, word: &str, count: u32) {
+        let estimate = self.sketch.add(word, count);
+        if let Some(known) = self.candidates.get_mut(word) {
+            *known = estimate;
+        } else if estimate > self.threshold {
+            self.candidates.insert(word.to_string(), estimate);
+            if self.candidates.len() >= 2 * CANDIDATES {
+                self.prune();
+            }
+        }
+    }
+
+    /// Adds the counts of a later file; its candidates are re-estimated
+    /// against the combined sketch.
+    pub fn merge(&mut self, other: ApproxCounts) {
+        self.sketch.merge(other.sketch);
+        self.threshold = self.threshold.max(other.threshold);
+        self.candidates
+            .extend(other.candidates.into_keys().map(|w| (w, 0)));
+        for (word, estimate) in self.candidates.iter_mut() {
+            *estimate = self.sketch.estimate(word);
+        }
+        self.prune();
+    }
+
+    /// The same counts as `exact`, for switching over under --max-memory.
+    pub fn from_exact(exact: &WordCounts) -> ApproxCounts {
+        let mut approx = ApproxCounts::default();
+        for (word, count) in exact.iter() {
+            approx.add_count(word, u32::try_from(count).unwrap_or(u32::MAX));
+        }
+        approx
+    }
+
+    /// Rough heap size, for --max-memory.
+    pub fn heap_bytes(&self) -> usize {
+        self.sketch.counters.capacity() * std::mem::size_of::<u32>()
+            + self
+                .candidates
+                .keys()
+                .map(|w| w.capacity() + 40)
+                .sum::<usize>()
+    }
+
+    /// Candidate words with their estimated counts.
+    pub fn counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.candidates
+            .iter()
+            .map(|(word, estimate)| (word.as_str(), *estimate as usize))
+    }
+
+    /// Keeps the CANDIDATES best words, ties alphabetically so the result
+    /// doesn't depend on hash order.
+    fn prune(&mut self) {
+        if self.candidates.len() <= CANDIDATES {
+            return;
+        }
+        let mut ranked: Vec<(String, u32)> = self.candidates.drain().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(CANDIDATES);
+        self.threshold = ranked.last().map_or(0, |(_, estimate)| *estimate);
+        self.candidates = ranked.into_iter().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn never_undercounts() {
+        let mut sketch = CountMinSketch::default();
+        assert_eq!(sketch.estimate("error"), 0);
+        assert_eq!(sketch.add("error", 3), 3);
+        assert_eq!(sketch.add("error", 2), 5);
+        for i in 0..10_000 {
+            sketch.add(&format!("word-{}", i), 1);
+        }
+        assert!(sketch.estimate("error") >= 5);
+        assert!(sketch.estimate("word-42") >= 1);
+
+        let mut other = CountMinSketch::default();
+        other.add("error", 4);
+        sketch.merge(other);
+        assert!(sketch.estimate("error") >= 9);
+        let mut empty = CountMinSketch::default();
+        empty.merge(sketch);
+        assert!(empty.estimate("error") >= 9);
+    }
+
+    #[test]
+    fn keeps_the_top_words() {
+        let mut approx = ApproxCounts::default();
+        // Many rare words push the candidates past a prune
+        for i in 0..3 * CANDIDATES {
+            approx.add(&format!("rare-{}", i));
+        }
+        for _ in 0..50 {
+            approx.add("timeout");
+        }
+        approx.add_count("refused", 20);
+        let counts: HashMap<&str, usize> = approx.counts().collect();
+        assert!(counts.len() <= 2 * CANDIDATES);
+        assert_eq!(counts["timeout"], 50);
+        assert_eq!(counts["refused"], 20);
+    }
+
+    #[test]
+    fn merges_and_converts() {
+        let mut exact = WordCounts::default();
+        exact.add("timeout", 7);
+        exact.add("refused", 2);
+        let mut approx = ApproxCounts::from_exact(&exact);
+        let mut later = ApproxCounts::default();
+        later.add_count("timeout", 3);
+        later.add_count("reset", 1);
+        approx.merge(later);
+        let counts: HashMap<&str, usize> = approx.counts().collect();
+        assert_eq!(counts["timeout"], 10);
+        assert_eq!(counts["refused"], 2);
+        assert_eq!(counts["reset"], 1);
+        assert!(approx.heap_bytes() >= DEPTH * WIDTH * 4);
+    }
+}
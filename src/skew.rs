@@ -0,0 +1,87 @@
+//! Estimates clock skew between files by lining up events that share a
+//! correlation key (a request id, trace id, ...): when the same key shows
+//! up in two files, the difference between their timestamps is one sample
+//! of how far apart the two clocks are.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// First timestamp (Unix millis) of every key value, per file index.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SkewTracker {
+    seen: HashMap<String, Vec<(usize, i64)>>,
+}
+
+/// How far one file's clock is from the reference file's.
+#[derive(Debug)]
+pub struct SkewEstimate {
+    /// Index into ScanTotals::files
+    pub file: usize,
+    /// Median of (this file - reference) over shared keys; positive means ahead
+    pub offset_ms: i64,
+    pub samples: usize,
+}
+
+impl SkewTracker {
+    pub fn record(&mut self, key: &str, file: usize, millis: i64) {
+        let entries = self.seen.entry(key.to_string()).or_default();
+        if !entries.iter().any(|(f, _)| *f == file) {
+            entries.push((file, millis));
+        }
+    }
+
+    /// Adds the keys of another tracker whose file indexes start at
+    /// `file_offset` in this one.
+    pub fn merge(&mut self, other: SkewTracker, file_offset: usize) {
+        for (key, entries) in other.seen {
+            for (file, millis) in entries {
+                self.record(&key, file + file_offset, millis);
+            }
+        }
+    }
+
+    /// The file sharing keys with the most other files' events, and the
+    /// estimated offset of every other file that shares keys with it.
+    ///
+    /// The offsets include the normal delay between a request being logged
+    /// in one place and the next, so only offsets well above that delay
+    /// point at a skewed clock.
+    pub fn estimate(&self) -> Option<(usize, Vec<SkewEstimate>)> {
+        let mut shared_keys: HashMap<usize, usize> = HashMap::new();
+        for entries in self.seen.values().filter(|e| e.len() > 1) {
+            for (file, _) in entries {
+                *shared_keys.entry(*file).or_insert(0) += 1;
+            }
+        }
+        // Ties go to the earliest scanned file so the output is stable
+        let reference = shared_keys
+            .iter()
+            .max_by_key(|(file, count)| (**count, std::cmp::Reverse(**file)))
+            .map(|(file, _)| *file)?;
+
+        let mut diffs: HashMap<usize, Vec<i64>> = HashMap::new();
+        for entries in self.seen.values() {
+            let Some(&(_, base)) = entries.iter().find(|(f, _)| *f == reference) else {
+                continue;
+            };
+            for &(file, millis) in entries.iter().filter(|(f, _)| *f != reference) {
+                diffs.entry(file).or_default().push(millis - base);
+            }
+        }
+
+        let mut estimates: Vec<SkewEstimate> = diffs
+            .into_iter()
+            .map(|(file, mut d)| {
+                d.sort_unstable();
+                SkewEstimate {
+                    file,
+                    offset_ms: d[d.len() / 2],
+                    samples: d.len(),
+                }
+            })
+            .collect();
+        estimates.sort_by_key(|e| e.file);
+        Some((reference, estimates))
+    }
+}
@@ -0,0 +1,263 @@
+//! Where a scan's files come from when they aren't in a local folder: an
+//! S3 bucket (--s3), or a folder in S3, Google Cloud Storage or Azure Blob
+//! Storage, or on a web server, given to --path as a URL, or on another
+//! machine over SSH (--remote). A source is listed up front, then
+//! each file streamed as it is scanned, so nothing is downloaded first.
+
+use std::env;
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+use crate::azure::Azure;
+use crate::cli::Config;
+use crate::gcs::Gcs;
+use crate::http::Http;
+use crate::s3::S3;
+use crate::ssh::Ssh;
+
+/// One file in a source.
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    /// What the source finds it by, such as an object key
+    pub key: String,
+    /// Its name in the report: its path from the folder listed
+    pub name: String,
+    pub size: u64,
+    /// Unix millis
+    pub modified: Option<i64>,
+    /// Changes whenever the contents do, like an ETag
+    pub version: String,
+}
+
+pub trait Source: Sync {
+    /// The source as given, such as `s3://bucket/logs/`.
+    fn location(&self) -> &str;
+
+    /// Where `file` is, to show in warnings and for --cache.
+    fn locate(&self, file: &RemoteFile) -> String;
+
+    /// The files directly in the folder the source points at, or anywhere
+    /// below it when `recursive`.
+    fn list(&self, recursive: bool) -> Result<Vec<RemoteFile>, String>;
+
+    /// The contents of `file`, fetched as they are read.
+    fn open(&self, file: &RemoteFile) -> io::Result<Box<dyn Read>>;
+}
+
+/// The source the command line names, by --remote, --s3 or a URL for
+/// --path; None for a local folder.
+pub fn from_config(config: &Config) -> Result<Option<Box<dyn Source>>, String> {
+    if let Some(remote) = &config.remote {
+        return Ok(Some(Box::new(Ssh::new(remote)?)));
+    }
+    let (url, scheme) = match (&config.s3, &config.path) {
+        (Some(url), _) => return Ok(Some(Box::new(S3::new(url)?))),
+        (None, Some(path)) => match scheme(path) {
+            Some(scheme) => (path, scheme),
+            None => return Ok(None),
+        },
+        (None, None) => return Ok(None),
+    };
+    let source: Box<dyn Source> = match scheme {
+        "s3" => Box::new(S3::new(url)?),
+        "gs" => Box::new(Gcs::new(url)?),
+        "az" => Box::new(Azure::new(url)?),
+        "https" | "http" if Azure::is_blob_url(url) => Box::new(Azure::new(url)?),
+        "https" | "http" => Box::new(Http::new(url)?),
+        _ => return Err(format!("--path can't read {}:// URLs", scheme)),
+    };
+    Ok(Some(source))
+}
+
+/// Whether `path` is a URL rather than a local path: it starts with a
+/// scheme, like `gs://`.
+pub fn is_url(path: &str) -> bool {
+    scheme(path).is_some()
+}
+
+/// The scheme of a URL, lowercase letters before `://`.
+fn scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once("://")?;
+    (!scheme.is_empty()
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()))
+    .then_some(scheme)
+}
+
+/// An HTTP client for a source's requests, which answer errors with
+/// details in the body, so statuses other than 2xx aren't failures to it.
+pub fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// HMAC-SHA256 of `message` with `key`.
+pub fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// `text` percent-encoded for a URL, as the stores sign it too:
+/// everything but unreserved characters, and '/' too when `slash`.
+pub fn uri_encode(text: &str, slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric()
+            || matches!(b, b'-' | b'.' | b'_' | b'~')
+            || (b == b'/' && !slash)
+        {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Standard base64, padded.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The contents of each `<tag>` element in `xml`, still escaped. The
+/// stores' answers are flat enough that this is all the XML parsing they
+/// need.
+pub fn elements<'x>(xml: &'x str, tag: &str) -> Vec<&'x str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else {
+            break;
+        };
+        found.push(&after[..end]);
+        rest = &after[end + close.len()..];
+    }
+    found
+}
+
+/// The text of the first `<tag>` element in `xml`.
+pub fn element(xml: &str, tag: &str) -> Option<String> {
+    let text = elements(xml, tag).into_iter().next()?;
+    Some(
+        text.replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// An environment variable, unless it is unset or empty.
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_and_paths() {
+        assert!(is_url("gs://bucket/logs"));
+        assert!(is_url("s3://bucket"));
+        assert!(!is_url("./logs"));
+        assert!(!is_url("C:\\logs"));
+        assert!(!is_url("/var/log/a://b"));
+        assert!(!is_url("://nothing"));
+    }
+
+    #[test]
+    fn uri_encoding() {
+        assert_eq!(
+            uri_encode("logs/odd name+&.txt", false),
+            "logs/odd%20name%2B%26.txt"
+        );
+        assert_eq!(uri_encode("logs/a~b_c-d.e", true), "logs%2Fa~b_c-d.e");
+        assert_eq!(uri_encode("é", true), "%C3%A9");
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let hex =
+            |bytes: [u8; 32]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+        assert_eq!(
+            hex(hmac(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(hmac(&[0xaa; 20], &[0xdd; 50])),
+            "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"
+        );
+        // Keys longer than a block are hashed first
+        assert_eq!(
+            hex(hmac(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn base64_matches_rfc_4648() {
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (text, encoded) in cases {
+            assert_eq!(base64(text.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn xml_elements() {
+        let xml = "<R><Key>a &amp; b</Key><Key>&lt;c&gt;</Key><Size>3</Size></R>";
+        assert_eq!(elements(xml, "Key"), ["a &amp; b", "&lt;c&gt;"]);
+        assert_eq!(element(xml, "Key").as_deref(), Some("a & b"));
+        assert_eq!(element(xml, "Missing"), None);
+        assert_eq!(elements("<Key>open", "Key"), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,324 @@
+//! `--remote user@host:/var/log`: the files in a folder on another
+//! machine, scanned over SSH without copying them first. The system's ssh
+//! runs with the user's own ~/.ssh/config, keys, agent and known hosts;
+//! the first connection is kept open and shared by the rest, so each file
+//! costs a command, not a login. The folder is listed with GNU find and
+//! each file streamed through cat.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{self, Child, ChildStdout, Command, Stdio};
+
+use tracing::debug;
+
+use crate::source::{env_var, RemoteFile, Source};
+
+/// How long the shared connection outlives its last use, in seconds:
+/// long enough to go from one file to the next.
+const CONTROL_PERSIST: u32 = 60;
+
+pub struct Ssh {
+    /// As given on the command line
+    location: String,
+    /// `user@host` or `host`, as ssh takes it
+    destination: String,
+    port: Option<u16>,
+    /// Folder on the host, relative to the login's home unless absolute
+    dir: String,
+    /// The ssh program, `ssh` unless LOGBUDDY_SSH says
+    program: String,
+    /// Where the shared connection's socket goes, once there is one
+    control: PathBuf,
+}
+
+impl Ssh {
+    /// The host and folder of `remote`: `[user@]host:dir`, or
+    /// `ssh://[user@]host[:port]/dir`.
+    pub fn new(remote: &str) -> Result<Ssh, String> {
+        let invalid = || {
+            format!(
+                "--remote takes [user@]host:dir or ssh://[user@]host[:port]/dir, not '{}'",
+                remote
+            )
+        };
+        let (destination, port, dir) = match remote.strip_prefix("ssh://") {
+            Some(rest) => {
+                let (authority, dir) = match rest.find('/') {
+                    Some(i) => (&rest[..i], rest[i..].to_string()),
+                    None => (rest, ".".to_string()),
+                };
+                let (destination, port) = split_port(authority).ok_or_else(invalid)?;
+                (destination, port, dir)
+            }
+            None => {
+                let colon = host_end(remote).ok_or_else(invalid)?;
+                let dir = match &remote[colon + 1..] {
+                    "" => ".".to_string(),
+                    dir => dir.to_string(),
+                };
+                (remote[..colon].to_string(), None, dir)
+            }
+        };
+        if destination.is_empty() || destination.ends_with('@') {
+            return Err(invalid());
+        }
+        Ok(Ssh {
+            location: remote.to_string(),
+            destination,
+            port,
+            dir,
+            program: env_var("LOGBUDDY_SSH").unwrap_or_else(|| "ssh".to_string()),
+            control: env::temp_dir().join(format!("logbuddy-ssh-{}", process::id())),
+        })
+    }
+
+    /// ssh set up to run `command` on the host, over the shared
+    /// connection. Only the first (`interactive`) may ask for a password
+    /// or passphrase; the rest reuse its connection.
+    fn ssh(&self, command: &str, interactive: bool) -> Command {
+        let mut ssh = Command::new(&self.program);
+        ssh.arg("-o")
+            .arg(format!(
+                "BatchMode={}",
+                if interactive { "no" } else { "yes" }
+            ))
+            .arg("-o")
+            .arg("ControlMaster=auto")
+            .arg("-o")
+            .arg(format!("ControlPath={}", self.control.join("%C").display()))
+            .arg("-o")
+            .arg(format!("ControlPersist={}", CONTROL_PERSIST));
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        ssh.arg("--").arg(&self.destination).arg(command);
+        debug!("{} {}: {}", self.program, self.destination, command);
+        ssh
+    }
+}
+
+impl Drop for Ssh {
+    /// Closes the shared connection rather than leaving it to time out.
+    fn drop(&mut self) {
+        if !self.control.exists() {
+            return;
+        }
+        let closed = Command::new(&self.program)
+            .arg("-o")
+            .arg(format!("ControlPath={}", self.control.join("%C").display()))
+            .arg("-O")
+            .arg("exit")
+            .arg("--")
+            .arg(&self.destination)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        debug!("closed the shared ssh connection: {:?}", closed);
+        let _ = fs::remove_dir(&self.control);
+    }
+}
+
+impl Source for Ssh {
+    fn location(&self) -> &str {
+        &self.location
+    }
+
+    fn locate(&self, file: &RemoteFile) -> String {
+        format!(
+            "{}:{}/{}",
+            self.destination,
+            self.dir.trim_end_matches('/'),
+            file.key
+        )
+    }
+
+    /// Regular files in the folder, or below it when `recursive`, as find
+    /// prints them: size, modification time and path, NUL-terminated so
+    /// any file name survives.
+    fn list(&self, recursive: bool) -> Result<Vec<RemoteFile>, String> {
+        let command = format!(
+            "cd -- {} && find . {}-type f -printf '%s %T@ %P\\0'",
+            quote(&self.dir),
+            if recursive { "" } else { "-maxdepth 1 " }
+        );
+        fs::create_dir_all(&self.control)
+            .map_err(|e| format!("could not create {}: {}", self.control.display(), e))?;
+        let output = self
+            .ssh(&command, true)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| format!("could not run {}: {}", self.program, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "could not list {}: {}",
+                self.location,
+                failure(&output.stderr, output.status)
+            ));
+        }
+        Ok(parse_listing(&output.stdout))
+    }
+
+    fn open(&self, file: &RemoteFile) -> io::Result<Box<dyn Read>> {
+        let command = format!(
+            "cd -- {} && exec cat -- {}",
+            quote(&self.dir),
+            quote(&file.key)
+        );
+        let mut child = self
+            .ssh(&command, false)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Box::new(Streamed { child, stdout }))
+    }
+}
+
+/// A file coming through ssh's output. Its end is only the file's end if
+/// ssh then exits cleanly; otherwise reading fails with what ssh said.
+struct Streamed {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for Streamed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let mut stderr = Vec::new();
+            if let Some(mut pipe) = self.child.stderr.take() {
+                pipe.read_to_end(&mut stderr)?;
+            }
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(failure(&stderr, status)));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for Streamed {
+    /// A file left unread, as with --max-runtime, ends its ssh too.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The files in find's `-printf '%s %T@ %P\0'` output.
+fn parse_listing(output: &[u8]) -> Vec<RemoteFile> {
+    output
+        .split(|&b| b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let mut fields = entry.splitn(3, ' ');
+            let size = fields.next()?.parse().ok()?;
+            let mtime = fields.next()?;
+            let path = fields.next().filter(|p| !p.is_empty())?;
+            Some(RemoteFile {
+                key: path.to_string(),
+                name: path.to_string(),
+                size,
+                modified: mtime.parse::<f64>().ok().map(|secs| (secs * 1000.0) as i64),
+                version: mtime.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Where the host ends in `[user@]host:dir`: the first ':' after it,
+/// skipping an IPv6 address in brackets.
+fn host_end(remote: &str) -> Option<usize> {
+    let after_user = remote.find('@').map_or(0, |i| i + 1);
+    let from = if remote[after_user..].starts_with('[') {
+        after_user + remote[after_user..].find(']')?
+    } else {
+        after_user
+    };
+    Some(from + remote[from..].find(':')?)
+}
+
+/// `[user@]host[:port]` as the destination and the port.
+fn split_port(authority: &str) -> Option<(String, Option<u16>)> {
+    let colon = host_end(authority);
+    match colon {
+        Some(i) => Some((
+            authority[..i].to_string(),
+            Some(authority[i + 1..].parse().ok()?),
+        )),
+        None => Some((authority.to_string(), None)),
+    }
+}
+
+/// `text` quoted for the remote shell.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// What went wrong, from ssh's (or the command's) error output.
+fn failure(stderr: &[u8], status: process::ExitStatus) -> String {
+    let text = String::from_utf8_lossy(stderr);
+    match text.trim().lines().last() {
+        Some(line) => line.to_string(),
+        None => format!("ssh {}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remotes() {
+        let ssh = Ssh::new("ops@web-1:/var/log").unwrap();
+        assert_eq!((ssh.destination.as_str(), ssh.port), ("ops@web-1", None));
+        assert_eq!(ssh.dir, "/var/log");
+        let ssh = Ssh::new("web-1:").unwrap();
+        assert_eq!((ssh.destination.as_str(), ssh.dir.as_str()), ("web-1", "."));
+        let ssh = Ssh::new("ops@[fe80::1]:logs").unwrap();
+        assert_eq!(
+            (ssh.destination.as_str(), ssh.dir.as_str()),
+            ("ops@[fe80::1]", "logs")
+        );
+        let ssh = Ssh::new("ssh://ops@web-1:2222/var/log").unwrap();
+        assert_eq!(
+            (ssh.destination.as_str(), ssh.port),
+            ("ops@web-1", Some(2222))
+        );
+        assert_eq!(ssh.dir, "/var/log");
+        let ssh = Ssh::new("ssh://web-1").unwrap();
+        assert_eq!((ssh.destination.as_str(), ssh.dir.as_str()), ("web-1", "."));
+
+        assert!(Ssh::new("web-1").is_err());
+        assert!(Ssh::new(":/var/log").is_err());
+        assert!(Ssh::new("ops@:/var/log").is_err());
+        assert!(Ssh::new("ssh://web-1:port/var/log").is_err());
+    }
+
+    #[test]
+    fn find_output() {
+        let output = b"68 1704891600.5000000000 app.log\x00\
+                       12 1704891601.0 sub/odd name.log\x00\
+                       garbage\x00";
+        let files = parse_listing(output);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "app.log");
+        assert_eq!(files[0].size, 68);
+        assert_eq!(files[0].modified, Some(1_704_891_600_500));
+        assert_eq!(files[0].version, "1704891600.5000000000");
+        assert_eq!(files[1].key, "sub/odd name.log");
+        assert!(parse_listing(b"").is_empty());
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("/var/log"), "'/var/log'");
+        assert_eq!(quote("it's here"), "'it'\\''s here'");
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::units::{self, UnitKind};
+
+/// Percentiles are within this fraction of the true value.
+const QUANTILE_ACCURACY: f64 = 0.01;
+
+/// Up to this many values, percentiles come from the values themselves.
+const EXACT_VALUES: usize = 1024;
+
+/// Running count/min/max/sum over values in one canonical unit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NumericSummary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    quantiles: QuantileSketch,
+}
+
+impl Default for NumericSummary {
+    fn default() -> Self {
+        NumericSummary {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            quantiles: QuantileSketch::default(),
+        }
+    }
+}
+
+impl NumericSummary {
+    /// Counts `value`, unless it is NaN or infinite ("1e999ms" overflows):
+    /// one of those would make the min, max and mean meaningless.
+    fn add(&mut self, value: f64) -> bool {
+        if !value.is_finite() {
+            return false;
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.quantiles.add(value);
+        true
+    }
+
+    fn merge(&mut self, other: NumericSummary) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.quantiles.merge(other.quantiles);
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    /// The value `q` (0 to 1) of the way through the sorted values, within
+    /// QUANTILE_ACCURACY of it: 0.5 the median, 0.99 the 99th percentile.
+    pub fn percentile(&self, q: f64) -> f64 {
+        self.quantiles.quantile(q).clamp(self.min, self.max)
+    }
+}
+
+/// Values counted in logarithmic buckets, as DDSketch does: every bucket
+/// spans values within QUANTILE_ACCURACY of its middle, so percentiles
+/// stay that accurate in a few hundred buckets however many values come,
+/// and sketches of different files merge exactly. A few values are also
+/// kept as they are, for exact percentiles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QuantileSketch {
+    /// Every value, while there are at most EXACT_VALUES
+    exact: Option<Vec<f64>>,
+    /// Counts by bucket index
+    positive: BTreeMap<i32, u64>,
+    /// Counts by the bucket index of the value's magnitude
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        QuantileSketch {
+            exact: Some(Vec::new()),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zeros: 0,
+        }
+    }
+}
+
+impl QuantileSketch {
+    fn gamma() -> f64 {
+        (1.0 + QUANTILE_ACCURACY) / (1.0 - QUANTILE_ACCURACY)
+    }
+
+    fn index(magnitude: f64) -> i32 {
+        (magnitude.ln() / Self::gamma().ln()).ceil() as i32
+    }
+
+    /// The middle of bucket `index`, within QUANTILE_ACCURACY of every
+    /// value in it.
+    fn bucket_value(index: i32) -> f64 {
+        2.0 * Self::gamma().powi(index) / (Self::gamma() + 1.0)
+    }
+
+    fn add(&mut self, value: f64) {
+        if let Some(exact) = &mut self.exact {
+            exact.push(value);
+            if exact.len() > EXACT_VALUES {
+                self.exact = None;
+            }
+        }
+        if value.abs() < f64::MIN_POSITIVE {
+            self.zeros += 1;
+        } else if value > 0.0 {
+            *self.positive.entry(Self::index(value)).or_insert(0) += 1;
+        } else {
+            *self.negative.entry(Self::index(-value)).or_insert(0) += 1;
+        }
+    }
+
+    fn merge(&mut self, other: QuantileSketch) {
+        self.exact = match (self.exact.take(), other.exact) {
+            (Some(mut exact), Some(more)) if exact.len() + more.len() <= EXACT_VALUES => {
+                exact.extend(more);
+                Some(exact)
+            }
+            _ => None,
+        };
+        for (index, count) in other.positive {
+            *self.positive.entry(index).or_insert(0) += count;
+        }
+        for (index, count) in other.negative {
+            *self.negative.entry(index).or_insert(0) += count;
+        }
+        self.zeros += other.zeros;
+    }
+
+    fn quantile(&self, q: f64) -> f64 {
+        let q = q.clamp(0.0, 1.0);
+        if let Some(exact) = &self.exact {
+            let mut sorted = exact.clone();
+            sorted.sort_by(f64::total_cmp);
+            let rank = (q * sorted.len().saturating_sub(1) as f64).round() as usize;
+            return sorted.get(rank).copied().unwrap_or(0.0);
+        }
+        let count =
+            self.zeros + self.positive.values().sum::<u64>() + self.negative.values().sum::<u64>();
+        if count == 0 {
+            return 0.0;
+        }
+        let rank = (q * (count - 1) as f64).round() as u64;
+        // Smallest values first: the largest negative magnitudes
+        let buckets = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(&i, &c)| (-Self::bucket_value(i), c))
+            .chain(std::iter::once((0.0, self.zeros)))
+            .chain(
+                self.positive
+                    .iter()
+                    .map(|(&i, &c)| (Self::bucket_value(i), c)),
+            );
+        let mut seen = 0;
+        for (value, count) in buckets {
+            seen += count;
+            if seen > rank {
+                return value;
+            }
+        }
+        0.0
+    }
+}
+
+/// Values seen for one --stats-field, split by what their units measure so
+/// "15ms" and "512KiB" never end up in the same average.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FieldStats {
+    pub by_kind: BTreeMap<UnitKind, NumericSummary>,
+    /// Values that were present but not a number with a known unit
+    pub unparsed: usize,
+}
+
+impl FieldStats {
+    pub fn record(&mut self, raw: &str) {
+        let Some(q) = units::parse_quantity(raw) else {
+            self.unparsed += 1;
+            return;
+        };
+        let summary = self.by_kind.entry(q.kind).or_default();
+        if !summary.add(q.value) {
+            self.unparsed += 1;
+            if summary.count == 0 {
+                self.by_kind.remove(&q.kind);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: FieldStats) {
+        for (kind, summary) in other.by_kind {
+            match self.by_kind.get_mut(&kind) {
+                Some(s) => s.merge(summary),
+                None => {
+                    self.by_kind.insert(kind, summary);
+                }
+            }
+        }
+        self.unparsed += other.unparsed;
+    }
+
+    /// The kind most values had; the summary reports that one and lists
+    /// the rest as mismatched.
+    pub fn main_kind(&self) -> Option<UnitKind> {
+        self.by_kind
+            .iter()
+            .max_by_key(|(_, s)| s.count)
+            .map(|(kind, _)| *kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(values: &[&str]) -> FieldStats {
+        let mut stats = FieldStats::default();
+        for value in values {
+            stats.record(value);
+        }
+        stats
+    }
+
+    fn within_accuracy(got: f64, want: f64) -> bool {
+        (got - want).abs() <= want.abs() * QUANTILE_ACCURACY
+    }
+
+    #[test]
+    fn percentiles() {
+        let stats = stats_of(&["10ms", "2s", "30ms", "20ms", "40ms"]);
+        let s = &stats.by_kind[&UnitKind::Duration];
+        assert_eq!((s.count, s.min, s.max), (5, 10.0, 2000.0));
+        assert_eq!(s.mean(), 420.0);
+        assert_eq!(s.percentile(0.0), 10.0);
+        assert_eq!(s.percentile(0.5), 30.0);
+        assert_eq!(s.percentile(1.0), 2000.0);
+
+        // Past EXACT_VALUES the buckets answer, still within the accuracy
+        let mut s = NumericSummary::default();
+        for i in 1..=10_000 {
+            s.add(i as f64);
+        }
+        assert!(s.quantiles.exact.is_none());
+        for (q, want) in [(0.5, 5000.0), (0.95, 9500.0), (0.99, 9900.0)] {
+            let got = s.percentile(q);
+            assert!(within_accuracy(got, want), "p{} = {}", q * 100.0, got);
+        }
+        assert_eq!(s.percentile(0.0), 1.0);
+        assert!(within_accuracy(s.percentile(1.0), 10_000.0));
+
+        let mut s = NumericSummary::default();
+        for value in [-4.0, 0.0, 0.0, 8.0] {
+            s.add(value);
+        }
+        s.quantiles.exact = None;
+        assert!(within_accuracy(s.percentile(0.0), -4.0));
+        assert_eq!(s.percentile(0.5), 0.0);
+    }
+
+    #[test]
+    fn merge() {
+        let mut stats = stats_of(&["1KiB", "3KiB", "oops"]);
+        stats.merge(stats_of(&["2KiB", "5ms", "-"]));
+        let size = &stats.by_kind[&UnitKind::Size];
+        assert_eq!((size.count, size.min, size.max), (3, 1024.0, 3072.0));
+        assert_eq!(size.sum, 6144.0);
+        assert_eq!(size.percentile(0.5), 2048.0);
+        assert_eq!(stats.by_kind[&UnitKind::Duration].count, 1);
+        assert_eq!(stats.main_kind(), Some(UnitKind::Size));
+        assert_eq!(stats.unparsed, 2);
+
+        // Sketches that outgrew their exact values merge bucket by bucket
+        let (mut low, mut high) = (NumericSummary::default(), NumericSummary::default());
+        for i in 1..=2000 {
+            low.add(i as f64);
+            high.add((i + 2000) as f64);
+        }
+        low.merge(high);
+        assert_eq!((low.count, low.min, low.max), (4000, 1.0, 4000.0));
+        assert!(within_accuracy(low.percentile(0.5), 2000.0));
+        assert!(within_accuracy(low.percentile(0.75), 3000.0));
+    }
+
+    #[test]
+    fn non_finite_values_are_unparsed() {
+        // duration=NaN, and a number too large for an f64
+        let stats = stats_of(&["NaN", "inf", "1e999ms", "12ms", "18ms"]);
+        assert_eq!(stats.unparsed, 3);
+        let s = &stats.by_kind[&UnitKind::Duration];
+        assert_eq!((s.count, s.min, s.max, s.mean()), (2, 12.0, 18.0, 15.0));
+        assert_eq!(s.percentile(1.0), 18.0);
+
+        // Leaves no empty summary behind
+        let stats = stats_of(&["1e999s"]);
+        assert!(stats.by_kind.is_empty());
+        assert_eq!(stats.main_kind(), None);
+
+        let mut s = NumericSummary::default();
+        assert!(!s.add(f64::NAN));
+        assert!(!s.add(f64::NEG_INFINITY));
+        assert!(s.add(1.0));
+        assert_eq!((s.count, s.sum), (1, 1.0));
+    }
+}
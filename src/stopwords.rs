@@ -0,0 +1,126 @@
+//! Words left out of the word counts: --stopwords lists (with a built-in
+//! one of English function words and log noise) and words shorter or
+//! longer than --min-word-len / --max-word-len.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::charset;
+
+/// Left out along with any --stopwords file: English words too common to
+/// say anything, and words nearly every log line has.
+const BUILTIN: &[&str] = &[
+    "a",
+    "an",
+    "and",
+    "are",
+    "as",
+    "at",
+    "be",
+    "been",
+    "but",
+    "by",
+    "can",
+    "could",
+    "did",
+    "do",
+    "does",
+    "for",
+    "from",
+    "had",
+    "has",
+    "have",
+    "if",
+    "in",
+    "into",
+    "is",
+    "it",
+    "its",
+    "no",
+    "not",
+    "of",
+    "on",
+    "or",
+    "so",
+    "than",
+    "that",
+    "the",
+    "then",
+    "there",
+    "these",
+    "this",
+    "those",
+    "to",
+    "was",
+    "were",
+    "will",
+    "with",
+    "would",
+    "debug",
+    "info",
+    "information",
+    "notice",
+    "trace",
+    "verbose",
+    "warn",
+    "warning",
+    "error",
+    "err",
+    "fatal",
+    "critical",
+    "level",
+    "msg",
+    "message",
+    "log",
+    "logger",
+    "time",
+    "timestamp",
+];
+
+/// Which normalized words are counted.
+#[derive(Debug, Clone, Default)]
+pub struct WordFilter {
+    pub stopwords: HashSet<String>,
+    /// In characters; 0 and None leave every length in
+    pub min_len: usize,
+    pub max_len: Option<usize>,
+}
+
+impl WordFilter {
+    /// Adds the built-in list and the words in the file at `path`, one or
+    /// more per line, with `#` starting a comment; lowercased as counted
+    /// words are. `builtin` adds only the built-in list.
+    pub fn load_stopwords(&mut self, path: &str) -> Result<(), String> {
+        self.stopwords
+            .extend(BUILTIN.iter().map(|word| word.to_string()));
+        if path == "builtin" {
+            return Ok(());
+        }
+        let text = charset::read_to_string(Path::new(path), None)
+            .map_err(|e| format!("cannot read --stopwords {}: {}", path, e))?;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            self.stopwords
+                .extend(line.split_whitespace().map(str::to_lowercase));
+        }
+        Ok(())
+    }
+
+    /// The filter as text, for cache::scan_options.
+    pub fn describe(&self) -> String {
+        let mut stopwords: Vec<&str> = self.stopwords.iter().map(String::as_str).collect();
+        stopwords.sort_unstable();
+        format!("{:?} {}..{:?}", stopwords, self.min_len, self.max_len)
+    }
+
+    /// Whether `word`, as normalize_word made it, is counted.
+    pub fn keeps(&self, word: &str) -> bool {
+        if self.min_len > 1 || self.max_len.is_some() {
+            let len = word.chars().count();
+            if len < self.min_len || self.max_len.is_some_and(|max| len > max) {
+                return false;
+            }
+        }
+        !self.stopwords.contains(word)
+    }
+}
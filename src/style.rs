@@ -0,0 +1,243 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::level::Level;
+
+/// Value of --color and --hyperlinks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color only when stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+}
+
+/// A terminal color, written as an ANSI SGR code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    None,
+    Bold,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Gray,
+}
+
+impl Color {
+    fn from_name(name: &str) -> Option<Color> {
+        match name {
+            "none" => Some(Color::None),
+            "bold" => Some(Color::Bold),
+            "red" => Some(Color::Red),
+            "green" => Some(Color::Green),
+            "yellow" => Some(Color::Yellow),
+            "blue" => Some(Color::Blue),
+            "magenta" => Some(Color::Magenta),
+            "cyan" => Some(Color::Cyan),
+            "white" => Some(Color::White),
+            "gray" | "grey" => Some(Color::Gray),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Color::None => "",
+            Color::Bold => "1",
+            Color::Red => "1;31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+            Color::Magenta => "35",
+            Color::Cyan => "1;36",
+            Color::White => "37",
+            Color::Gray => "90",
+        }
+    }
+}
+
+/// Which color each kind of output gets.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// The matched --find text inside a hit line
+    pub hit: Color,
+    /// Section headings in the summary
+    pub header: Color,
+    /// file:line prefix of a hit
+    pub location: Color,
+    pub error: Color,
+    pub warn: Color,
+    pub info: Color,
+    pub debug: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            hit: Color::Red,
+            header: Color::Cyan,
+            location: Color::Magenta,
+            error: Color::Red,
+            warn: Color::Yellow,
+            info: Color::Green,
+            debug: Color::Gray,
+        }
+    }
+}
+
+impl Theme {
+    /// Applies a --theme list such as "hit=yellow,header=bold,error=magenta".
+    pub fn apply(&mut self, list: &str) -> Result<(), String> {
+        for pair in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("theme entry '{}' should look like key=color", pair))?;
+            let color = Color::from_name(value.trim())
+                .ok_or_else(|| format!("unknown color '{}'", value.trim()))?;
+
+            let slot = match key.trim() {
+                "hit" => &mut self.hit,
+                "header" => &mut self.header,
+                "location" => &mut self.location,
+                "error" => &mut self.error,
+                "warn" => &mut self.warn,
+                "info" => &mut self.info,
+                "debug" => &mut self.debug,
+                other => return Err(format!("unknown theme key '{}'", other)),
+            };
+            *slot = color;
+        }
+        Ok(())
+    }
+}
+
+/// Whether --hyperlinks allows OSC 8 links: `auto` needs stdout to be a
+/// terminal other than TERM=dumb.
+pub fn hyperlinks_enabled(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            io::stdout().is_terminal() && env::var("TERM").map_or(true, |t| t != "dumb")
+        }
+    }
+}
+
+/// Wraps `text` in an OSC 8 hyperlink to `url`. Terminals without OSC 8
+/// support show just the text.
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Decides whether to color output, and does the coloring.
+pub struct Style {
+    enabled: bool,
+    theme: Theme,
+}
+
+impl Style {
+    pub fn new(choice: ColorChoice, theme: &Theme) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        };
+        Style {
+            enabled,
+            theme: theme.clone(),
+        }
+    }
+
+    fn paint(&self, color: Color, text: &str) -> String {
+        if !self.enabled || color == Color::None {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    }
+
+    pub fn header(&self, text: &str) -> String {
+        self.paint(self.theme.header, text)
+    }
+
+    pub fn location(&self, text: &str) -> String {
+        self.paint(self.theme.location, text)
+    }
+
+    /// Colors level words (ERROR, WARN, ...) and every case-insensitive
+    /// occurrence of `needle` (already lowercase) in a hit line.
+    pub fn hit_line(&self, line: &str, needle: &str) -> String {
+        if !self.enabled {
+            return line.to_string();
+        }
+
+        let lower = line.to_lowercase();
+        // Byte offsets only line up when lowercasing kept every char the same width
+        let can_highlight = !needle.is_empty() && lower.len() == line.len();
+
+        let mut out = String::with_capacity(line.len() + 32);
+        let mut pos = 0;
+        while pos < line.len() {
+            if can_highlight && lower.get(pos..).is_some_and(|l| l.starts_with(needle)) {
+                if let Some(matched) = line.get(pos..pos + needle.len()) {
+                    out.push_str(&self.paint(self.theme.hit, matched));
+                    pos += needle.len();
+                    continue;
+                }
+            }
+
+            let rest = &line[pos..];
+            let at_word_start = pos == 0 || !line[..pos].ends_with(|c: char| c.is_alphanumeric());
+            if at_word_start {
+                if let Some((word, color)) = self.level_word(rest) {
+                    out.push_str(&self.paint(color, word));
+                    pos += word.len();
+                    continue;
+                }
+            }
+
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            pos += c.len_utf8();
+        }
+        out
+    }
+
+    /// A level keyword at the start of `text`, if it is a whole word.
+    fn level_word<'t>(&self, text: &'t str) -> Option<(&'t str, Color)> {
+        let end = text
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let word = &text[..end];
+        Some((word, self.level_color(Level::from_word(word)?)))
+    }
+
+    /// `text` in the color of `level`.
+    pub fn level(&self, level: Level, text: &str) -> String {
+        self.paint(self.level_color(level), text)
+    }
+
+    fn level_color(&self, level: Level) -> Color {
+        match level {
+            Level::Fatal | Level::Error => self.theme.error,
+            Level::Warn => self.theme.warn,
+            Level::Info => self.theme.info,
+            Level::Debug | Level::Trace => self.theme.debug,
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! --syslog: lines in the BSD (RFC 3164) or IETF (RFC 5424) syslog
+//! format, read as fields (priority, facility, severity, time, host, app,
+//! pid, msg), with lines counted by facility and severity.
+//!
+//! `<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed for lonvick`
+//! `<165>1 2003-10-11T22:14:15.003Z mymachine evntslog 12 ID47 [exampleSDID@32473 iut="3"] An application event`
+//!
+//! Files written by rsyslog often leave the `<PRI>` out, and some write
+//! an ISO 8601 time in the BSD layout; both are read too.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fields::{self, Object};
+use crate::timestamp;
+
+/// Facility names, by facility code.
+pub const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// Severity names, by severity code; all are level words (level::Level).
+pub const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// The fields of a syslog line; None if it isn't one. BSD timestamps
+/// carry no year: they get the one that puts them at most a day after
+/// `near` (Unix millis), when the file was last written or else now.
+pub fn parse(line: &str, near: i64) -> Option<Object> {
+    let mut object = Object::new();
+    let mut rest = line.trim_start();
+    if let Some((priority, after)) = priority(rest) {
+        object.insert("priority".to_string(), priority.into());
+        if let Some(facility) = FACILITIES.get(priority as usize / 8) {
+            object.insert("facility".to_string(), (*facility).into());
+        }
+        object.insert(
+            "severity".to_string(),
+            SEVERITIES[priority as usize % 8].into(),
+        );
+        rest = after;
+    }
+    let parsed = match rest.strip_prefix("1 ") {
+        Some(after) => parse_5424(after, &mut object),
+        None => parse_3164(rest, near, &mut object),
+    };
+    parsed.map(|()| object)
+}
+
+/// `<PRI>` and the text after it.
+fn priority(text: &str) -> Option<(u8, &str)> {
+    let inner = text.strip_prefix('<')?;
+    let end = inner.find('>')?;
+    if end == 0 || end > 3 || !inner[..end].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let priority = inner[..end].parse().ok().filter(|&p: &u8| p < 192)?;
+    Some((priority, &inner[end + 1..]))
+}
+
+/// `TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`, with
+/// `-` for a value left out.
+fn parse_5424(text: &str, object: &mut Object) -> Option<()> {
+    let mut rest = text;
+    for name in ["time", "host", "app", "pid", "msgid"] {
+        let (value, after) = word(rest)?;
+        if value != "-" {
+            object.insert(name.to_string(), value.into());
+        }
+        rest = after;
+    }
+    if let Some(time) = object.get("time").and_then(Value::as_str) {
+        timestamp::parse_iso(time)?;
+    }
+    let (data, message) = structured_data(rest)?;
+    if data != "-" {
+        object.insert("structured_data".to_string(), data.into());
+    }
+    // A UTF-8 message may start with a byte order mark
+    let message = message.trim_start_matches('\u{feff}');
+    object.insert("msg".to_string(), message.into());
+    Some(())
+}
+
+/// `TIMESTAMP HOSTNAME TAG[PID]: MSG`, the timestamp as `Mmm dd HH:MM:SS`
+/// or ISO 8601.
+fn parse_3164(text: &str, near: i64, object: &mut Object) -> Option<()> {
+    let bsd = timestamp::parse_syslog_near(text, near);
+    let (millis, rest) = bsd.map(|millis| (millis, &text[15..])).or_else(|| {
+        let (iso, rest) = word(text)?;
+        Some((
+            timestamp::parse_line(iso, near).filter(|_| iso.contains('T'))?,
+            rest,
+        ))
+    })?;
+    object.insert("time".to_string(), timestamp::format_iso(millis).into());
+    let (host, rest) = word(rest)?;
+    object.insert("host".to_string(), host.into());
+
+    // The tag is the app name, up to a `[pid]` or `:`
+    let tag_end = rest
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/')))
+        .unwrap_or(rest.len());
+    let after_tag = &rest[tag_end..];
+    let (pid, after_pid) = match after_tag.strip_prefix('[') {
+        Some(inner) => match inner.find(']') {
+            Some(end) => (Some(&inner[..end]), &inner[end + 1..]),
+            None => (None, after_tag),
+        },
+        None => (None, after_tag),
+    };
+    let message = match after_pid.strip_prefix(':') {
+        Some(message) if tag_end > 0 => {
+            object.insert("app".to_string(), rest[..tag_end].into());
+            if let Some(pid) = pid {
+                object.insert("pid".to_string(), pid.into());
+            }
+            message.trim_start()
+        }
+        _ => rest,
+    };
+    object.insert("msg".to_string(), message.into());
+    Some(())
+}
+
+/// The text up to the next space, and the text after the spaces after it.
+fn word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let end = text.find(' ').unwrap_or(text.len());
+    if end == 0 {
+        return None;
+    }
+    Some((&text[..end], text[end..].trim_start()))
+}
+
+/// RFC 5424 structured data (`-` or `[id k="v"]...`, where values may
+/// hold escaped `]` and `"`) and the message after it.
+fn structured_data(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix('-') {
+        return Some(("-", rest.strip_prefix(' ').unwrap_or(rest)));
+    }
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while bytes.get(i) == Some(&b'[') {
+        let mut quoted = false;
+        i += 1;
+        loop {
+            match bytes.get(i)? {
+                b'\\' if quoted => i += 1,
+                b'"' => quoted = !quoted,
+                b']' if !quoted => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i += 1;
+    }
+    if i == 0 {
+        return None;
+    }
+    let rest = &text[i..];
+    Some((&text[..i], rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+/// Lines by facility and severity, and by app.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyslogTally {
+    /// Per facility code, lines per severity code; only lines with a
+    /// `<PRI>` count
+    pub facilities: BTreeMap<u8, [usize; 8]>,
+    pub apps: HashMap<String, usize>,
+}
+
+impl SyslogTally {
+    /// Counts the line in `object`, as parse made it.
+    pub fn record(&mut self, object: &Object) {
+        if let Some(priority) = object.get("priority").and_then(Value::as_u64) {
+            let severities = self.facilities.entry(priority as u8 / 8).or_default();
+            severities[priority as usize % 8] += 1;
+        }
+        if let Some(app) = fields::field(object, "app") {
+            match self.apps.get_mut(app.as_ref()) {
+                Some(count) => *count += 1,
+                None => {
+                    self.apps.insert(app.into_owned(), 1);
+                }
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: SyslogTally) {
+        for (facility, more) in other.facilities {
+            let severities = self.facilities.entry(facility).or_default();
+            for (count, more) in severities.iter_mut().zip(more) {
+                *count += more;
+            }
+        }
+        for (app, count) in other.apps {
+            *self.apps.entry(app).or_insert(0) += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-01 12:00:00 UTC
+    const NEAR: i64 = 1_714_564_800_000;
+
+    fn text(object: &Object, name: &str) -> Option<String> {
+        fields::field(object, name).map(|v| v.into_owned())
+    }
+
+    #[test]
+    fn bsd_lines() {
+        let line = parse(
+            "<34>Apr 11 22:14:15 mymachine su[230]: 'su root' failed",
+            NEAR,
+        )
+        .unwrap();
+        assert_eq!(line["priority"], 34);
+        assert_eq!(text(&line, "facility").as_deref(), Some("auth"));
+        assert_eq!(text(&line, "severity").as_deref(), Some("crit"));
+        assert_eq!(
+            text(&line, "time").as_deref(),
+            Some("2024-04-11T22:14:15.000Z")
+        );
+        assert_eq!(text(&line, "host").as_deref(), Some("mymachine"));
+        assert_eq!(text(&line, "app").as_deref(), Some("su"));
+        assert_eq!(text(&line, "pid").as_deref(), Some("230"));
+        assert_eq!(text(&line, "msg").as_deref(), Some("'su root' failed"));
+
+        // rsyslog files: no <PRI>, and maybe an ISO time
+        let line = parse("Apr 30 09:00:01 web-1 CRON: session opened", NEAR).unwrap();
+        assert_eq!(line.get("priority"), None);
+        assert_eq!(text(&line, "app").as_deref(), Some("CRON"));
+        assert_eq!(text(&line, "pid"), None);
+        let line = parse("2024-04-30T09:00:01+00:00 web-1 kernel: oops", NEAR).unwrap();
+        assert_eq!(
+            text(&line, "time").as_deref(),
+            Some("2024-04-30T09:00:01.000Z")
+        );
+        assert_eq!(text(&line, "msg").as_deref(), Some("oops"));
+
+        // No tag: the whole rest is the message
+        let line = parse("Apr 30 09:00:01 web-1 -- MARK --", NEAR).unwrap();
+        assert_eq!(text(&line, "app"), None);
+        assert_eq!(text(&line, "msg").as_deref(), Some("-- MARK --"));
+
+        assert!(parse("2024-04-30 09:00:01 INFO not syslog", NEAR).is_none());
+        assert!(parse("plain text", NEAR).is_none());
+        assert!(parse("<999>Apr 30 09:00:01 web-1 app: bad priority", NEAR).is_none());
+    }
+
+    #[test]
+    fn ietf_lines() {
+        let line = r#"<165>1 2003-10-11T22:14:15.003Z mymachine evntslog 12 ID47 [exampleSDID@32473 iut="3" eventID="1011"] An application event"#;
+        let line = parse(line, NEAR).unwrap();
+        assert_eq!(text(&line, "facility").as_deref(), Some("local4"));
+        assert_eq!(text(&line, "severity").as_deref(), Some("notice"));
+        assert_eq!(
+            text(&line, "time").as_deref(),
+            Some("2003-10-11T22:14:15.003Z")
+        );
+        assert_eq!(text(&line, "app").as_deref(), Some("evntslog"));
+        assert_eq!(text(&line, "pid").as_deref(), Some("12"));
+        assert_eq!(text(&line, "msgid").as_deref(), Some("ID47"));
+        assert_eq!(
+            text(&line, "structured_data").as_deref(),
+            Some(r#"[exampleSDID@32473 iut="3" eventID="1011"]"#)
+        );
+        assert_eq!(text(&line, "msg").as_deref(), Some("An application event"));
+
+        // `-` for what's left out, a BOM before the message, `]` in a value
+        let line = parse("<14>1 - - - - - - \u{feff}hello", NEAR).unwrap();
+        assert_eq!(text(&line, "time"), None);
+        assert_eq!(text(&line, "host"), None);
+        assert_eq!(text(&line, "structured_data"), None);
+        assert_eq!(text(&line, "msg").as_deref(), Some("hello"));
+        let line = parse(r#"<14>1 - h a - - [x k="a\]b"][y] done"#, NEAR).unwrap();
+        assert_eq!(
+            text(&line, "structured_data").as_deref(),
+            Some(r#"[x k="a\]b"][y]"#)
+        );
+        assert_eq!(text(&line, "msg").as_deref(), Some("done"));
+
+        assert!(parse("<14>1 yesterday h a - - - bad time", NEAR).is_none());
+        assert!(parse(r#"<14>1 - h a - - [x k="open"#, NEAR).is_none());
+    }
+
+    #[test]
+    fn tallies() {
+        let mut tally = SyslogTally::default();
+        for line in [
+            "<34>Apr 11 22:14:15 h su: a",
+            "<35>Apr 11 22:14:15 h su: b",
+            "Apr 11 22:14:15 h cron: c",
+        ] {
+            tally.record(&parse(line, NEAR).unwrap());
+        }
+        let mut other = SyslogTally::default();
+        other.record(&parse("<34>Apr 11 22:14:16 h sshd: d", NEAR).unwrap());
+        tally.merge(other);
+        assert_eq!(tally.facilities.len(), 1);
+        assert_eq!(tally.facilities[&4], [0, 0, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(tally.apps["su"], 2);
+        assert_eq!(tally.apps["cron"], 1);
+        assert_eq!(tally.apps["sshd"], 1);
+    }
+}
@@ -0,0 +1,460 @@
+//! Recognizes timestamps at or near the start of log lines and converts
+//! them to Unix milliseconds (UTC). Timestamps without a zone are taken as
+//! UTC.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Days of the week, from the one 1970-01-01 fell on.
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// How far into a line an ISO 8601 timestamp is looked for, after a
+/// host name, level or `{"ts":"` say.
+const SEARCH_BYTES: usize = 64;
+
+/// Epoch timestamps are taken as such only between 2000 and 2100, so
+/// that ids and counts at the start of a line aren't.
+const EPOCH_RANGE: std::ops::Range<i64> = 946_684_800..4_102_444_800;
+
+/// Finds the timestamp of a line, in any supported format: ISO 8601
+/// (`2024-05-01T12:00:00.123Z`, `2024-05-01 12:00:00`), syslog
+/// (`May  1 12:00:00`) or epoch seconds or milliseconds (`1714564800`,
+/// `1714564800123`, `1714564800.123`) at the start; Apache CLF
+/// (`... [01/May/2024:12:00:00 +0000] ...`) anywhere, and ISO 8601 within
+/// the first few words. Syslog dates carry no year: they get the one that
+/// puts them at most a day after `near` (Unix millis), when the file was
+/// last written or else now.
+pub fn parse_line(line: &str, near: i64) -> Option<i64> {
+    let line = line.trim_start();
+    parse_iso(line)
+        .or_else(|| parse_syslog_near(line, near))
+        .or_else(|| parse_epoch(line))
+        .or_else(|| parse_clf(line))
+        .or_else(|| find_iso(line))
+}
+
+/// A point in time given on the command line (--since, --until): `now`,
+/// a date, a date and time down to the minute or second, or any ISO 8601
+/// or epoch timestamp parse_line knows. Unix milliseconds, like it.
+pub fn parse_arg(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    if text == "now" {
+        return Some(now);
+    }
+    let padded = match text.len() {
+        10 => format!("{} 00:00:00", text),
+        16 => format!("{}:00", text),
+        _ => text.to_string(),
+    };
+    parse_iso(&padded).or_else(|| parse_epoch(text))
+}
+
+/// An ISO 8601 timestamp starting a word within the first SEARCH_BYTES.
+fn find_iso(line: &str) -> Option<i64> {
+    let window = &line.as_bytes()[..line.len().min(SEARCH_BYTES)];
+    (1..window.len())
+        .filter(|&i| window[i].is_ascii_digit() && !window[i - 1].is_ascii_alphanumeric())
+        .find_map(|i| parse_iso(line.get(i..)?))
+}
+
+/// Seconds (10 digits, optionally with a fraction) or milliseconds (13
+/// digits) since 1970, followed by a space or the end of the line.
+fn parse_epoch(text: &str) -> Option<i64> {
+    let len = text.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &text[len..];
+    let (millis, rest) = match len {
+        10 => {
+            let secs = digits(&text[..10])?;
+            match rest.strip_prefix('.') {
+                Some(frac) => {
+                    let frac_len = frac.bytes().take_while(u8::is_ascii_digit).count();
+                    let mut ms = frac[..frac_len.min(3)].to_string();
+                    while ms.len() < 3 {
+                        ms.push('0');
+                    }
+                    (secs * 1000 + digits(&ms)?, &frac[frac_len..])
+                }
+                None => (secs * 1000, rest),
+            }
+        }
+        13 => (digits(&text[..13])?, rest),
+        _ => return None,
+    };
+    if !rest.is_empty() && !rest.starts_with(|c: char| c.is_whitespace()) {
+        return None;
+    }
+    EPOCH_RANGE.contains(&(millis / 1000)).then_some(millis)
+}
+
+/// `YYYY-MM-DD[T ]HH:MM:SS[.frac][Z|+hh:mm|-hhmm]`, optionally in brackets.
+pub fn parse_iso(text: &str) -> Option<i64> {
+    let text = text.strip_prefix('[').unwrap_or(text);
+    let b = text.as_bytes();
+    if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    if b[10] != b'T' && b[10] != b' ' {
+        return None;
+    }
+
+    let year = digits(text.get(0..4)?)?;
+    let month = digits(text.get(5..7)?)?;
+    let day = digits(text.get(8..10)?)?;
+    let hour = digits(text.get(11..13)?)?;
+    let minute = digits(text.get(14..16)?)?;
+    let second = digits(text.get(17..19)?)?;
+
+    let mut rest = text.get(19..)?;
+    let mut millis = 0;
+    if let Some(frac) = rest.strip_prefix('.').or_else(|| rest.strip_prefix(',')) {
+        let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        // Keep millisecond precision: "5" -> 500, "123456" -> 123
+        let mut ms = frac[..len.min(3)].to_string();
+        while ms.len() < 3 {
+            ms.push('0');
+        }
+        millis = digits(&ms)?;
+        rest = &frac[len..];
+    }
+
+    let offset = parse_offset(rest).unwrap_or(0);
+    let secs = to_epoch(year, month, day, hour, minute, second)? - offset;
+    Some(secs * 1000 + millis)
+}
+
+/// A syslog timestamp, `Mmm dd HH:MM:SS` with the day maybe space
+/// padded, at the start of `text` in the year that puts it at most a day
+/// after `near` (Unix millis, say when the file was last written): lines
+/// from December in a file written in January are from the year before.
+pub fn parse_syslog_near(text: &str, near: i64) -> Option<i64> {
+    let (year, ..) = civil_time(near.div_euclid(1000));
+    let millis = parse_syslog_in(text, year)?;
+    if millis > near + 86_400_000 {
+        parse_syslog_in(text, year - 1)
+    } else {
+        Some(millis)
+    }
+}
+
+/// `Mmm dd HH:MM:SS` in `year`.
+fn parse_syslog_in(text: &str, year: i64) -> Option<i64> {
+    if text.len() < 15 {
+        return None;
+    }
+    let month = MONTHS.iter().position(|m| text.starts_with(m))? as i64 + 1;
+    let b = text.as_bytes();
+    if b[3] != b' ' || b[9] != b':' || b[12] != b':' {
+        return None;
+    }
+    let day = digits(text.get(4..6)?.trim_start())?;
+    let hour = digits(text.get(7..9)?)?;
+    let minute = digits(text.get(10..12)?)?;
+    let second = digits(text.get(13..15)?)?;
+
+    Some(to_epoch(year, month, day, hour, minute, second)? * 1000)
+}
+
+/// An HTTP date, `Wed, 10 Jan 2024 10:00:00 GMT`, as Last-Modified
+/// headers and Azure's listings give them. Unix milliseconds.
+pub fn parse_http_date(text: &str) -> Option<i64> {
+    let (_, rest) = text.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day = digits(parts.next()?)?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year = digits(parts.next()?)?;
+    let mut time = parts.next()?.split(':');
+    let hour = digits(time.next()?)?;
+    let minute = digits(time.next()?)?;
+    let second = digits(time.next()?)?;
+    if parts.next() != Some("GMT") {
+        return None;
+    }
+    Some(to_epoch(year, month, day, hour, minute, second)? * 1000)
+}
+
+/// Unix seconds as an HTTP date, which parse_http_date reads back.
+pub fn format_http_date(epoch: i64) -> String {
+    let (y, mo, d, h, mi, s) = civil_time(epoch);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[epoch.div_euclid(86_400).rem_euclid(7) as usize],
+        d,
+        month_name(mo),
+        y,
+        h,
+        mi,
+        s
+    )
+}
+
+/// `[dd/Mmm/yyyy:HH:MM:SS +zzzz]` anywhere in the first part of the line,
+/// after other brackets if need be (`[worker-3] 1.2.3.4 - - [10/Oct/...`).
+fn parse_clf(text: &str) -> Option<i64> {
+    text.match_indices('[')
+        .find_map(|(start, _)| parse_clf_at(&text[start + 1..]))
+}
+
+/// The CLF timestamp at the start of `text`, just inside its `[`.
+fn parse_clf_at(text: &str) -> Option<i64> {
+    let inner = text.get(..26)?;
+    let b = inner.as_bytes();
+    if b[2] != b'/' || b[6] != b'/' || b[11] != b':' || b[14] != b':' || b[17] != b':' {
+        return None;
+    }
+
+    let day = digits(inner.get(0..2)?)?;
+    let month = MONTHS.iter().position(|m| Some(*m) == inner.get(3..6))? as i64 + 1;
+    let year = digits(inner.get(7..11)?)?;
+    let hour = digits(inner.get(12..14)?)?;
+    let minute = digits(inner.get(15..17)?)?;
+    let second = digits(inner.get(18..20)?)?;
+    let offset = parse_offset(inner.get(20..)?.trim_start()).unwrap_or(0);
+
+    Some((to_epoch(year, month, day, hour, minute, second)? - offset) * 1000)
+}
+
+/// `Z`, `+hh:mm`, `-hhmm` -> offset east of UTC in seconds.
+fn parse_offset(text: &str) -> Option<i64> {
+    if text.starts_with('Z') {
+        return Some(0);
+    }
+    let sign = match text.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let rest = text[1..].replace(':', "");
+    let hours = digits(rest.get(0..2)?)?;
+    let minutes = digits(rest.get(2..4)?)?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn digits(text: &str) -> Option<i64> {
+    if text.is_empty() || !text.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Calendar date and time (UTC) to Unix seconds, rejecting impossible
+/// values such as February 30th or 24:00.
+fn to_epoch(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Days in `month` (1-based) of `year`, in the Gregorian calendar.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Howard Hinnant's days-from-civil algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Splits Unix seconds into UTC (year, month, day, hour, minute, second).
+pub fn civil_time(epoch: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = epoch.div_euclid(86_400);
+    let secs = epoch.rem_euclid(86_400);
+
+    // Howard Hinnant's days-to-civil algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        (secs / 3600) as u32,
+        (secs / 60 % 60) as u32,
+        (secs % 60) as u32,
+    )
+}
+
+/// The time now, as Unix millis.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// `2024-05-01 12:00:00.123` (UTC), for reports.
+pub fn format_millis(millis: i64) -> String {
+    let (y, mo, d, h, mi, s) = civil_time(millis.div_euclid(1000));
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        y,
+        mo,
+        d,
+        h,
+        mi,
+        s,
+        millis.rem_euclid(1000)
+    )
+}
+
+/// `2024-05-01T12:00:00.000Z`, which parse_line reads back.
+pub fn format_iso(millis: i64) -> String {
+    format_millis(millis).replacen(' ', "T", 1) + "Z"
+}
+
+/// Short month name for a 1-based month number.
+pub fn month_name(month: u32) -> &'static str {
+    MONTHS[month as usize - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_dates_take_the_year_near() {
+        // 2024-05-01 12:00:00 UTC
+        let near = 1_714_564_800_000;
+        let may = parse_line("May  1 11:00:00 web-1 sshd[42]: ok", near);
+        assert_eq!(may, Some(1_714_561_200_000));
+        assert_eq!(format_millis(may.unwrap()), "2024-05-01 11:00:00.000");
+        // December lines in a file written in May are from the year before
+        let december = parse_line("Dec 31 23:00:00 web-1 cron: ok", near).unwrap();
+        assert_eq!(format_millis(december), "2023-12-31 23:00:00.000");
+        // Up to a day of clock difference stays in the same year
+        let tomorrow = parse_syslog_near("May  2 11:00:00", near).unwrap();
+        assert_eq!(format_millis(tomorrow), "2024-05-02 11:00:00.000");
+        // Feb 29 only in leap years
+        assert!(parse_syslog_near("Feb 29 00:00:00", near).is_some());
+        assert_eq!(
+            parse_syslog_near("Feb 29 00:00:00", 1_683_000_000_000),
+            None
+        );
+    }
+
+    #[test]
+    fn formats() {
+        // 2024-05-01 12:00:00 UTC
+        let noon = 1_714_564_800_000;
+        assert_eq!(parse_line("2024-05-01T12:00:00Z GET /", 0), Some(noon));
+        assert_eq!(parse_line("2024-05-01 12:00:00 INFO up", 0), Some(noon));
+        assert_eq!(
+            parse_line("[2024-05-01T12:00:00.5] ok", 0),
+            Some(noon + 500)
+        );
+        assert_eq!(
+            parse_line("2024-05-01 12:00:00,123456 ok", 0),
+            Some(noon + 123)
+        );
+        assert_eq!(parse_line("2024-05-01T14:00:00+02:00 ok", 0), Some(noon));
+        assert_eq!(parse_line("2024-05-01T07:30:00-0430 ok", 0), Some(noon));
+        assert_eq!(parse_line("1714564800 up", 0), Some(noon));
+        assert_eq!(parse_line("1714564800.25 up", 0), Some(noon + 250));
+        assert_eq!(parse_line("1714564800123 up", 0), Some(noon + 123));
+        assert_eq!(
+            parse_line(
+                r#"10.0.0.1 - - [01/May/2024:14:00:00 +0200] "GET / HTTP/1.1" 200"#,
+                0
+            ),
+            Some(noon)
+        );
+        // Past brackets that aren't the timestamp
+        assert_eq!(
+            parse_line(
+                r#"[worker-3] [é] 10.0.0.1 - - [01/May/2024:12:00:00 +0000] "GET / HTTP/1.1" 200"#,
+                0
+            ),
+            Some(noon)
+        );
+        assert_eq!(parse_line("[worker-3] [01/May/2024] up", 0), None);
+        assert_eq!(
+            parse_line(r#"web-1 INFO {"ts":"2024-05-01T12:00:00Z"}"#, 0),
+            Some(noon)
+        );
+
+        // Numbers that aren't times
+        assert_eq!(parse_line("123456789 requests", 0), None);
+        assert_eq!(parse_line("1714564800x", 0), None);
+        assert_eq!(parse_line("9999999999 is out of range", 0), None);
+        assert_eq!(parse_line("request done", 0), None);
+        assert_eq!(parse_line("2024-05-01T12:00:00. ok", 0), None);
+    }
+
+    #[test]
+    fn arguments() {
+        let noon = 1_714_564_800_000;
+        assert_eq!(parse_arg("now", 7), Some(7));
+        assert_eq!(parse_arg("2024-05-01", 0), Some(noon - 43_200_000));
+        assert_eq!(parse_arg("2024-05-01 12:00", 0), Some(noon));
+        assert_eq!(parse_arg(" 2024-05-01T12:00:00Z ", 0), Some(noon));
+        assert_eq!(parse_arg("1714564800", 0), Some(noon));
+        assert_eq!(parse_arg("yesterday", 0), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let millis = 1_714_564_800_123;
+        assert_eq!(format_millis(millis), "2024-05-01 12:00:00.123");
+        assert_eq!(parse_iso(&format_iso(millis)), Some(millis));
+        assert_eq!(civil_time(1_714_564_800), (2024, 5, 1, 12, 0, 0));
+        assert_eq!(civil_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(month_name(5), "May");
+    }
+
+    #[test]
+    fn impossible_dates() {
+        assert_eq!(parse_line("2024-02-30 00:00:00 boot", 0), None);
+        assert_eq!(parse_line("2023-02-29 00:00:00 boot", 0), None);
+        assert_eq!(parse_line("2024-04-31T00:00:00Z boot", 0), None);
+        assert_eq!(parse_line("2024-13-01T00:00:00Z boot", 0), None);
+        assert_eq!(parse_line("2024-05-01T24:00:00Z boot", 0), None);
+        assert!(parse_line("2024-02-29 00:00:00 boot", 0).is_some());
+        assert!(parse_line("2000-02-29 00:00:00 boot", 0).is_some());
+        assert_eq!(parse_line("1900-02-29 00:00:00 boot", 0), None);
+        assert!(parse_line("2024-12-31T23:59:60Z leap second", 0).is_some());
+    }
+
+    #[test]
+    fn http_dates() {
+        let millis = parse_http_date("Wed, 10 Jan 2024 13:00:00 GMT");
+        assert_eq!(millis, Some(1_704_891_600_000));
+        assert_eq!(
+            format_http_date(1_704_891_600),
+            "Wed, 10 Jan 2024 13:00:00 GMT"
+        );
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            parse_http_date(&format_http_date(951_782_400)),
+            Some(951_782_400_000)
+        );
+        assert_eq!(parse_http_date("Wed, 10 Jan 2024 13:00:00 +0100"), None);
+        assert_eq!(parse_http_date("10 Jan 2024 13:00:00 GMT"), None);
+    }
+}
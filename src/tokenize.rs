@@ -0,0 +1,184 @@
+//! Splitting messages into the words that are counted, at Unicode word
+//! boundaries (UAX #29): runs of letters, digits and `_`, held together by
+//! `.` and `'` between letters or digits (`e.g`, `1.5`, `don't`) and by
+//! `,` between digits (`1,024`). Logs also write dates and times with `-`
+//! and `:` between digits, so those hold words together too. Chinese and
+//! Japanese aren't split into dictionary words: each Han character and
+//! hiragana is a word of its own, since those scripts don't put spaces
+//! between words, while katakana runs and Korean (which does space its
+//! words) stay whole. --tokenizer whitespace splits at spaces only, as
+//! logbuddy used to.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Value of --tokenizer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tokenizer {
+    #[default]
+    Unicode,
+    /// Whitespace-separated tokens, punctuation trimmed from their ends
+    /// only: faster, and keeps `user=bob` or `/api/v1` whole
+    Whitespace,
+}
+
+impl Tokenizer {
+    pub fn from_name(name: &str) -> Option<Tokenizer> {
+        match name {
+            "unicode" => Some(Tokenizer::Unicode),
+            "whitespace" => Some(Tokenizer::Whitespace),
+            _ => None,
+        }
+    }
+
+    /// Calls `f` with every word of `text`, not yet normalized.
+    pub fn for_each_word<'t>(self, text: &'t str, f: impl FnMut(&'t str)) {
+        match self {
+            Tokenizer::Whitespace => text.split_whitespace().for_each(f),
+            Tokenizer::Unicode => segment(text, f),
+        }
+    }
+}
+
+/// The words of `text` by UAX #29, from the unicode-segmentation crate,
+/// with digits either side of a `-` or `:` held together as well.
+fn segment<'t>(text: &'t str, mut f: impl FnMut(&'t str)) {
+    // Byte range of the word so far, and whether a `-` or `:` follows it
+    let mut word: Option<(usize, usize)> = None;
+    let mut joiner = false;
+    for (i, piece) in text.split_word_bound_indices() {
+        let end = i + piece.len();
+        if piece.chars().any(char::is_alphanumeric) {
+            match &mut word {
+                Some((_, word_end))
+                    if joiner && piece.starts_with(|c: char| c.is_ascii_digit()) =>
+                {
+                    *word_end = end;
+                }
+                _ => {
+                    if let Some((start, word_end)) = word.replace((i, end)) {
+                        f(&text[start..word_end]);
+                    }
+                }
+            }
+            joiner = false;
+        } else if matches!(piece, "-" | ":")
+            && !joiner
+            && word.is_some_and(|(_, word_end)| word_end == i)
+            && text[..i].ends_with(|c: char| c.is_ascii_digit())
+        {
+            joiner = true;
+        } else {
+            if let Some((start, word_end)) = word.take() {
+                f(&text[start..word_end]);
+            }
+            joiner = false;
+        }
+    }
+    if let Some((start, word_end)) = word {
+        f(&text[start..word_end]);
+    }
+}
+
+/// Combining marks: accents written as their own code point after the
+/// letter.
+pub fn is_mark(c: char) -> bool {
+    matches!(c,
+        '\u{300}'..='\u{36f}'
+        | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{fe20}'..='\u{fe2f}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(tokenizer: Tokenizer, text: &str) -> Vec<&str> {
+        let mut words = Vec::new();
+        tokenizer.for_each_word(text, |w| words.push(w));
+        words
+    }
+
+    fn unicode(text: &str) -> Vec<&str> {
+        words(Tokenizer::Unicode, text)
+    }
+
+    #[test]
+    fn ascii() {
+        assert_eq!(
+            unicode("ERROR user=bob at 12:30:01, e.g. don't retry 1,024 x-y /api/v1"),
+            [
+                "ERROR", "user", "bob", "at", "12:30:01", "e.g", "don't", "retry", "1,024", "x",
+                "y", "api", "v1"
+            ]
+        );
+        assert_eq!(unicode("2024-05-01 end."), ["2024-05-01", "end"]);
+        assert_eq!(
+            unicode("at 2024-05-01T12:30:01Z user_id=7 a.1 -5 5- 1--2"),
+            [
+                "at",
+                "2024-05-01T12:30:01Z",
+                "user_id",
+                "7",
+                "a",
+                "1",
+                "5",
+                "5",
+                "1",
+                "2"
+            ]
+        );
+        assert!(unicode("  -- ... ").is_empty());
+    }
+
+    #[test]
+    fn accented_and_cyrillic() {
+        assert_eq!(unicode("échec: café fermé"), ["échec", "café", "fermé"]);
+        // "é" as e + combining acute accent stays in its word
+        assert_eq!(unicode("cafe\u{301} ok"), ["cafe\u{301}", "ok"]);
+        assert_eq!(
+            unicode("Ошибка: соединение разорвано"),
+            ["Ошибка", "соединение", "разорвано"]
+        );
+        assert_eq!(unicode("l’erreur"), ["l’erreur"]);
+    }
+
+    #[test]
+    fn cjk() {
+        // Han and hiragana a character at a time, katakana and Hangul
+        // runs whole
+        assert_eq!(unicode("接続に失敗"), ["接", "続", "に", "失", "敗"]);
+        assert_eq!(unicode("サーバー停止"), ["サーバー", "停", "止"]);
+        assert_eq!(unicode("연결 실패"), ["연결", "실패"]);
+    }
+
+    #[test]
+    fn mixed_scripts() {
+        assert_eq!(
+            unicode("timeout таймаут 超时 v2.1"),
+            ["timeout", "таймаут", "超", "时", "v2.1"]
+        );
+        assert_eq!(unicode("db连接失败"), ["db", "连", "接", "失", "败"]);
+    }
+
+    #[test]
+    fn whitespace_keeps_tokens_whole() {
+        assert_eq!(
+            words(
+                Tokenizer::Whitespace,
+                "ERROR user=bob /api/v1, 12:30:01 e.g."
+            ),
+            ["ERROR", "user=bob", "/api/v1,", "12:30:01", "e.g."]
+        );
+        assert_eq!(
+            words(Tokenizer::Whitespace, "超时 エラー"),
+            ["超时", "エラー"]
+        );
+        assert_eq!(
+            Tokenizer::from_name("whitespace"),
+            Some(Tokenizer::Whitespace)
+        );
+        assert_eq!(Tokenizer::from_name("icu"), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Stack traces in --multiline entries: Java (and JavaScript) `at ...`
+//! frames after an exception line, and Python tracebacks. Each is reduced
+//! to a signature, the exception type and its top frames without line
+//! numbers or addresses, so the same failure counts once however often
+//! and wherever it is logged.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Frames of a trace that make up its signature: the ones nearest the
+/// throw.
+const SIGNATURE_FRAMES: usize = 3;
+
+/// An exception and where it was thrown, as found in one entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    /// `java.lang.NullPointerException`, `ValueError`, ...
+    pub exception: String,
+    /// The message after the type, if any
+    pub message: String,
+    /// Normalized, the frame nearest the throw first
+    pub frames: Vec<String>,
+}
+
+impl Trace {
+    fn signature(&self) -> String {
+        format!("{} {}", self.exception, self.frames.join(" < "))
+    }
+}
+
+/// The stack trace in `entry`, if it holds one.
+pub fn find(entry: &str) -> Option<Trace> {
+    let lines: Vec<&str> = entry.lines().collect();
+    find_python(&lines).or_else(|| find_java(&lines))
+}
+
+/// `Traceback (most recent call last):`, then `File "x.py", line 3, in f`
+/// frames (with their source lines), then `ValueError: message`.
+fn find_python(lines: &[&str]) -> Option<Trace> {
+    let start = lines.iter().position(|l| {
+        l.trim_start()
+            .starts_with("Traceback (most recent call last)")
+    })?;
+    let mut frames = Vec::new();
+    let mut exception = None;
+    for line in &lines[start + 1..] {
+        let trimmed = line.trim();
+        if let Some(frame) = trimmed.strip_prefix("File \"") {
+            let (file, rest) = frame.split_once('"')?;
+            let function = rest.rsplit_once(", in ").map_or("", |(_, f)| f.trim());
+            frames.push(format!("{} in {}", file, function));
+        } else if !line.starts_with(char::is_whitespace) && !trimmed.is_empty() {
+            exception = Some(trimmed);
+            break;
+        }
+    }
+    let (exception, message) = exception_line(exception?)?;
+    // Python lists the frame nearest the throw last
+    frames.reverse();
+    frames.truncate(SIGNATURE_FRAMES);
+    Some(Trace {
+        exception,
+        message,
+        frames,
+    })
+}
+
+/// An exception line, like `java.lang.IllegalStateException: closed`,
+/// followed by `at com.example.Foo.bar(Foo.java:42)` frames.
+fn find_java(lines: &[&str]) -> Option<Trace> {
+    let first = lines.iter().position(|l| java_frame(l).is_some())?;
+    let (exception, message) = exception_line(lines[..first].last()?)?;
+    let frames = lines[first..]
+        .iter()
+        .map_while(|l| java_frame(l))
+        .take(SIGNATURE_FRAMES)
+        .collect();
+    Some(Trace {
+        exception,
+        message,
+        frames,
+    })
+}
+
+/// The frame in an `at method(File.java:42)` line, normalized: without
+/// line and column numbers, with number runs in generated names
+/// (`lambda$main$0`, `GeneratedMethodAccessor12`) as `N` and addresses
+/// (`0x51a8c`) as `0x`.
+fn java_frame(line: &str) -> Option<String> {
+    if !line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let frame = line.trim_start().strip_prefix("at ")?.trim_end();
+    if frame.is_empty() {
+        return None;
+    }
+    let (method, location) = match frame.split_once('(') {
+        Some((method, location)) => (method.trim_end(), location.trim_end_matches(')')),
+        None => (frame, ""),
+    };
+    let mut location = location;
+    while let Some((before, number)) = location.rsplit_once(':') {
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            break;
+        }
+        location = before;
+    }
+    let method = without_numbers(method);
+    if location.is_empty() {
+        Some(method)
+    } else {
+        Some(format!("{}({})", method, location))
+    }
+}
+
+fn without_numbers(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(hex) = rest.strip_prefix("0x") {
+            out.push_str("0x");
+            rest = hex.trim_start_matches(|c: char| c.is_ascii_hexdigit());
+        } else if c.is_ascii_digit() {
+            out.push('N');
+            rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// The exception type and message of a line like `Caused by:
+/// x.y.Oops: message`, `Exception in thread "main" x.y.Oops` or
+/// `... ERROR request failed: KeyError: 'id'`. The type is the first word
+/// before a `: ` that is dotted or ends in Exception, Error or the like,
+/// else the one before the first `: `; None if that isn't a name.
+fn exception_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim().trim_end_matches(':');
+    let line = line.strip_prefix("Caused by:").unwrap_or(line).trim_start();
+    let parts: Vec<&str> = line.split(": ").collect();
+    let typed = parts.iter().position(|part| {
+        type_name(part).is_some_and(|word| {
+            word.contains('.')
+                || ["Exception", "Error", "Throwable", "Warning", "Panic"]
+                    .iter()
+                    .any(|suffix| word.ends_with(suffix))
+        })
+    });
+    let at = typed.unwrap_or(0);
+    let exception = type_name(parts[at])?;
+    Some((exception.to_string(), parts[at + 1..].join(": ")))
+}
+
+/// The last word of `part`, if it is a type name.
+fn type_name(part: &str) -> Option<&str> {
+    part.rsplit(' ').next().filter(|word| is_type(word))
+}
+
+fn is_type(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+/// The entries with one signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceStat {
+    pub count: usize,
+    pub exception: String,
+    pub frames: Vec<String>,
+    /// `file:line` and message of the first entry seen
+    pub example: String,
+    pub message: String,
+}
+
+/// Stack traces by signature.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TraceTally {
+    pub traces: HashMap<String, TraceStat>,
+}
+
+impl TraceTally {
+    pub fn record(&mut self, trace: Trace, file_name: &str, line_number: usize) {
+        let signature = trace.signature();
+        match self.traces.get_mut(&signature) {
+            Some(stat) => stat.count += 1,
+            None => {
+                self.traces.insert(
+                    signature,
+                    TraceStat {
+                        count: 1,
+                        exception: trace.exception,
+                        frames: trace.frames,
+                        example: format!("{}:{}", file_name, line_number),
+                        message: trace.message,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Adds the traces of files scanned after the ones already in `self`,
+    /// keeping the earlier examples.
+    pub fn merge(&mut self, other: TraceTally) {
+        for (signature, more) in other.traces {
+            match self.traces.get_mut(&signature) {
+                Some(stat) => stat.count += more.count,
+                None => {
+                    self.traces.insert(signature, more);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+/// What a parsed number measures. Values are normalized to one canonical
+/// unit per kind, so "2.3s" and "15ms" can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum UnitKind {
+    /// Canonical unit: milliseconds
+    Duration,
+    /// Canonical unit: bytes
+    Size,
+    /// A bare number such as "42" or "1.2e3"
+    Plain,
+}
+
+impl UnitKind {
+    pub fn canonical_unit(self) -> &'static str {
+        match self {
+            UnitKind::Duration => "ms",
+            UnitKind::Size => "B",
+            UnitKind::Plain => "",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitKind::Duration => "duration",
+            UnitKind::Size => "size",
+            UnitKind::Plain => "number",
+        }
+    }
+}
+
+/// A number with its unit already applied: `value` is in the kind's
+/// canonical unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub kind: UnitKind,
+}
+
+/// Unit suffixes and their factor to the canonical unit. A suffix matches
+/// whole, so "ms" is never "m" followed by something.
+const UNITS: &[(&str, f64, UnitKind)] = &[
+    ("ns", 1e-6, UnitKind::Duration),
+    ("us", 1e-3, UnitKind::Duration),
+    ("µs", 1e-3, UnitKind::Duration),
+    ("ms", 1.0, UnitKind::Duration),
+    ("min", 60_000.0, UnitKind::Duration),
+    ("s", 1000.0, UnitKind::Duration),
+    ("m", 60_000.0, UnitKind::Duration),
+    ("h", 3_600_000.0, UnitKind::Duration),
+    ("d", 86_400_000.0, UnitKind::Duration),
+    ("KiB", 1024.0, UnitKind::Size),
+    ("MiB", 1024.0 * 1024.0, UnitKind::Size),
+    ("GiB", 1024.0 * 1024.0 * 1024.0, UnitKind::Size),
+    ("TiB", 1024.0 * 1024.0 * 1024.0 * 1024.0, UnitKind::Size),
+    ("kB", 1e3, UnitKind::Size),
+    ("KB", 1e3, UnitKind::Size),
+    ("MB", 1e6, UnitKind::Size),
+    ("GB", 1e9, UnitKind::Size),
+    ("TB", 1e12, UnitKind::Size),
+    ("B", 1.0, UnitKind::Size),
+];
+
+/// Parses "15ms", "2.3s", "512KiB", "1.2e3" or "42" into a normalized
+/// quantity. Returns None for anything that isn't a number with a known unit.
+pub fn parse_quantity(text: &str) -> Option<Quantity> {
+    let text = text.trim();
+    let number_len = number_prefix_len(text);
+    if number_len == 0 {
+        return None;
+    }
+
+    let value: f64 = text[..number_len].parse().ok()?;
+    let suffix = text[number_len..].trim_start();
+
+    if suffix.is_empty() {
+        return Some(Quantity {
+            value,
+            kind: UnitKind::Plain,
+        });
+    }
+
+    let (_, factor, kind) = UNITS.iter().find(|(unit, _, _)| *unit == suffix)?;
+    Some(Quantity {
+        value: value * factor,
+        kind: *kind,
+    })
+}
+
+/// Length of the leading decimal number, including sign, fraction and
+/// exponent ("1.2e3rest" -> 5). An "e" only counts when digits follow it.
+fn number_prefix_len(text: &str) -> usize {
+    let b = text.as_bytes();
+    let mut i = 0;
+    if i < b.len() && (b[i] == b'-' || b[i] == b'+') {
+        i += 1;
+    }
+    let int_start = i;
+    while i < b.len() && b[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i < b.len() && b[i] == b'.' {
+        i += 1;
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    if i == int_start || (i == int_start + 1 && b[int_start] == b'.') {
+        return 0;
+    }
+
+    if i < b.len() && (b[i] == b'e' || b[i] == b'E') {
+        let mut j = i + 1;
+        if j < b.len() && (b[j] == b'-' || b[j] == b'+') {
+            j += 1;
+        }
+        let exp_start = j;
+        while j < b.len() && b[j].is_ascii_digit() {
+            j += 1;
+        }
+        if j > exp_start {
+            i = j;
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(text: &str) -> Option<(f64, UnitKind)> {
+        parse_quantity(text).map(|q| (q.value, q.kind))
+    }
+
+    #[test]
+    fn parses_quantities() {
+        assert_eq!(quantity("15ms"), Some((15.0, UnitKind::Duration)));
+        assert_eq!(quantity("250µs"), Some((0.25, UnitKind::Duration)));
+        assert_eq!(quantity("250us"), quantity("250µs"));
+        assert_eq!(quantity("2.3s"), Some((2300.0, UnitKind::Duration)));
+        assert_eq!(quantity("2m"), Some((120_000.0, UnitKind::Duration)));
+        assert_eq!(quantity("2min"), quantity("2m"));
+        assert_eq!(quantity("512KiB"), Some((524_288.0, UnitKind::Size)));
+        assert_eq!(quantity("3kB"), Some((3000.0, UnitKind::Size)));
+        assert_eq!(quantity(" 1.5 MiB "), Some((1_572_864.0, UnitKind::Size)));
+        assert_eq!(quantity("-1.2e3"), Some((-1200.0, UnitKind::Plain)));
+        assert_eq!(quantity("42"), Some((42.0, UnitKind::Plain)));
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        for text in ["15 parsecs", "15mss", "15Ms", "12kib", "ms", "", "-", "1e"] {
+            assert_eq!(quantity(text), None, "{:?}", text);
+        }
+        // "1e" is a number followed by an unknown "e"
+        assert_eq!(number_prefix_len("1e"), 1);
+        assert_eq!(number_prefix_len("1.2e3rest"), 5);
+    }
+}
@@ -0,0 +1,53 @@
+//! logbuddy's own resource usage (peak memory, CPU time, I/O wait), read
+//! from /proc on Linux; elsewhere it is not reported.
+
+use std::fs;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Clock ticks per second in /proc/self/stat. Fixed at 100 (USER_HZ) for
+/// everything the kernel exports there.
+const TICKS_PER_SECOND: u64 = 100;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ResourceUsage {
+    /// Peak resident set size
+    pub peak_rss_bytes: u64,
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+    /// Time spent waiting for block I/O; 0 unless the kernel has delay
+    /// accounting enabled
+    pub io_wait_ms: u64,
+}
+
+impl ResourceUsage {
+    /// Usage of this process so far; None where /proc isn't available.
+    pub fn current() -> Option<ResourceUsage> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let peak_kib: u64 = status
+            .lines()
+            .find_map(|l| l.strip_prefix("VmHWM:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+
+        // Fields after the command name, which is in parentheses and may
+        // itself hold spaces; the first one is field 3 (state)
+        let stat = fs::read_to_string("/proc/self/stat").ok()?;
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        let ticks = |field: usize| -> Option<u64> {
+            let ticks: u64 = fields.get(field - 3)?.parse().ok()?;
+            Some(ticks * 1000 / TICKS_PER_SECOND)
+        };
+
+        Some(ResourceUsage {
+            peak_rss_bytes: peak_kib * 1024,
+            user_cpu_ms: ticks(14)?,
+            system_cpu_ms: ticks(15)?,
+            io_wait_ms: ticks(42).unwrap_or(0),
+        })
+    }
+}
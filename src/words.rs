@@ -0,0 +1,137 @@
+//! Exact word counts with interned words: every distinct word is stored
+//! once in a shared text buffer and looked up by hash, instead of one
+//! heap-allocated String per map key.
+
+use std::hash::BuildHasher;
+
+use hashbrown::{DefaultHashBuilder, HashTable};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Default)]
+pub struct WordCounts {
+    /// All distinct words, back to back
+    text: String,
+    /// (start, end) of each word in `text`, by word id
+    spans: Vec<(usize, usize)>,
+    counts: Vec<usize>,
+    /// Word ids, hashed by their text
+    table: HashTable<u32>,
+    hasher: DefaultHashBuilder,
+}
+
+impl WordCounts {
+    pub fn add(&mut self, word: &str, count: usize) {
+        let hash = self.hasher.hash_one(word);
+        let (text, spans) = (&self.text, &self.spans);
+        let found = self.table.find(hash, |&id| {
+            let (start, end) = spans[id as usize];
+            &text[start..end] == word
+        });
+        if let Some(&id) = found {
+            self.counts[id as usize] += count;
+            return;
+        }
+
+        let id = u32::try_from(self.spans.len()).expect("more than 4 billion distinct words");
+        let start = self.text.len();
+        self.text.push_str(word);
+        self.spans.push((start, self.text.len()));
+        self.counts.push(count);
+        let (text, spans, hasher) = (&self.text, &self.spans, &self.hasher);
+        self.table.insert_unique(hash, id, |&id| {
+            let (start, end) = spans[id as usize];
+            hasher.hash_one(&text[start..end])
+        });
+    }
+
+    /// Adds the counts of a file scanned later.
+    pub fn merge(&mut self, other: WordCounts) {
+        if self.spans.is_empty() {
+            *self = other;
+            return;
+        }
+        for (word, count) in other.iter() {
+            self.add(word, count);
+        }
+    }
+
+    /// Rough heap size, for --max-memory.
+    pub fn heap_bytes(&self) -> usize {
+        self.text.capacity()
+            + self.spans.capacity() * std::mem::size_of::<(usize, usize)>()
+            + self.counts.capacity() * std::mem::size_of::<usize>()
+            // A u32 plus a control byte per bucket
+            + self.table.capacity() * 5
+    }
+
+    /// Every word with its count, in the order first seen.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.spans
+            .iter()
+            .zip(&self.counts)
+            .map(|(&(start, end), &count)| (&self.text[start..end], count))
+    }
+}
+
+/// Saved as (word, count) pairs in first-seen order.
+impl Serialize for WordCounts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for WordCounts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs: Vec<(String, usize)> = Vec::deserialize(deserializer)?;
+        let mut counts = WordCounts::default();
+        for (word, count) in &pairs {
+            counts.add(word, *count);
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_in_first_seen_order() {
+        let mut words = WordCounts::default();
+        words.add("timeout", 1);
+        words.add("refused", 2);
+        words.add("timeout", 3);
+        words.add("", 1);
+        let counts: Vec<(&str, usize)> = words.iter().collect();
+        assert_eq!(counts, [("timeout", 4), ("refused", 2), ("", 1)]);
+        assert!(words.heap_bytes() >= "timeoutrefused".len());
+    }
+
+    #[test]
+    fn merges() {
+        let mut words = WordCounts::default();
+        let mut first = WordCounts::default();
+        first.add("a", 1);
+        words.merge(first);
+        let mut second = WordCounts::default();
+        second.add("b", 2);
+        second.add("a", 3);
+        words.merge(second);
+        let counts: Vec<(&str, usize)> = words.iter().collect();
+        assert_eq!(counts, [("a", 4), ("b", 2)]);
+    }
+
+    #[test]
+    fn round_trips_as_pairs() {
+        let mut words = WordCounts::default();
+        words.add("héllo", 2);
+        words.add("world", 1);
+        let json = serde_json::to_string(&words).unwrap();
+        assert_eq!(json, r#"[["héllo",2],["world",1]]"#);
+        let back: WordCounts = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            back.iter().collect::<Vec<_>>(),
+            words.iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! Runs the logbuddy binary over small folders of logs made for each test.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// A new empty folder for one test's logs.
+fn folder(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "logbuddy-test-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn logbuddy(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_logbuddy"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+/// The --format json report of scanning `dir` with `args`.
+fn report(dir: &Path, args: &[&str]) -> (Value, String) {
+    let mut all = vec!["--path", dir.to_str().unwrap(), "--format", "json"];
+    all.extend_from_slice(args);
+    let output = logbuddy(&all);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "logbuddy failed: {}", stderr);
+    (serde_json::from_slice(&output.stdout).unwrap(), stderr)
+}
+
+/// Unix seconds `secs` as a syslog date, `Mmm dd HH:MM:SS` (UTC).
+fn syslog_date(secs: i64) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // Howard Hinnant's civil-from-days
+    let z = secs.div_euclid(86_400) + 719_468;
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "{} {:>2} {:02}:{:02}:{:02}",
+        MONTHS[month as usize - 1],
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[test]
+fn reports_a_folder_of_logs() {
+    let dir = folder("main-report");
+    fs::write(
+        dir.join("app.log"),
+        "2024-05-01 12:00:00 INFO service started\n\
+         2024-05-01 12:00:01 ERROR connection refused to db\n\
+         2024-05-01 12:00:02 WARN retrying connection\n\
+         2024-05-01 12:00:03 ERROR connection refused to db\n",
+    )
+    .unwrap();
+    fs::write(dir.join("notes.txt"), "plain line without level\n").unwrap();
+    fs::write(dir.join("image.png"), "not a log\n").unwrap();
+
+    let (report, _) = report(&dir, &[]);
+    assert_eq!(report["files_scanned"], 2);
+    assert_eq!(report["total_lines"], 5);
+    assert_eq!(report["total_bytes"], 213);
+    assert_eq!(report["lines_without_level"], 1);
+    let level = |name: &str| {
+        let levels = report["levels"].as_array().unwrap();
+        levels.iter().find(|l| l["level"] == name).unwrap()["count"].clone()
+    };
+    assert_eq!(
+        (level("ERROR"), level("WARN"), level("INFO")),
+        (2.into(), 1.into(), 1.into())
+    );
+    let names: Vec<&str> = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["app.log", "notes.txt"]);
+    assert_eq!(
+        report["files"][0]["first_timestamp_ms"],
+        1_714_564_800_000_i64
+    );
+    assert_eq!(report["top_words"][0]["word"], "2024-05-01");
+    assert_eq!(report["top_words"][1]["word"], "connection");
+    assert_eq!(report["top_words"][1]["count"], 3);
+    assert_eq!(report["top_templates"][0]["count"], 2);
+
+    // The same numbers in the text summary
+    let output = logbuddy(&["--path", dir.to_str().unwrap(), "--no-progress"]);
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    for line in [
+        "Files processed  : 2",
+        "Total lines read : 5",
+        "Time range       : 2024-05-01 12:00:00.000 to 2024-05-01 12:00:03.000 (UTC)",
+        "    ERROR           2  40.0%",
+        "    (none)          1",
+        "<*> <*> ERROR connection refused to db",
+    ] {
+        assert!(text.contains(line), "no {:?} in\n{}", line, text);
+    }
+    assert!(!text.contains("image.png"), "{}", text);
+
+    let empty = folder("empty-report");
+    let output = logbuddy(&["--path", empty.to_str().unwrap(), "--no-progress"]);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("Nothing to report."), "{}", text);
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(empty).unwrap();
+}
+
+#[test]
+fn jobs_scan_files_in_their_order() {
+    let dir = folder("jobs");
+    // More files than the threads may scan ahead of the totals
+    for i in 0..60 {
+        let lines: String = (0..i % 7 + 1)
+            .map(|n| format!("ERROR job{} step{} failed\n", i, n))
+            .collect();
+        fs::write(dir.join(format!("{:02}.log", i)), lines).unwrap();
+    }
+    let summary = |jobs: &str| {
+        let (report, _) = report(&dir, &["--jobs", jobs, "--find", "step3"]);
+        let names: Vec<Value> = report["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].clone())
+            .collect();
+        (names, report["total_lines"].clone(), report["hits"].clone())
+    };
+    let one = summary("1");
+    assert_eq!(one.0.len(), 60);
+    assert_eq!(one.0[59], "59.log");
+    assert_eq!(summary("3"), one);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn async_reads_count_as_sync_ones() {
+    let dir = folder("async");
+    // A few of its 512 KiB chunks, with lines across their ends
+    let text: String = (0..40_000)
+        .map(|i| {
+            format!(
+                "2024-05-01 12:00:{:02} WARN worker{} slow {}ms\n",
+                i % 60,
+                i % 13,
+                i
+            )
+        })
+        .collect();
+    fs::write(dir.join("big.log"), text).unwrap();
+    let counts = |args: &[&str]| {
+        let (report, _) = report(&dir, &[&["--find", "worker7"][..], args].concat());
+        (
+            report["total_lines"].clone(),
+            report["total_bytes"].clone(),
+            report["hits"].clone(),
+            report["top_words"].clone(),
+        )
+    };
+    let sync = counts(&[]);
+    assert_eq!(sync.0, 40_000);
+    assert_eq!(counts(&["--io-backend", "async"]), sync);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn last_keeps_this_years_syslog_lines() {
+    let dir = folder("last");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let text = format!(
+        "{} web-1 sshd[7]: ERROR old failure\n{} web-1 sshd[7]: ERROR new failure\n",
+        syslog_date(now - 3 * 3600),
+        syslog_date(now - 600)
+    );
+    fs::write(dir.join("auth.log"), text).unwrap();
+
+    let (hour, _) = report(&dir, &["--last", "1h"]);
+    assert_eq!(hour["total_lines"], 2);
+    assert_eq!(hour["lines_outside_window"], 1);
+    let (day, _) = report(&dir, &["--last", "1d"]);
+    assert_eq!(day["lines_outside_window"], 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn last_takes_unchanged_files_from_the_cache() {
+    let dir = folder("last-cache");
+    let cache = folder("last-cache-entries");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let text = format!(
+        "{} web-1 sshd[7]: ERROR old failure\n{} web-1 sshd[7]: ERROR new failure\n",
+        syslog_date(now - 3 * 3600),
+        syslog_date(now - 600)
+    );
+    fs::write(dir.join("auth.log"), text).unwrap();
+    let run = |last: &str| {
+        let (report, _) = report(&dir, &["--cache", cache.to_str().unwrap(), "--last", last]);
+        (
+            report["files_cached"].clone(),
+            report["lines_outside_window"].clone(),
+        )
+    };
+
+    assert_eq!(run("1h"), (json!(0), json!(1)));
+    // A moment later --last starts elsewhere, but leaves out the same line
+    assert_eq!(run("1h"), (json!(1), json!(1)));
+    // Not when it would keep the old line too
+    assert_eq!(run("1d"), (json!(0), json!(0)));
+    assert_eq!(run("2d"), (json!(1), json!(0)));
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn cached_files_take_their_name_in_this_scan() {
+    let dir = folder("renamed");
+    let cache = folder("renamed-cache");
+    fs::create_dir(dir.join("sub")).unwrap();
+    fs::write(
+        dir.join("sub/a.log"),
+        "ERROR open failed: ENOENT\nINFO ok\n",
+    )
+    .unwrap();
+    let cache_arg = ["--cache", cache.to_str().unwrap()];
+
+    let (whole, _) = report(
+        &dir,
+        &[&cache_arg[..], &["-r", "--find", "ENOENT"]].concat(),
+    );
+    assert_eq!(whole["files"][0]["name"], "sub/a.log");
+    let (sub, _) = report(
+        &dir.join("sub"),
+        &[&cache_arg[..], &["--find", "ENOENT"]].concat(),
+    );
+    assert_eq!(sub["files_cached"], 1);
+    assert_eq!(sub["files"][0]["name"], "a.log");
+    let text = sub.to_string();
+    assert!(!text.contains("sub/a.log"), "{}", text);
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn merge_compensates_clock_skew() {
+    let dir = folder("skew");
+    fs::write(
+        dir.join("api.log"),
+        "2024-01-10T10:00:00Z req=r1 received\n2024-01-10T10:00:10Z req=r2 received\n",
+    )
+    .unwrap();
+    // db's clock is 37s ahead
+    fs::write(
+        dir.join("db.log"),
+        "2024-01-10T10:00:38Z req=r1 queried\n2024-01-10T10:00:48Z req=r2 queried\n",
+    )
+    .unwrap();
+    let merged = |args: &[&str]| {
+        let mut all = vec!["merge", "--path", dir.to_str().unwrap()];
+        all.extend_from_slice(args);
+        let output = logbuddy(&all);
+        assert!(output.status.success());
+        let events: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().to_string())
+            .collect();
+        (events, String::from_utf8(output.stderr).unwrap())
+    };
+
+    let (events, _) = merged(&[]);
+    assert_eq!(events, ["received", "received", "queried", "queried"]);
+    let (events, stderr) = merged(&["--compensate-skew", "--skew-key", "req"]);
+    assert_eq!(events, ["received", "queried", "received", "queried"]);
+    assert!(
+        stderr.contains("db.log: +38.0s against api.log (2 shared events)"),
+        "{}",
+        stderr
+    );
+    let (events, _) = merged(&["--offset", "db.log=+37s"]);
+    assert_eq!(events, ["received", "queried", "received", "queried"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn files_table_groups_rotated_logs() {
+    let dir = folder("rotated");
+    fs::write(dir.join("app.log"), "2024-01-10T10:00:00Z INFO new\n").unwrap();
+    fs::write(dir.join("app.log.1"), "2024-01-09T10:00:00Z INFO old\n").unwrap();
+    fs::write(dir.join("other.log"), "INFO other\n").unwrap();
+    let table = |args: &[&str]| {
+        let mut all = vec![
+            "--path",
+            dir.to_str().unwrap(),
+            "--hide",
+            "all",
+            "--show",
+            "files",
+        ];
+        all.extend_from_slice(args);
+        let output = logbuddy(&all);
+        assert!(output.status.success());
+        let text = String::from_utf8(output.stdout).unwrap();
+        text.lines()
+            .skip_while(|line| *line != "Files:")
+            .skip(2)
+            .map(|line| {
+                line.split("  ")
+                    .find(|s| !s.is_empty())
+                    .unwrap()
+                    .trim()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(table(&[]), ["app.log (2 files)", "other.log"]);
+    assert_eq!(
+        table(&["-v"]),
+        ["app.log (2 files)", "app.log.1", "app.log", "other.log"]
+    );
+    let (report, _) = report(&dir, &[]);
+    assert_eq!(report["files"].as_array().unwrap().len(), 3);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn whitespace_tokenizer_keeps_ascii_tokens_whole() {
+    let dir = folder("tokens");
+    fs::write(
+        dir.join("app.log"),
+        "ERROR user=bob GET /api/v1, retry.\nERROR user=bob timeout\n",
+    )
+    .unwrap();
+    let words = |args: &[&str]| -> Vec<(String, u64)> {
+        let (report, _) = report(&dir, args);
+        report["top_words"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| {
+                (
+                    w["word"].as_str().unwrap().to_string(),
+                    w["count"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    };
+    let expected = |list: &[(&str, u64)]| -> Vec<(String, u64)> {
+        list.iter().map(|&(w, n)| (w.to_string(), n)).collect()
+    };
+
+    assert_eq!(
+        words(&["--tokenizer", "whitespace"]),
+        expected(&[
+            ("error", 2),
+            ("user=bob", 2),
+            ("api/v1", 1),
+            ("get", 1),
+            ("retry", 1),
+            ("timeout", 1)
+        ])
+    );
+    assert_eq!(
+        words(&[]),
+        expected(&[
+            ("bob", 2),
+            ("error", 2),
+            ("user", 2),
+            ("api", 1),
+            ("get", 1),
+            ("retry", 1),
+            ("timeout", 1),
+            ("v1", 1)
+        ])
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn gate_counts_levels_as_the_scan_does() {
+    let dir = folder("gate");
+    let logs = dir.join("logs");
+    fs::create_dir(&logs).unwrap();
+    // Colored, gzipped and rotated lines count as the scan counts them
+    fs::write(
+        logs.join("app.log"),
+        "2024-01-10 10:00:00 ERROR db timeout after 30s\n\
+         \x1b[31mERROR\x1b[0m colored failure\n\
+         WARN slow\nINFO timeout, retrying\n",
+    )
+    .unwrap();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut gz, b"2024-01-09 10:00:00 ERROR db timeout after 31s\n")
+        .unwrap();
+    fs::write(logs.join("app.log.1.gz"), gz.finish().unwrap()).unwrap();
+    let config = dir.join("gate.yaml");
+    fs::write(
+        &config,
+        format!(
+            "path: {}
+budgets:
+  - level: ERROR
+    max: 3
+  - level: WARN
+  - match: TIMEOUT
+    max: 1
+new_fingerprints:
+  baseline: {}
+",
+            logs.display(),
+            dir.join("baseline.txt").display()
+        ),
+    )
+    .unwrap();
+
+    let (scan, _) = report(&logs, &[]);
+    let errors = &scan["levels"][1];
+    assert_eq!(
+        (&errors["level"], errors["count"].as_u64()),
+        (&"ERROR".into(), Some(3))
+    );
+
+    let gate = |args: &[&str]| {
+        let mut all = vec!["gate", "--config", config.to_str().unwrap()];
+        all.extend_from_slice(args);
+        let output = logbuddy(&all);
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+        )
+    };
+    // Found, max and result of the row labelled `label`
+    let row = |table: &str, label: &str| -> Vec<String> {
+        let line = table.lines().find(|l| l.starts_with(label)).unwrap();
+        line[label.len()..]
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    };
+    let (code, table) = gate(&[]);
+    assert_eq!(code, Some(1));
+    assert_eq!(row(&table, "at most 3 ERROR lines"), ["3", "3", "PASS"]);
+    assert_eq!(row(&table, "no WARN lines"), ["1", "0", "FAIL"]);
+    assert_eq!(
+        row(&table, "at most 1 lines containing 'TIMEOUT'"),
+        ["3", "1", "FAIL"]
+    );
+    // The two timeouts share a fingerprint
+    assert_eq!(row(&table, "new fingerprints"), ["2", "0", "FAIL"]);
+
+    assert_eq!(gate(&["--update-baseline"]).0, Some(0));
+    let (_, table) = gate(&[]);
+    assert_eq!(row(&table, "new fingerprints"), ["0", "0", "PASS"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn utf16_multiline_counts_each_byte_once() {
+    let dir = folder("utf16");
+    let text = "2024-01-10 10:00:00 ERROR boom\n  at frame one\n  at frame two\n\
+                2024-01-10 10:00:01 INFO ok\n";
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    fs::write(dir.join("app.log"), &bytes).unwrap();
+
+    for args in [&[][..], &["--multiline"][..]] {
+        let (report, stderr) = report(&dir, args);
+        assert_eq!(report["total_bytes"], bytes.len(), "with {:?}", args);
+        assert_eq!(report["files_transcoded"], 1);
+        assert!(!stderr.contains("grew"), "with {:?}: {}", args, stderr);
+    }
+    let (report, _) = report(&dir, &["--multiline"]);
+    assert_eq!(report["total_lines"], 4);
+    assert_eq!(report["continuation_lines"], 2);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn closed_stdout_ends_the_run_quietly() {
+    let dir = folder("closed-stdout");
+    for i in 1..=3 {
+        fs::write(
+            dir.join(format!("app{}.log", i)),
+            "2024-01-10 10:00:00 ERROR boom\n",
+        )
+        .unwrap();
+    }
+    let path = dir.to_str().unwrap();
+    let metrics = dir.join("metrics.prom");
+    let prometheus = format!("prometheus:{}", metrics.display());
+    let find = [
+        "--path",
+        path,
+        "--find",
+        "boom",
+        "-j",
+        "1",
+        "--sink",
+        "console",
+        "--sink",
+        &prometheus,
+    ];
+    assert!(logbuddy(&["index", "--path", path]).status.success());
+    let query = ["query", "boom", "--path", path];
+    for args in [
+        &["--schema"][..],
+        &["--path", path][..],
+        &find[..],
+        &query[..],
+    ] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_logbuddy"))
+            .args(args)
+            .env_remove("RUST_LOG")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Like `| head` gone before anything was written
+        drop(child.stdout.take());
+        let output = child.wait_with_output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "with {:?}: {}", args, stderr);
+        assert!(
+            !stderr.contains("Broken pipe"),
+            "with {:?}: {}",
+            args,
+            stderr
+        );
+    }
+    // The hit lines had nowhere to go, so the scan stopped after the first file
+    let metrics = fs::read_to_string(metrics).unwrap();
+    assert!(
+        metrics.contains("logbuddy_files_scanned 1\n"),
+        "{}",
+        metrics
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_at_the_folder_prompt_quits() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_logbuddy"))
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Held open, so the prompt keeps waiting for a folder
+    let _stdin = child.stdin.take();
+    thread::sleep(Duration::from_millis(300));
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("still waiting at the prompt after Ctrl-C");
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert_eq!(status.signal(), Some(2), "{:?}", status);
+}